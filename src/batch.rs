@@ -58,3 +58,21 @@ pub struct BurnFromAction {
     pub amount: Uint128,
     pub memo: Option<String>,
 }
+
+/// How an `AllowanceAction` changes the current allowance
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowanceOperation {
+    Increase,
+    Decrease,
+    Set,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct AllowanceAction {
+    pub spender: String,
+    pub amount: Uint128,
+    pub expiration: Option<u64>,
+    pub operation: AllowanceOperation,
+}
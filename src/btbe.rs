@@ -13,16 +13,19 @@ use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
 use crate::constants::{ADDRESS_BYTES_LEN, IMPOSSIBLE_ADDR};
-use crate::dwb::{amount_u64, constant_time_if_else_u32, DelayedWriteBufferEntry, TxBundle};
+use crate::dwb::{
+    amount_u64, constant_time_if_else_u32, DelayedWriteBufferEntry, TxBundle, TX_NODES,
+};
 #[cfg(feature = "gas_tracking")]
 use crate::gas_tracker::GasTracker;
-use crate::state::{safe_add, safe_add_u64, INTERNAL_SECRET_SENSITIVE};
+use crate::state::{safe_add, safe_add_u64, CONFIG, INTERNAL_SECRET_SENSITIVE};
 
 pub const KEY_BTBE_ENTRY_HISTORY: &[u8] = b"btbe-entry-hist";
 pub const KEY_BTBE_BUCKETS_COUNT: &[u8] = b"btbe-buckets-cnt";
 pub const KEY_BTBE_BUCKETS: &[u8] = b"btbe-buckets";
 pub const KEY_BTBE_TRIE_NODES: &[u8] = b"btbe-trie-nodes";
 pub const KEY_BTBE_TRIE_NODES_COUNT: &[u8] = b"btbe-trie-nodes-cnt";
+pub const KEY_BTBE_HISTORY_FLOOR: &[u8] = b"btbe-hist-floor";
 
 const BUCKETING_SALT_BYTES: &[u8; 14] = b"bucketing-salt";
 
@@ -252,6 +255,70 @@ impl StoredEntry {
         // add to list
         self.push_tx_bundle(storage, &tx_bundle)?;
 
+        // drop the oldest bundles once the account keeps more txs than the configured cap
+        let max_tx_history = CONFIG
+            .may_load(storage)?
+            .and_then(|config| config.max_tx_history_per_account);
+        if let Some(max_tx_history) = max_tx_history {
+            self.prune_tx_history(storage, max_tx_history)?;
+        }
+
+        Ok(())
+    }
+
+    /// position of the oldest tx bundle that has not been pruned
+    pub fn history_floor(&self, storage: &dyn Storage) -> u32 {
+        HISTORY_FLOOR
+            .add_suffix(self.address_slice())
+            .load(storage)
+            .unwrap_or_default()
+    }
+
+    /// removes the oldest tx bundles, and their tx nodes, while the account's settled history
+    /// holds more than `max_txs` transactions. the newest bundle is always kept, and bundle
+    /// positions and offsets are left unchanged so the remaining history reads as before.
+    fn prune_tx_history(&self, storage: &mut dyn Storage, max_txs: u32) -> StdResult<()> {
+        let history_len = self.history_len()?;
+        if history_len == 0 {
+            return Ok(());
+        }
+        let last_tx_bundle = self.get_tx_bundle_at(storage, history_len - 1)?;
+        let tx_count = last_tx_bundle.offset + last_tx_bundle.list_len as u32;
+
+        let floor = self.history_floor(storage);
+        let mut new_floor = floor;
+        while new_floor + 1 < history_len {
+            let tx_bundle = self.get_tx_bundle_at(storage, new_floor)?;
+            if tx_count - tx_bundle.offset <= max_txs {
+                break;
+            }
+
+            // remove the bundle's tx nodes; the transactions themselves are shared with the
+            // other party and stay in place
+            let mut node_id = tx_bundle.head_node;
+            while node_id > 0 {
+                let node = TX_NODES.add_suffix(&node_id.to_be_bytes());
+                let next = node.load(storage)?.next;
+                node.remove(storage);
+                node_id = next;
+            }
+
+            storage.remove(
+                &[
+                    KEY_BTBE_ENTRY_HISTORY,
+                    self.address_slice(),
+                    new_floor.to_be_bytes().as_slice(),
+                ]
+                .concat(),
+            );
+            new_floor += 1;
+        }
+
+        if new_floor != floor {
+            HISTORY_FLOOR
+                .add_suffix(self.address_slice())
+                .save(storage, &new_floor)?;
+        }
         Ok(())
     }
 
@@ -385,6 +452,8 @@ pub struct BitwiseTrieNode {
 
 pub static BTBE_TRIE_NODES: Item<BitwiseTrieNode> = Item::new(KEY_BTBE_TRIE_NODES);
 pub static BTBE_TRIE_NODES_COUNT: Item<u64> = Item::new(KEY_BTBE_TRIE_NODES_COUNT);
+/// used with add_suffix(canonical addr of account)
+pub static HISTORY_FLOOR: Item<u32> = Item::new(KEY_BTBE_HISTORY_FLOOR);
 
 impl BitwiseTrieNode {
     // creates a new leaf node
@@ -499,7 +568,8 @@ pub fn find_start_bundle(
     let (node, _, _) = locate_btbe_node(storage, account)?;
     let bucket = node.bucket(storage)?;
     if let Some((_, entry)) = bucket.constant_time_find_address(account) {
-        let mut left = 0u32;
+        // pruned bundles can no longer be searched
+        let mut left = entry.history_floor(storage);
         let mut right = entry.history_len()?;
 
         while left <= right {
@@ -542,6 +612,17 @@ pub fn stored_balance(storage: &dyn Storage, address: &CanonicalAddr) -> StdResu
     }
 }
 
+/// Returns the number of settled transactions that were pruned from the start of an account's history
+pub fn pruned_tx_count(storage: &dyn Storage, entry: &Option<StoredEntry>) -> StdResult<u32> {
+    if let Some(entry) = entry {
+        let floor = entry.history_floor(storage);
+        if floor > 0 {
+            return Ok(entry.get_tx_bundle_at(storage, floor)?.offset);
+        }
+    }
+    Ok(0)
+}
+
 /// Returns the total number of settled transactions for an account by peeking at last bundle
 pub fn stored_tx_count(storage: &dyn Storage, entry: &Option<StoredEntry>) -> StdResult<u32> {
     if let Some(entry) = entry {
@@ -732,6 +813,26 @@ pub fn settle_dwb_entry(
     Ok(())
 }
 
+/// inserts a zero-balance entry with no history for the account, unless it already has one
+pub fn initialize_stored_entry(
+    storage: &mut dyn Storage,
+    address: &CanonicalAddr,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
+) -> StdResult<()> {
+    if stored_entry(storage, address)?.is_some() {
+        return Ok(());
+    }
+
+    // an empty dwb entry only leaves a ghost tx bundle, which the first real one overwrites
+    settle_dwb_entry(
+        storage,
+        &DelayedWriteBufferEntry::new(address)?,
+        None,
+        #[cfg(feature = "gas_tracking")]
+        tracker,
+    )
+}
+
 /// initializes the btbe
 pub fn initialize_btbe(storage: &mut dyn Storage) -> StdResult<()> {
     let bucket = BtbeBucket::new()?;
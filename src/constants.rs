@@ -16,3 +16,21 @@ pub const IMPOSSIBLE_ADDR: [u8; ADDRESS_BYTES_LEN] = [
     0x29, 0xCF, 0xC6, 0x37, 0x62, 0x55, 0xA7, 0x84, 0x51, 0xEE, 0xB4, 0xB1, 0x29, 0xED, 0x8E, 0xAC,
     0xFF, 0xA2, 0xFE, 0xEF,
 ];
+
+/// maximum length in bytes of a memo stored in transaction history
+pub const MAX_MEMO_LENGTH: usize = 256;
+
+/// maximum length in bytes of the label an account can register alongside its receiver code hash
+pub const MAX_RECEIVER_LABEL_LENGTH: usize = 64;
+
+/// maximum number of native denoms the contract can accept deposits in
+pub const MAX_SUPPORTED_DENOMS: usize = 10;
+
+/// maximum length in bytes of a batch idempotency key
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 64;
+
+/// number of seconds an idempotency key is remembered for after it is used
+pub const IDEMPOTENCY_KEY_TTL_SECONDS: u64 = 24 * 60 * 60;
+
+/// number of blocks an account must wait between `CreateViewingKey` calls
+pub const VIEWING_KEY_CREATION_COOLDOWN_BLOCKS: u64 = 10;
@@ -3,9 +3,9 @@ use cosmwasm_std::Api;
 /// This contract implements SNIP-20 standard:
 /// https://github.com/SecretFoundation/SNIPs/blob/master/SNIP-20.md
 use cosmwasm_std::{
-    entry_point, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    entry_point, to_binary, Binary, CanonicalAddr, Deps, DepsMut, Env, MessageInfo, Reply,
+    Response, StdError, StdResult, Storage,
 };
-use secret_toolkit::notification::{DirectChannel, GroupChannel};
 use secret_toolkit::permit::{Permit, TokenPermissions};
 use secret_toolkit::utils::{pad_handle_result, pad_query_result};
 use secret_toolkit::viewing_key::{ViewingKey, ViewingKeyStore};
@@ -16,31 +16,32 @@ use crate::{
 };
 
 #[cfg(feature = "gas_tracking")]
-use crate::dwb::log_dwb;
+use crate::dwb::{log_account_status, log_dwb};
 use crate::dwb::{DelayedWriteBuffer, DWB};
 
 use crate::btbe::initialize_btbe;
 
+use crate::constants::MAX_SUPPORTED_DENOMS;
 #[cfg(feature = "gas_tracking")]
-use crate::gas_tracker::GasTracker;
+use crate::gas_tracker::{query_gas_profile, GasTracker};
 #[cfg(feature = "gas_evaporation")]
 use crate::msg::Evaporator;
 use crate::msg::{
     ContractStatusLevel, ExecuteMsg, InstantiateMsg, QueryAnswer, QueryMsg, QueryWithPermit,
+    RemainderPolicy,
 };
-use crate::notifications::{
-    AllowanceNotification, MultiRecvdNotification, MultiSpentNotification, RecvdNotification,
-    SpentNotification,
-};
+use crate::notifications::builtin_channel_schemata;
 use crate::state::{
-    Config, MintersStore, CHANNELS, CONFIG, CONTRACT_STATUS, INTERNAL_SECRET_RELAXED,
-    INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED, TOTAL_SUPPLY,
+    Config, MintersStore, OperationFlagsStore, PermitEpochStore, CALLBACK_REPLY_ID, CHANNELS,
+    CHANNEL_EMISSION_LOG_ENABLED, CHANNEL_SCHEMATA, CONFIG, CONTRACT_STATUS,
+    INTERNAL_SECRET_RELAXED, INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED, OPERATION_BURN,
+    OPERATION_MINT, OPERATION_SEND, OPERATION_TRANSFER, TOTAL_SUPPLY,
 };
 use crate::strings::TRANSFER_HISTORY_UNSUPPORTED_MSG;
+use crate::transaction_history::TOKEN_DENOM;
 
 /// We make sure that responses from `handle` are padded to a multiple of this size.
 pub const RESPONSE_BLOCK_SIZE: usize = 256;
-pub const NOTIFICATION_BLOCK_SIZE: usize = 1;
 
 #[entry_point]
 pub fn instantiate(
@@ -65,6 +66,18 @@ pub fn instantiate(
     }
 
     let init_config = msg.config.unwrap_or_default();
+    if init_config.native_decimals() > 18 {
+        return Err(StdError::generic_err("Native decimals must not exceed 18"));
+    }
+    if init_config.max_tx_history_per_account() == Some(0) {
+        return Err(StdError::generic_err(
+            "Max tx history per account must be greater than zero",
+        ));
+    }
+    let burn_sink = init_config
+        .burn_sink_address()
+        .map(|address| deps.api.addr_validate(&address))
+        .transpose()?;
 
     let admin = match msg.admin {
         Some(admin_addr) => deps.api.addr_validate(admin_addr.as_str())?,
@@ -110,25 +123,21 @@ pub fn instantiate(
     )?;
     INTERNAL_SECRET_RELAXED.save(deps.storage, &internal_secret_relaxed)?;
 
-    // Hard-coded channels
-    let channels: Vec<String> = vec![
-        RecvdNotification::CHANNEL_ID.to_string(),
-        SpentNotification::CHANNEL_ID.to_string(),
-        AllowanceNotification::CHANNEL_ID.to_string(),
-        MultiRecvdNotification::CHANNEL_ID.to_string(),
-        MultiSpentNotification::CHANNEL_ID.to_string(),
-    ];
-
-    for channel in channels {
-        CHANNELS.insert(deps.storage, &channel)?;
+    // Built-in channels, with the templates that `ChannelInfo` serves them from
+    for schema in builtin_channel_schemata() {
+        CHANNELS.insert(deps.storage, &schema.channel)?;
+        CHANNEL_SCHEMATA.insert(deps.storage, &schema.channel, &schema)?;
     }
 
     NOTIFICATIONS_ENABLED.save(deps.storage, &true)?;
+    CHANNEL_EMISSION_LOG_ENABLED.save(deps.storage, &init_config.channel_emission_log_enabled())?;
 
     let mut rng = ContractPrng::new(rng_seed.as_slice(), &sha_256(&msg.prng_seed.0));
+    let mut initial_holders = vec![];
     for balance in initial_balances {
         let amount = balance.amount.u128();
         let balance_address = deps.api.addr_canonicalize(balance.address.as_str())?;
+        initial_holders.push(balance_address.clone());
         #[cfg(feature = "gas_tracking")]
         let mut tracker = GasTracker::new(deps.api);
         execute_mint_burn::perform_mint(
@@ -137,7 +146,7 @@ pub fn instantiate(
             &raw_admin,
             &balance_address,
             amount,
-            msg.symbol.clone(),
+            TOKEN_DENOM.to_string(),
             Some("Initial Balance".to_string()),
             &env.block,
             #[cfg(feature = "gas_tracking")]
@@ -154,6 +163,7 @@ pub fn instantiate(
     }
 
     let supported_denoms = msg.supported_denoms.unwrap_or_default();
+    validate_supported_denoms(&supported_denoms)?;
 
     CONFIG.save(
         deps.storage,
@@ -162,7 +172,7 @@ pub fn instantiate(
             symbol: msg.symbol,
             decimals: msg.decimals,
             admin: admin.clone(),
-            total_supply_is_public: init_config.public_total_supply(),
+            total_supply_visibility: init_config.total_supply_visibility(),
             deposit_is_enabled: init_config.deposit_enabled(),
             redeem_is_enabled: init_config.redeem_enabled(),
             mint_is_enabled: init_config.mint_enabled(),
@@ -170,10 +180,28 @@ pub fn instantiate(
             contract_address: env.contract.address,
             supported_denoms,
             can_modify_denoms: init_config.can_modify_denoms(),
+            notify_allowance_use: init_config.notify_allowance_use(),
+            aggregate_batch_notifications: init_config.aggregate_batch_notifications(),
+            native_decimals: init_config.native_decimals(),
+            native_display_denom: init_config.native_display_denom(),
+            require_send_msg: init_config.require_send_msg(),
+            allow_zero_transfers: init_config.allow_zero_transfers(),
+            max_tx_history_per_account: init_config.max_tx_history_per_account(),
+            snapshot_enabled: init_config.snapshot_enabled(),
+            require_memo_on_native: init_config.require_memo_on_native(),
+            burn_sink,
         },
     )?;
     TOTAL_SUPPLY.save(deps.storage, &total_supply)?;
     CONTRACT_STATUS.save(deps.storage, &ContractStatusLevel::NormalRun)?;
+
+    // initial balances were minted before the config was saved, so checkpoint them now
+    let initial_holders: Vec<&CanonicalAddr> = initial_holders.iter().collect();
+    DWB.load(deps.storage)?.checkpoint_balances(
+        deps.storage,
+        &initial_holders,
+        env.block.height,
+    )?;
     let minters = if init_config.mint_enabled() {
         Vec::from([admin])
     } else {
@@ -216,10 +244,32 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
 
                     execute_admin::set_contract_status(deps, level)
                 }
-                ExecuteMsg::Redeem { amount, denom, .. }
+                ExecuteMsg::Redeem {
+                    amount,
+                    denom,
+                    memo,
+                    ..
+                } if contract_status == ContractStatusLevel::StopAllButRedeems => {
+                    execute_deposit_redeem::try_redeem(deps, env, info, amount, denom, memo)
+                }
+                ExecuteMsg::RedeemFrom {
+                    owner,
+                    amount,
+                    denom,
+                    recipient,
+                    memo,
+                    ..
+                } if contract_status == ContractStatusLevel::StopAllButRedeems => {
+                    execute_deposit_redeem::try_redeem_from(
+                        deps, env, info, owner, amount, denom, recipient, memo,
+                    )
+                }
+                // redeem correctness depends on an account's buffered receipts being settled,
+                // so holders must still be able to flush their delayed write buffer entry
+                ExecuteMsg::SettleSelf { .. }
                     if contract_status == ContractStatusLevel::StopAllButRedeems =>
                 {
-                    execute_deposit_redeem::try_redeem(deps, env, info, amount, denom)
+                    execute::try_settle_self(deps, info)
                 }
                 _ => Err(StdError::generic_err(
                     "This contract is stopped and this action is not allowed",
@@ -227,33 +277,84 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             };
             return pad_handle_result(response, RESPONSE_BLOCK_SIZE);
         }
+        // the redeem circuit breaker tripped, so everything but redeems keeps running
+        ContractStatusLevel::RedeemPaused => {
+            if matches!(
+                msg,
+                ExecuteMsg::Redeem { .. } | ExecuteMsg::RedeemFrom { .. }
+            ) {
+                return pad_handle_result(
+                    Err(StdError::generic_err(
+                        "Redeems are paused until the admin resets the contract status",
+                    )),
+                    RESPONSE_BLOCK_SIZE,
+                );
+            }
+        }
         ContractStatusLevel::NormalRun => {} // If it's a normal run just continue
     }
 
+    // operations the admin has disabled individually are rejected even in a normal run
+    if let Err(err) = OperationFlagsStore::ensure_enabled(deps.storage, operation_flag(&msg)) {
+        return pad_handle_result(Err(err), RESPONSE_BLOCK_SIZE);
+    }
+
     let response = match msg.clone() {
         // Native
-        ExecuteMsg::Deposit { .. } => {
-            execute_deposit_redeem::try_deposit(deps, env, info, &mut rng)
+        ExecuteMsg::Deposit { memo, callback, .. } => {
+            execute_deposit_redeem::try_deposit(deps, env, info, memo, callback, &mut rng)
         }
-        ExecuteMsg::Redeem { amount, denom, .. } => {
-            execute_deposit_redeem::try_redeem(deps, env, info, amount, denom)
+        ExecuteMsg::Redeem {
+            amount,
+            denom,
+            memo,
+            ..
+        } => execute_deposit_redeem::try_redeem(deps, env, info, amount, denom, memo),
+        ExecuteMsg::SetPreferredRedeemDenom { denom, .. } => {
+            execute_deposit_redeem::try_set_preferred_redeem_denom(deps, info, denom)
         }
+        ExecuteMsg::SetRedeemAllowance {
+            spender, amount, ..
+        } => execute_deposit_redeem::try_set_redeem_allowance(deps, info, spender, amount),
+        ExecuteMsg::RedeemFrom {
+            owner,
+            amount,
+            denom,
+            recipient,
+            memo,
+            ..
+        } => execute_deposit_redeem::try_redeem_from(
+            deps, env, info, owner, amount, denom, recipient, memo,
+        ),
 
         // Base
         ExecuteMsg::Transfer {
             recipient,
             amount,
             memo,
+            memo_encrypted,
+            want_receipt,
             ..
-        } => {
-            execute_transfer_send::try_transfer(deps, env, info, &mut rng, recipient, amount, memo)
-        }
+        } => execute_transfer_send::try_transfer(
+            deps,
+            env,
+            info,
+            &mut rng,
+            recipient,
+            amount,
+            memo,
+            memo_encrypted.unwrap_or(false),
+            want_receipt.unwrap_or(false),
+        ),
         ExecuteMsg::Send {
             recipient,
             recipient_code_hash,
             amount,
             msg,
             memo,
+            memo_encrypted,
+            reply_on_error,
+            want_receipt,
             ..
         } => execute_transfer_send::try_send(
             deps,
@@ -264,19 +365,134 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             recipient_code_hash,
             amount,
             memo,
+            memo_encrypted.unwrap_or(false),
             msg,
+            reply_on_error.unwrap_or(false),
+            want_receipt.unwrap_or(false),
+        ),
+        ExecuteMsg::RetryCallback { id, .. } => {
+            execute_transfer_send::try_retry_callback(deps, info, id)
+        }
+        ExecuteMsg::TransferWithTimeout {
+            recipient,
+            amount,
+            refund_after,
+            memo,
+            ..
+        } => execute_transfer_send::try_transfer_with_timeout(
+            deps,
+            env,
+            info,
+            &mut rng,
+            recipient,
+            amount,
+            refund_after,
+            memo,
         ),
-        ExecuteMsg::BatchTransfer { actions, .. } => {
-            execute_transfer_send::try_batch_transfer(deps, env, info, &mut rng, actions)
+        ExecuteMsg::ClaimPending { id, .. } => {
+            execute_transfer_send::try_claim_pending(deps, env, info, &mut rng, id)
         }
-        ExecuteMsg::BatchSend { actions, .. } => {
-            execute_transfer_send::try_batch_send(deps, env, info, &mut rng, actions)
+        ExecuteMsg::RefundPending { id, .. } => {
+            execute_transfer_send::try_refund_pending(deps, env, info, &mut rng, id)
+        }
+        ExecuteMsg::CreateStream {
+            recipient,
+            total,
+            start_time,
+            end_time,
+            ..
+        } => execute_transfer_send::try_create_stream(
+            deps, env, info, &mut rng, recipient, total, start_time, end_time,
+        ),
+        ExecuteMsg::WithdrawStream { id, .. } => {
+            execute_transfer_send::try_withdraw_stream(deps, env, info, &mut rng, id)
         }
+        ExecuteMsg::TransferAll {
+            recipient,
+            memo,
+            leave,
+            ..
+        } => execute_transfer_send::try_transfer_all(
+            deps,
+            env,
+            info,
+            &mut rng,
+            recipient,
+            memo,
+            leave.unwrap_or_default(),
+        ),
+        ExecuteMsg::TransferSplit {
+            recipients,
+            total,
+            memo,
+            remainder_policy,
+            ..
+        } => execute_transfer_send::try_transfer_split(
+            deps,
+            env,
+            info,
+            &mut rng,
+            recipients,
+            total,
+            memo,
+            remainder_policy.unwrap_or(RemainderPolicy::FirstRecipient),
+        ),
+        ExecuteMsg::TransferWithAuth {
+            from,
+            to,
+            amount,
+            nonce,
+            signature,
+            ..
+        } => execute_transfer_send::try_transfer_with_auth(
+            deps, env, &mut rng, from, to, amount, nonce, signature,
+        ),
+        ExecuteMsg::BatchTransfer {
+            actions,
+            deadline,
+            idempotency_key,
+            ..
+        } => execute_transfer_send::try_batch_transfer(
+            deps,
+            env,
+            info,
+            &mut rng,
+            actions,
+            deadline,
+            idempotency_key,
+        ),
+        ExecuteMsg::BatchSend {
+            actions,
+            coalesce_callbacks,
+            idempotency_key,
+            ..
+        } => execute_transfer_send::try_batch_send(
+            deps,
+            env,
+            info,
+            &mut rng,
+            actions,
+            coalesce_callbacks.unwrap_or(false),
+            idempotency_key,
+        ),
         ExecuteMsg::Burn { amount, memo, .. } => {
             execute_mint_burn::try_burn(deps, env, info, amount, memo)
         }
-        ExecuteMsg::RegisterReceive { code_hash, .. } => {
-            execute::try_register_receive(deps, info, code_hash)
+        ExecuteMsg::BurnTo { amount, memo, .. } => {
+            execute_mint_burn::try_burn_to(deps, env, info, amount, memo)
+        }
+        ExecuteMsg::RegisterReceive {
+            code_hash, label, ..
+        } => execute::try_register_receive(deps, info, code_hash, label),
+        ExecuteMsg::SettleSelf { .. } => execute::try_settle_self(deps, info),
+        ExecuteMsg::InitializeAccount { address, .. } => {
+            execute::try_initialize_account(deps, address)
+        }
+        ExecuteMsg::SetChannelPreference {
+            channel, enabled, ..
+        } => execute::try_set_channel_preference(deps, info, channel, enabled),
+        ExecuteMsg::SetMemoDecryptionKey { pubkey, .. } => {
+            execute::try_set_memo_decryption_key(deps, info, pubkey)
         }
         ExecuteMsg::CreateViewingKey { entropy, .. } => {
             execute::try_create_key(deps, env, info, entropy, &mut rng)
@@ -288,14 +504,43 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             spender,
             amount,
             expiration,
+            grace_seconds,
+            notify_self,
             ..
-        } => execute::try_increase_allowance(deps, env, info, spender, amount, expiration),
+        } => execute::try_increase_allowance(
+            deps,
+            env,
+            info,
+            spender,
+            amount,
+            expiration,
+            grace_seconds,
+            notify_self.unwrap_or(false),
+        ),
         ExecuteMsg::DecreaseAllowance {
             spender,
             amount,
             expiration,
+            notify_self,
             ..
-        } => execute::try_decrease_allowance(deps, env, info, spender, amount, expiration),
+        } => execute::try_decrease_allowance(
+            deps,
+            env,
+            info,
+            spender,
+            amount,
+            expiration,
+            notify_self.unwrap_or(false),
+        ),
+        ExecuteMsg::ClearAllowanceExpiration { spender, .. } => {
+            execute::try_clear_allowance_expiration(deps, env, info, spender)
+        }
+        ExecuteMsg::BatchSetAllowance { actions, .. } => {
+            execute::try_batch_set_allowance(deps, env, info, actions)
+        }
+        ExecuteMsg::SetAllowancesPublic { public, .. } => {
+            execute::try_set_allowances_public(deps, info, public)
+        }
         ExecuteMsg::TransferFrom {
             owner,
             recipient,
@@ -325,21 +570,41 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             memo,
             msg,
         ),
-        ExecuteMsg::BatchTransferFrom { actions, .. } => {
-            execute_transfer_send::try_batch_transfer_from(deps, &env, info, &mut rng, actions)
-        }
-        ExecuteMsg::BatchSendFrom { actions, .. } => {
-            execute_transfer_send::try_batch_send_from(deps, env, &info, &mut rng, actions)
-        }
+        ExecuteMsg::BatchTransferFrom {
+            actions,
+            idempotency_key,
+            ..
+        } => execute_transfer_send::try_batch_transfer_from(
+            deps,
+            &env,
+            info,
+            &mut rng,
+            actions,
+            idempotency_key,
+        ),
+        ExecuteMsg::BatchSendFrom {
+            actions,
+            idempotency_key,
+            ..
+        } => execute_transfer_send::try_batch_send_from(
+            deps,
+            env,
+            &info,
+            &mut rng,
+            actions,
+            idempotency_key,
+        ),
         ExecuteMsg::BurnFrom {
             owner,
             amount,
             memo,
             ..
         } => execute_mint_burn::try_burn_from(deps, &env, info, owner, amount, memo),
-        ExecuteMsg::BatchBurnFrom { actions, .. } => {
-            execute_mint_burn::try_batch_burn_from(deps, &env, info, actions)
-        }
+        ExecuteMsg::BatchBurnFrom {
+            actions,
+            idempotency_key,
+            ..
+        } => execute_mint_burn::try_batch_burn_from(deps, &env, info, actions, idempotency_key),
 
         // Mint
         ExecuteMsg::Mint {
@@ -348,9 +613,16 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             memo,
             ..
         } => execute_mint_burn::try_mint(deps, env, info, &mut rng, recipient, amount, memo),
-        ExecuteMsg::BatchMint { actions, .. } => {
-            execute_mint_burn::try_batch_mint(deps, env, info, &mut rng, actions)
-        }
+        ExecuteMsg::BatchMint {
+            actions,
+            idempotency_key,
+            ..
+        } => execute_mint_burn::try_batch_mint(deps, env, info, &mut rng, actions, idempotency_key),
+        ExecuteMsg::MintCampaign {
+            actions,
+            campaign_cap,
+            ..
+        } => execute_mint_burn::try_mint_campaign(deps, env, info, &mut rng, actions, campaign_cap),
 
         // SNIP-24
         ExecuteMsg::RevokePermit { permit_name, .. } => {
@@ -364,9 +636,13 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
         ExecuteMsg::DeletePermitRevocation { revocation_id, .. } => {
             execute::delete_permit_revocation(deps, info, revocation_id)
         }
+        ExecuteMsg::ResetCredentials { new_key, .. } => {
+            execute::try_reset_credentials(deps, info, &mut rng, new_key)
+        }
+        ExecuteMsg::AcceptAdmin { .. } => execute::try_accept_admin(deps, info),
 
         // Admin functions
-        _ => admin_execute(deps, info, msg),
+        _ => admin_execute(deps, env, info, msg),
     };
 
     let padded_result = pad_handle_result(response, RESPONSE_BLOCK_SIZE);
@@ -377,7 +653,39 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
     padded_result
 }
 
-pub fn admin_execute(deps: DepsMut, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+/// the OPERATION_* flag that disables the given message, or 0 if it cannot be disabled
+fn operation_flag(msg: &ExecuteMsg) -> u8 {
+    match msg {
+        ExecuteMsg::Mint { .. }
+        | ExecuteMsg::BatchMint { .. }
+        | ExecuteMsg::MintCampaign { .. } => OPERATION_MINT,
+        ExecuteMsg::Send { .. }
+        | ExecuteMsg::BatchSend { .. }
+        | ExecuteMsg::SendFrom { .. }
+        | ExecuteMsg::BatchSendFrom { .. } => OPERATION_SEND,
+        ExecuteMsg::Burn { .. }
+        | ExecuteMsg::BurnTo { .. }
+        | ExecuteMsg::BurnFrom { .. }
+        | ExecuteMsg::BatchBurnFrom { .. } => OPERATION_BURN,
+        ExecuteMsg::Transfer { .. }
+        | ExecuteMsg::BatchTransfer { .. }
+        | ExecuteMsg::TransferAll { .. }
+        | ExecuteMsg::TransferSplit { .. }
+        | ExecuteMsg::TransferWithAuth { .. }
+        | ExecuteMsg::TransferWithTimeout { .. }
+        | ExecuteMsg::CreateStream { .. }
+        | ExecuteMsg::TransferFrom { .. }
+        | ExecuteMsg::BatchTransferFrom { .. } => OPERATION_TRANSFER,
+        _ => 0,
+    }
+}
+
+pub fn admin_execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> StdResult<Response> {
     // load contract config from storage
     let mut config = CONFIG.load(deps.storage)?;
 
@@ -389,9 +697,8 @@ pub fn admin_execute(deps: DepsMut, info: MessageInfo, msg: ExecuteMsg) -> StdRe
     }
 
     match msg {
-        ExecuteMsg::ChangeAdmin { address, .. } => {
-            execute_admin::change_admin(deps, &mut config, address)
-        }
+        ExecuteMsg::ChangeAdmin { address, .. } => execute_admin::change_admin(deps, address),
+        ExecuteMsg::CancelAdminTransfer { .. } => execute_admin::cancel_admin_transfer(deps),
         ExecuteMsg::SetContractStatus { level, .. } => {
             execute_admin::set_contract_status(deps, level)
         }
@@ -410,29 +717,104 @@ pub fn admin_execute(deps: DepsMut, info: MessageInfo, msg: ExecuteMsg) -> StdRe
         ExecuteMsg::RemoveSupportedDenoms { denoms, .. } => {
             execute_admin::remove_supported_denoms(deps, &mut config, denoms)
         }
+        ExecuteMsg::SetDenomStatus {
+            denom,
+            deposit_enabled,
+            redeem_enabled,
+            ..
+        } => execute_admin::set_denom_status(deps, &config, denom, deposit_enabled, redeem_enabled),
 
         // SNIP-52
+        ExecuteMsg::SetOperationFlags {
+            disable_mint,
+            disable_send,
+            disable_burn,
+            disable_transfer,
+            ..
+        } => execute_admin::set_operation_flags(
+            deps,
+            disable_mint,
+            disable_send,
+            disable_burn,
+            disable_transfer,
+        ),
+        ExecuteMsg::SetRedeemRateLimit { limit, .. } => {
+            execute_admin::set_redeem_rate_limit(deps, limit)
+        }
+        ExecuteMsg::SetMaxTxHistoryPerAccount { max, .. } => {
+            execute_admin::set_max_tx_history_per_account(deps, &mut config, max)
+        }
         ExecuteMsg::SetNotificationStatus { enabled, .. } => {
             execute_admin::set_notification_status(deps, enabled)
         }
+        ExecuteMsg::RotateSecrets { .. } => execute_admin::rotate_secrets(deps, &env, &info),
+        ExecuteMsg::RegisterChannel {
+            channel,
+            cddl,
+            bloom_params,
+            ..
+        } => execute_admin::register_channel(deps, channel, cddl, bloom_params),
+        ExecuteMsg::UnregisterChannel { channel, .. } => {
+            execute_admin::unregister_channel(deps, channel)
+        }
         _ => panic!("This execute type is not an admin function"),
     }
 }
 
+#[entry_point]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> StdResult<Response> {
+    match msg.id {
+        CALLBACK_REPLY_ID => execute_transfer_send::try_record_failed_callback(deps, msg.result),
+        id => Err(StdError::generic_err(format!("Unknown reply id {id}"))),
+    }
+}
+
 #[entry_point]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     pad_query_result(
         match msg {
             QueryMsg::TokenInfo {} => query::query_token_info(deps.storage),
             QueryMsg::TokenConfig {} => query::query_token_config(deps.storage),
-            QueryMsg::ContractStatus {} => query::query_contract_status(deps.storage),
+            QueryMsg::ContractStatus {} => {
+                query::query_contract_status(deps.storage, env.block.time.seconds())
+            }
             QueryMsg::ExchangeRate {} => query::query_exchange_rate(deps.storage),
+            QueryMsg::ExchangeRates {} => query::query_exchange_rates(deps.storage),
+            QueryMsg::TotalBurnedToSink {} => query::query_total_burned_to_sink(deps.storage),
             QueryMsg::Minters { .. } => query::query_minters(deps),
-            QueryMsg::ListChannels {} => query::query_list_channels(deps),
+            QueryMsg::PendingAdmin {} => query::query_pending_admin(deps.storage),
+            QueryMsg::ActiveDepositDenoms {} => query::query_active_deposit_denoms(deps.storage),
+            QueryMsg::DenomInfo {} => query::query_denom_info(deps.storage),
+            QueryMsg::ReceiverHash { address } => query::query_receiver_hash(deps, address),
+            QueryMsg::MemoDecryptionKey { address } => {
+                query::query_memo_decryption_key(deps, address)
+            }
+            QueryMsg::PublicAllowancesGiven {
+                owner,
+                page,
+                page_size,
+            } => query::query_public_allowances_given(deps, owner, page.unwrap_or(0), page_size),
+            QueryMsg::PermitEpoch { account } => query::query_permit_epoch(deps, account),
+            QueryMsg::ListChannels { page, page_size } => {
+                query::query_list_channels(deps, page, page_size)
+            }
+            QueryMsg::ChannelSchema { channel } => {
+                query::query_channel_schema(deps.storage, channel)
+            }
+            QueryMsg::NotificationChannelsForTransfer {} => {
+                query::query_notification_channels_for_transfer(deps.storage)
+            }
+            QueryMsg::ValidatePermit { permit } => query_validate_permit(deps, env, permit),
+            QueryMsg::DwbStats {} => query::query_dwb_stats(deps.storage),
+            QueryMsg::FormatAmount { amount } => query::query_format_amount(deps.storage, amount),
             QueryMsg::WithPermit { permit, query } => permit_queries(deps, env, permit, query),
 
             #[cfg(feature = "gas_tracking")]
             QueryMsg::Dwb {} => log_dwb(deps.storage),
+            #[cfg(feature = "gas_tracking")]
+            QueryMsg::AccountStatus { address } => log_account_status(deps, address),
+            #[cfg(feature = "gas_tracking")]
+            QueryMsg::GasProfile {} => query_gas_profile(deps.storage),
 
             _ => viewing_keys_queries(deps, env, msg),
         },
@@ -446,11 +828,7 @@ fn permit_queries(
     permit: Permit,
     query: QueryWithPermit,
 ) -> Result<Binary, StdError> {
-    // Validate permit content
-    let token_address = CONFIG.load(deps.storage)?.contract_address;
-
-    let account =
-        secret_toolkit::permit::validate(deps, &env, &permit, token_address.into_string(), None)?;
+    let account = validate_permit(deps, &env, &permit)?;
 
     // Permit validated! We can now execute the query.
     match query {
@@ -463,12 +841,28 @@ fn permit_queries(
                 )));
             }
 
-            query::query_balance(deps, account)
+            query::query_balance(deps, env, account)
+        }
+        QueryWithPermit::BalanceAt { height } => {
+            if !permit.check_permission(&TokenPermissions::Balance)
+                && !permit.check_permission(&TokenPermissions::Owner) {
+                return Err(StdError::generic_err(format!(
+                    "No permission to query balance, got permissions {:?}",
+                    permit.params.permissions
+                )));
+            }
+
+            query::query_balance_at(deps, account, height)
         }
         QueryWithPermit::TransferHistory { .. } => {
             Err(StdError::generic_err(TRANSFER_HISTORY_UNSUPPORTED_MSG))
         }
-        QueryWithPermit::TransactionHistory { page, page_size } => {
+        QueryWithPermit::TransactionHistory {
+            page,
+            page_size,
+            action_filter,
+            order,
+        } => {
             if !permit.check_permission(&TokenPermissions::History)
                 && !permit.check_permission(&TokenPermissions::Owner) {
                 return Err(StdError::generic_err(format!(
@@ -477,7 +871,14 @@ fn permit_queries(
                 )));
             }
 
-            query::query_transactions(deps, account, page.unwrap_or(0), page_size)
+            query::query_transactions(
+                deps,
+                account,
+                page.unwrap_or(0),
+                page_size,
+                action_filter,
+                order,
+            )
         }
         QueryWithPermit::Allowance { owner, spender } => {
             if !permit.check_permission(&TokenPermissions::Allowance)
@@ -495,7 +896,7 @@ fn permit_queries(
                 )));
             }
 
-            query::query_allowance(deps, owner, spender)
+            query::query_allowance(deps, env, owner, spender)
         }
         QueryWithPermit::AllowancesGiven {
             owner,
@@ -548,6 +949,22 @@ fn permit_queries(
             txhash,
             deps.api.addr_canonicalize(account.as_str())?,
         ),
+        QueryWithPermit::NotificationId { channel, txhash } => query::query_notification_id(
+            deps,
+            channel,
+            txhash,
+            deps.api.addr_canonicalize(account.as_str())?,
+        ),
+        QueryWithPermit::ChannelPreferences {} => query::query_channel_preferences(deps, account),
+        QueryWithPermit::TotalSupply {} => {
+            if !permit.check_permission(&TokenPermissions::Owner) {
+                return Err(StdError::generic_err(format!(
+                    "No permission to query total supply, got permissions {:?}",
+                    permit.params.permissions
+                )));
+            }
+            query::query_total_supply(deps.storage, account.as_str())
+        }
         QueryWithPermit::ListPermitRevocations { .. } => {
             if !permit.check_permission(&TokenPermissions::Owner) {
                 return Err(StdError::generic_err(format!(
@@ -560,113 +977,253 @@ fn permit_queries(
     }
 }
 
-pub fn viewing_keys_queries(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    let (addresses, key) = msg.get_validation_params(deps.api)?;
-
-    for address in addresses {
-        let result = ViewingKey::check(deps.storage, address.as_str(), key.as_str());
-        if result.is_ok() {
-            return match msg {
-                // Base
-                QueryMsg::Balance { address, .. } => query::query_balance(deps, address),
-                QueryMsg::TransferHistory { .. } => {
-                    return Err(StdError::generic_err(TRANSFER_HISTORY_UNSUPPORTED_MSG));
-                }
-                QueryMsg::TransactionHistory {
-                    address,
-                    page,
-                    page_size,
-                    ..
-                } => query::query_transactions(deps, address, page.unwrap_or(0), page_size),
-                QueryMsg::Allowance { owner, spender, .. } => {
-                    query::query_allowance(deps, owner, spender)
-                }
-                QueryMsg::AllowancesGiven {
-                    owner,
-                    page,
-                    page_size,
-                    ..
-                } => query::query_allowances_given(deps, owner, page.unwrap_or(0), page_size),
-                QueryMsg::AllowancesReceived {
-                    spender,
-                    page,
-                    page_size,
-                    ..
-                } => query::query_allowances_received(deps, spender, page.unwrap_or(0), page_size),
-                QueryMsg::ChannelInfo {
-                    channels,
-                    txhash,
-                    viewer,
-                } => query::query_channel_info(
-                    deps,
-                    env,
-                    channels,
-                    txhash,
-                    deps.api.addr_canonicalize(viewer.address.as_str())?,
-                ),
-                QueryMsg::ListPermitRevocations { viewer, .. } => {
-                    query::query_list_permit_revocations(deps, viewer.address.as_str())
-                }
-                _ => panic!("This query type does not require authentication"),
-            };
-        }
-    }
-
-    to_binary(&QueryAnswer::ViewingKeyError {
-        msg: "Wrong viewing key for this address or viewing key not set".to_string(),
-    })
-}
+/// Once an account has reset its credentials, only permits whose name ends with `#<epoch>`
+/// for the account's current permit epoch are accepted.
+fn validate_permit(deps: Deps, env: &Env, permit: &Permit) -> StdResult<String> {
+    // Validate permit content
+    let token_address = CONFIG.load(deps.storage)?.contract_address;
 
-// pub fn migrate(
-//     _deps: DepsMut,
-//     _env: Env,
-//     _msg: MigrateMsg,
-// ) -> StdResult<MigrateResponse> {
-//     Ok(MigrateResponse::default())
-//     Ok(MigrateResponse::default())
-// }
+    let account =
+        secret_toolkit::permit::validate(deps, env, permit, token_address.into_string(), None)?;
 
-// helper functions
+    // permits signed before the account's last credential reset are no longer valid
+    check_permit_epoch(deps.storage, &account, permit)?;
 
-fn is_valid_name(name: &str) -> bool {
-    let len = name.len();
-    (3..=30).contains(&len)
+    Ok(account)
 }
 
-fn is_valid_symbol(symbol: &str) -> bool {
-    let len = symbol.len();
-    let len_is_valid = (3..=20).contains(&len);
+fn query_validate_permit(deps: Deps, env: Env, permit: Permit) -> StdResult<Binary> {
+    let account = validate_permit(deps, &env, &permit)?;
 
-    len_is_valid && symbol.bytes().all(|byte| byte.is_ascii_alphabetic())
+    to_binary(&QueryAnswer::ValidatePermit {
+        account: deps.api.addr_validate(&account)?,
+        permissions: permit.params.permissions,
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use std::any::Any;
-
-    use cosmwasm_std::{
-        from_binary, testing::*, Addr, Api, BlockInfo, Coin, ContractInfo, CosmosMsg, MessageInfo,
-        OwnedDeps, QueryResponse, ReplyOn, SubMsg, Timestamp, TransactionInfo, Uint128, WasmMsg,
-    };
-    use secret_toolkit::permit::{PermitParams, PermitSignature, PubKey};
+fn check_permit_epoch(storage: &dyn Storage, account: &str, permit: &Permit) -> StdResult<()> {
+    let permit_epoch = PermitEpochStore::load(storage, account);
+    if permit_epoch > 0
+        && permit
+            .params
+            .permit_name
+            .rsplit_once('#')
+            .map(|(_, epoch)| epoch)
+            != Some(permit_epoch.to_string().as_str())
+    {
+        return Err(StdError::generic_err(format!(
+            "Permit was signed for an outdated permit epoch, permit name must end with #{permit_epoch}",
+        )));
+    }
 
-    use crate::batch;
-    use crate::btbe::stored_balance;
-    use crate::dwb::{TX_NODES, TX_NODES_COUNT};
-    use crate::msg::{
-        ExecuteAnswer, InitConfig, InitialBalance, ResponseStatus, ResponseStatus::Success,
-    };
-    use crate::receiver::Snip20ReceiveMsg;
-    use crate::state::{AllowancesStore, ReceiverHashStore, TX_COUNT};
-    use crate::transaction_history::{Tx, TxAction};
+    Ok(())
+}
 
-    use super::*;
+pub fn viewing_keys_queries(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    let (addresses, key) = msg.get_validation_params(deps.api)?;
 
-    pub const VIEWING_KEY_SIZE: usize = 32;
+    // check the key against every address before dispatching, so the time taken does not reveal
+    // which address, if any, the key belongs to
+    let mut authenticated = false;
+    for address in addresses {
+        let result = ViewingKey::check(deps.storage, address.as_str(), key.as_str());
+        authenticated |= result.is_ok();
+    }
 
-    // Helper functions
+    if !authenticated {
+        return to_binary(&QueryAnswer::ViewingKeyError {
+            msg: "Wrong viewing key for this address or viewing key not set".to_string(),
+        });
+    }
 
-    fn init_helper(
+    match msg {
+        // Base
+        QueryMsg::Balance { address, .. } => query::query_balance(deps, env, address),
+        QueryMsg::BalanceAt {
+            address, height, ..
+        } => query::query_balance_at(deps, address, height),
+        QueryMsg::AccountPolicies { address, .. } => query::query_account_policies(deps, address),
+        QueryMsg::TransferHistory { .. } => {
+            Err(StdError::generic_err(TRANSFER_HISTORY_UNSUPPORTED_MSG))
+        }
+        QueryMsg::TransactionHistory {
+            address,
+            page,
+            page_size,
+            action_filter,
+            order,
+            ..
+        } => query::query_transactions(
+            deps,
+            address,
+            page.unwrap_or(0),
+            page_size,
+            action_filter,
+            order,
+        ),
+        QueryMsg::TransactionsWith {
+            address,
+            counterparty,
+            page,
+            page_size,
+            ..
+        } => query::query_transactions_with(
+            deps,
+            address,
+            counterparty,
+            page.unwrap_or(0),
+            page_size,
+        ),
+        QueryMsg::Allowance { owner, spender, .. } => {
+            query::query_allowance(deps, env, owner, spender)
+        }
+        QueryMsg::AllowancesGiven {
+            owner,
+            page,
+            page_size,
+            ..
+        } => query::query_allowances_given(deps, owner, page.unwrap_or(0), page_size),
+        QueryMsg::AllowancesReceived {
+            spender,
+            page,
+            page_size,
+            ..
+        } => query::query_allowances_received(deps, spender, page.unwrap_or(0), page_size),
+        QueryMsg::TotalAllowanceGiven { owner, .. } => {
+            query::query_total_allowance_given(deps, env, owner)
+        }
+        QueryMsg::Nonce { address, .. } => query::query_nonce(deps, address),
+        QueryMsg::ChannelInfo {
+            channels,
+            txhash,
+            viewer,
+        } => query::query_channel_info(
+            deps,
+            env,
+            channels,
+            txhash,
+            deps.api.addr_canonicalize(viewer.address.as_str())?,
+        ),
+        QueryMsg::PendingCallbacks { viewer } => {
+            query::query_pending_callbacks(deps, viewer.address)
+        }
+        QueryMsg::ChannelPreferences { viewer } => {
+            query::query_channel_preferences(deps, viewer.address)
+        }
+        QueryMsg::ChannelEmissionLog {
+            channel,
+            page,
+            page_size,
+            viewer,
+        } => query::query_channel_emission_log(
+            deps,
+            viewer.address,
+            channel,
+            page.unwrap_or(0),
+            page_size,
+        ),
+        QueryMsg::ListPermitRevocations { viewer, .. } => {
+            query::query_list_permit_revocations(deps, viewer.address.as_str())
+        }
+        _ => panic!("This query type does not require authentication"),
+    }
+}
+
+// pub fn migrate(
+//     _deps: DepsMut,
+//     _env: Env,
+//     _msg: MigrateMsg,
+// ) -> StdResult<MigrateResponse> {
+//     Ok(MigrateResponse::default())
+//     Ok(MigrateResponse::default())
+// }
+
+// helper functions
+
+fn is_valid_name(name: &str) -> bool {
+    let len = name.len();
+    (3..=30).contains(&len)
+}
+
+fn is_valid_symbol(symbol: &str) -> bool {
+    let len = symbol.len();
+    let len_is_valid = (3..=20).contains(&len);
+
+    len_is_valid && symbol.bytes().all(|byte| byte.is_ascii_alphabetic())
+}
+
+/// checks the denom against the sdk's base denom format [a-zA-Z][a-zA-Z0-9/:._-]{2,127}
+pub(crate) fn is_valid_denom(denom: &str) -> bool {
+    let len = denom.len();
+    let len_is_valid = (3..=128).contains(&len);
+
+    len_is_valid
+        && denom.as_bytes()[0].is_ascii_alphabetic()
+        && denom
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"/:._-".contains(&byte))
+}
+
+/// validates the format of each denom and that the total number of supported denoms is capped
+pub(crate) fn validate_supported_denoms(denoms: &[String]) -> StdResult<()> {
+    if let Some(denom) = denoms.iter().find(|denom| !is_valid_denom(denom)) {
+        return Err(StdError::generic_err(format!(
+            "Denom {denom:?} is not in the expected format [a-zA-Z][a-zA-Z0-9/:._-]{{2,127}}"
+        )));
+    }
+    if denoms.len() > MAX_SUPPORTED_DENOMS {
+        return Err(StdError::generic_err(format!(
+            "Cannot support more than {MAX_SUPPORTED_DENOMS} denoms"
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+
+    use cosmwasm_std::{
+        from_binary, testing::*, Addr, Api, BankMsg, BlockInfo, Coin, ContractInfo, CosmosMsg,
+        MessageInfo, Order, OwnedDeps, QueryResponse, Reply, ReplyOn, SubMsg, SubMsgResult,
+        Timestamp, TransactionInfo, Uint128, Uint64, WasmMsg,
+    };
+    use secret_toolkit::notification::{
+        get_seed, notification_id, BloomParameters, ChannelInfoData, DirectChannel, GroupChannel,
+    };
+    use secret_toolkit::permit::{PermitParams, PermitSignature, PubKey};
+
+    use crate::batch;
+    use crate::btbe::{locate_btbe_node, stored_balance, stored_entry, stored_tx_count};
+    use crate::constants::{
+        IDEMPOTENCY_KEY_TTL_SECONDS, MAX_IDEMPOTENCY_KEY_LENGTH, MAX_MEMO_LENGTH,
+        MAX_RECEIVER_LABEL_LENGTH, VIEWING_KEY_CREATION_COOLDOWN_BLOCKS,
+    };
+    use crate::dwb::{DWB_LEN, TX_NODES, TX_NODES_COUNT};
+    use crate::msg::{
+        DenomStatus, DepositCallback, ExecuteAnswer, InitConfig, InitialBalance, OperationFlags,
+        RedeemRateLimit, RedeemRateLimitStatus, ResponseStatus, ResponseStatus::Success,
+        TotalSupplyVisibility, ViewerInfo,
+    };
+    use crate::notifications::{
+        counter_notification_id, operation_channels, AllowanceNotification, MultiRecvdNotification,
+        MultiSpentNotification, RecvdNotification, SpentNotification, OPERATION_CHANNELS,
+        RECVD_COUNTER_CHANNEL_ID,
+    };
+    use crate::receiver::{Snip20BatchReceiveAction, Snip20BatchReceiveMsg, Snip20ReceiveMsg};
+    use crate::state::{
+        AllowancesStore, ReceiverHashStore, RedeemAllowancesStore, IDEMPOTENCY_KEYS, TX_COUNT,
+    };
+    use crate::transaction_history::{obfuscated_tx_id, Tx, TxAction, TxActionFilter};
+
+    use super::*;
+
+    pub const VIEWING_KEY_SIZE: usize = 32;
+
+    // Helper functions
+
+    fn init_helper(
         initial_balances: Vec<InitialBalance>,
     ) -> (
         StdResult<Response>,
@@ -736,6 +1293,32 @@ mod tests {
         (instantiate(deps.as_mut(), env, info, init_msg), deps)
     }
 
+    fn init_helper_with_raw_config(
+        initial_balances: Vec<InitialBalance>,
+        config: &str,
+    ) -> (
+        StdResult<Response>,
+        OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    ) {
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let env = mock_env();
+        let info = mock_info("instantiator", &[]);
+
+        let init_config: InitConfig = from_binary(&Binary::from(config.as_bytes())).unwrap();
+        let init_msg = InstantiateMsg {
+            name: "sec-sec".to_string(),
+            admin: Some("admin".to_string()),
+            symbol: "SECSEC".to_string(),
+            decimals: 8,
+            initial_balances: Some(initial_balances),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: None,
+        };
+
+        (instantiate(deps.as_mut(), env, info, init_msg), deps)
+    }
+
     fn extract_error_msg<T: Any>(error: StdResult<T>) -> String {
         match error {
             Ok(response) => {
@@ -760,20 +1343,49 @@ mod tests {
         match handle_result {
             ExecuteAnswer::Deposit { status }
             | ExecuteAnswer::Redeem { status }
-            | ExecuteAnswer::Transfer { status }
-            | ExecuteAnswer::Send { status }
+            | ExecuteAnswer::SetPreferredRedeemDenom { status }
+            | ExecuteAnswer::SetRedeemAllowance { status }
+            | ExecuteAnswer::RedeemFrom { status }
+            | ExecuteAnswer::Transfer { status, .. }
+            | ExecuteAnswer::Send { status, .. }
+            | ExecuteAnswer::TransferSplit { status }
+            | ExecuteAnswer::TransferWithAuth { status }
+            | ExecuteAnswer::BatchSend { status }
             | ExecuteAnswer::Burn { status }
+            | ExecuteAnswer::BurnTo { status }
             | ExecuteAnswer::RegisterReceive { status }
+            | ExecuteAnswer::SettleSelf { status }
+            | ExecuteAnswer::InitializeAccount { status }
+            | ExecuteAnswer::SetChannelPreference { status }
+            | ExecuteAnswer::SetMemoDecryptionKey { status }
+            | ExecuteAnswer::SetAllowancesPublic { status }
+            | ExecuteAnswer::BatchSetAllowance { status }
+            | ExecuteAnswer::RotateSecrets { status }
+            | ExecuteAnswer::RegisterChannel { status }
+            | ExecuteAnswer::UnregisterChannel { status }
+            | ExecuteAnswer::RetryCallback { status }
+            | ExecuteAnswer::ClaimPending { status }
+            | ExecuteAnswer::RefundPending { status }
+            | ExecuteAnswer::CreateStream { status, .. }
+            | ExecuteAnswer::WithdrawStream { status, .. }
             | ExecuteAnswer::SetViewingKey { status }
             | ExecuteAnswer::TransferFrom { status }
             | ExecuteAnswer::SendFrom { status }
             | ExecuteAnswer::BurnFrom { status }
             | ExecuteAnswer::Mint { status }
+            | ExecuteAnswer::MintCampaign { status }
             | ExecuteAnswer::ChangeAdmin { status }
+            | ExecuteAnswer::CancelAdminTransfer { status }
+            | ExecuteAnswer::AcceptAdmin { status }
+            | ExecuteAnswer::SetOperationFlags { status }
+            | ExecuteAnswer::SetRedeemRateLimit { status }
+            | ExecuteAnswer::SetMaxTxHistoryPerAccount { status }
             | ExecuteAnswer::SetContractStatus { status }
             | ExecuteAnswer::SetMinters { status }
             | ExecuteAnswer::AddMinters { status }
-            | ExecuteAnswer::RemoveMinters { status } => {
+            | ExecuteAnswer::RemoveMinters { status }
+            | ExecuteAnswer::AddSupportedDenoms { status }
+            | ExecuteAnswer::SetDenomStatus { status } => {
                 matches!(status, ResponseStatus::Success { .. })
             }
             _ => panic!(
@@ -831,7 +1443,10 @@ mod tests {
         assert_eq!(constants.admin, Addr::unchecked("admin".to_string()));
         assert_eq!(constants.symbol, "SECSEC".to_string());
         assert_eq!(constants.decimals, 8);
-        assert_eq!(constants.total_supply_is_public, false);
+        assert_eq!(
+            constants.total_supply_visibility,
+            TotalSupplyVisibility::Private
+        );
 
         ViewingKey::set(deps.as_mut().storage, "lebron", "lolz fun yay");
         let is_vk_correct = ViewingKey::check(&deps.storage, "lebron", "lolz fun yay");
@@ -868,7 +1483,10 @@ mod tests {
         assert_eq!(constants.admin, Addr::unchecked("admin".to_string()));
         assert_eq!(constants.symbol, "SECSEC".to_string());
         assert_eq!(constants.decimals, 8);
-        assert_eq!(constants.total_supply_is_public, false);
+        assert_eq!(
+            constants.total_supply_visibility,
+            TotalSupplyVisibility::Private
+        );
         assert_eq!(constants.deposit_is_enabled, true);
         assert_eq!(constants.redeem_is_enabled, true);
         assert_eq!(constants.mint_is_enabled, true);
@@ -922,6 +1540,8 @@ mod tests {
             recipient: "alice".to_string(),
             amount: Uint128::new(1000),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -967,6 +1587,8 @@ mod tests {
             recipient: "charlie".to_string(),
             amount: Uint128::new(100),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1011,6 +1633,8 @@ mod tests {
             recipient: "alice".to_string(),
             amount: Uint128::new(500),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1061,9 +1685,10 @@ mod tests {
                 },
                 coins: Coin {
                     amount: Uint128::from(500_u128),
-                    denom: "SECSEC".to_string(),
+                    denom: TOKEN_DENOM.to_string(),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1076,9 +1701,10 @@ mod tests {
                 },
                 coins: Coin {
                     amount: Uint128::from(1000_u128),
-                    denom: "SECSEC".to_string(),
+                    denom: TOKEN_DENOM.to_string(),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1090,6 +1716,8 @@ mod tests {
             recipient: "ernie".to_string(),
             amount: Uint128::new(200),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1138,6 +1766,8 @@ mod tests {
             recipient: "dora".to_string(),
             amount: Uint128::new(50),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1184,6 +1814,8 @@ mod tests {
                 recipient,
                 amount: Uint128::new(1),
                 memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
                 #[cfg(feature = "gas_evaporation")]
                 gas_target: None,
                 padding: None,
@@ -1213,6 +1845,8 @@ mod tests {
             recipient,
             amount: Uint128::new(1),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1239,6 +1873,8 @@ mod tests {
             recipient,
             amount: Uint128::new(1),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1266,6 +1902,8 @@ mod tests {
                 recipient: "alice".to_string(),
                 amount: Uint128::new(i.into()),
                 memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
                 #[cfg(feature = "gas_evaporation")]
                 gas_target: None,
                 padding: None,
@@ -1292,6 +1930,8 @@ mod tests {
             recipient: "dora".to_string(),
             amount: Uint128::new(1),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1313,6 +1953,8 @@ mod tests {
                 recipient: "alice".to_string(),
                 amount: Uint128::new(i.into()),
                 memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
                 #[cfg(feature = "gas_evaporation")]
                 gas_target: None,
                 padding: None,
@@ -1352,7 +1994,7 @@ mod tests {
         };
         let query_result = query(deps.as_ref(), mock_env(), query_msg);
         let balance = match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::Balance { amount } => amount,
+            QueryAnswer::Balance { amount, .. } => amount,
             _ => panic!("Unexpected"),
         };
         assert_eq!(balance, Uint128::new(3999));
@@ -1367,6 +2009,8 @@ mod tests {
             key: "key".to_string(),
             page: None,
             page_size: 3,
+            action_filter: None,
+            order: None,
         };
         let query_result = query(deps.as_ref(), mock_env(), query_msg);
         let transfers = match from_binary(&query_result.unwrap()).unwrap() {
@@ -1387,6 +2031,7 @@ mod tests {
                     amount: Uint128::from(50u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1402,6 +2047,7 @@ mod tests {
                     amount: Uint128::from(49u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1417,6 +2063,7 @@ mod tests {
                     amount: Uint128::from(48u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1433,6 +2080,8 @@ mod tests {
             key: "key".to_string(),
             page: Some(8),
             page_size: 6,
+            action_filter: None,
+            order: None,
         };
         let query_result = query(deps.as_ref(), mock_env(), query_msg);
         let transfers = match from_binary(&query_result.unwrap()).unwrap() {
@@ -1453,6 +2102,7 @@ mod tests {
                     amount: Uint128::from(2u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1468,6 +2118,7 @@ mod tests {
                     amount: Uint128::from(1u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1483,6 +2134,7 @@ mod tests {
                     amount: Uint128::from(1u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1498,6 +2150,7 @@ mod tests {
                     amount: Uint128::from(50u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1513,6 +2166,7 @@ mod tests {
                     amount: Uint128::from(49u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1528,6 +2182,7 @@ mod tests {
                     amount: Uint128::from(48u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1545,6 +2200,8 @@ mod tests {
             key: "key".to_string(),
             page: Some(3),
             page_size: 33,
+            action_filter: None,
+            order: None,
             //page: None,
             //page_size: 500,
         };
@@ -1567,6 +2224,7 @@ mod tests {
                     amount: Uint128::from(2u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1582,6 +2240,7 @@ mod tests {
                     amount: Uint128::from(1u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1597,6 +2256,7 @@ mod tests {
                     amount: Uint128::from(50u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1612,6 +2272,7 @@ mod tests {
                     amount: Uint128::from(500u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1627,6 +2288,7 @@ mod tests {
                     amount: Uint128::from(1000u128),
                 },
                 memo: None,
+                memo_encrypted: false,
                 block_time: 1571797419,
                 block_height: 12345,
             },
@@ -1642,6 +2304,8 @@ mod tests {
             recipient: "alice".to_string(),
             amount: Uint128::new(10000),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
@@ -1655,7 +2319,7 @@ mod tests {
     }
 
     #[test]
-    fn test_handle_send() {
+    fn test_execute_transfer_to_self() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -1666,63 +2330,84 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::RegisterReceive {
-            code_hash: "this_is_a_hash_of_a_code".to_string(),
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("contract", &[]);
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(1000),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let result = handle_result.unwrap();
-        assert!(ensure_success(result));
+        let query_msg = QueryMsg::Balance {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let balance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(balance, Uint128::new(5000));
 
-        let handle_msg = ExecuteMsg::Send {
-            recipient: "contract".to_string(),
-            recipient_code_hash: None,
-            amount: Uint128::new(100),
-            memo: Some("my memo".to_string()),
-            padding: None,
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let (txs, total) = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, total, .. } => (txs, total),
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(total, Some(2));
+        assert_eq!(
+            txs[0].action,
+            TxAction::Transfer {
+                from: Addr::unchecked("bob".to_string()),
+                sender: Addr::unchecked("bob".to_string()),
+                recipient: Addr::unchecked("bob".to_string()),
+            }
+        );
+
+        // a self-transfer still cannot exceed the balance
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(6000),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            msg: Some(to_binary("hey hey you you").unwrap()),
+            padding: None,
         };
         let info = mock_info("bob", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let result = handle_result.unwrap();
-        assert!(ensure_success(result.clone()));
-        let id = 0;
-        assert!(result.messages.contains(&SubMsg {
-            id,
-            msg: CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "contract".to_string(),
-                code_hash: "this_is_a_hash_of_a_code".to_string(),
-                msg: Snip20ReceiveMsg::new(
-                    Addr::unchecked("bob".to_string()),
-                    Addr::unchecked("bob".to_string()),
-                    Uint128::new(100),
-                    Some("my memo".to_string()),
-                    Some(to_binary("hey hey you you").unwrap())
-                )
-                .into_binary()
-                .unwrap(),
-                funds: vec![],
-            })
-            .into(),
-            reply_on: match id {
-                0 => ReplyOn::Never,
-                _ => ReplyOn::Always,
-            },
-            gas_limit: None,
-        }));
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient funds"));
     }
 
+    #[cfg(feature = "gas_tracking")]
     #[test]
-    fn test_handle_register_receive() {
+    fn test_query_account_status() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -1733,28 +2418,68 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::RegisterReceive {
-            code_hash: "this_is_a_hash_of_a_code".to_string(),
+        let account_status = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let query_msg = QueryMsg::AccountStatus {
+                address: "alice".to_string(),
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::AccountStatus {
+                    in_dwb,
+                    dwb_amount,
+                    settled_balance,
+                    dwb_tx_count,
+                } => (
+                    in_dwb,
+                    dwb_amount.u128(),
+                    settled_balance.u128(),
+                    dwb_tx_count,
+                ),
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(1000),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("contract", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        // the received amount is still buffered
+        assert_eq!(account_status(&deps), (true, 1000, 0, 1));
 
-        let result = handle_result.unwrap();
-        assert!(ensure_success(result));
+        // spending settles alice's buffered amount
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let hash =
-            ReceiverHashStore::may_load(&deps.storage, &Addr::unchecked("contract".to_string()))
-                .unwrap()
-                .unwrap();
-        assert_eq!(hash, "this_is_a_hash_of_a_code".to_string());
+        assert_eq!(account_status(&deps), (false, 0, 900, 0));
     }
 
+    #[cfg(feature = "gas_tracking")]
     #[test]
-    fn test_handle_create_viewing_key() {
+    fn test_execute_transfer_gas_tracking_error() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -1765,38 +2490,48 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::CreateViewingKey {
-            entropy: None,
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(10000),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
         let info = mock_info("bob", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-        let answer: ExecuteAnswer = from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+        // the logs recorded before the failure are kept, followed by the error itself
+        assert!(error.starts_with("insufficient funds"));
+        assert!(error.contains("gas.perform_transfer.1="));
+        assert!(error.contains("gas.error="));
 
-        let key = match answer {
-            ExecuteAnswer::CreateViewingKey { key } => key,
-            _ => panic!("NOPE"),
+        // the other tracked handlers keep their logs on failure too
+        let handle_msg = ExecuteMsg::Send {
+            recipient: "alice".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(10000),
+            memo: None,
+            msg: None,
+            memo_encrypted: None,
+            reply_on_error: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
         };
-        // let bob_canonical = deps.as_mut().api.addr_canonicalize("bob").unwrap();
-
-        let result = ViewingKey::check(&deps.storage, "bob", key.as_str());
-        assert!(result.is_ok());
-
-        // let saved_vk = read_viewing_key(&deps.storage, &bob_canonical).unwrap();
-        // assert!(key.check_viewing_key(saved_vk.as_slice()));
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.starts_with("insufficient funds"));
+        assert!(error.contains("gas.error="));
     }
 
     #[test]
-    fn test_handle_set_viewing_key() {
+    fn test_execute_transfer_memo_length() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -1807,150 +2542,155 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Set VK
-        let handle_msg = ExecuteMsg::SetViewingKey {
-            key: "hi lol".to_string(),
+        let transfer = |memo_len: usize| ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(100),
+            memo: Some("m".repeat(memo_len)),
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
+
         let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, transfer(MAX_MEMO_LENGTH));
+        assert!(ensure_success(handle_result.unwrap()));
 
+        let info = mock_info("bob", &[]);
+        let handle_msg = transfer(MAX_MEMO_LENGTH + 1);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("memo too long"));
+    }
 
-        let unwrapped_result: ExecuteAnswer =
-            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-        assert_eq!(
-            to_binary(&unwrapped_result).unwrap(),
-            to_binary(&ExecuteAnswer::SetViewingKey {
-                status: ResponseStatus::Success
-            })
-            .unwrap(),
+    #[test]
+    fn test_execute_transfer_with_timeout() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
         );
 
-        // Set valid VK
-        let actual_vk = "x".to_string().repeat(VIEWING_KEY_SIZE);
-        let handle_msg = ExecuteMsg::SetViewingKey {
-            key: actual_vk.clone(),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
+        for account in ["bob", "alice"] {
+            let handle_msg = ExecuteMsg::SetViewingKey {
+                key: "key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(account, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let balance = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, address: &str| {
+            let query_msg = QueryMsg::Balance {
+                address: address.to_string(),
+                key: "key".to_string(),
+            };
+            match from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap() {
+                QueryAnswer::Balance { amount, .. } => amount.u128(),
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+        let transfer_with_timeout = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                                     amount: u128| {
+            let handle_msg = ExecuteMsg::TransferWithTimeout {
+                recipient: "alice".to_string(),
+                amount: Uint128::new(amount),
+                refund_after: mock_env().block.time.seconds() + 100,
+                memo: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result =
+                execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+            match from_binary(&handle_result.unwrap().data.unwrap()).unwrap() {
+                ExecuteAnswer::TransferWithTimeout { id, .. } => id,
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+        let resolve = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                       sender: &str,
+                       id: u64,
+                       claim: bool,
+                       after_timeout: bool| {
+            let mut env = mock_env();
+            if after_timeout {
+                env.block.time = env.block.time.plus_seconds(200);
+            }
+            let handle_msg = if claim {
+                ExecuteMsg::ClaimPending {
+                    id,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                }
+            } else {
+                ExecuteMsg::RefundPending {
+                    id,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                }
+            };
+            execute(deps.as_mut(), env, mock_info(sender, &[]), handle_msg)
+        };
 
-        let unwrapped_result: ExecuteAnswer =
-            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-        assert_eq!(
-            to_binary(&unwrapped_result).unwrap(),
-            to_binary(&ExecuteAnswer::SetViewingKey { status: Success }).unwrap(),
-        );
+        // claimed before the timeout
+        let id = transfer_with_timeout(&mut deps, 1000);
+        assert_eq!(balance(&deps, "bob"), 4000);
+        assert_eq!(balance(&deps, "alice"), 0);
 
-        let result = ViewingKey::check(&deps.storage, "bob", actual_vk.as_str());
-        assert!(result.is_ok());
-    }
+        let error = extract_error_msg(resolve(&mut deps, "charlie", id, true, false));
+        assert!(error.contains(&format!("Pending transfer {id} not found")));
 
-    fn revoke_permit(
-        permit_name: &str,
-        user_address: &str,
-        deps: &mut OwnedDeps<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>,
-    ) -> Result<Response, StdError> {
-        let handle_msg = ExecuteMsg::RevokePermit {
-            permit_name: permit_name.to_string(),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info(user_address, &[]);
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-        handle_result
-    }
+        assert!(ensure_success(
+            resolve(&mut deps, "alice", id, true, false).unwrap()
+        ));
+        assert_eq!(balance(&deps, "alice"), 1000);
 
-    fn get_balance_with_permit_qry_msg(
-        permit_name: &str,
-        chain_id: &str,
-        pub_key_value: &str,
-        signature: &str,
-    ) -> QueryMsg {
-        let permit = gen_permit_obj(
-            permit_name,
-            chain_id,
-            pub_key_value,
-            signature,
-            TokenPermissions::Balance,
-        );
+        let error = extract_error_msg(resolve(&mut deps, "alice", id, true, false));
+        assert!(error.contains("has already been claimed"));
+        let error = extract_error_msg(resolve(&mut deps, "bob", id, false, true));
+        assert!(error.contains("has already been claimed"));
 
-        QueryMsg::WithPermit {
-            permit,
-            query: QueryWithPermit::Balance {},
-        }
-    }
+        // refunded after the timeout
+        let id = transfer_with_timeout(&mut deps, 500);
+        assert_eq!(balance(&deps, "bob"), 3500);
 
-    fn gen_permit_obj(
-        permit_name: &str,
-        chain_id: &str,
-        pub_key_value: &str,
-        signature: &str,
-        permit_type: TokenPermissions,
-    ) -> Permit {
-        let permit: Permit = Permit {
-            params: PermitParams {
-                allowed_tokens: vec![MOCK_CONTRACT_ADDR.to_string()],
-                permit_name: permit_name.to_string(),
-                chain_id: chain_id.to_string(),
-                permissions: vec![permit_type],
-                created: None,
-                expires: None,
-            },
-            signature: PermitSignature {
-                pub_key: PubKey {
-                    r#type: "tendermint/PubKeySecp256k1".to_string(),
-                    value: Binary::from_base64(pub_key_value).unwrap(),
-                },
-                signature: Binary::from_base64(signature).unwrap(),
-            },
-        };
-        permit
-    }
+        let error = extract_error_msg(resolve(&mut deps, "bob", id, false, false));
+        assert!(error.contains("cannot be refunded before"));
+        let error = extract_error_msg(resolve(&mut deps, "alice", id, true, true));
+        assert!(error.contains("can only be refunded"));
 
-    fn get_allowances_given_permit(
-        permit_name: &str,
-        chain_id: &str,
-        pub_key_value: &str,
-        signature: &str,
-        spender: String,
-    ) -> QueryMsg {
-        let permit = gen_permit_obj(
-            permit_name,
-            chain_id,
-            pub_key_value,
-            signature,
-            TokenPermissions::Owner,
-        );
+        assert!(ensure_success(
+            resolve(&mut deps, "bob", id, false, true).unwrap()
+        ));
+        assert_eq!(balance(&deps, "bob"), 4000);
+        assert_eq!(balance(&deps, "alice"), 1000);
 
-        QueryMsg::WithPermit {
-            permit,
-            query: QueryWithPermit::AllowancesReceived {
-                spender,
-                page: None,
-                page_size: 0,
-            },
-        }
+        let error = extract_error_msg(resolve(&mut deps, "bob", id, false, true));
+        assert!(error.contains("has already been refunded"));
+        let error = extract_error_msg(resolve(&mut deps, "alice", id, true, false));
+        assert!(error.contains("has already been refunded"));
     }
 
     #[test]
-    fn test_permit_query_allowances_given_should_fail() {
-        let user_address = "secret18mdrja40gfuftt5yx6tgj0fn5lurplezyp894y";
-        let permit_name = "default";
-        let chain_id = "secretdev-1";
-        let pub_key = "AkZqxdKMtPq2w0kGDGwWGejTAed0H7azPMHtrCX0XYZG";
-        let signature = "ZXyFMlAy6guMG9Gj05rFvcMi5/JGfClRtJpVTHiDtQY3GtSfBHncY70kmYiTXkKIxSxdnh/kS8oXa+GSX5su6Q==";
-
-        // Init the contract
-        let (init_result, deps) = init_helper(vec![InitialBalance {
-            address: user_address.to_string(),
-            amount: Uint128::new(50000000),
+    fn test_execute_stream() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
         }]);
         assert!(
             init_result.is_ok(),
@@ -1958,101 +2698,186 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let msg = get_allowances_given_permit(
-            permit_name,
-            chain_id,
-            pub_key,
-            signature,
-            "secret1kmgdagt5efcz2kku0ak9ezfgntg29g2vr88q0e".to_string(),
-        );
-        let query_result = query(deps.as_ref(), mock_env(), msg);
+        for account in ["bob", "alice"] {
+            let handle_msg = ExecuteMsg::SetViewingKey {
+                key: "key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(account, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
 
-        assert_eq!(query_result.is_err(), true);
-    }
+        let now = mock_env().block.time.seconds();
+        let env_at = |offset: u64| {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_seconds(offset);
+            env
+        };
+        let balance = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                       address: &str,
+                       offset: u64| {
+            let query_msg = QueryMsg::Balance {
+                address: address.to_string(),
+                key: "key".to_string(),
+            };
+            match from_binary(&query(deps.as_ref(), env_at(offset), query_msg).unwrap()).unwrap() {
+                QueryAnswer::Balance {
+                    amount,
+                    claimable_streams,
+                } => (amount.u128(), claimable_streams.map(|c| c.u128())),
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+        let create_stream = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                             total: u128,
+                             start_time: u64,
+                             end_time: u64| {
+            let handle_msg = ExecuteMsg::CreateStream {
+                recipient: "alice".to_string(),
+                total: Uint128::new(total),
+                start_time,
+                end_time,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg)
+        };
+        let withdraw = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                        sender: &str,
+                        id: u64,
+                        offset: u64| {
+            let handle_msg = ExecuteMsg::WithdrawStream {
+                id,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            execute(
+                deps.as_mut(),
+                env_at(offset),
+                mock_info(sender, &[]),
+                handle_msg,
+            )
+        };
 
-    #[test]
-    fn test_permit_query_allowances_given() {
-        let user_address = "secret18mdrja40gfuftt5yx6tgj0fn5lurplezyp894y";
-        let permit_name = "default";
-        let chain_id = "secretdev-1";
-        let pub_key = "AkZqxdKMtPq2w0kGDGwWGejTAed0H7azPMHtrCX0XYZG";
-        let signature = "ZXyFMlAy6guMG9Gj05rFvcMi5/JGfClRtJpVTHiDtQY3GtSfBHncY70kmYiTXkKIxSxdnh/kS8oXa+GSX5su6Q==";
+        let error = extract_error_msg(create_stream(&mut deps, 0, now, now + 100));
+        assert!(error.contains("stream total must be positive"));
+        let error = extract_error_msg(create_stream(&mut deps, 1000, now + 100, now + 100));
+        assert!(error.contains("stream end_time must be after start_time"));
+        let error = extract_error_msg(create_stream(&mut deps, 1000, now - 200, now - 100));
+        assert!(error.contains("stream end_time must be in the future"));
 
-        // Init the contract
-        let (init_result, deps) = init_helper(vec![InitialBalance {
-            address: user_address.to_string(),
-            amount: Uint128::new(50000000),
-        }]);
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
-        );
+        let id = match from_binary(
+            &create_stream(&mut deps, 1000, now, now + 100)
+                .unwrap()
+                .data
+                .unwrap(),
+        )
+        .unwrap()
+        {
+            ExecuteAnswer::CreateStream { id, .. } => id,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(balance(&deps, "bob", 0), (4000, None));
+        assert_eq!(balance(&deps, "alice", 0), (0, Some(0)));
+        assert_eq!(balance(&deps, "alice", 25), (0, Some(250)));
 
-        let msg = get_allowances_given_permit(
-            permit_name,
-            chain_id,
-            pub_key,
-            signature,
-            "secret18mdrja40gfuftt5yx6tgj0fn5lurplezyp894y".to_string(),
-        );
-        let query_result = query(deps.as_ref(), mock_env(), msg);
+        let error = extract_error_msg(withdraw(&mut deps, "alice", id, 0));
+        assert!(error.contains(&format!("Nothing to withdraw from stream {id}")));
+        let error = extract_error_msg(withdraw(&mut deps, "bob", id, 25));
+        assert!(error.contains(&format!("Stream {id} not found")));
 
-        assert_eq!(query_result.is_ok(), true);
+        // partial withdrawal a quarter of the way through
+        match from_binary(&withdraw(&mut deps, "alice", id, 25).unwrap().data.unwrap()).unwrap() {
+            ExecuteAnswer::WithdrawStream { amount, .. } => assert_eq!(amount.u128(), 250),
+            other => panic!("Unexpected: {:?}", other),
+        }
+        assert_eq!(balance(&deps, "alice", 25), (250, Some(0)));
+        assert_eq!(balance(&deps, "alice", 50), (250, Some(250)));
+
+        let error = extract_error_msg(withdraw(&mut deps, "alice", id, 25));
+        assert!(error.contains(&format!("Nothing to withdraw from stream {id}")));
+
+        // the remainder once the stream has ended
+        match from_binary(&withdraw(&mut deps, "alice", id, 200).unwrap().data.unwrap()).unwrap() {
+            ExecuteAnswer::WithdrawStream { amount, .. } => assert_eq!(amount.u128(), 750),
+            other => panic!("Unexpected: {:?}", other),
+        }
+        assert_eq!(balance(&deps, "alice", 200), (1000, None));
+        assert_eq!(balance(&deps, "bob", 200), (4000, None));
+
+        let error = extract_error_msg(withdraw(&mut deps, "alice", id, 300));
+        assert!(error.contains(&format!("Nothing to withdraw from stream {id}")));
     }
 
     #[test]
-    fn test_permit_revoke() {
-        let user_address = "secret1kmgdagt5efcz2kku0ak9ezfgntg29g2vr88q0e";
-        let permit_name = "to_be_revoked";
-        let chain_id = "blabla";
-
-        // Note that 'signature'was generated with the specific values of the above:
-        // user_address, permit_name, chain_id, pub_key_value
-        let pub_key_value = "Ahlb7vwjo4aTY6dqfgpPmPYF7XhTAIReVwncQwlq8Sct";
-        let signature = "VS13F7iv1qxKABxrCAvZQPy2IruLQsIyfTewy/PIhNtybtq417lr3FxsWjV/i9YTqCUxg7weoZwHmYs0YgYX4w==";
+    fn test_execute_transfer_zero_amount() {
+        let zero_transfers = |config: &str| {
+            let (init_result, mut deps) = init_helper_with_raw_config(
+                vec![InitialBalance {
+                    address: "bob".to_string(),
+                    amount: Uint128::new(5000),
+                }],
+                config,
+            );
+            assert!(
+                init_result.is_ok(),
+                "Init failed: {}",
+                init_result.err().unwrap()
+            );
 
-        // Init the contract
-        let (init_result, mut deps) = init_helper(vec![InitialBalance {
-            address: user_address.to_string(),
-            amount: Uint128::new(50000000),
-        }]);
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
-        );
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: "alice".to_string(),
+                amount: Uint128::zero(),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("bob", &[]);
+            let transfer_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+            let handle_msg = ExecuteMsg::BatchTransfer {
+                actions: vec![batch::TransferAction {
+                    recipient: "alice".to_string(),
+                    amount: Uint128::zero(),
+                    memo: None,
+                }],
+                deadline: None,
+                idempotency_key: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("bob", &[]);
+            let batch_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        // Query the account's balance
-        let balance_with_permit_msg =
-            get_balance_with_permit_qry_msg(permit_name, chain_id, pub_key_value, signature);
-        let query_result = query(deps.as_ref(), mock_env(), balance_with_permit_msg);
-        let balance = match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::Balance { amount } => amount,
-            _ => panic!("Unexpected result from query"),
+            (transfer_result, batch_result)
         };
-        assert_eq!(balance.u128(), 50000000);
 
-        // Revoke the Balance permit
-        let handle_result = revoke_permit(permit_name, user_address, &mut deps);
-        let status = match from_binary(&handle_result.unwrap().data.unwrap()).unwrap() {
-            ExecuteAnswer::RevokePermit { status } => status,
-            _ => panic!("NOPE"),
-        };
-        assert_eq!(status, ResponseStatus::Success);
+        let (transfer_result, batch_result) = zero_transfers("{}");
+        let error = extract_error_msg(transfer_result);
+        assert!(error.contains("transfer amount must be positive"));
+        let error = extract_error_msg(batch_result);
+        assert!(error.contains("transfer amount must be positive"));
 
-        // Try to query the balance with permit and fail because the permit is now revoked
-        let balance_with_permit_msg =
-            get_balance_with_permit_qry_msg(permit_name, chain_id, pub_key_value, signature);
-        let query_result = query(deps.as_ref(), mock_env(), balance_with_permit_msg);
-        let error = extract_error_msg(query_result);
-        assert!(
-            error.contains(format!("Permit \"{}\" was revoked by account", permit_name).as_str())
-        );
+        let (transfer_result, batch_result) = zero_transfers(r#"{ "allow_zero_transfers": true }"#);
+        assert!(ensure_success(transfer_result.unwrap()));
+        assert!(ensure_success(batch_result.unwrap()));
     }
 
     #[test]
-    fn test_execute_transfer_from() {
+    fn test_execute_transfer_all() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -2063,155 +2888,128 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Transfer before allowance
-        let handle_msg = ExecuteMsg::TransferFrom {
-            owner: "bob".to_string(),
+        // settle bob's balance by spending from it
+        let handle_msg = ExecuteMsg::Transfer {
             recipient: "alice".to_string(),
-            amount: Uint128::new(2500),
+            amount: Uint128::new(1000),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
-
-        // Transfer more than allowance
-        let handle_msg = ExecuteMsg::IncreaseAllowance {
-            spender: "alice".to_string(),
-            amount: Uint128::new(2000),
-            padding: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            expiration: Some(1_571_797_420),
-        };
         let info = mock_info("bob", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-        let handle_msg = ExecuteMsg::TransferFrom {
-            owner: "bob".to_string(),
-            recipient: "alice".to_string(),
-            amount: Uint128::new(2500),
+        // then leave some funds for bob in the buffer
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(300),
             memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
         let info = mock_info("alice", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        let bob = deps.api.addr_canonicalize("bob").unwrap();
+        assert_eq!(stored_balance(&deps.storage, &bob).unwrap(), 4000);
+        let dwb = DWB.load(&deps.storage).unwrap();
+        assert_eq!(dwb.account_balance(&deps.storage, &bob).unwrap(), 4300);
 
-        // Transfer after allowance expired
-        let handle_msg = ExecuteMsg::TransferFrom {
-            owner: "bob".to_string(),
-            recipient: "alice".to_string(),
-            amount: Uint128::new(2000),
+        let handle_msg = ExecuteMsg::TransferAll {
+            recipient: "charlie".to_string(),
             memo: None,
+            leave: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-
-        let info = MessageInfo {
-            sender: Addr::unchecked("bob".to_string()),
-            funds: vec![],
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        match from_binary(&handle_result.unwrap().data.unwrap()).unwrap() {
+            ExecuteAnswer::TransferAll { amount, status } => {
+                assert_eq!(status, ResponseStatus::Success);
+                assert_eq!(amount, Uint128::new(4300));
+            }
+            other => panic!("Unexpected: {:?}", other),
         };
 
-        let handle_result = execute(
-            deps.as_mut(),
-            Env {
-                block: BlockInfo {
-                    height: 12_345,
-                    time: Timestamp::from_seconds(1_571_797_420),
-                    chain_id: "cosmos-testnet-14002".to_string(),
-                    random: Some(Binary::from(&[
-                        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
-                        21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31,
-                    ])),
-                },
-                transaction: Some(TransactionInfo {
-                    index: 3,
-                    hash: "1010".to_string(),
-                }),
-                contract: ContractInfo {
-                    address: Addr::unchecked(MOCK_CONTRACT_ADDR.to_string()),
-                    code_hash: "".to_string(),
-                },
-            },
-            info,
-            handle_msg,
-        );
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        let charlie = deps.api.addr_canonicalize("charlie").unwrap();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        assert_eq!(dwb.account_balance(&deps.storage, &bob).unwrap(), 0);
+        assert_eq!(dwb.account_balance(&deps.storage, &charlie).unwrap(), 4300);
 
-        // Sanity check
-        let handle_msg = ExecuteMsg::TransferFrom {
-            owner: "bob".to_string(),
-            recipient: "alice".to_string(),
-            amount: Uint128::new(2000),
+        // nothing left to sweep
+        let handle_msg = ExecuteMsg::TransferAll {
+            recipient: "charlie".to_string(),
             memo: None,
+            leave: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
-
+        let info = mock_info("bob", &[]);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "No funds to transfer");
+    }
 
+    #[test]
+    fn test_handle_transfer_all_leave() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
         );
-        let bob_canonical = deps
-            .api
-            .addr_canonicalize(Addr::unchecked("bob".to_string()).as_str())
-            .unwrap();
-        let alice_canonical = deps
-            .api
-            .addr_canonicalize(Addr::unchecked("alice".to_string()).as_str())
-            .unwrap();
 
-        let bob_balance = stored_balance(&deps.storage, &bob_canonical).unwrap();
-        let alice_balance = stored_balance(&deps.storage, &alice_canonical).unwrap();
-        assert_eq!(bob_balance, 5000 - 2000);
-        assert_ne!(alice_balance, 2000);
-        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        assert_eq!(total_supply, 5000);
+        let transfer_all = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                            leave: u128| {
+            let handle_msg = ExecuteMsg::TransferAll {
+                recipient: "charlie".to_string(),
+                memo: None,
+                leave: Some(Uint128::new(leave)),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg)
+        };
 
-        // Second send more than allowance
-        let handle_msg = ExecuteMsg::TransferFrom {
-            owner: "bob".to_string(),
-            recipient: "alice".to_string(),
-            amount: Uint128::new(1),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        let error = extract_error_msg(transfer_all(&mut deps, 5001));
+        assert!(error.contains("insufficient funds to leave"));
+
+        match from_binary(&transfer_all(&mut deps, 150).unwrap().data.unwrap()).unwrap() {
+            ExecuteAnswer::TransferAll { amount, status } => {
+                assert_eq!(status, ResponseStatus::Success);
+                assert_eq!(amount, Uint128::new(4850));
+            }
+            other => panic!("Unexpected: {:?}", other),
         };
-        let info = mock_info("alice", &[]);
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let bob = deps.api.addr_canonicalize("bob").unwrap();
+        let charlie = deps.api.addr_canonicalize("charlie").unwrap();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        assert_eq!(dwb.account_balance(&deps.storage, &bob).unwrap(), 150);
+        assert_eq!(dwb.account_balance(&deps.storage, &charlie).unwrap(), 4850);
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        // the residual is all that is left, so there is nothing more to sweep
+        let error = extract_error_msg(transfer_all(&mut deps, 150));
+        assert_eq!(error, "No funds to transfer");
     }
 
     #[test]
-    fn test_handle_send_from() {
+    fn test_handle_transfer_split() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -2222,157 +3020,78 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Send before allowance
-        let handle_msg = ExecuteMsg::SendFrom {
-            owner: "bob".to_string(),
-            recipient: "alice".to_string(),
-            recipient_code_hash: None,
-            amount: Uint128::new(2500),
+        let transfer_split = |recipients: &[&str], total: u128| ExecuteMsg::TransferSplit {
+            recipients: recipients.iter().map(|r| r.to_string()).collect(),
+            total: Uint128::new(total),
             memo: None,
-            msg: None,
+            remainder_policy: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
+        let balance = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, address: &str| {
+            let address = deps.api.addr_canonicalize(address).unwrap();
+            DWB.load(&deps.storage)
+                .unwrap()
+                .account_balance(&deps.storage, &address)
+                .unwrap()
+        };
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(&[], 1000),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("recipients must not be empty"));
 
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(&["alice", "charlie", "alice"], 1000),
+        );
         let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        assert!(error.contains("duplicate recipient: alice"));
 
-        // Send more than allowance
-        let handle_msg = ExecuteMsg::IncreaseAllowance {
-            spender: "alice".to_string(),
-            amount: Uint128::new(2000),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-            expiration: None,
-        };
-        let info = mock_info("bob", &[]);
+        // 1000 does not divide evenly by 3, so the first recipient also gets the remainder
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(&["alice", "charlie", "dave"], 1000),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let credited = [
+            balance(&deps, "alice"),
+            balance(&deps, "charlie"),
+            balance(&deps, "dave"),
+        ];
+        assert_eq!(credited, [334, 333, 333]);
+        assert_eq!(credited.iter().sum::<u128>(), 1000);
+        assert_eq!(balance(&deps, "bob"), 4000);
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-        let handle_msg = ExecuteMsg::SendFrom {
-            owner: "bob".to_string(),
-            recipient: "alice".to_string(),
-            recipient_code_hash: None,
-            amount: Uint128::new(2500),
-            memo: None,
-            msg: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
-
-        // Sanity check
-        let handle_msg = ExecuteMsg::RegisterReceive {
-            code_hash: "lolz".to_string(),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("contract", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-        let send_msg = Binary::from(r#"{ "some_msg": { "some_key": "some_val" } }"#.as_bytes());
-        let snip20_msg = Snip20ReceiveMsg::new(
-            Addr::unchecked("alice".to_string()),
-            Addr::unchecked("bob".to_string()),
-            Uint128::new(2000),
-            Some("my memo".to_string()),
-            Some(send_msg.clone()),
-        );
-        let handle_msg = ExecuteMsg::SendFrom {
-            owner: "bob".to_string(),
-            recipient: "contract".to_string(),
-            recipient_code_hash: None,
-            amount: Uint128::new(2000),
-            memo: Some("my memo".to_string()),
-            msg: Some(send_msg),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        // an even split has no remainder
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(&["charlie", "dave"], 500),
         );
-        assert!(handle_result.unwrap().messages.contains(
-            &into_cosmos_submsg(
-                snip20_msg,
-                "lolz".to_string(),
-                Addr::unchecked("contract".to_string()),
-                0
-            )
-            .unwrap()
-        ));
-
-        let bob_canonical = deps
-            .api
-            .addr_canonicalize(Addr::unchecked("bob".to_string()).as_str())
-            .unwrap();
-        let contract_canonical = deps
-            .api
-            .addr_canonicalize(Addr::unchecked("contract".to_string()).as_str())
-            .unwrap();
-
-        let bob_balance = stored_balance(&deps.storage, &bob_canonical).unwrap();
-        let contract_balance = stored_balance(&deps.storage, &contract_canonical).unwrap();
-        assert_eq!(bob_balance, 5000 - 2000);
-        assert_ne!(contract_balance, 2000);
-        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        assert_eq!(total_supply, 5000);
-
-        // Second send more than allowance
-        let handle_msg = ExecuteMsg::SendFrom {
-            owner: "bob".to_string(),
-            recipient: "alice".to_string(),
-            recipient_code_hash: None,
-            amount: Uint128::new(1),
-            memo: None,
-            msg: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(balance(&deps, "charlie"), 583);
+        assert_eq!(balance(&deps, "dave"), 583);
+        assert_eq!(balance(&deps, "bob"), 3500);
     }
 
     #[test]
-    fn test_handle_burn_from() {
+    fn test_handle_transfer_split_remainder_policy() {
         let (init_result, mut deps) = init_helper_with_config(
             vec![InitialBalance {
                 address: "bob".to_string(),
-                amount: Uint128::new(10000),
+                amount: Uint128::new(5000),
             }],
             false,
             false,
@@ -2387,329 +3106,468 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+        let transfer_split =
+            |recipients: &[&str], total: u128, policy: RemainderPolicy| ExecuteMsg::TransferSplit {
+                recipients: recipients.iter().map(|r| r.to_string()).collect(),
+                total: Uint128::new(total),
+                memo: None,
+                remainder_policy: Some(policy),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+        let balance = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, address: &str| {
+            let address = deps.api.addr_canonicalize(address).unwrap();
+            DWB.load(&deps.storage)
+                .unwrap()
+                .account_balance(&deps.storage, &address)
+                .unwrap()
+        };
+
+        // the first recipient gets the remainder
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(
+                &["alice", "charlie", "dave"],
+                1000,
+                RemainderPolicy::FirstRecipient,
+            ),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(
+            [
+                balance(&deps, "alice"),
+                balance(&deps, "charlie"),
+                balance(&deps, "dave"),
+            ],
+            [334, 333, 333]
+        );
+        assert_eq!(balance(&deps, "bob"), 4000);
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+
+        // the last recipient gets the remainder
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(
+                &["alice", "charlie", "dave"],
+                1000,
+                RemainderPolicy::LastRecipient,
+            ),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(
+            [
+                balance(&deps, "alice"),
+                balance(&deps, "charlie"),
+                balance(&deps, "dave"),
+            ],
+            [667, 666, 667]
+        );
+        assert_eq!(balance(&deps, "bob"), 3000);
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+
+        // the remainder is burned, so the sender is still debited the full total
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(&["alice", "charlie", "dave"], 1000, RemainderPolicy::Burn),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(
+            [
+                balance(&deps, "alice"),
+                balance(&deps, "charlie"),
+                balance(&deps, "dave"),
+            ],
+            [1000, 999, 1000]
+        );
+        assert_eq!(balance(&deps, "bob"), 2000);
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 4999);
+
+        // an even split burns nothing
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer_split(&["alice", "charlie"], 500, RemainderPolicy::Burn),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(balance(&deps, "alice"), 1250);
+        assert_eq!(balance(&deps, "charlie"), 1249);
+        assert_eq!(balance(&deps, "bob"), 1500);
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 4999);
+    }
+
+    #[test]
+    fn test_handle_transfer_split_burn_remainder_disabled() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
-            amount: Uint128::new(10000),
+            amount: Uint128::new(5000),
         }]);
         assert!(
-            init_result_for_failure.is_ok(),
+            init_result.is_ok(),
             "Init failed: {}",
-            init_result_for_failure.err().unwrap()
+            init_result.err().unwrap()
         );
-        // test when burn disabled
-        let handle_msg = ExecuteMsg::BurnFrom {
-            owner: "bob".to_string(),
-            amount: Uint128::new(2500),
+
+        let handle_msg = ExecuteMsg::TransferSplit {
+            recipients: vec!["alice".to_string(), "charlie".to_string()],
+            total: Uint128::new(1001),
             memo: None,
+            remainder_policy: Some(RemainderPolicy::Burn),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
-
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
         let error = extract_error_msg(handle_result);
         assert!(error.contains("Burn functionality is not enabled for this token."));
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+    }
 
-        // Burn before allowance
-        let handle_msg = ExecuteMsg::BurnFrom {
-            owner: "bob".to_string(),
-            amount: Uint128::new(2500),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+    #[test]
+    fn test_handle_transfer_with_auth() {
+        let owner = "secret1h52w6nztc2vq8t2npygx64sdw9ekjqe0zqqrsx";
+        let owner_pub_key = "Aii386AZdJzOb8Z3r6j65y7BDoEe1LBOGWMUPO+HZUt1";
+        let other_pub_key = "AnGzV99Wz8dykbdeX1UNcmh8G++E/56ttJRutVUWqJ1v";
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: owner.to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
 
-        // Burn more than allowance
-        let handle_msg = ExecuteMsg::IncreaseAllowance {
-            spender: "alice".to_string(),
-            amount: Uint128::new(2000),
-            padding: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            expiration: None,
+        // the signatures below are over the sha256 of the authorization's JSON, e.g.
+        // {"chain_id":"secret-4","contract":"secret1token","from":"secret1h52w...","to":"alice",
+        // "amount":"1000","nonce":0}
+        let mut env = mock_env();
+        env.block.chain_id = "secret-4".to_string();
+        env.contract.address = Addr::unchecked("secret1token");
+
+        let transfer_with_auth = |amount: u128, nonce: u64, pub_key: &str, signature: &str| {
+            ExecuteMsg::TransferWithAuth {
+                from: owner.to_string(),
+                to: "alice".to_string(),
+                amount: Uint128::new(amount),
+                nonce,
+                signature: PermitSignature {
+                    pub_key: PubKey {
+                        r#type: "tendermint/PubKeySecp256k1".to_string(),
+                        value: Binary::from_base64(pub_key).unwrap(),
+                    },
+                    signature: Binary::from_base64(signature).unwrap(),
+                },
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            }
         };
-        let info = mock_info("bob", &[]);
+        let nonce0_signature =
+            "rXCfKi/mxupKNGbmZrRUo/UWDv6402io95Tw1a8gAQw23ve0KUB9hI4Aw+3Tnyn20RgMl8rKbzgzD2Zfllt3AA==";
+        let nonce2_signature =
+            "KkWfuvYl0AsaVmLt1C5YMsHTKzEeivQa800syrZsmeRoL+zIVjOAHSJFhfiCvMr7QgsSYqJyS+UIjH0Y5B6CMw==";
+        let other_key_signature =
+            "8qiMh7j5GkJA/aRwoCWBut0g4GZ/wNqYAPOPVc5mBoEP8KJGpj0eAvunf0zaPZO+77qcXxbZYd0aYctY93w2aA==";
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        // a key other than the owner's cannot authorize the transfer
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("relayer", &[]),
+            transfer_with_auth(1000, 0, other_pub_key, other_key_signature),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains(&format!("authorization must be signed by {owner}")));
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        // the signature must cover the amount being transferred
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("relayer", &[]),
+            transfer_with_auth(999, 0, owner_pub_key, nonce0_signature),
         );
-        let handle_msg = ExecuteMsg::BurnFrom {
-            owner: "bob".to_string(),
-            amount: Uint128::new(2500),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("alice", &[]);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Failed to verify signature"));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        // a relayer submits a valid authorization
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("relayer", &[]),
+            transfer_with_auth(1000, 0, owner_pub_key, nonce0_signature),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        let owner_canonical = deps.api.addr_canonicalize(owner).unwrap();
+        let alice_canonical = deps.api.addr_canonicalize("alice").unwrap();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        assert_eq!(
+            dwb.account_balance(&deps.storage, &owner_canonical)
+                .unwrap(),
+            4000
+        );
+        assert_eq!(
+            dwb.account_balance(&deps.storage, &alice_canonical)
+                .unwrap(),
+            1000
+        );
 
-        // Sanity check
-        let handle_msg = ExecuteMsg::BurnFrom {
-            owner: "bob".to_string(),
-            amount: Uint128::new(2000),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        // the same authorization cannot be replayed
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("relayer", &[]),
+            transfer_with_auth(1000, 0, owner_pub_key, nonce0_signature),
         );
-        let bob_canonical = deps
-            .api
-            .addr_canonicalize(Addr::unchecked("bob".to_string()).as_str())
-            .unwrap();
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("invalid nonce: expected 1, got 0"));
 
-        let bob_balance = stored_balance(&deps.storage, &bob_canonical).unwrap();
-        assert_eq!(bob_balance, 10000 - 2000);
-        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        assert_eq!(total_supply, 10000 - 2000);
+        // nor can one signed for a later nonce be used out of order
+        let handle_result = execute(
+            deps.as_mut(),
+            env,
+            mock_info("relayer", &[]),
+            transfer_with_auth(1000, 2, owner_pub_key, nonce2_signature),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("invalid nonce: expected 1, got 2"));
 
-        // Second burn more than allowance
-        let handle_msg = ExecuteMsg::BurnFrom {
-            owner: "bob".to_string(),
-            amount: Uint128::new(1),
-            memo: None,
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info(owner, &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        let query_msg = QueryMsg::Nonce {
+            address: owner.to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let nonce = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Nonce { nonce } => nonce,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(nonce, 1);
     }
 
     #[test]
-    fn test_handle_batch_burn_from() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![
-                InitialBalance {
-                    address: "bob".to_string(),
-                    amount: Uint128::new(10000),
-                },
-                InitialBalance {
-                    address: "jerry".to_string(),
-                    amount: Uint128::new(10000),
-                },
-                InitialBalance {
-                    address: "mike".to_string(),
-                    amount: Uint128::new(10000),
-                },
-            ],
-            false,
-            false,
-            false,
-            true,
-            0,
-            vec![],
-        );
+    fn test_handle_transfer_full_buffered_balance() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "bob".to_string(),
-            amount: Uint128::new(10000),
-        }]);
-        assert!(
-            init_result_for_failure.is_ok(),
-            "Init failed: {}",
-            init_result_for_failure.err().unwrap()
-        );
-        // test when burn disabled
-        let actions: Vec<_> = ["bob", "jerry", "mike"]
-            .iter()
-            .map(|name| batch::BurnFromAction {
-                owner: name.to_string(),
-                amount: Uint128::new(2500),
-                memo: None,
-            })
-            .collect();
-        let handle_msg = ExecuteMsg::BatchBurnFrom {
-            actions,
+        let transfer = |recipient: &str, amount: u128| ExecuteMsg::Transfer {
+            recipient: recipient.to_string(),
+            amount: Uint128::new(amount),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
+
+        // alice's funds only exist in the buffer, she has never been settled
         let handle_result = execute(
-            deps_for_failure.as_mut(),
+            deps.as_mut(),
             mock_env(),
-            info,
-            handle_msg.clone(),
+            mock_info("bob", &[]),
+            transfer("alice", 1000),
         );
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Burn functionality is not enabled for this token."));
-
-        // Burn before allowance
-        let info = mock_info("alice", &[]);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let alice_canonical = deps.api.addr_canonicalize("alice").unwrap();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        assert_eq!(
+            dwb.entries[dwb.recipient_match(&alice_canonical)]
+                .amount()
+                .unwrap(),
+            1000
+        );
+        assert_eq!(stored_balance(&deps.storage, &alice_canonical).unwrap(), 0);
 
+        // spending a single unit more than the buffered amount fails
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            transfer("charlie", 1001),
+        );
         let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
-
-        // Burn more than allowance
-        let allowance_size = 2000;
-        for name in &["bob", "jerry", "mike"] {
-            let handle_msg = ExecuteMsg::IncreaseAllowance {
-                spender: "alice".to_string(),
-                amount: Uint128::new(allowance_size),
-                padding: None,
-                #[cfg(feature = "gas_evaporation")]
-                gas_target: None,
-                expiration: None,
-            };
-            let info = mock_info(*name, &[]);
-            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-            assert!(
-                handle_result.is_ok(),
-                "handle() failed: {}",
-                handle_result.err().unwrap()
-            );
-            let handle_msg = ExecuteMsg::BurnFrom {
-                owner: "name".to_string(),
-                amount: Uint128::new(2500),
-                memo: None,
-                #[cfg(feature = "gas_evaporation")]
-                gas_target: None,
-                padding: None,
-            };
-            let info = mock_info("alice", &[]);
-
-            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(error.contains("insufficient funds to transfer: balance=1000, required=1001"));
 
-            let error = extract_error_msg(handle_result);
-            assert!(error.contains("insufficient allowance"));
-        }
+        // spending exactly the buffered amount settles it and then debits all of it
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            transfer("charlie", 1000),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        // Burn some of the allowance
-        let actions: Vec<_> = [("bob", 200_u128), ("jerry", 300), ("mike", 400)]
-            .iter()
-            .map(|(name, amount)| batch::BurnFromAction {
-                owner: name.to_string(),
-                amount: Uint128::new(*amount),
-                memo: None,
-            })
-            .collect();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        let alice_index = dwb.recipient_match(&alice_canonical);
+        assert_eq!(dwb.entries[alice_index].amount().unwrap(), 0);
+        assert_eq!(dwb.entries[alice_index].list_len().unwrap(), 0);
+        assert_eq!(stored_balance(&deps.storage, &alice_canonical).unwrap(), 0);
 
-        let handle_msg = ExecuteMsg::BatchBurnFrom {
-            actions,
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let query_msg = QueryMsg::Balance {
+            address: "alice".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let balance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(balance, Uint128::zero());
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        // the history has both the incoming tx from the buffer and the outgoing one
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "alice".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let txs = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, .. } => txs,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        let transfers = txs
+            .into_iter()
+            .map(|tx| (tx.action, tx.coins.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transfers,
+            vec![
+                (
+                    TxAction::Transfer {
+                        from: Addr::unchecked("alice"),
+                        sender: Addr::unchecked("alice"),
+                        recipient: Addr::unchecked("charlie"),
+                    },
+                    Uint128::new(1000)
+                ),
+                (
+                    TxAction::Transfer {
+                        from: Addr::unchecked("bob"),
+                        sender: Addr::unchecked("bob"),
+                        recipient: Addr::unchecked("alice"),
+                    },
+                    Uint128::new(1000)
+                ),
+            ]
         );
-        for (name, amount) in &[("bob", 200_u128), ("jerry", 300), ("mike", 400)] {
-            let name_canon = deps
-                .api
-                .addr_canonicalize(Addr::unchecked(name.to_string()).as_str())
-                .unwrap();
-            let balance = stored_balance(&deps.storage, &name_canon).unwrap();
-            assert_eq!(balance, 10000 - amount);
-        }
-        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        assert_eq!(total_supply, 10000 * 3 - (200 + 300 + 400));
+    }
 
-        // Burn the rest of the allowance
-        let actions: Vec<_> = [("bob", 200_u128), ("jerry", 300), ("mike", 400)]
-            .iter()
-            .map(|(name, amount)| batch::BurnFromAction {
-                owner: name.to_string(),
-                amount: Uint128::new(allowance_size - *amount),
-                memo: None,
-            })
-            .collect();
+    #[test]
+    fn test_handle_send() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
 
-        let handle_msg = ExecuteMsg::BatchBurnFrom {
-            actions,
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "this_is_a_hash_of_a_code".to_string(),
+            label: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("alice", &[]);
+        let info = mock_info("contract", &[]);
 
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-        for name in &["bob", "jerry", "mike"] {
-            let name_canon = deps
-                .api
-                .addr_canonicalize(Addr::unchecked(name.to_string()).as_str())
-                .unwrap();
-            let balance = stored_balance(&deps.storage, &name_canon).unwrap();
-            assert_eq!(balance, 10000 - allowance_size);
-        }
-        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        assert_eq!(total_supply, 3 * (10000 - allowance_size));
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result));
 
-        // Second burn more than allowance
-        let actions: Vec<_> = ["bob", "jerry", "mike"]
-            .iter()
-            .map(|name| batch::BurnFromAction {
-                owner: name.to_string(),
-                amount: Uint128::new(1),
-                memo: None,
-            })
-            .collect();
-        let handle_msg = ExecuteMsg::BatchBurnFrom {
-            actions,
+        let handle_msg = ExecuteMsg::Send {
+            recipient: "contract".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(100),
+            memo: Some("my memo".to_string()),
+            memo_encrypted: None,
+            want_receipt: None,
+            padding: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            padding: None,
+            msg: Some(to_binary("hey hey you you").unwrap()),
+            reply_on_error: None,
         };
-        let info = mock_info("alice", &[]);
+        let info = mock_info("bob", &[]);
 
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("insufficient allowance"));
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result.clone()));
+        let id = 0;
+        assert!(result.messages.contains(&SubMsg {
+            id,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "contract".to_string(),
+                code_hash: "this_is_a_hash_of_a_code".to_string(),
+                msg: Snip20ReceiveMsg::new(
+                    Addr::unchecked("bob".to_string()),
+                    Addr::unchecked("bob".to_string()),
+                    Uint128::new(100),
+                    Some("my memo".to_string()),
+                    Some(to_binary("hey hey you you").unwrap())
+                )
+                .into_binary()
+                .unwrap(),
+                funds: vec![],
+            })
+            .into(),
+            reply_on: match id {
+                0 => ReplyOn::Never,
+                _ => ReplyOn::Always,
+            },
+            gas_limit: None,
+        }));
     }
 
     #[test]
-    fn test_handle_decrease_allowance() {
+    fn test_handle_send_to_token_contract() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -2720,35 +3578,24 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::DecreaseAllowance {
-            spender: "alice".to_string(),
-            amount: Uint128::new(2000),
+        let contract_address = mock_env().contract.address.to_string();
+
+        let handle_msg = ExecuteMsg::Send {
+            recipient: contract_address.clone(),
+            recipient_code_hash: None,
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             padding: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            expiration: None,
+            msg: Some(to_binary("hey hey you you").unwrap()),
+            reply_on_error: None,
         };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-
-        let bob_canonical = Addr::unchecked("bob".to_string());
-        let alice_canonical = Addr::unchecked("alice".to_string());
-
-        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
-        assert_eq!(
-            allowance,
-            crate::state::Allowance {
-                amount: 0,
-                expiration: None
-            }
-        );
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.starts_with("cannot send to the token contract"));
 
         let handle_msg = ExecuteMsg::IncreaseAllowance {
             spender: "alice".to_string(),
@@ -2757,47 +3604,68 @@ mod tests {
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             expiration: None,
+            notify_self: None,
+            grace_seconds: None,
         };
-        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let handle_msg = ExecuteMsg::SendFrom {
+            owner: "bob".to_string(),
+            recipient: contract_address.clone(),
+            recipient_code_hash: None,
+            amount: Uint128::new(100),
+            memo: None,
+            msg: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "cannot send to the token contract");
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        // the rejected send_from did not use up any of the allowance
+        let allowance = AllowancesStore::load(
+            &deps.storage,
+            &Addr::unchecked("bob".to_string()),
+            &Addr::unchecked("alice".to_string()),
         );
+        assert_eq!(allowance.amount, 2000);
 
-        let handle_msg = ExecuteMsg::DecreaseAllowance {
-            spender: "alice".to_string(),
-            amount: Uint128::new(50),
-            padding: None,
+        // a plain transfer to the contract is still allowed, e.g. as a donation
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: contract_address.clone(),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            expiration: None,
+            padding: None,
         };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result.clone()));
+        assert!(result.messages.is_empty());
 
-        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
+        let dwb = DWB.load(&deps.storage).unwrap();
+        let contract_raw = deps.api.addr_canonicalize(&contract_address).unwrap();
+        let bob_raw = deps.api.addr_canonicalize("bob").unwrap();
         assert_eq!(
-            allowance,
-            crate::state::Allowance {
-                amount: 1950,
-                expiration: None
-            }
+            dwb.account_balance(&deps.storage, &contract_raw).unwrap(),
+            100
         );
+        assert_eq!(dwb.account_balance(&deps.storage, &bob_raw).unwrap(), 4900);
     }
 
     #[test]
-    fn test_handle_increase_allowance() {
+    fn test_handle_transfer_receipt() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -2808,66 +3676,97 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::IncreaseAllowance {
-            spender: "alice".to_string(),
-            amount: Uint128::new(2000),
-            padding: None,
+        let transfer = |want_receipt: Option<bool>| ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            expiration: None,
+            padding: None,
+        };
+        let receipt = |result: Response| match from_binary(&result.data.unwrap()).unwrap() {
+            ExecuteAnswer::Transfer { receipt, .. } | ExecuteAnswer::Send { receipt, .. } => {
+                receipt
+            }
+            other => panic!("Unexpected: {:?}", other),
         };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        // no receipt unless requested
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer(None),
         );
+        assert_eq!(receipt(handle_result.unwrap()), None);
 
-        let bob_canonical = Addr::unchecked("bob".to_string());
-        let alice_canonical = Addr::unchecked("alice".to_string());
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer(Some(true)),
+        );
+        let transfer_receipt = receipt(handle_result.unwrap()).unwrap();
 
-        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
+        // the receipt is reproducible from the tx id shown in history, parties, amount and block height
+        let query_result =
+            query::query_transactions(deps.as_ref(), "bob".to_string(), 0, 1, None, None);
+        let tx_id = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, .. } => txs[0].id,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        let internal_secret = INTERNAL_SECRET_RELAXED.load(&deps.storage).unwrap();
         assert_eq!(
-            allowance,
-            crate::state::Allowance {
-                amount: 2000,
-                expiration: None
-            }
+            tx_id,
+            obfuscated_tx_id(&internal_secret, TX_COUNT.load(&deps.storage).unwrap())
         );
+        let expected = execute_transfer_send::transfer_receipt(
+            tx_id,
+            &Addr::unchecked("bob"),
+            &Addr::unchecked("alice"),
+            Uint128::new(100),
+            mock_env().block.height,
+        );
+        assert_eq!(transfer_receipt, expected);
+        assert_eq!(transfer_receipt.len(), 64);
 
-        let handle_msg = ExecuteMsg::IncreaseAllowance {
-            spender: "alice".to_string(),
-            amount: Uint128::new(2000),
+        // an identical transfer gets a distinct receipt since it has a new tx id
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer(Some(true)),
+        );
+        assert_ne!(receipt(handle_result.unwrap()).unwrap(), transfer_receipt);
+
+        let handle_msg = ExecuteMsg::Send {
+            recipient: "alice".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: Some(true),
             padding: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            expiration: None,
+            msg: None,
+            reply_on_error: None,
         };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-
-        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
-        assert_eq!(
-            allowance,
-            crate::state::Allowance {
-                amount: 4000,
-                expiration: None
-            }
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        let expected = execute_transfer_send::transfer_receipt(
+            obfuscated_tx_id(&internal_secret, TX_COUNT.load(&deps.storage).unwrap()),
+            &Addr::unchecked("bob"),
+            &Addr::unchecked("alice"),
+            Uint128::new(100),
+            mock_env().block.height,
         );
+        assert_eq!(receipt(handle_result.unwrap()), Some(expected));
     }
 
     #[test]
-    fn test_handle_change_admin() {
+    fn test_handle_send_recipient_code_hash() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
             amount: Uint128::new(5000),
@@ -2878,30 +3777,71 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::ChangeAdmin {
-            address: "bob".to_string(),
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "this_is_a_hash_of_a_code".to_string(),
+            label: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("admin", &[]);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("contract", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let send = |recipient_code_hash: Option<&str>| ExecuteMsg::Send {
+            recipient: "contract".to_string(),
+            recipient_code_hash: recipient_code_hash.map(String::from),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            msg: None,
+            reply_on_error: None,
+        };
+        let callback_code_hash = |result: Response| match &result.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { code_hash, .. }) => code_hash.clone(),
+            other => panic!("Unexpected: {:?}", other),
+        };
 
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        for invalid_hash in ["", "not_a_hash", &"a".repeat(63), &"g".repeat(64)] {
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("bob", &[]),
+                send(Some(invalid_hash)),
+            );
+            let error = extract_error_msg(handle_result);
+            assert!(error.starts_with("invalid recipient code hash"));
+        }
+
+        // without a hash, the callback falls back to the registered one
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), send(None));
+        assert_eq!(
+            callback_code_hash(handle_result.unwrap()),
+            "this_is_a_hash_of_a_code"
         );
 
-        let admin = CONFIG.load(&deps.storage).unwrap().admin;
-        assert_eq!(admin, Addr::unchecked("bob".to_string()));
+        let code_hash = "AB".repeat(32);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            send(Some(&code_hash)),
+        );
+        assert_eq!(callback_code_hash(handle_result.unwrap()), code_hash);
     }
 
     #[test]
-    fn test_handle_set_contract_status() {
+    fn test_handle_batch_send_coalesce_callbacks() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
-            address: "admin".to_string(),
+            address: "bob".to_string(),
             amount: Uint128::new(5000),
         }]);
         assert!(
@@ -2910,165 +3850,381 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::SetContractStatus {
-            level: ContractStatusLevel::StopAll,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        for (contract, code_hash) in [("contract_a", "hash_a"), ("contract_b", "hash_b")] {
+            let handle_msg = ExecuteMsg::RegisterReceive {
+                code_hash: code_hash.to_string(),
+                label: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(contract, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let action =
+            |recipient: &str, recipient_code_hash: Option<&str>, amount: u128| batch::SendAction {
+                recipient: recipient.to_string(),
+                recipient_code_hash: recipient_code_hash.map(String::from),
+                amount: Uint128::new(amount),
+                msg: Some(to_binary(&amount).unwrap()),
+                memo: None,
+            };
+        let batch_send = |coalesce_callbacks: Option<bool>| ExecuteMsg::BatchSend {
+            actions: vec![
+                action("contract_a", None, 1),
+                action("contract_b", None, 2),
+                action("contract_a", None, 3),
+                // same contract, but a different code hash
+                action(
+                    "contract_a",
+                    Some("1e4c5bcc0e2c7a9e6ff4d9eb2a3a7f0c9ed4e66a5c9d3c1b5ea6e19b8a28c8f0"),
+                    4,
+                ),
+            ],
+            coalesce_callbacks,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let wasm_msg = |contract: &str, code_hash: &str, msg: Binary| {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                code_hash: code_hash.to_string(),
+                msg,
+                funds: vec![],
+            })
+        };
+        let receive_msg = |amount: u128| {
+            Snip20ReceiveMsg::new(
+                Addr::unchecked("bob"),
+                Addr::unchecked("bob"),
+                Uint128::new(amount),
+                None,
+                Some(to_binary(&amount).unwrap()),
+            )
+            .into_binary()
+            .unwrap()
+        };
+
+        // without coalescing, every action gets its own callback
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            batch_send(None),
+        );
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result.clone()));
+        let messages: Vec<CosmosMsg> = result.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            messages,
+            vec![
+                wasm_msg("contract_a", "hash_a", receive_msg(1)),
+                wasm_msg("contract_b", "hash_b", receive_msg(2)),
+                wasm_msg("contract_a", "hash_a", receive_msg(3)),
+                wasm_msg(
+                    "contract_a",
+                    "1e4c5bcc0e2c7a9e6ff4d9eb2a3a7f0c9ed4e66a5c9d3c1b5ea6e19b8a28c8f0",
+                    receive_msg(4)
+                ),
+            ]
         );
 
-        let contract_status = CONTRACT_STATUS.load(&deps.storage).unwrap();
-        assert!(matches!(
-            contract_status,
-            ContractStatusLevel::StopAll { .. }
-        ));
+        // with coalescing, only callbacks to an identical target are combined
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            batch_send(Some(true)),
+        );
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result.clone()));
+        let messages: Vec<CosmosMsg> = result.messages.into_iter().map(|m| m.msg).collect();
+        let batch_receive_msg = Snip20BatchReceiveMsg {
+            sender: Addr::unchecked("bob"),
+            actions: [1u128, 3]
+                .into_iter()
+                .map(|amount| Snip20BatchReceiveAction {
+                    from: Addr::unchecked("bob"),
+                    amount: Uint128::new(amount),
+                    memo: None,
+                    msg: Some(to_binary(&amount).unwrap()),
+                })
+                .collect(),
+        }
+        .into_binary()
+        .unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                wasm_msg("contract_a", "hash_a", batch_receive_msg),
+                wasm_msg("contract_b", "hash_b", receive_msg(2)),
+                wasm_msg(
+                    "contract_a",
+                    "1e4c5bcc0e2c7a9e6ff4d9eb2a3a7f0c9ed4e66a5c9d3c1b5ea6e19b8a28c8f0",
+                    receive_msg(4)
+                ),
+            ]
+        );
     }
 
     #[test]
-    fn test_handle_redeem() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "butler".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            true,
-            false,
-            false,
-            1000,
-            vec!["uscrt".to_string()],
-        );
+    fn test_handle_batch_transfer_deadline_and_idempotency_key() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let (init_result_no_reserve, mut deps_no_reserve) = init_helper_with_config(
+        let batch_transfer =
+            |deadline: Option<u64>, idempotency_key: Option<&str>| ExecuteMsg::BatchTransfer {
+                actions: vec![batch::TransferAction {
+                    recipient: "alice".to_string(),
+                    amount: Uint128::new(100),
+                    memo: None,
+                }],
+                deadline,
+                idempotency_key: idempotency_key.map(String::from),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+        let now = mock_env().block.time.seconds();
+
+        let info = mock_info("bob", &[]);
+        let handle_msg = batch_transfer(Some(now - 1), None);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Batch deadline"));
+
+        let info = mock_info("bob", &[]);
+        let handle_msg = batch_transfer(Some(now), Some("batch-1"));
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let info = mock_info("bob", &[]);
+        let handle_msg = batch_transfer(None, Some("batch-1"));
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("duplicate idempotency key"));
+
+        let info = mock_info("bob", &[]);
+        let handle_msg = batch_transfer(None, Some("batch-2"));
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // oversized keys are rejected
+        let long_key = "k".repeat(MAX_IDEMPOTENCY_KEY_LENGTH + 1);
+        let info = mock_info("bob", &[]);
+        let handle_msg = batch_transfer(None, Some(&long_key));
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("idempotency key too long"));
+
+        // only a hash of the key is stored
+        let used_keys = IDEMPOTENCY_KEYS.add_suffix(b"bob");
+        assert_eq!(used_keys.get_len(&deps.storage).unwrap(), 2);
+        assert!(used_keys
+            .get(&deps.storage, &sha_256(b"bob:batch-1"))
+            .is_some());
+
+        // a key can be reused once it expires, which also prunes the other expired key
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(IDEMPOTENCY_KEY_TTL_SECONDS);
+        let info = mock_info("bob", &[]);
+        let handle_msg = batch_transfer(None, Some("batch-1"));
+        let handle_result = execute(deps.as_mut(), env, info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(used_keys.get_len(&deps.storage).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_handle_batch_idempotency_key() {
+        let (init_result, mut deps) = init_helper_with_config(
             vec![InitialBalance {
-                address: "butler".to_string(),
+                address: "bob".to_string(),
                 amount: Uint128::new(5000),
             }],
             false,
-            true,
-            false,
             false,
+            true,
+            true,
             0,
-            vec!["uscrt".to_string()],
+            vec![],
         );
         assert!(
-            init_result_no_reserve.is_ok(),
+            init_result.is_ok(),
             "Init failed: {}",
-            init_result_no_reserve.err().unwrap()
+            init_result.err().unwrap()
         );
 
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "butler".to_string(),
-            amount: Uint128::new(5000),
-        }]);
-        assert!(
-            init_result_for_failure.is_ok(),
-            "Init failed: {}",
-            init_result_for_failure.err().unwrap()
-        );
-        // test when redeem disabled
-        let handle_msg = ExecuteMsg::Redeem {
-            amount: Uint128::new(1000),
-            denom: None,
+        let batch_mint = || ExecuteMsg::BatchMint {
+            actions: vec![batch::MintAction {
+                recipient: "bob".to_string(),
+                amount: Uint128::new(100),
+                memo: None,
+            }],
+            idempotency_key: Some("mint-1".to_string()),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("butler", &[]);
-
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            batch_mint(),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
+        // a retried batch with the same key is rejected and mints nothing
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            batch_mint(),
+        );
         let error = extract_error_msg(handle_result);
-        assert!(error.contains("Redeem functionality is not enabled for this token."));
+        assert_eq!(error, "duplicate idempotency key");
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5100);
 
-        // try to redeem when contract has 0 balance
-        let handle_msg = ExecuteMsg::Redeem {
-            amount: Uint128::new(1000),
-            denom: None,
+        let batch_send = |amount: u128| ExecuteMsg::BatchSend {
+            actions: vec![batch::SendAction {
+                recipient: "alice".to_string(),
+                recipient_code_hash: None,
+                amount: Uint128::new(amount),
+                msg: None,
+                memo: None,
+            }],
+            coalesce_callbacks: None,
+            idempotency_key: Some("send-1".to_string()),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("butler", &[]);
-
-        let handle_result = execute(deps_no_reserve.as_mut(), mock_env(), info, handle_msg);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            batch_send(300),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            batch_send(300),
+        );
         let error = extract_error_msg(handle_result);
-        assert_eq!(
-            error,
-            "You are trying to redeem for more uscrt than the contract has in its reserve"
+        assert_eq!(error, "duplicate idempotency key");
+
+        // keys are scoped per sender, so another account can use the same key
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            batch_send(100),
         );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        // test without denom
-        let handle_msg = ExecuteMsg::Redeem {
+        for account in ["bob", "alice"] {
+            let handle_msg = ExecuteMsg::SetViewingKey {
+                key: "key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(account, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+        for (account, expected) in [("bob", 4800), ("alice", 200)] {
+            let query_msg = QueryMsg::Balance {
+                address: account.to_string(),
+                key: "key".to_string(),
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            let balance = match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::Balance { amount, .. } => amount,
+                other => panic!("Unexpected: {:?}", other),
+            };
+            assert_eq!(balance, Uint128::new(expected));
+        }
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
             amount: Uint128::new(1000),
-            denom: None,
-            padding: None,
+            expiration: None,
+            grace_seconds: None,
+            notify_self: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
+            padding: None,
         };
-        let info = mock_info("butler", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        // test with denom specified
-        let handle_msg = ExecuteMsg::Redeem {
-            amount: Uint128::new(1000),
-            denom: Option::from("uscrt".to_string()),
+        let batch_burn_from = || ExecuteMsg::BatchBurnFrom {
+            actions: vec![batch::BurnFromAction {
+                owner: "bob".to_string(),
+                amount: Uint128::new(100),
+                memo: None,
+            }],
+            idempotency_key: Some("burn-1".to_string()),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("butler", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            batch_burn_from(),
         );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let canonical = deps
-            .api
-            .addr_canonicalize(Addr::unchecked("butler".to_string()).as_str())
-            .unwrap();
-        assert_eq!(stored_balance(&deps.storage, &canonical).unwrap(), 3000)
+        // a retried burn with the same key is rejected and burns nothing
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            batch_burn_from(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "duplicate idempotency key");
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+        let allowance = AllowancesStore::load(
+            &deps.storage,
+            &Addr::unchecked("bob".to_string()),
+            &Addr::unchecked("alice".to_string()),
+        );
+        assert_eq!(allowance.amount, 900);
     }
 
     #[test]
-    fn test_handle_deposit() {
-        let (init_result, mut deps) = init_helper_with_config(
+    fn test_handle_send_require_msg() {
+        let (init_result, mut deps) = init_helper_with_raw_config(
             vec![InitialBalance {
-                address: "lebron".to_string(),
+                address: "bob".to_string(),
                 amount: Uint128::new(5000),
             }],
-            true,
-            false,
-            false,
-            false,
-            0,
-            vec!["uscrt".to_string()],
+            r#"{ "require_send_msg": true }"#,
         );
         assert!(
             init_result.is_ok(),
@@ -3076,388 +4232,478 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "lebron".to_string(),
-            amount: Uint128::new(5000),
-        }]);
-        assert!(
-            init_result_for_failure.is_ok(),
-            "Init failed: {}",
-            init_result_for_failure.err().unwrap()
-        );
-        // test when deposit disabled
-        let handle_msg = ExecuteMsg::Deposit {
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "this_is_a_hash_of_a_code".to_string(),
+            label: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info(
-            "lebron",
-            &[Coin {
-                denom: "uscrt".to_string(),
-                amount: Uint128::new(1000),
-            }],
-        );
-
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Tried to deposit an unsupported coin uscrt"));
+        let info = mock_info("contract", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_msg = ExecuteMsg::Deposit {
+        let send = |recipient: &str, msg: Option<Binary>| ExecuteMsg::Send {
+            recipient: recipient.to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            padding: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            padding: None,
+            msg,
+            reply_on_error: None,
         };
 
-        let info = mock_info(
-            "lebron",
-            &[Coin {
-                denom: "uscrt".to_string(),
-                amount: Uint128::new(1000),
-            }],
-        );
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, send("contract", None));
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("A msg is required"));
 
+        let info = mock_info("bob", &[]);
+        let handle_msg = send("contract", Some(to_binary("hey hey you you").unwrap()));
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-
-        let canonical = deps
-            .api
-            .addr_canonicalize(Addr::unchecked("lebron".to_string()).as_str())
-            .unwrap();
-
-        // stored balance not updated, still in dwb
-        assert_ne!(stored_balance(&deps.storage, &canonical).unwrap(), 6000);
-
-        let create_vk_msg = ExecuteMsg::CreateViewingKey {
-            entropy: Some("34".to_string()),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("lebron", &[]);
-        let handle_response = execute(deps.as_mut(), mock_env(), info, create_vk_msg).unwrap();
-        let vk = match from_binary(&handle_response.data.unwrap()).unwrap() {
-            ExecuteAnswer::CreateViewingKey { key } => key,
-            _ => panic!("Unexpected result from handle"),
-        };
-
-        let query_balance_msg = QueryMsg::Balance {
-            address: "lebron".to_string(),
-            key: vk,
-        };
+        let result = handle_result.unwrap();
+        assert_eq!(result.messages.len(), 1);
+        assert!(ensure_success(result));
 
-        let query_response = query(deps.as_ref(), mock_env(), query_balance_msg).unwrap();
-        let balance = match from_binary(&query_response).unwrap() {
-            QueryAnswer::Balance { amount } => amount,
-            _ => panic!("Unexpected result from query"),
-        };
-        assert_eq!(balance, Uint128::new(6000));
+        // recipients without a registered receiver do not need a msg
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, send("alice", None));
+        assert!(ensure_success(handle_result.unwrap()));
     }
 
     #[test]
-    fn test_handle_burn() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "lebron".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            false,
-            false,
-            true,
-            0,
-            vec![],
-        );
+    fn test_handle_send_reply_on_error() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "lebron".to_string(),
-            amount: Uint128::new(5000),
-        }]);
-        assert!(
-            init_result_for_failure.is_ok(),
-            "Init failed: {}",
-            init_result_for_failure.err().unwrap()
-        );
-        // test when burn disabled
-        let handle_msg = ExecuteMsg::Burn {
-            amount: Uint128::new(100),
-            memo: None,
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "this_is_a_hash_of_a_code".to_string(),
+            label: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("lebron", &[]);
+        let info = mock_info("contract", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+        let handle_msg = ExecuteMsg::Send {
+            recipient: "contract".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            msg: Some(to_binary("hey hey you you").unwrap()),
+            reply_on_error: Some(true),
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let result = handle_result.unwrap();
+        assert_eq!(result.messages.len(), 1);
+        assert_eq!(result.messages[0].reply_on, ReplyOn::Error);
+        assert_eq!(result.messages[0].id, CALLBACK_REPLY_ID);
+        let callback_msg = result.messages[0].msg.clone();
+        assert!(ensure_success(result));
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Burn functionality is not enabled for this token."));
+        // the receiver fails, but the transfer is kept
+        let reply_msg = Reply {
+            id: CALLBACK_REPLY_ID,
+            result: SubMsgResult::Err("receiver failed".to_string()),
+        };
+        let reply_result = reply(deps.as_mut(), mock_env(), reply_msg);
+        assert!(reply_result.is_ok());
 
-        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        let burn_amount: u128 = 100;
-        let handle_msg = ExecuteMsg::Burn {
-            amount: Uint128::new(burn_amount),
-            memo: None,
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("lebron", &[]);
-
+        let info = mock_info("contract", &[]);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        assert!(
-            handle_result.is_ok(),
-            "Pause handle failed: {}",
-            handle_result.err().unwrap()
-        );
+        let query_msg = QueryMsg::Balance {
+            address: "contract".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => assert_eq!(amount, Uint128::new(100)),
+            _ => panic!("Unexpected"),
+        }
 
-        let new_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        assert_eq!(new_supply, supply - burn_amount);
+        let pending_callbacks = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let query_msg = QueryMsg::PendingCallbacks {
+                viewer: ViewerInfo {
+                    address: "contract".to_string(),
+                    viewing_key: "key".to_string(),
+                },
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::PendingCallbacks { callbacks } => callbacks,
+                _ => panic!("Unexpected"),
+            }
+        };
+        let callbacks = pending_callbacks(&deps);
+        assert_eq!(callbacks.len(), 1);
+        assert_eq!(callbacks[0].code_hash, "this_is_a_hash_of_a_code");
+
+        // only the recipient can retry its callbacks
+        let retry = |id: u64| ExecuteMsg::RetryCallback {
+            id,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, retry(callbacks[0].id));
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("No pending callback"));
+
+        let info = mock_info("contract", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, retry(callbacks[0].id));
+        let result = handle_result.unwrap();
+        assert_eq!(result.messages.len(), 1);
+        assert_eq!(result.messages[0].msg, callback_msg);
+        assert!(ensure_success(result));
+        assert!(pending_callbacks(&deps).is_empty());
     }
 
     #[test]
-    fn test_handle_mint() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "lebron".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            false,
-            true,
-            false,
-            0,
-            vec![],
-        );
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
-        );
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "lebron".to_string(),
+    fn test_handle_register_receive() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
             amount: Uint128::new(5000),
         }]);
         assert!(
-            init_result_for_failure.is_ok(),
+            init_result.is_ok(),
             "Init failed: {}",
-            init_result_for_failure.err().unwrap()
+            init_result.err().unwrap()
         );
-        // try to mint when mint is disabled
-        let mint_amount: u128 = 100;
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "lebron".to_string(),
-            amount: Uint128::new(mint_amount),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("admin", &[]);
-
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Mint functionality is not enabled for this token"));
 
-        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        let mint_amount: u128 = 100;
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "lebron".to_string(),
-            amount: Uint128::new(mint_amount),
-            memo: None,
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "this_is_a_hash_of_a_code".to_string(),
+            label: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("admin", &[]);
+        let info = mock_info("contract", &[]);
 
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        assert!(
-            handle_result.is_ok(),
-            "Pause handle failed: {}",
-            handle_result.err().unwrap()
-        );
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result));
 
-        let new_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
-        assert_eq!(new_supply, supply + mint_amount);
+        let hash =
+            ReceiverHashStore::may_load(&deps.storage, &Addr::unchecked("contract".to_string()))
+                .unwrap()
+                .unwrap();
+        assert_eq!(hash, "this_is_a_hash_of_a_code".to_string());
     }
 
     #[test]
-    fn test_handle_admin_commands() {
-        let admin_err = "Admin commands can only be run from admin address".to_string();
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "lebron".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            false,
-            true,
-            false,
-            0,
-            vec![],
-        );
+    fn test_query_receiver_hash() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let pause_msg = ExecuteMsg::SetContractStatus {
-            level: ContractStatusLevel::StopAllButRedeems,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        let query_msg = QueryMsg::ReceiverHash {
+            address: "contract".to_string(),
         };
-        let info = mock_info("not_admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, pause_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains(&admin_err.clone()));
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let code_hash = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ReceiverHash { code_hash, .. } => code_hash,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(code_hash, None);
 
-        let mint_msg = ExecuteMsg::AddMinters {
-            minters: vec!["not_admin".to_string()],
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "this_is_a_hash_of_a_code".to_string(),
+            label: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("not_admin", &[]);
+        let info = mock_info("contract", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, mint_msg);
+        let query_msg = QueryMsg::ReceiverHash {
+            address: "contract".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let code_hash = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ReceiverHash { code_hash, .. } => code_hash,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(code_hash, Some("this_is_a_hash_of_a_code".to_string()));
+    }
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains(&admin_err.clone()));
+    #[test]
+    fn test_query_receiver_hash_label() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
 
-        let mint_msg = ExecuteMsg::RemoveMinters {
-            minters: vec!["admin".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        let register = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                        label: Option<String>| {
+            let handle_msg = ExecuteMsg::RegisterReceive {
+                code_hash: "this_is_a_hash_of_a_code".to_string(),
+                label,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("contract", &[]),
+                handle_msg,
+            )
+        };
+        let receiver_hash = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let query_msg = QueryMsg::ReceiverHash {
+                address: "contract".to_string(),
+            };
+            match from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap() {
+                QueryAnswer::ReceiverHash { code_hash, label } => (code_hash, label),
+                other => panic!("Unexpected: {:?}", other),
+            }
         };
-        let info = mock_info("not_admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, mint_msg);
 
+        let handle_result = register(&mut deps, Some("x".repeat(MAX_RECEIVER_LABEL_LENGTH + 1)));
         let error = extract_error_msg(handle_result);
-        assert!(error.contains(&admin_err.clone()));
+        assert!(error.contains("label too long"));
+        assert_eq!(receiver_hash(&deps), (None, None));
 
-        let mint_msg = ExecuteMsg::SetMinters {
-            minters: vec!["not_admin".to_string()],
+        let handle_result = register(&mut deps, Some("dex router v2".to_string()));
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(
+            receiver_hash(&deps),
+            (
+                Some("this_is_a_hash_of_a_code".to_string()),
+                Some("dex router v2".to_string())
+            )
+        );
+
+        // registering again without a label clears it
+        let handle_result = register(&mut deps, None);
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(
+            receiver_hash(&deps),
+            (Some("this_is_a_hash_of_a_code".to_string()), None)
+        );
+    }
+
+    #[test]
+    fn test_handle_set_memo_decryption_key() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::MemoDecryptionKey {
+            address: "bob".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let pubkey = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::MemoDecryptionKey { pubkey } => pubkey,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(pubkey, None);
+
+        // not a compressed public key
+        let handle_msg = ExecuteMsg::SetMemoDecryptionKey {
+            pubkey: Binary::from(vec![4u8; 65]),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("not_admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, mint_msg);
-
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
         let error = extract_error_msg(handle_result);
-        assert!(error.contains(&admin_err.clone()));
+        assert!(error.contains("33-byte compressed secp256k1 public key"));
 
-        let change_admin_msg = ExecuteMsg::ChangeAdmin {
-            address: "not_admin".to_string(),
+        let pubkey = Binary::from_base64("A57BKPfLO+mFm8LZTxTRMI8VYkUPrJV7F+n1rp4nLRvO").unwrap();
+        let handle_msg = ExecuteMsg::SetMemoDecryptionKey {
+            pubkey: pubkey.clone(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("not_admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, change_admin_msg);
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains(&admin_err.clone()));
+        let query_msg = QueryMsg::MemoDecryptionKey {
+            address: "bob".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let published = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::MemoDecryptionKey { pubkey } => pubkey,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(published, Some(pubkey));
     }
 
     #[test]
-    fn test_handle_pause_with_withdrawals() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "lebron".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            true,
-            false,
-            false,
-            5000,
-            vec!["uscrt".to_string()],
-        );
+    fn test_handle_create_viewing_key() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let pause_msg = ExecuteMsg::SetContractStatus {
-            level: ContractStatusLevel::StopAllButRedeems,
+        let handle_msg = ExecuteMsg::CreateViewingKey {
+            entropy: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
+        let info = mock_info("bob", &[]);
 
-        let info = mock_info("admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, pause_msg);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
         assert!(
             handle_result.is_ok(),
-            "Pause handle failed: {}",
+            "handle() failed: {}",
             handle_result.err().unwrap()
         );
+        let answer: ExecuteAnswer = from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
 
-        let send_msg = ExecuteMsg::Transfer {
-            recipient: "account".to_string(),
-            amount: Uint128::new(123),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        let key = match answer {
+            ExecuteAnswer::CreateViewingKey { key } => key,
+            _ => panic!("NOPE"),
         };
-        let info = mock_info("admin", &[]);
+        // let bob_canonical = deps.as_mut().api.addr_canonicalize("bob").unwrap();
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, send_msg);
+        let result = ViewingKey::check(&deps.storage, "bob", key.as_str());
+        assert!(result.is_ok());
 
-        let error = extract_error_msg(handle_result);
-        assert_eq!(
-            error,
-            "This contract is stopped and this action is not allowed".to_string()
-        );
+        // let saved_vk = read_viewing_key(&deps.storage, &bob_canonical).unwrap();
+        // assert!(key.check_viewing_key(saved_vk.as_slice()));
+    }
 
-        let withdraw_msg = ExecuteMsg::Redeem {
+    #[test]
+    fn test_handle_create_viewing_key_cooldown() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
             amount: Uint128::new(5000),
-            denom: Option::from("uscrt".to_string()),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let create_vk_msg = || ExecuteMsg::CreateViewingKey {
+            entropy: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("lebron", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, withdraw_msg);
+        let mut env = mock_env();
 
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            create_vk_msg(),
+        );
         assert!(
             handle_result.is_ok(),
-            "Withdraw failed: {}",
+            "handle() failed: {}",
             handle_result.err().unwrap()
         );
+
+        // immediately retrying is rejected
+        env.block.height += 1;
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            create_vk_msg(),
+        );
+        assert_eq!(
+            extract_error_msg(handle_result),
+            "viewing key creation rate-limited"
+        );
+
+        // other accounts and explicit keys are unaffected
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            create_vk_msg(),
+        );
+        assert!(handle_result.is_ok());
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // succeeds once the cooldown has passed
+        env.block.height = mock_env().block.height + VIEWING_KEY_CREATION_COOLDOWN_BLOCKS;
+        let handle_result = execute(deps.as_mut(), env, mock_info("bob", &[]), create_vk_msg());
+        let key = match from_binary(&handle_result.unwrap().data.unwrap()).unwrap() {
+            ExecuteAnswer::CreateViewingKey { key } => key,
+            _ => panic!("NOPE"),
+        };
+        assert!(ViewingKey::check(&deps.storage, "bob", key.as_str()).is_ok());
     }
 
     #[test]
-    fn test_handle_pause_all() {
+    fn test_handle_set_viewing_key() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
-            address: "lebron".to_string(),
+            address: "bob".to_string(),
             amount: Uint128::new(5000),
         }]);
         assert!(
@@ -3466,399 +4712,560 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let pause_msg = ExecuteMsg::SetContractStatus {
-            level: ContractStatusLevel::StopAll,
+        // Set VK
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "hi lol".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
+        let info = mock_info("bob", &[]);
 
-        let info = mock_info("admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, pause_msg);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        assert!(
-            handle_result.is_ok(),
-            "Pause handle failed: {}",
-            handle_result.err().unwrap()
+        let unwrapped_result: ExecuteAnswer =
+            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+        assert_eq!(
+            to_binary(&unwrapped_result).unwrap(),
+            to_binary(&ExecuteAnswer::SetViewingKey {
+                status: ResponseStatus::Success
+            })
+            .unwrap(),
         );
 
-        let send_msg = ExecuteMsg::Transfer {
-            recipient: "account".to_string(),
-            amount: Uint128::new(123),
-            memo: None,
+        // Set valid VK
+        let actual_vk = "x".to_string().repeat(VIEWING_KEY_SIZE);
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: actual_vk.clone(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("admin", &[]);
+        let info = mock_info("bob", &[]);
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, send_msg);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        let error = extract_error_msg(handle_result);
+        let unwrapped_result: ExecuteAnswer =
+            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
         assert_eq!(
-            error,
-            "This contract is stopped and this action is not allowed".to_string()
+            to_binary(&unwrapped_result).unwrap(),
+            to_binary(&ExecuteAnswer::SetViewingKey { status: Success }).unwrap(),
         );
 
-        let withdraw_msg = ExecuteMsg::Redeem {
-            amount: Uint128::new(5000),
-            denom: Option::from("uscrt".to_string()),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("lebron", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, withdraw_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert_eq!(
-            error,
-            "This contract is stopped and this action is not allowed".to_string()
-        );
+        let result = ViewingKey::check(&deps.storage, "bob", actual_vk.as_str());
+        assert!(result.is_ok());
     }
 
-    #[test]
-    fn test_handle_set_minters() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "bob".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            false,
-            true,
-            false,
-            0,
-            vec![],
-        );
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
-        );
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "bob".to_string(),
-            amount: Uint128::new(5000),
-        }]);
-        assert!(
-            init_result_for_failure.is_ok(),
-            "Init failed: {}",
-            init_result_for_failure.err().unwrap()
-        );
-        // try when mint disabled
-        let handle_msg = ExecuteMsg::SetMinters {
-            minters: vec!["bob".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("admin", &[]);
-
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Mint functionality is not enabled for this token"));
-
-        let handle_msg = ExecuteMsg::SetMinters {
-            minters: vec!["bob".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Admin commands can only be run from admin address"));
-
-        let handle_msg = ExecuteMsg::SetMinters {
-            minters: vec!["bob".to_string()],
+    fn revoke_permit(
+        permit_name: &str,
+        user_address: &str,
+        deps: &mut OwnedDeps<cosmwasm_std::MemoryStorage, MockApi, MockQuerier>,
+    ) -> Result<Response, StdError> {
+        let handle_msg = ExecuteMsg::RevokePermit {
+            permit_name: permit_name.to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("admin", &[]);
-
+        let info = mock_info(user_address, &[]);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        handle_result
+    }
 
-        assert!(ensure_success(handle_result.unwrap()));
-
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+    fn get_balance_with_permit_qry_msg(
+        permit_name: &str,
+        chain_id: &str,
+        pub_key_value: &str,
+        signature: &str,
+    ) -> QueryMsg {
+        let permit = gen_permit_obj(
+            permit_name,
+            chain_id,
+            pub_key_value,
+            signature,
+            TokenPermissions::Balance,
+        );
 
-        assert!(ensure_success(handle_result.unwrap()));
+        QueryMsg::WithPermit {
+            permit,
+            query: QueryWithPermit::Balance {},
+        }
+    }
 
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+    fn gen_permit_obj(
+        permit_name: &str,
+        chain_id: &str,
+        pub_key_value: &str,
+        signature: &str,
+        permit_type: TokenPermissions,
+    ) -> Permit {
+        let permit: Permit = Permit {
+            params: PermitParams {
+                allowed_tokens: vec![MOCK_CONTRACT_ADDR.to_string()],
+                permit_name: permit_name.to_string(),
+                chain_id: chain_id.to_string(),
+                permissions: vec![permit_type],
+                created: None,
+                expires: None,
+            },
+            signature: PermitSignature {
+                pub_key: PubKey {
+                    r#type: "tendermint/PubKeySecp256k1".to_string(),
+                    value: Binary::from_base64(pub_key_value).unwrap(),
+                },
+                signature: Binary::from_base64(signature).unwrap(),
+            },
         };
-        let info = mock_info("admin", &[]);
+        permit
+    }
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+    fn get_allowances_given_permit(
+        permit_name: &str,
+        chain_id: &str,
+        pub_key_value: &str,
+        signature: &str,
+        spender: String,
+    ) -> QueryMsg {
+        let permit = gen_permit_obj(
+            permit_name,
+            chain_id,
+            pub_key_value,
+            signature,
+            TokenPermissions::Owner,
+        );
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("allowed to minter accounts only"));
+        QueryMsg::WithPermit {
+            permit,
+            query: QueryWithPermit::AllowancesReceived {
+                spender,
+                page: None,
+                page_size: 0,
+            },
+        }
     }
 
     #[test]
-    fn test_handle_add_minters() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "bob".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            false,
-            true,
-            false,
-            0,
-            vec![],
-        );
+    fn test_permit_query_allowances_given_should_fail() {
+        let user_address = "secret18mdrja40gfuftt5yx6tgj0fn5lurplezyp894y";
+        let permit_name = "default";
+        let chain_id = "secretdev-1";
+        let pub_key = "AkZqxdKMtPq2w0kGDGwWGejTAed0H7azPMHtrCX0XYZG";
+        let signature = "ZXyFMlAy6guMG9Gj05rFvcMi5/JGfClRtJpVTHiDtQY3GtSfBHncY70kmYiTXkKIxSxdnh/kS8oXa+GSX5su6Q==";
+
+        // Init the contract
+        let (init_result, deps) = init_helper(vec![InitialBalance {
+            address: user_address.to_string(),
+            amount: Uint128::new(50000000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "bob".to_string(),
-            amount: Uint128::new(5000),
+
+        let msg = get_allowances_given_permit(
+            permit_name,
+            chain_id,
+            pub_key,
+            signature,
+            "secret1kmgdagt5efcz2kku0ak9ezfgntg29g2vr88q0e".to_string(),
+        );
+        let query_result = query(deps.as_ref(), mock_env(), msg);
+
+        assert_eq!(query_result.is_err(), true);
+    }
+
+    #[test]
+    fn test_permit_query_allowances_given() {
+        let user_address = "secret18mdrja40gfuftt5yx6tgj0fn5lurplezyp894y";
+        let permit_name = "default";
+        let chain_id = "secretdev-1";
+        let pub_key = "AkZqxdKMtPq2w0kGDGwWGejTAed0H7azPMHtrCX0XYZG";
+        let signature = "ZXyFMlAy6guMG9Gj05rFvcMi5/JGfClRtJpVTHiDtQY3GtSfBHncY70kmYiTXkKIxSxdnh/kS8oXa+GSX5su6Q==";
+
+        // Init the contract
+        let (init_result, deps) = init_helper(vec![InitialBalance {
+            address: user_address.to_string(),
+            amount: Uint128::new(50000000),
         }]);
         assert!(
-            init_result_for_failure.is_ok(),
+            init_result.is_ok(),
             "Init failed: {}",
-            init_result_for_failure.err().unwrap()
+            init_result.err().unwrap()
         );
-        // try when mint disabled
-        let handle_msg = ExecuteMsg::AddMinters {
-            minters: vec!["bob".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("admin", &[]);
-
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Mint functionality is not enabled for this token"));
+        let msg = get_allowances_given_permit(
+            permit_name,
+            chain_id,
+            pub_key,
+            signature,
+            "secret18mdrja40gfuftt5yx6tgj0fn5lurplezyp894y".to_string(),
+        );
+        let query_result = query(deps.as_ref(), mock_env(), msg);
 
-        let handle_msg = ExecuteMsg::AddMinters {
-            minters: vec!["bob".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
+        assert_eq!(query_result.is_ok(), true);
+    }
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Admin commands can only be run from admin address"));
+    #[test]
+    fn test_permit_query_notification_id() {
+        let user_address = "secret1kmgdagt5efcz2kku0ak9ezfgntg29g2vr88q0e";
+        let permit_name = "to_be_revoked";
+        let chain_id = "blabla";
+        let pub_key_value = "Ahlb7vwjo4aTY6dqfgpPmPYF7XhTAIReVwncQwlq8Sct";
+        let signature = "VS13F7iv1qxKABxrCAvZQPy2IruLQsIyfTewy/PIhNtybtq417lr3FxsWjV/i9YTqCUxg7weoZwHmYs0YgYX4w==";
 
-        let handle_msg = ExecuteMsg::AddMinters {
-            minters: vec!["bob".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("admin", &[]);
+        let (init_result, deps) = init_helper(vec![InitialBalance {
+            address: user_address.to_string(),
+            amount: Uint128::new(50000000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let txhash = "6e2e3c1e4e35a6e3dc3e77f5d1cfd87d3a7b3ad0f2c1e5b1c7f2c8a4d1c2b3a4".to_string();
+        for channel in [
+            RecvdNotification::CHANNEL_ID,
+            SpentNotification::CHANNEL_ID,
+            AllowanceNotification::CHANNEL_ID,
+        ] {
+            let query_msg = QueryMsg::WithPermit {
+                permit: gen_permit_obj(
+                    permit_name,
+                    chain_id,
+                    pub_key_value,
+                    signature,
+                    TokenPermissions::Balance,
+                ),
+                query: QueryWithPermit::NotificationId {
+                    channel: channel.to_string(),
+                    txhash: txhash.clone(),
+                },
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            let id = match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::NotificationId { id } => id,
+                _ => panic!("Unexpected result from query"),
+            };
 
-        assert!(ensure_success(handle_result.unwrap()));
+            let query_msg = QueryMsg::WithPermit {
+                permit: gen_permit_obj(
+                    permit_name,
+                    chain_id,
+                    pub_key_value,
+                    signature,
+                    TokenPermissions::Balance,
+                ),
+                query: QueryWithPermit::ChannelInfo {
+                    channels: vec![channel.to_string()],
+                    txhash: Some(txhash.clone()),
+                },
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            let channels = match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::ChannelInfo { channels, .. } => channels,
+                _ => panic!("Unexpected result from query"),
+            };
+            assert_eq!(channels[0].answer_id, Some(id));
+        }
 
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        let query_msg = QueryMsg::WithPermit {
+            permit: gen_permit_obj(
+                permit_name,
+                chain_id,
+                pub_key_value,
+                signature,
+                TokenPermissions::Balance,
+            ),
+            query: QueryWithPermit::NotificationId {
+                channel: "nonexistent".to_string(),
+                txhash,
+            },
         };
-        let info = mock_info("bob", &[]);
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let error = extract_error_msg(query_result);
+        assert_eq!(error, "`nonexistent` channel is undefined");
+    }
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+    #[test]
+    fn test_permit_revoke() {
+        let user_address = "secret1kmgdagt5efcz2kku0ak9ezfgntg29g2vr88q0e";
+        let permit_name = "to_be_revoked";
+        let chain_id = "blabla";
 
-        assert!(ensure_success(handle_result.unwrap()));
+        // Note that 'signature'was generated with the specific values of the above:
+        // user_address, permit_name, chain_id, pub_key_value
+        let pub_key_value = "Ahlb7vwjo4aTY6dqfgpPmPYF7XhTAIReVwncQwlq8Sct";
+        let signature = "VS13F7iv1qxKABxrCAvZQPy2IruLQsIyfTewy/PIhNtybtq417lr3FxsWjV/i9YTqCUxg7weoZwHmYs0YgYX4w==";
 
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        // Init the contract
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: user_address.to_string(),
+            amount: Uint128::new(50000000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Query the account's balance
+        let balance_with_permit_msg =
+            get_balance_with_permit_qry_msg(permit_name, chain_id, pub_key_value, signature);
+        let query_result = query(deps.as_ref(), mock_env(), balance_with_permit_msg);
+        let balance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            _ => panic!("Unexpected result from query"),
         };
-        let info = mock_info("admin", &[]);
+        assert_eq!(balance.u128(), 50000000);
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        // Revoke the Balance permit
+        let handle_result = revoke_permit(permit_name, user_address, &mut deps);
+        let status = match from_binary(&handle_result.unwrap().data.unwrap()).unwrap() {
+            ExecuteAnswer::RevokePermit { status } => status,
+            _ => panic!("NOPE"),
+        };
+        assert_eq!(status, ResponseStatus::Success);
 
-        assert!(ensure_success(handle_result.unwrap()));
+        // Try to query the balance with permit and fail because the permit is now revoked
+        let balance_with_permit_msg =
+            get_balance_with_permit_qry_msg(permit_name, chain_id, pub_key_value, signature);
+        let query_result = query(deps.as_ref(), mock_env(), balance_with_permit_msg);
+        let error = extract_error_msg(query_result);
+        assert!(
+            error.contains(format!("Permit \"{}\" was revoked by account", permit_name).as_str())
+        );
     }
 
     #[test]
-    fn test_handle_remove_minters() {
-        let (init_result, mut deps) = init_helper_with_config(
-            vec![InitialBalance {
-                address: "bob".to_string(),
-                amount: Uint128::new(5000),
-            }],
-            false,
-            false,
-            true,
-            false,
-            0,
-            vec![],
-        );
+    fn test_query_format_amount() {
+        let (init_result, mut deps) = init_helper(vec![]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
-        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
-            address: "bob".to_string(),
+
+        let cases: [(u8, u128, &str); 17] = [
+            (0, 0, "0"),
+            (0, 1, "1"),
+            (0, 123456, "123456"),
+            (6, 0, "0"),
+            (6, 1, "0.000001"),
+            (6, 999_999, "0.999999"),
+            (6, 1_000_000, "1"),
+            (6, 1_000_001, "1.000001"),
+            (6, 1_500_000, "1.5"),
+            (6, 120_000_000, "120"),
+            (8, 123456, "0.00123456"),
+            (8, 10_000_000, "0.1"),
+            (8, 99_999_999, "0.99999999"),
+            (8, 100_000_000, "1"),
+            (8, 100_000_010, "1.0000001"),
+            (8, 2_050_000_000, "20.5"),
+            (8, u128::MAX, "3402823669209384634633746074317.68211455"),
+        ];
+        for (decimals, amount, expected) in cases {
+            let mut config = CONFIG.load(&deps.storage).unwrap();
+            config.decimals = decimals;
+            CONFIG.save(&mut deps.storage, &config).unwrap();
+
+            let query_msg = QueryMsg::FormatAmount {
+                amount: Uint128::new(amount),
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            let display = match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::FormatAmount { display } => display,
+                other => panic!("Unexpected: {:?}", other),
+            };
+            assert_eq!(display, expected, "{amount} with {decimals} decimals");
+        }
+    }
+
+    #[test]
+    fn test_query_dwb_stats() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "lebron".to_string(),
             amount: Uint128::new(5000),
         }]);
         assert!(
-            init_result_for_failure.is_ok(),
+            init_result.is_ok(),
             "Init failed: {}",
-            init_result_for_failure.err().unwrap()
+            init_result.err().unwrap()
         );
-        // try when mint disabled
-        let handle_msg = ExecuteMsg::RemoveMinters {
-            minters: vec!["bob".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+
+        let dwb_stats = |deps: Deps| {
+            let query_result = query(deps, mock_env(), QueryMsg::DwbStats {});
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::DwbStats {
+                    capacity,
+                    used,
+                    empty_space_counter,
+                } => (capacity, used, empty_space_counter),
+                _ => panic!("unexpected"),
+            }
         };
-        let info = mock_info("admin", &[]);
 
-        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+        let (capacity, used, empty_space_counter) = dwb_stats(deps.as_ref());
+        assert_eq!(capacity, (DWB_LEN - 1) as u32);
+        assert_eq!(used + empty_space_counter, capacity);
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Mint functionality is not enabled for this token"));
+        for recipient in ["alice", "bob", "charlie"] {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(100),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("lebron", &[]);
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+            assert!(ensure_success(handle_result.unwrap()));
+        }
 
-        let handle_msg = ExecuteMsg::RemoveMinters {
-            minters: vec!["admin".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
+        let (_, new_used, new_empty_space_counter) = dwb_stats(deps.as_ref());
+        assert_eq!(new_used, used + 3);
+        assert_eq!(new_empty_space_counter, empty_space_counter - 3);
+    }
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+    #[test]
+    fn test_query_validate_permit() {
+        let user_address = "secret1kmgdagt5efcz2kku0ak9ezfgntg29g2vr88q0e";
+        let permit_name = "to_be_revoked";
+        let chain_id = "blabla";
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("Admin commands can only be run from admin address"));
+        // Note that 'signature'was generated with the specific values of the above:
+        // user_address, permit_name, chain_id, pub_key_value
+        let pub_key_value = "Ahlb7vwjo4aTY6dqfgpPmPYF7XhTAIReVwncQwlq8Sct";
+        let signature = "VS13F7iv1qxKABxrCAvZQPy2IruLQsIyfTewy/PIhNtybtq417lr3FxsWjV/i9YTqCUxg7weoZwHmYs0YgYX4w==";
 
-        let handle_msg = ExecuteMsg::RemoveMinters {
-            minters: vec!["admin".to_string()],
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let validate_msg = QueryMsg::ValidatePermit {
+            permit: gen_permit_obj(
+                permit_name,
+                chain_id,
+                pub_key_value,
+                signature,
+                TokenPermissions::Balance,
+            ),
         };
-        let info = mock_info("admin", &[]);
+        let query_result = query(deps.as_ref(), mock_env(), validate_msg.clone());
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ValidatePermit {
+                account,
+                permissions,
+            } => {
+                assert_eq!(account, Addr::unchecked(user_address));
+                assert_eq!(permissions, vec![TokenPermissions::Balance]);
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let handle_result = revoke_permit(permit_name, user_address, &mut deps);
+        assert!(handle_result.is_ok());
 
-        assert!(ensure_success(handle_result.unwrap()));
+        let query_result = query(deps.as_ref(), mock_env(), validate_msg);
+        let error = extract_error_msg(query_result);
+        assert!(
+            error.contains(format!("Permit \"{}\" was revoked by account", permit_name).as_str())
+        );
+    }
 
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
+    #[test]
+    fn test_reset_credentials() {
+        let user_address = "secret1kmgdagt5efcz2kku0ak9ezfgntg29g2vr88q0e";
+        let permit_name = "to_be_revoked";
+        let chain_id = "blabla";
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        // Note that 'signature'was generated with the specific values of the above:
+        // user_address, permit_name, chain_id, pub_key_value
+        let pub_key_value = "Ahlb7vwjo4aTY6dqfgpPmPYF7XhTAIReVwncQwlq8Sct";
+        let signature = "VS13F7iv1qxKABxrCAvZQPy2IruLQsIyfTewy/PIhNtybtq417lr3FxsWjV/i9YTqCUxg7weoZwHmYs0YgYX4w==";
 
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("allowed to minter accounts only"));
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
 
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        // permit works before the reset
+        let validate_msg = QueryMsg::ValidatePermit {
+            permit: gen_permit_obj(
+                permit_name,
+                chain_id,
+                pub_key_value,
+                signature,
+                TokenPermissions::Balance,
+            ),
         };
-        let info = mock_info("admin", &[]);
+        let query_result = query(deps.as_ref(), mock_env(), validate_msg.clone());
+        assert!(query_result.is_ok());
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("allowed to minter accounts only"));
-
-        // Removing another extra time to ensure nothing funky happens
-        let handle_msg = ExecuteMsg::RemoveMinters {
-            minters: vec!["admin".to_string()],
+        let handle_msg = ExecuteMsg::ResetCredentials {
+            new_key: "new key".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("admin", &[]);
-
+        let info = mock_info(user_address, &[]);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let permit_epoch = match from_binary(&handle_result.unwrap().data.unwrap()).unwrap() {
+            ExecuteAnswer::ResetCredentials {
+                status,
+                permit_epoch,
+            } => {
+                assert_eq!(status, ResponseStatus::Success);
+                permit_epoch
+            }
+            _ => panic!("NOPE"),
+        };
+        assert_ne!(permit_epoch, 0);
+        assert_eq!(
+            PermitEpochStore::load(&deps.storage, user_address),
+            permit_epoch
+        );
 
-        assert!(ensure_success(handle_result.unwrap()));
+        // the new viewing key is set
+        let result = ViewingKey::check(&deps.storage, user_address, "new key");
+        assert!(result.is_ok());
 
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("allowed to minter accounts only"));
-
-        let handle_msg = ExecuteMsg::Mint {
-            recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: None,
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        // permits from before the reset are rejected
+        let query_result = query(deps.as_ref(), mock_env(), validate_msg);
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("outdated permit epoch"));
+
+        // only a permit naming exactly the current epoch passes the epoch check
+        let check_name = |name: String| {
+            let permit = gen_permit_obj(
+                &name,
+                chain_id,
+                pub_key_value,
+                signature,
+                TokenPermissions::Balance,
+            );
+            check_permit_epoch(&deps.storage, user_address, &permit)
         };
-        let info = mock_info("admin", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let error = extract_error_msg(handle_result);
-        assert!(error.contains("allowed to minter accounts only"));
+        assert!(check_name(format!("{permit_name}#{permit_epoch}")).is_ok());
+        assert!(check_name(format!("{permit_name}#1")).is_err());
+        assert!(check_name(format!("{permit_name}#1{permit_epoch}")).is_err());
+        assert!(check_name(format!("{permit_name}#{permit_epoch}#1")).is_err());
     }
 
-    // Query tests
-
     #[test]
-    fn test_authenticated_queries() {
+    fn test_query_permit_epoch() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
-            address: "giannis".to_string(),
+            address: "bob".to_string(),
             amount: Uint128::new(5000),
         }]);
         assert!(
@@ -3867,776 +5274,7755 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        let no_vk_yet_query_msg = QueryMsg::Balance {
-            address: "giannis".to_string(),
-            key: "no_vk_yet".to_string(),
-        };
-        let query_result = query(deps.as_ref(), mock_env(), no_vk_yet_query_msg);
-        let error = extract_error_msg(query_result);
-        assert_eq!(
-            error,
-            "Wrong viewing key for this address or viewing key not set".to_string()
-        );
-
-        let create_vk_msg = ExecuteMsg::CreateViewingKey {
-            entropy: Some("34".to_string()),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
+        let query_msg = QueryMsg::PermitEpoch {
+            account: "bob".to_string(),
         };
-        let info = mock_info("giannis", &[]);
-        let handle_response = execute(deps.as_mut(), mock_env(), info, create_vk_msg).unwrap();
-        let vk = match from_binary(&handle_response.data.unwrap()).unwrap() {
-            ExecuteAnswer::CreateViewingKey { key } => key,
-            _ => panic!("Unexpected result from handle"),
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let permit_epoch = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::PermitEpoch { permit_epoch } => permit_epoch,
+            _ => panic!("Unexpected"),
         };
+        assert_eq!(permit_epoch, 0);
 
-        let query_balance_msg = QueryMsg::Balance {
-            address: "giannis".to_string(),
-            key: vk,
-        };
+        let mut reset_epoch = 0;
+        for _ in 0..2 {
+            let handle_msg = ExecuteMsg::ResetCredentials {
+                new_key: "new key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("bob", &[]);
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+            reset_epoch = match from_binary(&handle_result.unwrap().data.unwrap()).unwrap() {
+                ExecuteAnswer::ResetCredentials { permit_epoch, .. } => permit_epoch,
+                _ => panic!("Unexpected"),
+            };
+        }
 
-        let query_response = query(deps.as_ref(), mock_env(), query_balance_msg).unwrap();
-        let balance = match from_binary(&query_response).unwrap() {
-            QueryAnswer::Balance { amount } => amount,
-            _ => panic!("Unexpected result from query"),
+        let query_msg = QueryMsg::PermitEpoch {
+            account: "bob".to_string(),
         };
-        assert_eq!(balance, Uint128::new(5000));
-
-        let wrong_vk_query_msg = QueryMsg::Balance {
-            address: "giannis".to_string(),
-            key: "wrong_vk".to_string(),
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let permit_epoch = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::PermitEpoch { permit_epoch } => permit_epoch,
+            _ => panic!("Unexpected"),
         };
-        let query_result = query(deps.as_ref(), mock_env(), wrong_vk_query_msg);
-        let error = extract_error_msg(query_result);
-        assert_eq!(
-            error,
-            "Wrong viewing key for this address or viewing key not set".to_string()
-        );
+        assert_ne!(permit_epoch, 0);
+        assert_eq!(permit_epoch, reset_epoch);
     }
 
     #[test]
-    fn test_query_token_info() {
-        let init_name = "sec-sec".to_string();
-        let init_admin = Addr::unchecked("admin".to_string());
-        let init_symbol = "SECSEC".to_string();
-        let init_decimals = 8;
-        let init_config: InitConfig = from_binary(&Binary::from(
-            r#"{ "public_total_supply": true }"#.as_bytes(),
-        ))
-        .unwrap();
-        let init_supply = Uint128::new(5000);
-
-        let mut deps = mock_dependencies_with_balance(&[]);
-        let info = mock_info("instantiator", &[]);
-        let env = mock_env();
-        let init_msg = InstantiateMsg {
-            name: init_name.clone(),
-            admin: Some(init_admin.into_string()),
-            symbol: init_symbol.clone(),
-            decimals: init_decimals.clone(),
-            initial_balances: Some(vec![InitialBalance {
-                address: "giannis".to_string(),
-                amount: init_supply,
-            }]),
-            prng_seed: Binary::from("lolz fun yay".as_bytes()),
-            config: Some(init_config),
-            supported_denoms: None,
-        };
-        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+    fn test_execute_transfer_from_allowance_grace_period() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let query_msg = QueryMsg::TokenInfo {};
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: Some(100),
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
         assert!(
-            query_result.is_ok(),
-            "Init failed: {}",
-            query_result.err().unwrap()
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
         );
-        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
-        match query_answer {
-            QueryAnswer::TokenInfo {
-                name,
-                symbol,
-                decimals,
-                total_supply,
-            } => {
-                assert_eq!(name, init_name);
-                assert_eq!(symbol, init_symbol);
-                assert_eq!(decimals, init_decimals);
-                assert_eq!(total_supply, Some(Uint128::new(5000)));
-            }
-            _ => panic!("unexpected"),
-        }
-    }
 
-    #[test]
-    fn test_query_token_config() {
-        let init_name = "sec-sec".to_string();
-        let init_admin = Addr::unchecked("admin".to_string());
-        let init_symbol = "SECSEC".to_string();
-        let init_decimals = 8;
-        let init_config: InitConfig = from_binary(&Binary::from(
-            format!(
-                "{{\"public_total_supply\":{},
-            \"enable_deposit\":{},
-            \"enable_redeem\":{},
-            \"enable_mint\":{},
-            \"enable_burn\":{}}}",
-                true, false, false, true, false
-            )
-            .as_bytes(),
-        ))
-        .unwrap();
+        let transfer_from = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "charlie".to_string(),
+            amount: Uint128::new(500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
 
-        let init_supply = Uint128::new(5000);
+        // the allowance cannot be spent before the grace period passes
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(99);
+        let handle_result = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            transfer_from.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "allowance not yet active");
 
-        let mut deps = mock_dependencies_with_balance(&[]);
-        let info = mock_info("instantiator", &[]);
-        let env = mock_env();
-        let init_msg = InstantiateMsg {
-            name: init_name.clone(),
-            admin: Some(init_admin.into_string()),
-            symbol: init_symbol.clone(),
-            decimals: init_decimals.clone(),
-            initial_balances: Some(vec![InitialBalance {
-                address: "giannis".to_string(),
-                amount: init_supply,
-            }]),
-            prng_seed: Binary::from("lolz fun yay".as_bytes()),
-            config: Some(init_config),
-            supported_denoms: None,
-        };
-        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        env.block.time = env.block.time.plus_seconds(1);
+        let handle_result = execute(deps.as_mut(), env, mock_info("alice", &[]), transfer_from);
         assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
         );
+        let allowance = AllowancesStore::load(
+            &deps.storage,
+            &Addr::unchecked("bob"),
+            &Addr::unchecked("alice"),
+        );
+        assert_eq!(allowance.amount, 1500);
 
-        let query_msg = QueryMsg::TokenConfig {};
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        // without a grace period the allowance is usable immediately
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "dave".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: Some(0),
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
         assert!(
-            query_result.is_ok(),
-            "Init failed: {}",
-            query_result.err().unwrap()
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_msg = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "charlie".to_string(),
+            amount: Uint128::new(500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("dave", &[]),
+            handle_msg,
+        );
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
         );
-        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
-        match query_answer {
-            QueryAnswer::TokenConfig {
-                public_total_supply,
-                deposit_enabled,
-                redeem_enabled,
-                mint_enabled,
-                burn_enabled,
-                supported_denoms,
-            } => {
-                assert_eq!(public_total_supply, true);
-                assert_eq!(deposit_enabled, false);
-                assert_eq!(redeem_enabled, false);
-                assert_eq!(mint_enabled, true);
-                assert_eq!(burn_enabled, false);
-                assert_eq!(supported_denoms.len(), 0);
-            }
-            _ => panic!("unexpected"),
-        }
     }
 
     #[test]
-    fn test_query_exchange_rate() {
-        // test more dec than SCRT
-        let init_name = "sec-sec".to_string();
-        let init_admin = Addr::unchecked("admin".to_string());
-        let init_symbol = "SECSEC".to_string();
-        let init_decimals = 8;
+    fn test_execute_transfer_from_notify_allowance_use() {
+        for notify_allowance_use in [false, true] {
+            let (init_result, mut deps) = init_helper_with_raw_config(
+                vec![InitialBalance {
+                    address: "bob".to_string(),
+                    amount: Uint128::new(5000),
+                }],
+                &format!("{{\"notify_allowance_use\":{notify_allowance_use}}}"),
+            );
+            assert!(
+                init_result.is_ok(),
+                "Init failed: {}",
+                init_result.err().unwrap()
+            );
 
-        let init_supply = Uint128::new(5000);
+            let handle_msg = ExecuteMsg::IncreaseAllowance {
+                spender: "alice".to_string(),
+                amount: Uint128::new(2000),
+                padding: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                expiration: None,
+                notify_self: None,
+                grace_seconds: None,
+            };
+            let info = mock_info("bob", &[]);
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
 
-        let mut deps = mock_dependencies_with_balance(&[]);
-        let info = mock_info("instantiator", &[]);
-        let env = mock_env();
-        let init_config: InitConfig = from_binary(&Binary::from(
-            format!(
-                "{{\"public_total_supply\":{},
-                \"enable_deposit\":{},
-                \"enable_redeem\":{},
-                \"enable_mint\":{},
-                \"enable_burn\":{}}}",
-                true, true, false, false, false
-            )
-            .as_bytes(),
-        ))
-        .unwrap();
-        let init_msg = InstantiateMsg {
-            name: init_name.clone(),
-            admin: Some(init_admin.into_string()),
-            symbol: init_symbol.clone(),
-            decimals: init_decimals.clone(),
-            initial_balances: Some(vec![InitialBalance {
-                address: "giannis".to_string(),
-                amount: init_supply,
-            }]),
-            prng_seed: Binary::from("lolz fun yay".as_bytes()),
-            config: Some(init_config),
-            supported_denoms: Some(vec!["uscrt".to_string()]),
-        };
-        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
-        );
+            let handle_msg = ExecuteMsg::TransferFrom {
+                owner: "bob".to_string(),
+                recipient: "charlie".to_string(),
+                amount: Uint128::new(500),
+                memo: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("alice", &[]);
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+            let result = handle_result.unwrap();
 
-        let query_msg = QueryMsg::ExchangeRate {};
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        assert!(
-            query_result.is_ok(),
-            "Init failed: {}",
-            query_result.err().unwrap()
-        );
-        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
-        match query_answer {
-            QueryAnswer::ExchangeRate { rate, denom } => {
-                assert_eq!(rate, Uint128::new(100));
-                assert_eq!(denom, "SCRT");
-            }
-            _ => panic!("unexpected"),
+            // recvd + recvdcount + spent, plus the allowance notification for the owner when enabled
+            let expected_attributes = if notify_allowance_use { 4 } else { 3 };
+            assert_eq!(result.attributes.len(), expected_attributes);
+            assert!(ensure_success(result));
+
+            let allowance = AllowancesStore::load(
+                &deps.storage,
+                &Addr::unchecked("bob".to_string()),
+                &Addr::unchecked("alice".to_string()),
+            );
+            assert_eq!(allowance.amount, 1500);
+
+            // the owner's notification names the spender along with the remaining allowance
+            let notification = execute_transfer_send::allowance_use_notification(
+                &deps.storage,
+                &Addr::unchecked("bob".to_string()),
+                &Addr::unchecked("alice".to_string()),
+            );
+            assert_eq!(notification.notification_for, Addr::unchecked("bob"));
+            let mut payload = vec![0u8; 128];
+            let mut encoder = minicbor::Encoder::new(payload.as_mut_slice());
+            encoder.array(AllowanceNotification::ELEMENTS).unwrap();
+            notification
+                .data
+                .encode_cbor(&deps.api, &mut encoder)
+                .unwrap();
+            let mut decoder = minicbor::Decoder::new(&payload);
+            assert_eq!(decoder.array().unwrap(), Some(3));
+            decoder.skip().unwrap();
+            assert_eq!(
+                decoder.bytes().unwrap(),
+                deps.api.addr_canonicalize("alice").unwrap().as_slice()
+            );
+            assert_eq!(notification.data.amount, 1500);
         }
+    }
 
-        // test same number of decimals as SCRT
-        let init_name = "sec-sec".to_string();
-        let init_admin = Addr::unchecked("admin".to_string());
-        let init_symbol = "SECSEC".to_string();
-        let init_decimals = 6;
+    #[test]
+    fn test_handle_allowance_notify_self() {
+        for notify_self in [false, true] {
+            let (init_result, mut deps) = init_helper(vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }]);
+            assert!(
+                init_result.is_ok(),
+                "Init failed: {}",
+                init_result.err().unwrap()
+            );
 
-        let init_supply = Uint128::new(5000);
+            let env = mock_env();
+            let tx_hash = env.transaction.clone().unwrap().hash;
+            let secret = INTERNAL_SECRET_SENSITIVE.load(&deps.storage).unwrap();
+            let notification_key = |address: &str| {
+                let address_raw = deps.api.addr_canonicalize(address).unwrap();
+                let seed = get_seed(&address_raw, &secret).unwrap();
+                let id = notification_id(
+                    &seed,
+                    &AllowanceNotification::CHANNEL_ID.to_string(),
+                    &tx_hash,
+                )
+                .unwrap();
+                format!("snip52:{}", id.to_base64())
+            };
+            let expected_keys = if notify_self {
+                vec![notification_key("bob"), notification_key("alice")]
+            } else {
+                vec![notification_key("alice")]
+            };
 
-        let mut deps = mock_dependencies_with_balance(&[]);
-        let info = mock_info("instantiator", &[]);
-        let env = mock_env();
-        let init_config: InitConfig = from_binary(&Binary::from(
-            format!(
-                "{{\"public_total_supply\":{},
-            \"enable_deposit\":{},
-            \"enable_redeem\":{},
-            \"enable_mint\":{},
-            \"enable_burn\":{}}}",
-                true, true, false, false, false
+            let handle_msg = ExecuteMsg::IncreaseAllowance {
+                spender: "alice".to_string(),
+                amount: Uint128::new(2000),
+                expiration: None,
+                grace_seconds: None,
+                notify_self: Some(notify_self),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let result = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info("bob", &[]),
+                handle_msg,
             )
-            .as_bytes(),
-        ))
-        .unwrap();
-        let init_msg = InstantiateMsg {
-            name: init_name.clone(),
-            admin: Some(init_admin.into_string()),
-            symbol: init_symbol.clone(),
-            decimals: init_decimals.clone(),
-            initial_balances: Some(vec![InitialBalance {
-                address: "giannis".to_string(),
-                amount: init_supply,
-            }]),
-            prng_seed: Binary::from("lolz fun yay".as_bytes()),
-            config: Some(init_config),
-            supported_denoms: Some(vec!["uscrt".to_string()]),
-        };
-        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+            .unwrap();
+            let keys: Vec<String> = result.attributes.iter().map(|a| a.key.clone()).collect();
+            assert_eq!(keys, expected_keys);
+
+            let handle_msg = ExecuteMsg::DecreaseAllowance {
+                spender: "alice".to_string(),
+                amount: Uint128::new(500),
+                expiration: None,
+                notify_self: Some(notify_self),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let result = execute(deps.as_mut(), env, mock_info("bob", &[]), handle_msg).unwrap();
+            let keys: Vec<String> = result.attributes.iter().map(|a| a.key.clone()).collect();
+            assert_eq!(keys, expected_keys);
+        }
+    }
+
+    #[test]
+    fn test_execute_transfer_from() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let query_msg = QueryMsg::ExchangeRate {};
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        assert!(
-            query_result.is_ok(),
-            "Init failed: {}",
-            query_result.err().unwrap()
-        );
-        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
-        match query_answer {
-            QueryAnswer::ExchangeRate { rate, denom } => {
-                assert_eq!(rate, Uint128::new(1));
-                assert_eq!(denom, "SCRT");
-            }
-            _ => panic!("unexpected"),
-        }
+        // Transfer before allowance
+        let handle_msg = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            amount: Uint128::new(2500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
 
-        // test less decimal places than SCRT
-        let init_name = "sec-sec".to_string();
-        let init_admin = Addr::unchecked("admin".to_string());
-        let init_symbol = "SECSEC".to_string();
-        let init_decimals = 3;
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        let init_supply = Uint128::new(5000);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
 
-        let mut deps = mock_dependencies_with_balance(&[]);
-        let info = mock_info("instantiator", &[]);
-        let env = mock_env();
-        let init_config: InitConfig = from_binary(&Binary::from(
-            format!(
-                "{{\"public_total_supply\":{},
-            \"enable_deposit\":{},
-            \"enable_redeem\":{},
-            \"enable_mint\":{},
-            \"enable_burn\":{}}}",
-                true, true, false, false, false
-            )
-            .as_bytes(),
-        ))
-        .unwrap();
-        let init_msg = InstantiateMsg {
-            name: init_name.clone(),
-            admin: Some(init_admin.into_string()),
-            symbol: init_symbol.clone(),
-            decimals: init_decimals.clone(),
-            initial_balances: Some(vec![InitialBalance {
-                address: "giannis".to_string(),
-                amount: init_supply,
-            }]),
-            prng_seed: Binary::from("lolz fun yay".as_bytes()),
-            config: Some(init_config),
-            supported_denoms: Some(vec!["uscrt".to_string()]),
+        // Transfer more than allowance
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: Some(1_571_797_420),
+            notify_self: None,
+            grace_seconds: None,
         };
-        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
-        assert!(
-            init_result.is_ok(),
-            "Init failed: {}",
-            init_result.err().unwrap()
-        );
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        let query_msg = QueryMsg::ExchangeRate {};
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
         assert!(
-            query_result.is_ok(),
-            "Init failed: {}",
-            query_result.err().unwrap()
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
         );
-        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
-        match query_answer {
-            QueryAnswer::ExchangeRate { rate, denom } => {
-                assert_eq!(rate, Uint128::new(1000));
-                assert_eq!(denom, "SECSEC");
-            }
-            _ => panic!("unexpected"),
-        }
-
-        // test depost/redeem not enabled
-        let init_name = "sec-sec".to_string();
-        let init_admin = Addr::unchecked("admin".to_string());
-        let init_symbol = "SECSEC".to_string();
-        let init_decimals = 3;
+        let handle_msg = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            amount: Uint128::new(2500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+
+        // Transfer after allowance expired
+        let handle_msg = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            amount: Uint128::new(2000),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+
+        let info = MessageInfo {
+            sender: Addr::unchecked("bob".to_string()),
+            funds: vec![],
+        };
+
+        let handle_result = execute(
+            deps.as_mut(),
+            Env {
+                block: BlockInfo {
+                    height: 12_345,
+                    time: Timestamp::from_seconds(1_571_797_420),
+                    chain_id: "cosmos-testnet-14002".to_string(),
+                    random: Some(Binary::from(&[
+                        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+                        21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31,
+                    ])),
+                },
+                transaction: Some(TransactionInfo {
+                    index: 3,
+                    hash: "1010".to_string(),
+                }),
+                contract: ContractInfo {
+                    address: Addr::unchecked(MOCK_CONTRACT_ADDR.to_string()),
+                    code_hash: "".to_string(),
+                },
+            },
+            info,
+            handle_msg,
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+
+        // Sanity check
+        let handle_msg = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            amount: Uint128::new(2000),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let bob_canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("bob".to_string()).as_str())
+            .unwrap();
+        let alice_canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("alice".to_string()).as_str())
+            .unwrap();
+
+        let bob_balance = stored_balance(&deps.storage, &bob_canonical).unwrap();
+        let alice_balance = stored_balance(&deps.storage, &alice_canonical).unwrap();
+        assert_eq!(bob_balance, 5000 - 2000);
+        assert_ne!(alice_balance, 2000);
+        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(total_supply, 5000);
+
+        // Second send more than allowance
+        let handle_msg = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            amount: Uint128::new(1),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+    }
+
+    #[test]
+    fn test_handle_send_from() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Send before allowance
+        let handle_msg = ExecuteMsg::SendFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(2500),
+            memo: None,
+            msg: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+
+        // Send more than allowance
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_msg = ExecuteMsg::SendFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(2500),
+            memo: None,
+            msg: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+
+        // Sanity check
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "lolz".to_string(),
+            label: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("contract", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let send_msg = Binary::from(r#"{ "some_msg": { "some_key": "some_val" } }"#.as_bytes());
+        let snip20_msg = Snip20ReceiveMsg::new(
+            Addr::unchecked("alice".to_string()),
+            Addr::unchecked("bob".to_string()),
+            Uint128::new(2000),
+            Some("my memo".to_string()),
+            Some(send_msg.clone()),
+        );
+        let handle_msg = ExecuteMsg::SendFrom {
+            owner: "bob".to_string(),
+            recipient: "contract".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(2000),
+            memo: Some("my memo".to_string()),
+            msg: Some(send_msg),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert!(handle_result.unwrap().messages.contains(
+            &into_cosmos_submsg(
+                snip20_msg,
+                "lolz".to_string(),
+                Addr::unchecked("contract".to_string()),
+                0
+            )
+            .unwrap()
+        ));
+
+        let bob_canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("bob".to_string()).as_str())
+            .unwrap();
+        let contract_canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("contract".to_string()).as_str())
+            .unwrap();
+
+        let bob_balance = stored_balance(&deps.storage, &bob_canonical).unwrap();
+        let contract_balance = stored_balance(&deps.storage, &contract_canonical).unwrap();
+        assert_eq!(bob_balance, 5000 - 2000);
+        assert_ne!(contract_balance, 2000);
+        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(total_supply, 5000);
+
+        // Second send more than allowance
+        let handle_msg = ExecuteMsg::SendFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(1),
+            memo: None,
+            msg: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+    }
+
+    #[test]
+    fn test_handle_burn_from() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(10000),
+            }],
+            false,
+            false,
+            false,
+            true,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(10000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // test when burn disabled
+        let handle_msg = ExecuteMsg::BurnFrom {
+            owner: "bob".to_string(),
+            amount: Uint128::new(2500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Burn functionality is not enabled for this token."));
+
+        // Burn before allowance
+        let handle_msg = ExecuteMsg::BurnFrom {
+            owner: "bob".to_string(),
+            amount: Uint128::new(2500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+
+        // Burn more than allowance
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_msg = ExecuteMsg::BurnFrom {
+            owner: "bob".to_string(),
+            amount: Uint128::new(2500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+
+        // Sanity check
+        let handle_msg = ExecuteMsg::BurnFrom {
+            owner: "bob".to_string(),
+            amount: Uint128::new(2000),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let bob_canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("bob".to_string()).as_str())
+            .unwrap();
+
+        let bob_balance = stored_balance(&deps.storage, &bob_canonical).unwrap();
+        assert_eq!(bob_balance, 10000 - 2000);
+        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(total_supply, 10000 - 2000);
+
+        // Second burn more than allowance
+        let handle_msg = ExecuteMsg::BurnFrom {
+            owner: "bob".to_string(),
+            amount: Uint128::new(1),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+    }
+
+    #[test]
+    fn test_handle_burn_from_self() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(10000),
+            }],
+            false,
+            false,
+            false,
+            true,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // owner burns their own tokens through the from-path without any allowance
+        let handle_msg = ExecuteMsg::BurnFrom {
+            owner: "bob".to_string(),
+            amount: Uint128::new(2500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let bob_canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("bob".to_string()).as_str())
+            .unwrap();
+        assert_eq!(stored_balance(&deps.storage, &bob_canonical).unwrap(), 7500);
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 7500);
+
+        // but cannot burn more than their balance
+        let handle_msg = ExecuteMsg::BurnFrom {
+            owner: "bob".to_string(),
+            amount: Uint128::new(10000),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient funds"));
+    }
+
+    #[test]
+    fn test_handle_batch_burn_from() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![
+                InitialBalance {
+                    address: "bob".to_string(),
+                    amount: Uint128::new(10000),
+                },
+                InitialBalance {
+                    address: "jerry".to_string(),
+                    amount: Uint128::new(10000),
+                },
+                InitialBalance {
+                    address: "mike".to_string(),
+                    amount: Uint128::new(10000),
+                },
+            ],
+            false,
+            false,
+            false,
+            true,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(10000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // test when burn disabled
+        let actions: Vec<_> = ["bob", "jerry", "mike"]
+            .iter()
+            .map(|name| batch::BurnFromAction {
+                owner: name.to_string(),
+                amount: Uint128::new(2500),
+                memo: None,
+            })
+            .collect();
+        let handle_msg = ExecuteMsg::BatchBurnFrom {
+            actions,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+        let handle_result = execute(
+            deps_for_failure.as_mut(),
+            mock_env(),
+            info,
+            handle_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Burn functionality is not enabled for this token."));
+
+        // Burn before allowance
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+
+        // Burn more than allowance
+        let allowance_size = 2000;
+        for name in &["bob", "jerry", "mike"] {
+            let handle_msg = ExecuteMsg::IncreaseAllowance {
+                spender: "alice".to_string(),
+                amount: Uint128::new(allowance_size),
+                padding: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                expiration: None,
+                notify_self: None,
+                grace_seconds: None,
+            };
+            let info = mock_info(*name, &[]);
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+            let handle_msg = ExecuteMsg::BurnFrom {
+                owner: "name".to_string(),
+                amount: Uint128::new(2500),
+                memo: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("alice", &[]);
+
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+            let error = extract_error_msg(handle_result);
+            assert!(error.contains("insufficient allowance"));
+        }
+
+        // Burn some of the allowance
+        let actions: Vec<_> = [("bob", 200_u128), ("jerry", 300), ("mike", 400)]
+            .iter()
+            .map(|(name, amount)| batch::BurnFromAction {
+                owner: name.to_string(),
+                amount: Uint128::new(*amount),
+                memo: None,
+            })
+            .collect();
+
+        let handle_msg = ExecuteMsg::BatchBurnFrom {
+            actions,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        for (name, amount) in &[("bob", 200_u128), ("jerry", 300), ("mike", 400)] {
+            let name_canon = deps
+                .api
+                .addr_canonicalize(Addr::unchecked(name.to_string()).as_str())
+                .unwrap();
+            let balance = stored_balance(&deps.storage, &name_canon).unwrap();
+            assert_eq!(balance, 10000 - amount);
+        }
+        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(total_supply, 10000 * 3 - (200 + 300 + 400));
+
+        // Burn the rest of the allowance
+        let actions: Vec<_> = [("bob", 200_u128), ("jerry", 300), ("mike", 400)]
+            .iter()
+            .map(|(name, amount)| batch::BurnFromAction {
+                owner: name.to_string(),
+                amount: Uint128::new(allowance_size - *amount),
+                memo: None,
+            })
+            .collect();
+
+        let handle_msg = ExecuteMsg::BatchBurnFrom {
+            actions,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        for name in &["bob", "jerry", "mike"] {
+            let name_canon = deps
+                .api
+                .addr_canonicalize(Addr::unchecked(name.to_string()).as_str())
+                .unwrap();
+            let balance = stored_balance(&deps.storage, &name_canon).unwrap();
+            assert_eq!(balance, 10000 - allowance_size);
+        }
+        let total_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(total_supply, 3 * (10000 - allowance_size));
+
+        // Second burn more than allowance
+        let actions: Vec<_> = ["bob", "jerry", "mike"]
+            .iter()
+            .map(|name| batch::BurnFromAction {
+                owner: name.to_string(),
+                amount: Uint128::new(1),
+                memo: None,
+            })
+            .collect();
+        let handle_msg = ExecuteMsg::BatchBurnFrom {
+            actions,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient allowance"));
+    }
+
+    #[test]
+    fn test_handle_decrease_allowance() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::DecreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let bob_canonical = Addr::unchecked("bob".to_string());
+        let alice_canonical = Addr::unchecked("alice".to_string());
+
+        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
+        assert_eq!(
+            allowance,
+            crate::state::Allowance {
+                amount: 0,
+                expiration: None,
+                active_at: None
+            }
+        );
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::DecreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(50),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
+        assert_eq!(
+            allowance,
+            crate::state::Allowance {
+                amount: 1950,
+                expiration: None,
+                active_at: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_clear_allowance_expiration() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let expiration = mock_env().block.time.seconds() + 100;
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: Some(expiration),
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::ClearAllowanceExpiration {
+            spender: "alice".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        let result = handle_result.unwrap();
+        assert_eq!(result.attributes.len(), 1);
+        match from_binary(&result.data.unwrap()).unwrap() {
+            ExecuteAnswer::ClearAllowanceExpiration { allowance, .. } => {
+                assert_eq!(allowance, Uint128::new(2000))
+            }
+            _ => panic!("unexpected"),
+        }
+
+        let bob = Addr::unchecked("bob".to_string());
+        let alice = Addr::unchecked("alice".to_string());
+        let allowance = AllowancesStore::load(&deps.storage, &bob, &alice);
+        assert_eq!(
+            allowance,
+            crate::state::Allowance {
+                amount: 2000,
+                expiration: None,
+                active_at: None
+            }
+        );
+
+        // the allowance can still be used after the original expiration
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(1000);
+        assert!(!allowance.is_expired_at(&env.block));
+        let handle_msg = ExecuteMsg::TransferFrom {
+            owner: "bob".to_string(),
+            recipient: "alice".to_string(),
+            amount: Uint128::new(500),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), env, mock_info("alice", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // clearing an allowance that has already expired does not revive it
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "charlie".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: Some(expiration),
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(handle_result.is_ok());
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(1000);
+        let handle_msg = ExecuteMsg::ClearAllowanceExpiration {
+            spender: "charlie".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), env, mock_info("bob", &[]), handle_msg);
+        assert!(handle_result.is_ok());
+
+        let charlie = Addr::unchecked("charlie".to_string());
+        let allowance = AllowancesStore::load(&deps.storage, &bob, &charlie);
+        assert_eq!(
+            allowance,
+            crate::state::Allowance {
+                amount: 0,
+                expiration: None,
+                active_at: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_batch_set_allowance() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(1000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let action = |spender: &str, amount: u128, operation: batch::AllowanceOperation| {
+            batch::AllowanceAction {
+                spender: spender.to_string(),
+                amount: Uint128::new(amount),
+                expiration: None,
+                operation,
+            }
+        };
+        let handle_msg = ExecuteMsg::BatchSetAllowance {
+            actions: vec![
+                action("alice", 500, batch::AllowanceOperation::Increase),
+                action("alice", 200, batch::AllowanceOperation::Decrease),
+                batch::AllowanceAction {
+                    expiration: Some(1_571_797_500),
+                    ..action("charlie", 300, batch::AllowanceOperation::Set)
+                },
+                action("dave", 100, batch::AllowanceOperation::Increase),
+                action("dave", 30, batch::AllowanceOperation::Decrease),
+                action("erin", 10, batch::AllowanceOperation::Set),
+                action("erin", 20, batch::AllowanceOperation::Set),
+            ],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let bob = Addr::unchecked("bob");
+        let allowance =
+            |spender: &str| AllowancesStore::load(&deps.storage, &bob, &Addr::unchecked(spender));
+        assert_eq!(
+            allowance("alice"),
+            crate::state::Allowance {
+                amount: 1300,
+                expiration: None,
+                active_at: None
+            }
+        );
+        assert_eq!(
+            allowance("charlie"),
+            crate::state::Allowance {
+                amount: 300,
+                expiration: Some(1_571_797_500)
+            }
+        );
+        assert_eq!(allowance("dave").amount, 70);
+        assert_eq!(allowance("erin").amount, 20);
+
+        // an invalid spender fails the batch
+        let handle_msg = ExecuteMsg::BatchSetAllowance {
+            actions: vec![
+                action("alice", 1, batch::AllowanceOperation::Set),
+                action("", 1, batch::AllowanceOperation::Set),
+            ],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(handle_result.is_err());
+    }
+
+    #[test]
+    fn test_handle_increase_allowance() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let bob_canonical = Addr::unchecked("bob".to_string());
+        let alice_canonical = Addr::unchecked("alice".to_string());
+
+        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
+        assert_eq!(
+            allowance,
+            crate::state::Allowance {
+                amount: 2000,
+                expiration: None,
+                active_at: None
+            }
+        );
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let allowance = AllowancesStore::load(&deps.storage, &bob_canonical, &alice_canonical);
+        assert_eq!(
+            allowance,
+            crate::state::Allowance {
+                amount: 4000,
+                expiration: None,
+                active_at: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_change_admin() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::ChangeAdmin {
+            address: "bob".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // the admin only changes once the nominee accepts
+        let admin = CONFIG.load(&deps.storage).unwrap().admin;
+        assert_eq!(admin, Addr::unchecked("admin".to_string()));
+
+        let query_result = query(deps.as_ref(), mock_env(), QueryMsg::PendingAdmin {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::PendingAdmin { address } => {
+                assert_eq!(address, Some(Addr::unchecked("bob")))
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+
+        let accept_msg = ExecuteMsg::AcceptAdmin {
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            accept_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Only the pending admin can accept the admin role"));
+
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), accept_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let admin = CONFIG.load(&deps.storage).unwrap().admin;
+        assert_eq!(admin, Addr::unchecked("bob".to_string()));
+
+        let query_result = query(deps.as_ref(), mock_env(), QueryMsg::PendingAdmin {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::PendingAdmin { address } => assert_eq!(address, None),
+            other => panic!("Unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_handle_rotate_secrets() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let relaxed_secret = INTERNAL_SECRET_RELAXED.load(&deps.storage).unwrap();
+        let sensitive_secret = INTERNAL_SECRET_SENSITIVE.load(&deps.storage).unwrap();
+
+        let rotate_msg = ExecuteMsg::RotateSecrets {
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            rotate_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            rotate_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        assert_ne!(
+            INTERNAL_SECRET_RELAXED.load(&deps.storage).unwrap(),
+            relaxed_secret
+        );
+        assert_eq!(
+            INTERNAL_SECRET_SENSITIVE.load(&deps.storage).unwrap(),
+            sensitive_secret
+        );
+
+        // the existing viewing key still works and the balance can still be located
+        let query_msg = QueryMsg::Balance {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let balance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(balance, Uint128::new(5000));
+    }
+
+    #[test]
+    fn test_handle_cancel_admin_transfer() {
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let cancel_msg = ExecuteMsg::CancelAdminTransfer {
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            cancel_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("There is no pending admin transfer"));
+
+        let handle_msg = ExecuteMsg::ChangeAdmin {
+            address: "bob".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // only the admin can cancel
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            cancel_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            cancel_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // the withdrawn nominee can no longer accept
+        let accept_msg = ExecuteMsg::AcceptAdmin {
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), accept_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Only the pending admin can accept the admin role"));
+
+        let admin = CONFIG.load(&deps.storage).unwrap().admin;
+        assert_eq!(admin, Addr::unchecked("admin".to_string()));
+    }
+
+    #[test]
+    fn test_handle_set_contract_status() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "admin".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetContractStatus {
+            level: ContractStatusLevel::StopAll,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let contract_status = CONTRACT_STATUS.load(&deps.storage).unwrap();
+        assert!(matches!(
+            contract_status,
+            ContractStatusLevel::StopAll { .. }
+        ));
+    }
+
+    #[test]
+    fn test_handle_redeem() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "butler".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            true,
+            false,
+            false,
+            1000,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let (init_result_no_reserve, mut deps_no_reserve) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "butler".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            true,
+            false,
+            false,
+            0,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result_no_reserve.is_ok(),
+            "Init failed: {}",
+            init_result_no_reserve.err().unwrap()
+        );
+
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "butler".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // test when redeem disabled
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(1000),
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Redeem functionality is not enabled for this token."));
+
+        // try to redeem when contract has 0 balance
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(1000),
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+
+        let handle_result = execute(deps_no_reserve.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "You are trying to redeem for more uscrt than the contract has in its reserve (requested: 1000, reserve: 0)"
+        );
+
+        // test without denom
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(1000),
+            denom: None,
+            memo: None,
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+        };
+        let info = mock_info("butler", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // test with denom specified
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(1000),
+            denom: Option::from("uscrt".to_string()),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("butler".to_string()).as_str())
+            .unwrap();
+        assert_eq!(stored_balance(&deps.storage, &canonical).unwrap(), 3000)
+    }
+
+    #[test]
+    fn test_handle_redeem_rate_limit() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "butler".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            true,
+            false,
+            false,
+            5000,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetRedeemRateLimit {
+            limit: Some(RedeemRateLimit {
+                amount: Uint128::new(1000),
+                window_seconds: 3600,
+            }),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let redeem = |deps: DepsMut, env: Env, amount: u128| {
+            let handle_msg = ExecuteMsg::Redeem {
+                amount: Uint128::new(amount),
+                denom: None,
+                memo: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            execute(deps, env, mock_info("butler", &[]), handle_msg)
+        };
+
+        let contract_status = |deps: Deps, env: Env| {
+            let query_result = query(deps, env, QueryMsg::ContractStatus {});
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::ContractStatus {
+                    status,
+                    redeem_rate_limit,
+                    ..
+                } => (status, redeem_rate_limit.unwrap()),
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+        let (_, rate_limit) = contract_status(deps.as_ref(), mock_env());
+        assert_eq!(rate_limit.window_start, None);
+        assert_eq!(rate_limit.remaining, Uint128::new(1000));
+
+        // a caller without funds or allowance can't trip the circuit breaker
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::MAX,
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("mallory", &[]),
+            handle_msg,
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient funds to redeem"));
+
+        let handle_msg = ExecuteMsg::RedeemFrom {
+            owner: "butler".to_string(),
+            amount: Uint128::new(5000),
+            denom: None,
+            recipient: "mallory".to_string(),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("mallory", &[]),
+            handle_msg,
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("insufficient redeem allowance"));
+
+        let (status, rate_limit) = contract_status(deps.as_ref(), mock_env());
+        assert_eq!(status, ContractStatusLevel::NormalRun);
+        assert_eq!(rate_limit.window_start, None);
+        assert_eq!(rate_limit.remaining, Uint128::new(1000));
+
+        // redeeming up to the limit is fine
+        for amount in [600, 400] {
+            let handle_result = redeem(deps.as_mut(), mock_env(), amount);
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 4000);
+
+        let (status, rate_limit) = contract_status(deps.as_ref(), mock_env());
+        assert_eq!(status, ContractStatusLevel::NormalRun);
+        assert_eq!(
+            rate_limit,
+            RedeemRateLimitStatus {
+                limit: RedeemRateLimit {
+                    amount: Uint128::new(1000),
+                    window_seconds: 3600,
+                },
+                window_start: Some(mock_env().block.time.seconds()),
+                redeemed: Uint128::new(1000),
+                remaining: Uint128::zero(),
+            }
+        );
+
+        // going over it rejects the redeem and pauses redeems
+        let handle_result = redeem(deps.as_mut(), mock_env(), 1);
+        let result = handle_result.unwrap();
+        assert!(result.messages.is_empty());
+        assert!(!ensure_success(result));
+        assert_eq!(
+            CONTRACT_STATUS.load(&deps.storage).unwrap(),
+            ContractStatusLevel::RedeemPaused
+        );
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 4000);
+
+        // even once the window has passed
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let (status, rate_limit) = contract_status(deps.as_ref(), env.clone());
+        assert_eq!(status, ContractStatusLevel::RedeemPaused);
+        assert_eq!(rate_limit.window_start, None);
+        assert_eq!(rate_limit.redeemed, Uint128::zero());
+        let handle_result = redeem(deps.as_mut(), env, 1);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Redeems are paused"));
+
+        // everything else keeps running
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("butler", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // the admin resumes redeems, which also starts a fresh window
+        let handle_msg = ExecuteMsg::SetContractStatus {
+            level: ContractStatusLevel::NormalRun,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_result = redeem(deps.as_mut(), mock_env(), 1000);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // and the limit applies again per window
+        let handle_result = redeem(deps.as_mut(), mock_env(), 1);
+        assert!(!ensure_success(handle_result.unwrap()));
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 3000);
+    }
+
+    #[test]
+    fn test_handle_redeem_failure_ordering() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "butler".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            true,
+            false,
+            false,
+            1000,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // redeeming more than the signer's balance fails first
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(6000),
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "insufficient funds to redeem: balance=5000, required=6000"
+        );
+
+        // redeeming more than the total supply fails before the reserve is checked, even though
+        // the reserve of 1000 could not cover it either
+        TOTAL_SUPPLY.save(&mut deps.storage, &1500).unwrap();
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(2000),
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "You are trying to redeem more tokens than what is available in the total supply"
+        );
+
+        // redeeming more than the reserve fails last
+        TOTAL_SUPPLY.save(&mut deps.storage, &5000).unwrap();
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(2000),
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "You are trying to redeem for more uscrt than the contract has in its reserve (requested: 2000, reserve: 1000)"
+        );
+    }
+
+    #[test]
+    fn test_handle_set_preferred_redeem_denom() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "butler".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            true,
+            false,
+            false,
+            1000,
+            vec!["uatom".to_string(), "uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // without a preference the denom must be specified
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(1000),
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("multiple coins are supported"));
+
+        // unsupported denoms cannot be preferred
+        let handle_msg = ExecuteMsg::SetPreferredRedeemDenom {
+            denom: "uosmo".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("unsupported coin uosmo"));
+
+        let handle_msg = ExecuteMsg::SetPreferredRedeemDenom {
+            denom: "uscrt".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // redeem now falls back to the preferred denom
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(1000),
+            denom: None,
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let result = handle_result.unwrap();
+        assert_eq!(
+            result.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "butler".to_string(),
+                amount: vec![Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint128::new(1000),
+                }],
+            })
+        );
+        assert!(ensure_success(result));
+    }
+
+    #[test]
+    fn test_handle_redeem_from() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "butler".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            true,
+            false,
+            false,
+            1000,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // spender without a redeem allowance
+        let handle_msg = ExecuteMsg::RedeemFrom {
+            owner: "butler".to_string(),
+            amount: Uint128::new(1000),
+            denom: None,
+            recipient: "exchange".to_string(),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("hot_wallet", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "insufficient redeem allowance: allowance=0, required=1000"
+        );
+
+        let handle_msg = ExecuteMsg::SetRedeemAllowance {
+            spender: "hot_wallet".to_string(),
+            amount: Uint128::new(1500),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("butler", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // redeem within the allowance, paying out to the recipient
+        let handle_msg = ExecuteMsg::RedeemFrom {
+            owner: "butler".to_string(),
+            amount: Uint128::new(1000),
+            denom: None,
+            recipient: "exchange".to_string(),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("hot_wallet", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result.clone()));
+        assert_eq!(
+            result.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "exchange".to_string(),
+                amount: vec![Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint128::new(1000),
+                }],
+            })
+        );
+
+        let butler = Addr::unchecked("butler".to_string());
+        let hot_wallet = Addr::unchecked("hot_wallet".to_string());
+        assert_eq!(
+            RedeemAllowancesStore::load(&deps.storage, &butler, &hot_wallet),
+            500
+        );
+        let canonical = deps.api.addr_canonicalize(butler.as_str()).unwrap();
+        assert_eq!(stored_balance(&deps.storage, &canonical).unwrap(), 4000);
+
+        // more than the remaining allowance
+        let handle_msg = ExecuteMsg::RedeemFrom {
+            owner: "butler".to_string(),
+            amount: Uint128::new(1000),
+            denom: None,
+            recipient: "exchange".to_string(),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("hot_wallet", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "insufficient redeem allowance: allowance=500, required=1000"
+        );
+
+        // exhaust the allowance
+        let handle_msg = ExecuteMsg::RedeemFrom {
+            owner: "butler".to_string(),
+            amount: Uint128::new(500),
+            denom: None,
+            recipient: "exchange".to_string(),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("hot_wallet", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(
+            RedeemAllowancesStore::load(&deps.storage, &butler, &hot_wallet),
+            0
+        );
+        assert_eq!(stored_balance(&deps.storage, &canonical).unwrap(), 3500);
+
+        let handle_msg = ExecuteMsg::RedeemFrom {
+            owner: "butler".to_string(),
+            amount: Uint128::new(1),
+            denom: None,
+            recipient: "exchange".to_string(),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("hot_wallet", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "insufficient redeem allowance: allowance=0, required=1"
+        );
+    }
+
+    #[test]
+    fn test_handle_deposit_callback() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![],
+            true,
+            false,
+            false,
+            false,
+            0,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let code_hash = "ab".repeat(32);
+        let deposit = |callback: Option<DepositCallback>| ExecuteMsg::Deposit {
+            memo: Some("wrap".to_string()),
+            callback,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = || {
+            mock_info(
+                "lebron",
+                &[Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint128::new(1000),
+                }],
+            )
+        };
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info(), deposit(None));
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result.clone()));
+        assert!(result.messages.is_empty());
+
+        let callback = DepositCallback {
+            contract: "wrapper".to_string(),
+            code_hash: code_hash.clone(),
+            msg: Some(to_binary("mint derivative").unwrap()),
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), info(), deposit(Some(callback)));
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result.clone()));
+        assert_eq!(
+            result.messages,
+            vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "wrapper".to_string(),
+                code_hash,
+                msg: Snip20ReceiveMsg::new(
+                    Addr::unchecked("lebron"),
+                    Addr::unchecked("lebron"),
+                    Uint128::new(1000),
+                    Some("wrap".to_string()),
+                    Some(to_binary("mint derivative").unwrap()),
+                )
+                .into_binary()
+                .unwrap(),
+                funds: vec![],
+            }))]
+        );
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 2000);
+
+        // the second deposit went on to the callback contract
+        let lebron = deps.api.addr_canonicalize("lebron").unwrap();
+        let wrapper = deps.api.addr_canonicalize("wrapper").unwrap();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        assert_eq!(dwb.account_balance(&deps.storage, &lebron).unwrap(), 1000);
+        assert_eq!(dwb.account_balance(&deps.storage, &wrapper).unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_handle_add_supported_denoms_validation() {
+        let (init_result, mut deps) =
+            init_helper_with_raw_config(vec![], r#"{ "can_modify_denoms": true }"#);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let add_denoms = |denoms: Vec<String>| ExecuteMsg::AddSupportedDenoms {
+            denoms,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+        };
+
+        for denom in ["1uscrt", "us", "u scrt", &format!("u{}", "a".repeat(128))] {
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("admin", &[]),
+                add_denoms(vec![denom.to_string()]),
+            );
+            let error = extract_error_msg(handle_result);
+            assert!(
+                error.contains("is not in the expected format"),
+                "unexpected error for {denom}: {error}"
+            );
+        }
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            add_denoms(vec![
+                "uscrt".to_string(),
+                "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2".to_string(),
+            ]),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let too_many = (0..MAX_SUPPORTED_DENOMS - 1)
+            .map(|i| format!("udenom{i}"))
+            .collect();
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            add_denoms(too_many),
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            format!("Cannot support more than {MAX_SUPPORTED_DENOMS} denoms")
+        );
+        let config = CONFIG.load(&deps.storage).unwrap();
+        assert_eq!(config.supported_denoms.len(), 2);
+
+        // the initial denoms are validated the same way
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let init_msg = InstantiateMsg {
+            name: "sec-sec".to_string(),
+            admin: Some("admin".to_string()),
+            symbol: "SECSEC".to_string(),
+            decimals: 8,
+            initial_balances: None,
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: None,
+            supported_denoms: Some(vec!["uscrt".to_string(), "u$crt".to_string()]),
+        };
+        let init_result = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("instantiator", &[]),
+            init_msg,
+        );
+        let error = extract_error_msg(init_result);
+        assert!(error.contains("\"u$crt\" is not in the expected format"));
+    }
+
+    #[test]
+    fn test_handle_set_denom_status() {
+        let mut deps = mock_dependencies_with_balance(&[Coin {
+            denom: "uatom".to_string(),
+            amount: Uint128::new(1000),
+        }]);
+        let init_config: InitConfig = from_binary(&Binary::from(
+            r#"{ "enable_deposit": false, "enable_redeem": false, "can_modify_denoms": true }"#
+                .as_bytes(),
+        ))
+        .unwrap();
+        let init_msg = InstantiateMsg {
+            name: "sec-sec".to_string(),
+            admin: Some("admin".to_string()),
+            symbol: "SECSEC".to_string(),
+            decimals: 8,
+            initial_balances: None,
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: Some(vec!["uscrt".to_string(), "uatom".to_string()]),
+        };
+        let init_result = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("instantiator", &[]),
+            init_msg,
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let set_denom_status =
+            |denom: &str, deposit_enabled: bool, redeem_enabled: bool| ExecuteMsg::SetDenomStatus {
+                denom: denom.to_string(),
+                deposit_enabled,
+                redeem_enabled,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            set_denom_status("uscrt", true, false),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            set_denom_status("uosmo", true, true),
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "Cannot set the status of unsupported coin uosmo");
+
+        // uscrt is deposit-only and uatom is redeem-only
+        for (denom, deposit_enabled, redeem_enabled) in
+            [("uscrt", true, false), ("uatom", false, true)]
+        {
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("admin", &[]),
+                set_denom_status(denom, deposit_enabled, redeem_enabled),
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let query_result = query(deps.as_ref(), mock_env(), QueryMsg::DenomInfo {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::DenomInfo { denoms } => assert_eq!(
+                denoms,
+                vec![
+                    DenomStatus {
+                        denom: "uscrt".to_string(),
+                        deposit_enabled: true,
+                        redeem_enabled: false,
+                    },
+                    DenomStatus {
+                        denom: "uatom".to_string(),
+                        deposit_enabled: false,
+                        redeem_enabled: true,
+                    },
+                ]
+            ),
+            _ => panic!("unexpected"),
+        }
+
+        let deposit = ExecuteMsg::Deposit {
+            memo: None,
+            callback: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let funds = |denom: &str| {
+            vec![Coin {
+                denom: denom.to_string(),
+                amount: Uint128::new(1000),
+            }]
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &funds("uatom")),
+            deposit.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.starts_with("Deposit functionality is not enabled for uatom"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &funds("uscrt")),
+            deposit,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let redeem = |denom: &str| ExecuteMsg::Redeem {
+            amount: Uint128::new(100),
+            denom: Some(denom.to_string()),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            redeem("uscrt"),
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "Redeem functionality is not enabled for uscrt");
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            redeem("uatom"),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 900);
+    }
+
+    #[test]
+    fn test_handle_deposit() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            true,
+            false,
+            false,
+            false,
+            0,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "lebron".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // test when deposit disabled
+        let handle_msg = ExecuteMsg::Deposit {
+            memo: None,
+            callback: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info(
+            "lebron",
+            &[Coin {
+                denom: "uscrt".to_string(),
+                amount: Uint128::new(1000),
+            }],
+        );
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Tried to deposit an unsupported coin uscrt"));
+
+        let handle_msg = ExecuteMsg::Deposit {
+            memo: None,
+            callback: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+
+        let info = mock_info(
+            "lebron",
+            &[Coin {
+                denom: "uscrt".to_string(),
+                amount: Uint128::new(1000),
+            }],
+        );
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let canonical = deps
+            .api
+            .addr_canonicalize(Addr::unchecked("lebron".to_string()).as_str())
+            .unwrap();
+
+        // stored balance not updated, still in dwb
+        assert_ne!(stored_balance(&deps.storage, &canonical).unwrap(), 6000);
+
+        let create_vk_msg = ExecuteMsg::CreateViewingKey {
+            entropy: Some("34".to_string()),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("lebron", &[]);
+        let handle_response = execute(deps.as_mut(), mock_env(), info, create_vk_msg).unwrap();
+        let vk = match from_binary(&handle_response.data.unwrap()).unwrap() {
+            ExecuteAnswer::CreateViewingKey { key } => key,
+            _ => panic!("Unexpected result from handle"),
+        };
+
+        let query_balance_msg = QueryMsg::Balance {
+            address: "lebron".to_string(),
+            key: vk,
+        };
+
+        let query_response = query(deps.as_ref(), mock_env(), query_balance_msg).unwrap();
+        let balance = match from_binary(&query_response).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(balance, Uint128::new(6000));
+    }
+
+    #[test]
+    fn test_handle_require_memo_on_native() {
+        let mut deps = mock_dependencies_with_balance(&[Coin {
+            denom: "uscrt".to_string(),
+            amount: Uint128::new(1000),
+        }]);
+        let init_config: InitConfig = from_binary(&Binary::from(
+            r#"{ "enable_deposit": true, "enable_redeem": true, "require_memo_on_native": true }"#
+                .as_bytes(),
+        ))
+        .unwrap();
+        let init_msg = InstantiateMsg {
+            name: "sec-sec".to_string(),
+            admin: Some("admin".to_string()),
+            symbol: "SECSEC".to_string(),
+            decimals: 8,
+            initial_balances: None,
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: Some(vec!["uscrt".to_string()]),
+        };
+        let init_result = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("instantiator", &[]),
+            init_msg,
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let deposit = |memo: Option<&str>| ExecuteMsg::Deposit {
+            memo: memo.map(String::from),
+            callback: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let redeem = |memo: Option<&str>| ExecuteMsg::Redeem {
+            amount: Uint128::new(400),
+            denom: None,
+            memo: memo.map(String::from),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let funds = [Coin {
+            denom: "uscrt".to_string(),
+            amount: Uint128::new(1000),
+        }];
+
+        // deposits and redeems without a memo are rejected
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &funds),
+            deposit(None),
+        );
+        assert_eq!(extract_error_msg(handle_result), "memo required");
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &funds),
+            deposit(Some("deposit ref")),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            redeem(None),
+        );
+        assert_eq!(extract_error_msg(handle_result), "memo required");
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            redeem(Some("redeem ref")),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // the memos are recorded in the history
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "lebron".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let txs = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, .. } => txs,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        let memos: Vec<(TxAction, Option<String>)> =
+            txs.into_iter().map(|tx| (tx.action, tx.memo)).collect();
+        assert_eq!(
+            memos,
+            vec![
+                (TxAction::Redeem {}, Some("redeem ref".to_string())),
+                (TxAction::Deposit {}, Some("deposit ref".to_string())),
+            ]
+        );
+
+        // redeeming through an allowance needs a memo too
+        let handle_msg = ExecuteMsg::SetRedeemAllowance {
+            spender: "exchange".to_string(),
+            amount: Uint128::new(200),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let redeem_from = |memo: Option<&str>| ExecuteMsg::RedeemFrom {
+            owner: "lebron".to_string(),
+            amount: Uint128::new(200),
+            denom: None,
+            recipient: "exchange".to_string(),
+            memo: memo.map(String::from),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("exchange", &[]),
+            redeem_from(None),
+        );
+        assert_eq!(extract_error_msg(handle_result), "memo required");
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("exchange", &[]),
+            redeem_from(Some("redeem from ref")),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+    }
+
+    #[test]
+    fn test_handle_burn() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            false,
+            true,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "lebron".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // test when burn disabled
+        let handle_msg = ExecuteMsg::Burn {
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("lebron", &[]);
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Burn functionality is not enabled for this token."));
+
+        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        let burn_amount: u128 = 100;
+        let handle_msg = ExecuteMsg::Burn {
+            amount: Uint128::new(burn_amount),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("lebron", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "Pause handle failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let new_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(new_supply, supply - burn_amount);
+    }
+
+    #[test]
+    fn test_handle_burn_to() {
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let init_config: InitConfig = from_binary(&Binary::from(
+            r#"{ "public_total_supply": true, "enable_burn": true, "burn_sink_address": "burnsink" }"#
+                .as_bytes(),
+        ))
+        .unwrap();
+        let init_msg = InstantiateMsg {
+            name: "sec-sec".to_string(),
+            admin: Some("admin".to_string()),
+            symbol: "SECSEC".to_string(),
+            decimals: 8,
+            initial_balances: Some(vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }]),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: None,
+        };
+        let init_result = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("instantiator", &[]),
+            init_msg,
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::BurnTo {
+            amount: Uint128::new(100),
+            memo: Some("tombstone".to_string()),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // supply decreases just like a plain burn
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 4900);
+
+        let query_result = query(deps.as_ref(), mock_env(), QueryMsg::TotalBurnedToSink {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TotalBurnedToSink { sink, total_burned } => {
+                assert_eq!(sink, Some(Addr::unchecked("burnsink")));
+                assert_eq!(total_burned, Some(Uint128::new(100)));
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+
+        for address in ["lebron", "burnsink"] {
+            let handle_msg = ExecuteMsg::SetViewingKey {
+                key: "key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(address, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        // history shows the sink as the recipient
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "lebron".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let txs = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, .. } => txs,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(
+            txs[0].action,
+            TxAction::Transfer {
+                from: Addr::unchecked("lebron"),
+                sender: Addr::unchecked("lebron"),
+                recipient: Addr::unchecked("burnsink"),
+            }
+        );
+        assert_eq!(txs[0].coins.amount, Uint128::new(100));
+        assert_eq!(txs[0].memo, Some("tombstone".to_string()));
+
+        // the sink is never credited
+        let query_msg = QueryMsg::Balance {
+            address: "burnsink".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let balance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(balance, Uint128::zero());
+
+        // without a configured sink, BurnTo is rejected
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            false,
+            true,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let handle_msg = ExecuteMsg::BurnTo {
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            handle_msg,
+        );
+        assert_eq!(
+            extract_error_msg(handle_result),
+            "No burn sink address is configured"
+        );
+    }
+
+    #[test]
+    fn test_handle_mint() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "lebron".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // try to mint when mint is disabled
+        let mint_amount: u128 = 100;
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "lebron".to_string(),
+            amount: Uint128::new(mint_amount),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Mint functionality is not enabled for this token"));
+
+        let supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        let mint_amount: u128 = 100;
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "lebron".to_string(),
+            amount: Uint128::new(mint_amount),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "Pause handle failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let new_supply = TOTAL_SUPPLY.load(&deps.storage).unwrap();
+        assert_eq!(new_supply, supply + mint_amount);
+    }
+
+    #[test]
+    fn test_handle_batch_mint_overflow() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // each amount fits on top of the total supply, but together they overflow it
+        let half = u128::MAX / 2;
+        let handle_msg = ExecuteMsg::BatchMint {
+            actions: vec![
+                batch::MintAction {
+                    recipient: "lebron".to_string(),
+                    amount: Uint128::new(half),
+                    memo: None,
+                },
+                batch::MintAction {
+                    recipient: "giannis".to_string(),
+                    amount: Uint128::new(half),
+                    memo: None,
+                },
+            ],
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "mint would overflow total supply at action 1");
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_handle_mint_campaign() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mint_campaign = |campaign_cap: u128| ExecuteMsg::MintCampaign {
+            actions: [("giannis", 300), ("steph", 200), ("kevin", 500)]
+                .into_iter()
+                .map(|(recipient, amount)| batch::MintAction {
+                    recipient: recipient.to_string(),
+                    amount: Uint128::new(amount),
+                    memo: None,
+                })
+                .collect(),
+            campaign_cap: Uint128::new(campaign_cap),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+
+        // the amounts add up to more than the cap, so nothing is minted
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            mint_campaign(999),
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "campaign total 1000 exceeds campaign cap 999");
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+
+        // the cap still applies only to minters
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            mint_campaign(1000),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Minting is allowed to minter accounts only"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            mint_campaign(1000),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 6000);
+    }
+
+    #[test]
+    fn test_handle_batch_mint_invalid_recipient() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let actions = ["lebron", "giannis", "", "steph"]
+            .into_iter()
+            .map(|recipient| batch::MintAction {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(100),
+                memo: None,
+            })
+            .collect();
+        let handle_msg = ExecuteMsg::BatchMint {
+            actions,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Invalid recipient in action 2"));
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_handle_mint_overflow() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // the full amount cannot be credited, so the mint fails rather than crediting less
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "giannis".to_string(),
+            amount: Uint128::new(u128::MAX - 4999),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.starts_with("amount would overflow total supply"));
+        assert_eq!(TOTAL_SUPPLY.load(&deps.storage).unwrap(), 5000);
+    }
+
+    #[test]
+    fn test_handle_admin_commands() {
+        let admin_err = "Admin commands can only be run from admin address".to_string();
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let pause_msg = ExecuteMsg::SetContractStatus {
+            level: ContractStatusLevel::StopAllButRedeems,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("not_admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, pause_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains(&admin_err.clone()));
+
+        let mint_msg = ExecuteMsg::AddMinters {
+            minters: vec!["not_admin".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("not_admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, mint_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains(&admin_err.clone()));
+
+        let mint_msg = ExecuteMsg::RemoveMinters {
+            minters: vec!["admin".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("not_admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, mint_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains(&admin_err.clone()));
+
+        let mint_msg = ExecuteMsg::SetMinters {
+            minters: vec!["not_admin".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("not_admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, mint_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains(&admin_err.clone()));
+
+        let change_admin_msg = ExecuteMsg::ChangeAdmin {
+            address: "not_admin".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("not_admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, change_admin_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains(&admin_err.clone()));
+    }
+
+    #[test]
+    fn test_handle_pause_with_withdrawals() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            true,
+            false,
+            false,
+            5000,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let pause_msg = ExecuteMsg::SetContractStatus {
+            level: ContractStatusLevel::StopAllButRedeems,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, pause_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "Pause handle failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let send_msg = ExecuteMsg::Transfer {
+            recipient: "account".to_string(),
+            amount: Uint128::new(123),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, send_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "This contract is stopped and this action is not allowed".to_string()
+        );
+
+        let withdraw_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(5000),
+            denom: Option::from("uscrt".to_string()),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("lebron", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, withdraw_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "Withdraw failed: {}",
+            handle_result.err().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_handle_settle_self_with_withdrawals() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "lebron".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(1000),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("lebron", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, transfer_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let bob_raw = deps.api.addr_canonicalize("bob").unwrap();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        assert_ne!(dwb.recipient_match(&bob_raw), 0);
+
+        let pause_msg = ExecuteMsg::SetContractStatus {
+            level: ContractStatusLevel::StopAllButRedeems,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, pause_msg);
+        assert!(
+            handle_result.is_ok(),
+            "Pause handle failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let settle_msg = ExecuteMsg::SettleSelf {
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, settle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "SettleSelf failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // bob's receipt has moved from the buffer into his stored balance
+        let dwb = DWB.load(&deps.storage).unwrap();
+        let bob_index = dwb.recipient_match(&bob_raw);
+        assert!(bob_index == 0 || dwb.entries[bob_index].amount().unwrap() == 0);
+        assert_eq!(stored_balance(&deps.storage, &bob_raw).unwrap(), 1000);
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            recipient: "lebron".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, transfer_msg);
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "This contract is stopped and this action is not allowed".to_string()
+        );
+    }
+
+    #[test]
+    fn test_handle_initialize_account() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "lebron".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // free slots left in the bucket the account's stored entry belongs in
+        let bucket_capacity = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                               address: &CanonicalAddr| {
+            let (node, _, _) = locate_btbe_node(&deps.storage, address).unwrap();
+            node.bucket(&deps.storage).unwrap().capacity
+        };
+        let initialize = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let handle_msg = ExecuteMsg::InitializeAccount {
+                address: "bob".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("relayer", &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        };
+        let settle = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, account: &str| {
+            let handle_msg = ExecuteMsg::SettleSelf {
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(account, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        };
+
+        let bob_raw = deps.api.addr_canonicalize("bob").unwrap();
+        let charlie_raw = deps.api.addr_canonicalize("charlie").unwrap();
+        assert_eq!(stored_entry(&deps.storage, &bob_raw).unwrap(), None);
+
+        let capacity = bucket_capacity(&deps, &bob_raw);
+        initialize(&mut deps);
+        assert_eq!(bucket_capacity(&deps, &bob_raw), capacity - 1);
+        let bob_entry = stored_entry(&deps.storage, &bob_raw).unwrap().unwrap();
+        assert_eq!(bob_entry.balance().unwrap(), 0);
+        assert_eq!(bob_entry.history_len().unwrap(), 0);
+
+        // initializing again changes nothing
+        initialize(&mut deps);
+        assert_eq!(bucket_capacity(&deps, &bob_raw), capacity - 1);
+        assert_eq!(
+            stored_entry(&deps.storage, &bob_raw).unwrap(),
+            Some(bob_entry)
+        );
+
+        for recipient in ["bob", "charlie"] {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(1000),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("lebron", &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        // settling the initialized account merges into its existing entry
+        let capacity = bucket_capacity(&deps, &bob_raw);
+        settle(&mut deps, "bob");
+        assert_eq!(bucket_capacity(&deps, &bob_raw), capacity);
+
+        // while the uninitialized one still needs a new entry
+        let capacity = bucket_capacity(&deps, &charlie_raw);
+        settle(&mut deps, "charlie");
+        assert_eq!(bucket_capacity(&deps, &charlie_raw), capacity - 1);
+
+        // and both end up in the same state
+        for account in [&bob_raw, &charlie_raw] {
+            let entry = stored_entry(&deps.storage, account).unwrap().unwrap();
+            assert_eq!(entry.balance().unwrap(), 1000);
+            assert_eq!(entry.history_len().unwrap(), 1);
+            assert_eq!(stored_tx_count(&deps.storage, &Some(entry)).unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_handle_pause_all() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "lebron".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let pause_msg = ExecuteMsg::SetContractStatus {
+            level: ContractStatusLevel::StopAll,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, pause_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "Pause handle failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let send_msg = ExecuteMsg::Transfer {
+            recipient: "account".to_string(),
+            amount: Uint128::new(123),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, send_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "This contract is stopped and this action is not allowed".to_string()
+        );
+
+        let withdraw_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(5000),
+            denom: Option::from("uscrt".to_string()),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("lebron", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, withdraw_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert_eq!(
+            error,
+            "This contract is stopped and this action is not allowed".to_string()
+        );
+    }
+
+    #[test]
+    fn test_handle_set_operation_flags() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let flags_msg = ExecuteMsg::SetOperationFlags {
+            disable_mint: true,
+            disable_send: false,
+            disable_burn: false,
+            disable_transfer: false,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            flags_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            flags_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let mint_msg = ExecuteMsg::Mint {
+            recipient: "lebron".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            mint_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert_eq!(error, "operation temporarily disabled".to_string());
+
+        let query_result = query(deps.as_ref(), mock_env(), QueryMsg::ContractStatus {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ContractStatus {
+                status,
+                operation_flags,
+                redeem_rate_limit,
+            } => {
+                assert_eq!(status, ContractStatusLevel::NormalRun);
+                assert_eq!(
+                    operation_flags,
+                    OperationFlags {
+                        mint_disabled: true,
+                        send_disabled: false,
+                        burn_disabled: false,
+                        transfer_disabled: false,
+                    }
+                );
+                assert_eq!(redeem_rate_limit, None);
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+
+        // transfers are unaffected
+        let transfer_msg = ExecuteMsg::Transfer {
+            recipient: "giannis".to_string(),
+            amount: Uint128::new(123),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            transfer_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // re-enabling minting
+        let flags_msg = ExecuteMsg::SetOperationFlags {
+            disable_mint: false,
+            disable_send: false,
+            disable_burn: false,
+            disable_transfer: false,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            flags_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("admin", &[]), mint_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+    }
+
+    #[test]
+    fn test_handle_set_minters() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // try when mint disabled
+        let handle_msg = ExecuteMsg::SetMinters {
+            minters: vec!["bob".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Mint functionality is not enabled for this token"));
+
+        let handle_msg = ExecuteMsg::SetMinters {
+            minters: vec!["bob".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_msg = ExecuteMsg::SetMinters {
+            minters: vec!["bob".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("allowed to minter accounts only"));
+    }
+
+    #[test]
+    fn test_handle_add_minters() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // try when mint disabled
+        let handle_msg = ExecuteMsg::AddMinters {
+            minters: vec!["bob".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Mint functionality is not enabled for this token"));
+
+        let handle_msg = ExecuteMsg::AddMinters {
+            minters: vec!["bob".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_msg = ExecuteMsg::AddMinters {
+            minters: vec!["bob".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+    }
+
+    #[test]
+    fn test_handle_remove_minters() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            true,
+            false,
+            0,
+            vec![],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let (init_result_for_failure, mut deps_for_failure) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result_for_failure.is_ok(),
+            "Init failed: {}",
+            init_result_for_failure.err().unwrap()
+        );
+        // try when mint disabled
+        let handle_msg = ExecuteMsg::RemoveMinters {
+            minters: vec!["bob".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps_for_failure.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Mint functionality is not enabled for this token"));
+
+        let handle_msg = ExecuteMsg::RemoveMinters {
+            minters: vec!["admin".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_msg = ExecuteMsg::RemoveMinters {
+            minters: vec!["admin".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("allowed to minter accounts only"));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("allowed to minter accounts only"));
+
+        // Removing another extra time to ensure nothing funky happens
+        let handle_msg = ExecuteMsg::RemoveMinters {
+            minters: vec!["admin".to_string()],
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("allowed to minter accounts only"));
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("allowed to minter accounts only"));
+    }
+
+    // Query tests
+
+    #[test]
+    fn test_authenticated_queries() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "giannis".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let no_vk_yet_query_msg = QueryMsg::Balance {
+            address: "giannis".to_string(),
+            key: "no_vk_yet".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), no_vk_yet_query_msg);
+        let error = extract_error_msg(query_result);
+        assert_eq!(
+            error,
+            "Wrong viewing key for this address or viewing key not set".to_string()
+        );
+
+        let create_vk_msg = ExecuteMsg::CreateViewingKey {
+            entropy: Some("34".to_string()),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("giannis", &[]);
+        let handle_response = execute(deps.as_mut(), mock_env(), info, create_vk_msg).unwrap();
+        let vk = match from_binary(&handle_response.data.unwrap()).unwrap() {
+            ExecuteAnswer::CreateViewingKey { key } => key,
+            _ => panic!("Unexpected result from handle"),
+        };
+
+        let query_balance_msg = QueryMsg::Balance {
+            address: "giannis".to_string(),
+            key: vk,
+        };
+
+        let query_response = query(deps.as_ref(), mock_env(), query_balance_msg).unwrap();
+        let balance = match from_binary(&query_response).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(balance, Uint128::new(5000));
+
+        let wrong_vk_query_msg = QueryMsg::Balance {
+            address: "giannis".to_string(),
+            key: "wrong_vk".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), wrong_vk_query_msg);
+        let error = extract_error_msg(query_result);
+        assert_eq!(
+            error,
+            "Wrong viewing key for this address or viewing key not set".to_string()
+        );
+    }
+
+    #[test]
+    fn test_query_token_info() {
+        let init_name = "sec-sec".to_string();
+        let init_admin = Addr::unchecked("admin".to_string());
+        let init_symbol = "SECSEC".to_string();
+        let init_decimals = 8;
+        let init_config: InitConfig = from_binary(&Binary::from(
+            r#"{ "public_total_supply": true }"#.as_bytes(),
+        ))
+        .unwrap();
+        let init_supply = Uint128::new(5000);
+
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let info = mock_info("instantiator", &[]);
+        let env = mock_env();
+        let init_msg = InstantiateMsg {
+            name: init_name.clone(),
+            admin: Some(init_admin.into_string()),
+            symbol: init_symbol.clone(),
+            decimals: init_decimals.clone(),
+            initial_balances: Some(vec![InitialBalance {
+                address: "giannis".to_string(),
+                amount: init_supply,
+            }]),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: None,
+        };
+        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::TokenInfo {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        assert!(
+            query_result.is_ok(),
+            "Init failed: {}",
+            query_result.err().unwrap()
+        );
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::TokenInfo {
+                name,
+                symbol,
+                decimals,
+                total_supply,
+            } => {
+                assert_eq!(name, init_name);
+                assert_eq!(symbol, init_symbol);
+                assert_eq!(decimals, init_decimals);
+                assert_eq!(total_supply, Some(Uint128::new(5000)));
+            }
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_query_total_supply_visibility() {
+        let admin = "secret18mdrja40gfuftt5yx6tgj0fn5lurplezyp894y";
+        let permit_name = "default";
+        let chain_id = "secretdev-1";
+        let pub_key = "AkZqxdKMtPq2w0kGDGwWGejTAed0H7azPMHtrCX0XYZG";
+        let signature = "ZXyFMlAy6guMG9Gj05rFvcMi5/JGfClRtJpVTHiDtQY3GtSfBHncY70kmYiTXkKIxSxdnh/kS8oXa+GSX5su6Q==";
+
+        let init = |admin: &str, config: &str| {
+            let mut deps = mock_dependencies_with_balance(&[]);
+            let init_msg = InstantiateMsg {
+                name: "sec-sec".to_string(),
+                admin: Some(admin.to_string()),
+                symbol: "SECSEC".to_string(),
+                decimals: 8,
+                initial_balances: Some(vec![InitialBalance {
+                    address: "giannis".to_string(),
+                    amount: Uint128::new(5000),
+                }]),
+                prng_seed: Binary::from("lolz fun yay".as_bytes()),
+                config: Some(from_binary(&Binary::from(config.as_bytes())).unwrap()),
+                supported_denoms: None,
+            };
+            let init_result = instantiate(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("instantiator", &[]),
+                init_msg,
+            );
+            assert!(
+                init_result.is_ok(),
+                "Init failed: {}",
+                init_result.err().unwrap()
+            );
+            deps
+        };
+        let token_info_supply = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let query_result = query(deps.as_ref(), mock_env(), QueryMsg::TokenInfo {});
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::TokenInfo { total_supply, .. } => total_supply,
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+        let permit_supply = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let query_msg = QueryMsg::WithPermit {
+                permit: gen_permit_obj(
+                    permit_name,
+                    chain_id,
+                    pub_key,
+                    signature,
+                    TokenPermissions::Owner,
+                ),
+                query: QueryWithPermit::TotalSupply {},
+            };
+            query(deps.as_ref(), mock_env(), query_msg).map(|answer| {
+                match from_binary(&answer).unwrap() {
+                    QueryAnswer::TotalSupply { total_supply } => total_supply,
+                    other => panic!("Unexpected: {:?}", other),
+                }
+            })
+        };
+
+        // public: everyone sees the supply
+        let deps = init(admin, r#"{ "total_supply_visibility": "public" }"#);
+        assert_eq!(token_info_supply(&deps), Some(Uint128::new(5000)));
+        assert_eq!(permit_supply(&deps).unwrap(), Some(Uint128::new(5000)));
+
+        // admin only: hidden from token_info, but the admin can query it
+        let deps = init(admin, r#"{ "total_supply_visibility": "admin_only" }"#);
+        assert_eq!(token_info_supply(&deps), None);
+        assert_eq!(permit_supply(&deps).unwrap(), Some(Uint128::new(5000)));
+
+        // ... and nobody else can
+        let deps = init("admin", r#"{ "total_supply_visibility": "admin_only" }"#);
+        let error = extract_error_msg(permit_supply(&deps));
+        assert_eq!(error, "Total supply can only be queried by the admin");
+
+        // private: not even the admin sees it
+        let deps = init(admin, r#"{ "total_supply_visibility": "private" }"#);
+        assert_eq!(token_info_supply(&deps), None);
+        assert_eq!(permit_supply(&deps).unwrap(), None);
+
+        // the legacy flag still works, and the explicit visibility takes precedence over it
+        for (config, expected) in [
+            (r#"{}"#, TotalSupplyVisibility::Private),
+            (
+                r#"{ "public_total_supply": false }"#,
+                TotalSupplyVisibility::Private,
+            ),
+            (
+                r#"{ "public_total_supply": true }"#,
+                TotalSupplyVisibility::Public,
+            ),
+            (
+                r#"{ "public_total_supply": true, "total_supply_visibility": "admin_only" }"#,
+                TotalSupplyVisibility::AdminOnly,
+            ),
+        ] {
+            let deps = init(admin, config);
+            let constants = CONFIG.load(&deps.storage).unwrap();
+            assert_eq!(constants.total_supply_visibility, expected);
+        }
+    }
+
+    #[test]
+    fn test_query_token_config() {
+        let init_name = "sec-sec".to_string();
+        let init_admin = Addr::unchecked("admin".to_string());
+        let init_symbol = "SECSEC".to_string();
+        let init_decimals = 8;
+        let init_config: InitConfig = from_binary(&Binary::from(
+            format!(
+                "{{\"public_total_supply\":{},
+            \"enable_deposit\":{},
+            \"enable_redeem\":{},
+            \"enable_mint\":{},
+            \"enable_burn\":{}}}",
+                true, false, false, true, false
+            )
+            .as_bytes(),
+        ))
+        .unwrap();
+
+        let init_supply = Uint128::new(5000);
+
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let info = mock_info("instantiator", &[]);
+        let env = mock_env();
+        let init_msg = InstantiateMsg {
+            name: init_name.clone(),
+            admin: Some(init_admin.into_string()),
+            symbol: init_symbol.clone(),
+            decimals: init_decimals.clone(),
+            initial_balances: Some(vec![InitialBalance {
+                address: "giannis".to_string(),
+                amount: init_supply,
+            }]),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: None,
+        };
+        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::TokenConfig {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        assert!(
+            query_result.is_ok(),
+            "Init failed: {}",
+            query_result.err().unwrap()
+        );
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::TokenConfig {
+                public_total_supply,
+                deposit_enabled,
+                redeem_enabled,
+                mint_enabled,
+                burn_enabled,
+                supported_denoms,
+                can_modify_denoms,
+            } => {
+                assert_eq!(public_total_supply, true);
+                assert_eq!(deposit_enabled, false);
+                assert_eq!(redeem_enabled, false);
+                assert_eq!(mint_enabled, true);
+                assert_eq!(burn_enabled, false);
+                assert_eq!(supported_denoms.len(), 0);
+                assert_eq!(can_modify_denoms, false);
+            }
+            _ => panic!("unexpected"),
+        }
+
+        let (init_result, deps) =
+            init_helper_with_raw_config(vec![], r#"{ "can_modify_denoms": true }"#);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_result = query(deps.as_ref(), mock_env(), QueryMsg::TokenConfig {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TokenConfig {
+                can_modify_denoms, ..
+            } => assert!(can_modify_denoms),
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_query_denom_info() {
+        let denoms = vec!["uscrt".to_string(), "uatom".to_string()];
+        for (enable_deposit, enable_redeem) in [(true, false), (false, true)] {
+            let (init_result, deps) = init_helper_with_config(
+                vec![],
+                enable_deposit,
+                enable_redeem,
+                false,
+                false,
+                0,
+                denoms.clone(),
+            );
+            assert!(
+                init_result.is_ok(),
+                "Init failed: {}",
+                init_result.err().unwrap()
+            );
+
+            let query_result = query(deps.as_ref(), mock_env(), QueryMsg::DenomInfo {});
+            let denom_info = match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::DenomInfo { denoms } => denoms,
+                _ => panic!("unexpected"),
+            };
+            let expected: Vec<DenomStatus> = denoms
+                .iter()
+                .map(|denom| DenomStatus {
+                    denom: denom.clone(),
+                    deposit_enabled: enable_deposit,
+                    redeem_enabled: enable_redeem,
+                })
+                .collect();
+            assert_eq!(denom_info, expected);
+        }
+    }
+
+    #[test]
+    fn test_aggregate_batch_notifications() {
+        use secret_toolkit::notification::Notification;
+
+        let recvd = |recipient: &str, sender: &str, amount: u128| {
+            Notification::new(
+                Addr::unchecked(recipient.to_string()),
+                RecvdNotification {
+                    amount,
+                    sender: Some(Addr::unchecked(sender.to_string())),
+                    memo_len: 0,
+                    sender_is_owner: true,
+                },
+            )
+        };
+        let notifications = || {
+            vec![
+                recvd("alice", "bob", 100),
+                recvd("charlie", "bob", 200),
+                recvd("alice", "dave", 300),
+            ]
+        };
+
+        let group = MultiRecvdNotification::new(notifications(), false);
+        assert_eq!(group.0.len(), 3);
+
+        let group = MultiRecvdNotification::new(notifications(), true);
+        assert_eq!(group.0.len(), 2);
+        assert_eq!(group.0[0].notification_for, Addr::unchecked("alice"));
+        assert_eq!(group.0[0].data.amount, 400);
+        assert_eq!(group.0[0].data.sender, None);
+        assert_eq!(group.0[1].data.amount, 200);
+
+        let spent = |owner: &str, recipient: &str, amount: u128, balance: u128| {
+            Notification::new(
+                Addr::unchecked(owner.to_string()),
+                SpentNotification {
+                    amount,
+                    actions: 1,
+                    recipient: Some(Addr::unchecked(recipient.to_string())),
+                    balance,
+                    memo_len: 0,
+                },
+            )
+        };
+        let group = MultiSpentNotification::new(
+            vec![
+                spent("bob", "alice", 100, 900),
+                spent("bob", "charlie", 200, 700),
+                spent("dave", "alice", 50, 950),
+                spent("dave", "alice", 50, 900),
+            ],
+            true,
+        );
+        assert_eq!(group.0.len(), 2);
+        assert_eq!(group.0[0].data.amount, 300);
+        assert_eq!(group.0[0].data.actions, 2);
+        assert_eq!(group.0[0].data.balance, 700);
+        // a merged packet only names a recipient shared by every merged entry
+        assert_eq!(group.0[0].data.recipient, None);
+        assert_eq!(group.0[1].data.amount, 100);
+        assert_eq!(
+            group.0[1].data.recipient,
+            Some(Addr::unchecked("alice".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_send_notification_balance_ignores_reentry() {
+        use crate::execute_transfer_send::try_send_impl;
+
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::RegisterReceive {
+            code_hash: "this_is_a_hash_of_a_code".to_string(),
+            label: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("contract", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let mut rng = ContractPrng::new(b"seed", b"entropy");
+        let mut callbacks = vec![];
+        #[cfg(feature = "gas_tracking")]
+        let api = cosmwasm_std::testing::MockApi::default();
+        #[cfg(feature = "gas_tracking")]
+        let mut tracker = GasTracker::new(&api);
+        let (_, spent_notification) = try_send_impl(
+            &mut deps.as_mut(),
+            &mut rng,
+            &mut callbacks,
+            Addr::unchecked("bob"),
+            Addr::unchecked("contract"),
+            None,
+            Uint128::new(1000),
+            "SECSEC".to_string(),
+            None,
+            false,
+            Some(to_binary("reenter").unwrap()),
+            &mock_env().block,
+            #[cfg(feature = "gas_tracking")]
+            &mut tracker,
+        )
+        .unwrap();
+        assert_eq!(callbacks.len(), 1);
+        assert_eq!(spent_notification.data.balance, 4000);
+
+        // the receiver's callback re-enters the contract and returns the tokens to bob
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(1000),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("contract", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_msg = QueryMsg::Balance {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let balance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(balance, Uint128::new(5000));
+
+        // the notification still reports the balance as of the send
+        assert_eq!(spent_notification.data.balance, 4000);
+    }
+
+    #[test]
+    fn test_query_channel_emission_log() {
+        let (init_result, mut deps) = init_helper_with_raw_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            r#"{ "enable_channel_emission_log": true }"#,
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        for account in ["admin", "bob"] {
+            let handle_msg = ExecuteMsg::SetViewingKey {
+                key: "key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info(account, &[]);
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::BatchTransfer {
+            actions: vec![
+                batch::TransferAction {
+                    recipient: "alice".to_string(),
+                    amount: Uint128::new(100),
+                    memo: None,
+                },
+                batch::TransferAction {
+                    recipient: "charlie".to_string(),
+                    amount: Uint128::new(100),
+                    memo: None,
+                },
+            ],
+            deadline: None,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let emission_log =
+            |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, viewer: &str, channel: &str| {
+                let query_msg = QueryMsg::ChannelEmissionLog {
+                    channel: channel.to_string(),
+                    page: None,
+                    page_size: 10,
+                    viewer: ViewerInfo {
+                        address: viewer.to_string(),
+                        viewing_key: "key".to_string(),
+                    },
+                };
+                query(deps.as_ref(), mock_env(), query_msg)
+            };
+
+        let query_result = emission_log(&deps, "admin", "recvd");
+        let (total, emissions) = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ChannelEmissionLog { total, emissions } => (total, emissions),
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(total, 1);
+        assert_eq!(emissions[0].block_height, mock_env().block.height);
+        assert_eq!(emissions[0].marker.len(), 16);
+
+        // one record per real packet in the group notification
+        let query_result = emission_log(&deps, "admin", "multirecvd");
+        let (total, emissions) = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ChannelEmissionLog { total, emissions } => (total, emissions),
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(total, 2);
+        assert_eq!(emissions.len(), 2);
+        assert_ne!(emissions[0].marker, emissions[1].marker);
+
+        let query_result = emission_log(&deps, "bob", "recvd");
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("can only be queried by the admin"));
+
+        let query_result = emission_log(&deps, "admin", "nonexistent");
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Channel nonexistent does not exist"));
+    }
+
+    #[test]
+    fn test_query_notification_channels_for_transfer() {
+        let (init_result, deps) =
+            init_helper_with_raw_config(vec![], r#"{ "notify_allowance_use": true }"#);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::NotificationChannelsForTransfer {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let operations = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::NotificationChannelsForTransfer { operations } => operations,
+            _ => panic!("Unexpected"),
+        };
+        let channels_for = |operation: &str| {
+            operations
+                .iter()
+                .find(|entry| entry.operation == operation)
+                .unwrap()
+                .channels
+                .clone()
+        };
+
+        assert_eq!(
+            channels_for("transfer"),
+            vec!["recvd", "recvdcount", "spent"]
+        );
+        assert_eq!(
+            channels_for("transfer_all"),
+            vec!["recvd", "recvdcount", "spent"]
+        );
+        assert_eq!(
+            channels_for("send_from"),
+            vec!["recvd", "recvdcount", "spent", "allowance"]
+        );
+        assert_eq!(
+            channels_for("batch_transfer_from"),
+            vec!["multirecvd", "recvdcount", "multispent"]
+        );
+        assert_eq!(
+            channels_for("transfer_split"),
+            vec!["multirecvd", "recvdcount", "spent"]
+        );
+        assert_eq!(channels_for("burn"), vec!["spent"]);
+        assert_eq!(
+            channels_for("mint_campaign"),
+            vec!["multirecvd", "recvdcount"]
+        );
+        assert!(channels_for("transfer_with_timeout").is_empty());
+        assert!(channels_for("redeem").is_empty());
+
+        // every listed operation is an execute message
+        for (operation, _) in OPERATION_CHANNELS {
+            let msg = Binary::from(format!("{{\"{operation}\":{{}}}}").as_bytes());
+            if let Err(err) = from_binary::<ExecuteMsg>(&msg) {
+                assert!(
+                    !err.to_string().contains("unknown variant"),
+                    "{operation}: {err}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_notification_channels_match_emitted() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            false,
+            false,
+            false,
+            true,
+            0,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let operations = vec![
+            (
+                "transfer",
+                ExecuteMsg::Transfer {
+                    recipient: "alice".to_string(),
+                    amount: Uint128::new(100),
+                    memo: None,
+                    memo_encrypted: None,
+                    want_receipt: None,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                },
+            ),
+            (
+                "send",
+                ExecuteMsg::Send {
+                    recipient: "alice".to_string(),
+                    recipient_code_hash: None,
+                    amount: Uint128::new(100),
+                    msg: None,
+                    memo: None,
+                    memo_encrypted: None,
+                    reply_on_error: None,
+                    want_receipt: None,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                },
+            ),
+            (
+                "batch_transfer",
+                ExecuteMsg::BatchTransfer {
+                    actions: vec![batch::TransferAction {
+                        recipient: "alice".to_string(),
+                        amount: Uint128::new(100),
+                        memo: None,
+                    }],
+                    deadline: None,
+                    idempotency_key: None,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                },
+            ),
+            (
+                "transfer_split",
+                ExecuteMsg::TransferSplit {
+                    recipients: vec!["alice".to_string()],
+                    total: Uint128::new(100),
+                    memo: None,
+                    remainder_policy: None,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                },
+            ),
+            (
+                "burn",
+                ExecuteMsg::Burn {
+                    amount: Uint128::new(100),
+                    memo: None,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                },
+            ),
+        ];
+
+        // with a single recipient, each listed channel is emitted exactly once
+        for (operation, handle_msg) in operations {
+            let result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg)
+                .unwrap_or_else(|err| panic!("{operation}: {err}"));
+            let emitted = result
+                .attributes
+                .iter()
+                .filter(|attr| attr.key.starts_with("snip52:"))
+                .count();
+            assert_eq!(
+                emitted,
+                operation_channels(operation, false).len(),
+                "{operation}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_handle_set_channel_preference() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let transfer = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: "alice".to_string(),
+                amount: Uint128::new(100),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("bob", &[]);
+            execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap()
+        };
+        let set_preference = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+                              channel: &str,
+                              enabled: bool| {
+            let handle_msg = ExecuteMsg::SetChannelPreference {
+                channel: channel.to_string(),
+                enabled,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info("alice", &[]);
+            execute(deps.as_mut(), mock_env(), info, handle_msg)
+        };
+
+        // recvd + recvdcount + spent
+        let result = transfer(&mut deps);
+        assert_eq!(result.attributes.len(), 3);
+
+        let handle_result = set_preference(&mut deps, "nonexistent", false);
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Channel nonexistent does not exist"));
+
+        let handle_result = set_preference(&mut deps, "recvd", false);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // alice opted out of recvd, so only her recvdcount and bob's spent notifications remain
+        let result = transfer(&mut deps);
+        assert_eq!(result.attributes.len(), 2);
+        assert!(ensure_success(result));
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("alice", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_msg = QueryMsg::ChannelPreferences {
+            viewer: ViewerInfo {
+                address: "alice".to_string(),
+                viewing_key: "key".to_string(),
+            },
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let channels = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ChannelPreferences { channels } => channels,
+            _ => panic!("Unexpected"),
+        };
+        for preference in channels {
+            assert_eq!(preference.enabled, preference.channel != "recvd");
+        }
+
+        let handle_result = set_preference(&mut deps, "recvd", true);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let result = transfer(&mut deps);
+        assert_eq!(result.attributes.len(), 3);
+    }
+
+    #[test]
+    fn test_query_list_channels_paginated() {
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        for channel in ["custom1", "custom2", "custom3"] {
+            CHANNELS
+                .insert(&mut deps.storage, &channel.to_string())
+                .unwrap();
+        }
+
+        let list_channels = |page: Option<u32>, page_size: Option<u32>| {
+            let query_msg = QueryMsg::ListChannels { page, page_size };
+            match from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap() {
+                QueryAnswer::ListChannels { channels, total } => (channels, total),
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+
+        // without pagination fields every channel is returned
+        let (all_channels, total) = list_channels(None, None);
+        assert_eq!(total, 9);
+        assert_eq!(all_channels.len(), 9);
+        assert_eq!(
+            &all_channels[6..],
+            &[
+                "custom1".to_string(),
+                "custom2".to_string(),
+                "custom3".to_string()
+            ]
+        );
+
+        // paging through returns the same channels in the same order
+        let mut paged_channels = vec![];
+        for page in 0..3 {
+            let (channels, total) = list_channels(Some(page), Some(3));
+            assert_eq!(total, 9);
+            assert!(channels.len() <= 3);
+            paged_channels.extend(channels);
+        }
+        assert_eq!(paged_channels, all_channels);
+
+        let (channels, total) = list_channels(Some(3), Some(3));
+        assert!(channels.is_empty());
+        assert_eq!(total, 9);
+    }
+
+    #[test]
+    fn test_query_channel_info_from_schemata() {
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let txhash = "4A3B2C1D".to_string();
+        let secret = INTERNAL_SECRET_SENSITIVE.load(&deps.storage).unwrap();
+        let bob_raw = deps.api.addr_canonicalize("bob").unwrap();
+        let seed = get_seed(&bob_raw, &secret).unwrap();
+
+        let templates = builtin_channel_schemata();
+        assert_eq!(templates.len(), 6);
+        for template in templates {
+            // counter-mode channels report the viewer's counter instead of an answer ID
+            if template.mode == "counter" {
+                continue;
+            }
+
+            let query_msg = QueryMsg::ChannelInfo {
+                channels: vec![template.channel.clone()],
+                txhash: Some(txhash.clone()),
+                viewer: ViewerInfo {
+                    address: "bob".to_string(),
+                    viewing_key: "key".to_string(),
+                },
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            let mut channels = match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::ChannelInfo { channels, .. } => channels,
+                other => panic!("Unexpected: {:?}", other),
+            };
+            let info = channels.remove(0);
+
+            // the stored template comes back unchanged apart from the answer ID
+            let answer_id = notification_id(&seed, &template.channel, &txhash).unwrap();
+            assert_eq!(info.answer_id, Some(answer_id.clone()));
+            let expected = ChannelInfoData {
+                answer_id: Some(answer_id),
+                ..template
+            };
+            assert_eq!(to_binary(&info).unwrap(), to_binary(&expected).unwrap());
+
+            match info.channel.as_str() {
+                RecvdNotification::CHANNEL_ID
+                | SpentNotification::CHANNEL_ID
+                | AllowanceNotification::CHANNEL_ID => {
+                    assert_eq!(info.mode, "txhash");
+                    assert!(info.cddl.is_some());
+                    assert!(info.parameters.is_none());
+                    assert!(info.data.is_none());
+                }
+                MultiRecvdNotification::CHANNEL_ID => {
+                    assert_eq!(info.mode, "bloom");
+                    assert!(info.cddl.is_none());
+                    let parameters = info.parameters.unwrap();
+                    assert_eq!(parameters.m, MultiRecvdNotification::BLOOM_M);
+                    assert_eq!(parameters.k, MultiRecvdNotification::BLOOM_K);
+                    let data = info.data.unwrap();
+                    assert_eq!(data.r#type, "packet[16]");
+                    assert_eq!(data.packet_size, 16);
+                    let labels: Vec<String> =
+                        data.data.members.into_iter().map(|m| m.label).collect();
+                    assert_eq!(labels, vec!["flagsAndAmount", "ownerId"]);
+                }
+                MultiSpentNotification::CHANNEL_ID => {
+                    assert_eq!(info.mode, "bloom");
+                    assert!(info.cddl.is_none());
+                    let parameters = info.parameters.unwrap();
+                    assert_eq!(parameters.m, MultiSpentNotification::BLOOM_M);
+                    assert_eq!(parameters.k, MultiSpentNotification::BLOOM_K);
+                    let data = info.data.unwrap();
+                    assert_eq!(data.r#type, "packet[4]");
+                    assert_eq!(data.packet_size, 24);
+                    let labels: Vec<String> =
+                        data.data.members.into_iter().map(|m| m.label).collect();
+                    assert_eq!(labels, vec!["flagsAndAmount", "recipientId", "balance"]);
+                }
+                other => panic!("Unexpected channel: {other}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_query_channel_info_counter() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let secret = INTERNAL_SECRET_SENSITIVE.load(&deps.storage).unwrap();
+        let alice_raw = deps.api.addr_canonicalize("alice").unwrap();
+        let seed = get_seed(&alice_raw, &secret).unwrap();
+
+        let transfer = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: "alice".to_string(),
+                amount: Uint128::new(100),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg).unwrap()
+        };
+        let channel_info = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let query_msg = QueryMsg::ChannelInfo {
+                channels: vec![RECVD_COUNTER_CHANNEL_ID.to_string()],
+                txhash: Some("4A3B2C1D".to_string()),
+                viewer: ViewerInfo {
+                    address: "alice".to_string(),
+                    viewing_key: "key".to_string(),
+                },
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::ChannelInfo { mut channels, .. } => channels.remove(0),
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
+
+        // no transfers received yet
+        let info = channel_info(&deps);
+        assert_eq!(info.mode, "counter");
+        assert_eq!(info.answer_id, None);
+        assert_eq!(info.counter, Some(Uint64::zero()));
+        let next_id = counter_notification_id(&seed, RECVD_COUNTER_CHANNEL_ID, 1).unwrap();
+        assert_eq!(info.next_id, Some(next_id.clone()));
+
+        // the next transfer is notified under the predicted ID
+        let result = transfer(&mut deps);
+        assert!(result
+            .attributes
+            .iter()
+            .any(|attr| attr.key == format!("snip52:{}", next_id.to_base64())));
+
+        let info = channel_info(&deps);
+        assert_eq!(info.counter, Some(Uint64::new(1)));
+        assert_eq!(
+            info.next_id,
+            Some(counter_notification_id(&seed, RECVD_COUNTER_CHANNEL_ID, 2).unwrap())
+        );
+
+        transfer(&mut deps);
+        let info = channel_info(&deps);
+        assert_eq!(info.counter, Some(Uint64::new(2)));
+        assert_eq!(
+            info.next_id,
+            Some(counter_notification_id(&seed, RECVD_COUNTER_CHANNEL_ID, 3).unwrap())
+        );
+
+        // credits other than plain transfers advance the counter as well
+        let handle_msg = ExecuteMsg::BatchTransfer {
+            actions: vec![batch::TransferAction {
+                recipient: "alice".to_string(),
+                amount: Uint128::new(100),
+                memo: None,
+            }],
+            deadline: None,
+            idempotency_key: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg).unwrap();
+        let next_id = counter_notification_id(&seed, RECVD_COUNTER_CHANNEL_ID, 3).unwrap();
+        assert!(result
+            .attributes
+            .iter()
+            .any(|attr| attr.key == format!("snip52:{}", next_id.to_base64())));
+
+        let info = channel_info(&deps);
+        assert_eq!(info.counter, Some(Uint64::new(3)));
+
+        // the counter does not advance while alice has opted out of the channel
+        let handle_msg = ExecuteMsg::SetChannelPreference {
+            channel: RECVD_COUNTER_CHANNEL_ID.to_string(),
+            enabled: false,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        transfer(&mut deps);
+        let info = channel_info(&deps);
+        assert_eq!(info.counter, Some(Uint64::new(3)));
+    }
+
+    #[test]
+    fn test_query_channel_schema() {
+        let (init_result, deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let channel_schema = |channel: &str| {
+            let query_msg = QueryMsg::ChannelSchema {
+                channel: channel.to_string(),
+            };
+            query(deps.as_ref(), mock_env(), query_msg)
+        };
+
+        for (channel, cddl) in [
+            (
+                RecvdNotification::CHANNEL_ID,
+                RecvdNotification::CDDL_SCHEMA,
+            ),
+            (
+                SpentNotification::CHANNEL_ID,
+                SpentNotification::CDDL_SCHEMA,
+            ),
+            (
+                AllowanceNotification::CHANNEL_ID,
+                AllowanceNotification::CDDL_SCHEMA,
+            ),
+        ] {
+            match from_binary(&channel_schema(channel).unwrap()).unwrap() {
+                QueryAnswer::ChannelSchema {
+                    channel: answer_channel,
+                    mode,
+                    cddl: answer_cddl,
+                    parameters,
+                    data,
+                } => {
+                    assert_eq!(answer_channel, channel);
+                    assert_eq!(mode, "txhash");
+                    assert_eq!(answer_cddl, Some(cddl.to_string()));
+                    assert!(parameters.is_none());
+                    assert!(data.is_none());
+                }
+                other => panic!("Unexpected: {:?}", other),
+            }
+        }
+
+        for (channel, bloom_m, packet_size) in [
+            (
+                MultiRecvdNotification::CHANNEL_ID,
+                MultiRecvdNotification::BLOOM_M,
+                MultiRecvdNotification::PACKET_SIZE,
+            ),
+            (
+                MultiSpentNotification::CHANNEL_ID,
+                MultiSpentNotification::BLOOM_M,
+                MultiSpentNotification::PACKET_SIZE,
+            ),
+        ] {
+            match from_binary(&channel_schema(channel).unwrap()).unwrap() {
+                QueryAnswer::ChannelSchema {
+                    channel: answer_channel,
+                    mode,
+                    cddl,
+                    parameters,
+                    data,
+                } => {
+                    assert_eq!(answer_channel, channel);
+                    assert_eq!(mode, "bloom");
+                    assert!(cddl.is_none());
+                    assert_eq!(parameters.unwrap().m, bloom_m);
+                    assert_eq!(data.unwrap().packet_size, packet_size as u32);
+                }
+                other => panic!("Unexpected: {:?}", other),
+            }
+        }
+
+        let error = extract_error_msg(channel_schema("nonexistent"));
+        assert!(error.contains("`nonexistent` channel is undefined"));
+    }
+
+    #[test]
+    fn test_handle_register_channel() {
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let register_msg = || ExecuteMsg::RegisterChannel {
+            channel: "custom".to_string(),
+            cddl: Some("custom=[amount:biguint]".to_string()),
+            bloom_params: Some(BloomParameters {
+                m: 256,
+                k: 15,
+                h: "sha256".to_string(),
+            }),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+
+        // only the admin can register channels
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            register_msg(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            register_msg(),
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            register_msg(),
+        );
+        assert_eq!(
+            extract_error_msg(handle_result),
+            "`custom` channel already exists"
+        );
+        assert!(CHANNELS.contains(&deps.storage, &"custom".to_string()));
+
+        // the stored schema is served by ChannelInfo
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let channel_info = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let query_msg = QueryMsg::ChannelInfo {
+                channels: vec!["custom".to_string()],
+                txhash: Some("4A3B2C1D".to_string()),
+                viewer: ViewerInfo {
+                    address: "bob".to_string(),
+                    viewing_key: "key".to_string(),
+                },
+            };
+            query(deps.as_ref(), mock_env(), query_msg)
+        };
+        let channels = match from_binary(&channel_info(&deps).unwrap()).unwrap() {
+            QueryAnswer::ChannelInfo { channels, .. } => channels,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(channels[0].channel, "custom");
+        assert_eq!(channels[0].mode, "bloom");
+        assert_eq!(
+            channels[0].cddl,
+            Some("custom=[amount:biguint]".to_string())
+        );
+        assert_eq!(channels[0].parameters.as_ref().unwrap().m, 256);
+        assert!(channels[0].answer_id.is_some());
+
+        // built-in channels cannot be unregistered
+        let handle_msg = ExecuteMsg::UnregisterChannel {
+            channel: RecvdNotification::CHANNEL_ID.to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+        assert_eq!(
+            extract_error_msg(handle_result),
+            "`recvd` is a built-in channel and cannot be unregistered"
+        );
+
+        let handle_msg = ExecuteMsg::UnregisterChannel {
+            channel: "custom".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+        assert!(!CHANNELS.contains(&deps.storage, &"custom".to_string()));
+
+        let error = extract_error_msg(channel_info(&deps));
+        assert!(error.contains("`custom` channel is undefined"));
+    }
+
+    #[test]
+    fn test_query_active_deposit_denoms() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "lebron".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            true,
+            false,
+            false,
+            false,
+            0,
+            vec!["uscrt".to_string(), "uatom".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::ActiveDepositDenoms {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ActiveDepositDenoms { count, denoms } => {
+                assert_eq!(count, 0);
+                assert!(denoms.is_empty());
+            }
+            _ => panic!("unexpected"),
+        }
+
+        for _ in 0..2 {
+            let handle_msg = ExecuteMsg::Deposit {
+                memo: None,
+                callback: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info(
+                "lebron",
+                &[Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint128::new(1000),
+                }],
+            );
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let query_msg = QueryMsg::ActiveDepositDenoms {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ActiveDepositDenoms { count, denoms } => {
+                assert_eq!(count, 1);
+                assert_eq!(denoms, vec!["uscrt".to_string()]);
+            }
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_query_exchange_rate() {
+        // test more dec than SCRT
+        let init_name = "sec-sec".to_string();
+        let init_admin = Addr::unchecked("admin".to_string());
+        let init_symbol = "SECSEC".to_string();
+        let init_decimals = 8;
+
+        let init_supply = Uint128::new(5000);
+
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let info = mock_info("instantiator", &[]);
+        let env = mock_env();
+        let init_config: InitConfig = from_binary(&Binary::from(
+            format!(
+                "{{\"public_total_supply\":{},
+                \"enable_deposit\":{},
+                \"enable_redeem\":{},
+                \"enable_mint\":{},
+                \"enable_burn\":{}}}",
+                true, true, false, false, false
+            )
+            .as_bytes(),
+        ))
+        .unwrap();
+        let init_msg = InstantiateMsg {
+            name: init_name.clone(),
+            admin: Some(init_admin.into_string()),
+            symbol: init_symbol.clone(),
+            decimals: init_decimals.clone(),
+            initial_balances: Some(vec![InitialBalance {
+                address: "giannis".to_string(),
+                amount: init_supply,
+            }]),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: Some(vec!["uscrt".to_string()]),
+        };
+        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::ExchangeRate {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        assert!(
+            query_result.is_ok(),
+            "Init failed: {}",
+            query_result.err().unwrap()
+        );
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::ExchangeRate { rate, denom } => {
+                assert_eq!(rate, Uint128::new(100));
+                assert_eq!(denom, "SCRT");
+            }
+            _ => panic!("unexpected"),
+        }
+
+        // test same number of decimals as SCRT
+        let init_name = "sec-sec".to_string();
+        let init_admin = Addr::unchecked("admin".to_string());
+        let init_symbol = "SECSEC".to_string();
+        let init_decimals = 6;
+
+        let init_supply = Uint128::new(5000);
+
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let info = mock_info("instantiator", &[]);
+        let env = mock_env();
+        let init_config: InitConfig = from_binary(&Binary::from(
+            format!(
+                "{{\"public_total_supply\":{},
+            \"enable_deposit\":{},
+            \"enable_redeem\":{},
+            \"enable_mint\":{},
+            \"enable_burn\":{}}}",
+                true, true, false, false, false
+            )
+            .as_bytes(),
+        ))
+        .unwrap();
+        let init_msg = InstantiateMsg {
+            name: init_name.clone(),
+            admin: Some(init_admin.into_string()),
+            symbol: init_symbol.clone(),
+            decimals: init_decimals.clone(),
+            initial_balances: Some(vec![InitialBalance {
+                address: "giannis".to_string(),
+                amount: init_supply,
+            }]),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: Some(vec!["uscrt".to_string()]),
+        };
+        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::ExchangeRate {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        assert!(
+            query_result.is_ok(),
+            "Init failed: {}",
+            query_result.err().unwrap()
+        );
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::ExchangeRate { rate, denom } => {
+                assert_eq!(rate, Uint128::new(1));
+                assert_eq!(denom, "SCRT");
+            }
+            _ => panic!("unexpected"),
+        }
+
+        // test less decimal places than SCRT
+        let init_name = "sec-sec".to_string();
+        let init_admin = Addr::unchecked("admin".to_string());
+        let init_symbol = "SECSEC".to_string();
+        let init_decimals = 3;
+
+        let init_supply = Uint128::new(5000);
+
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let info = mock_info("instantiator", &[]);
+        let env = mock_env();
+        let init_config: InitConfig = from_binary(&Binary::from(
+            format!(
+                "{{\"public_total_supply\":{},
+            \"enable_deposit\":{},
+            \"enable_redeem\":{},
+            \"enable_mint\":{},
+            \"enable_burn\":{}}}",
+                true, true, false, false, false
+            )
+            .as_bytes(),
+        ))
+        .unwrap();
+        let init_msg = InstantiateMsg {
+            name: init_name.clone(),
+            admin: Some(init_admin.into_string()),
+            symbol: init_symbol.clone(),
+            decimals: init_decimals.clone(),
+            initial_balances: Some(vec![InitialBalance {
+                address: "giannis".to_string(),
+                amount: init_supply,
+            }]),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: Some(init_config),
+            supported_denoms: Some(vec!["uscrt".to_string()]),
+        };
+        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::ExchangeRate {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        assert!(
+            query_result.is_ok(),
+            "Init failed: {}",
+            query_result.err().unwrap()
+        );
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::ExchangeRate { rate, denom } => {
+                assert_eq!(rate, Uint128::new(1000));
+                assert_eq!(denom, "SECSEC");
+            }
+            _ => panic!("unexpected"),
+        }
+
+        // test depost/redeem not enabled
+        let init_name = "sec-sec".to_string();
+        let init_admin = Addr::unchecked("admin".to_string());
+        let init_symbol = "SECSEC".to_string();
+        let init_decimals = 3;
+
+        let init_supply = Uint128::new(5000);
+
+        let mut deps = mock_dependencies_with_balance(&[]);
+        let info = mock_info("instantiator", &[]);
+        let env = mock_env();
+        let init_msg = InstantiateMsg {
+            name: init_name.clone(),
+            admin: Some(init_admin.into_string()),
+            symbol: init_symbol.clone(),
+            decimals: init_decimals.clone(),
+            initial_balances: Some(vec![InitialBalance {
+                address: "giannis".to_string(),
+                amount: init_supply,
+            }]),
+            prng_seed: Binary::from("lolz fun yay".as_bytes()),
+            config: None,
+            supported_denoms: None,
+        };
+        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let query_msg = QueryMsg::ExchangeRate {};
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        assert!(
+            query_result.is_ok(),
+            "Init failed: {}",
+            query_result.err().unwrap()
+        );
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::ExchangeRate { rate, denom } => {
+                assert_eq!(rate, Uint128::new(0));
+                assert_eq!(denom, String::new());
+            }
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_query_exchange_rate_native_decimals() {
+        let exchange_rate = |native_decimals: u8, native_display_denom: Option<&str>| {
+            let display_denom_config = native_display_denom
+                .map(|denom| format!(r#", "native_display_denom": "{denom}""#))
+                .unwrap_or_default();
+            let (init_result, deps) = init_helper_with_raw_config(
+                vec![],
+                &format!(
+                    r#"{{ "enable_deposit": true, "native_decimals": {}{} }}"#,
+                    native_decimals, display_denom_config
+                ),
+            );
+            assert!(
+                init_result.is_ok(),
+                "Init failed: {}",
+                init_result.err().unwrap()
+            );
+
+            let query_result = query(deps.as_ref(), mock_env(), QueryMsg::ExchangeRate {});
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::ExchangeRate { rate, denom } => (rate, denom),
+                _ => panic!("unexpected"),
+            }
+        };
+
+        // the token has 8 decimals
+        assert_eq!(
+            exchange_rate(6, None),
+            (Uint128::new(100), "SCRT".to_string())
+        );
+        // the native side is labeled by the configured display denom, whatever its decimals
+        assert_eq!(
+            exchange_rate(6, Some("ATOM")),
+            (Uint128::new(100), "ATOM".to_string())
+        );
+        assert_eq!(
+            exchange_rate(8, Some("NATIVE")),
+            (Uint128::new(1), "NATIVE".to_string())
+        );
+        assert_eq!(
+            exchange_rate(18, None),
+            (Uint128::new(10_000_000_000), "SECSEC".to_string())
+        );
+
+        let (init_result, _deps) =
+            init_helper_with_raw_config(vec![], r#"{ "native_decimals": 19 }"#);
+        let error = extract_error_msg(init_result);
+        assert!(error.contains("Native decimals must not exceed 18"));
+    }
+
+    #[test]
+    fn test_query_exchange_rate_decimals() {
+        let (init_result, mut deps) =
+            init_helper_with_raw_config(vec![], r#"{ "enable_deposit": true }"#);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mut exchange_rate = |decimals: u8| {
+            CONFIG
+                .update(&mut deps.storage, |mut config| -> StdResult<_> {
+                    config.decimals = decimals;
+                    Ok(config)
+                })
+                .unwrap();
+
+            query(deps.as_ref(), mock_env(), QueryMsg::ExchangeRate {}).map(|result| {
+                match from_binary(&result).unwrap() {
+                    QueryAnswer::ExchangeRate { rate, denom } => (rate, denom),
+                    _ => panic!("unexpected"),
+                }
+            })
+        };
+
+        // a token with no decimals is worth 10^6 uscrt, so the rate is denominated in the token
+        assert_eq!(
+            exchange_rate(0).unwrap(),
+            (Uint128::new(1_000_000), "SECSEC".to_string())
+        );
+        assert_eq!(
+            exchange_rate(6).unwrap(),
+            (Uint128::new(1), "SCRT".to_string())
+        );
+        assert_eq!(
+            exchange_rate(18).unwrap(),
+            (Uint128::new(1_000_000_000_000), "SCRT".to_string())
+        );
+
+        let error = extract_error_msg(exchange_rate(45));
+        assert!(error.contains("decimals too large for exchange rate"));
+    }
+
+    #[test]
+    fn test_query_exchange_rates() {
+        let (init_result, mut deps) =
+            init_helper_with_raw_config(vec![], r#"{ "enable_deposit": true }"#);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mut exchange_rates = |decimals: u8| {
+            CONFIG
+                .update(&mut deps.storage, |mut config| -> StdResult<_> {
+                    config.decimals = decimals;
+                    Ok(config)
+                })
+                .unwrap();
+
+            query(deps.as_ref(), mock_env(), QueryMsg::ExchangeRates {}).map(|result| {
+                match from_binary(&result).unwrap() {
+                    QueryAnswer::ExchangeRates {
+                        token_per_native,
+                        native_per_token,
+                        native_denom,
+                    } => (token_per_native, native_per_token, native_denom),
+                    _ => panic!("unexpected"),
+                }
+            })
+        };
+
+        // token decimals above the native decimals, where a native coin is worth under one token
+        assert_eq!(
+            exchange_rates(8).unwrap(),
+            (Uint128::zero(), Uint128::new(100), "SCRT".to_string())
+        );
+        // equal decimals
+        assert_eq!(
+            exchange_rates(6).unwrap(),
+            (Uint128::new(1), Uint128::new(1), "SCRT".to_string())
+        );
+        // token decimals below the native decimals, where a token is worth under one native coin
+        assert_eq!(
+            exchange_rates(3).unwrap(),
+            (Uint128::new(1000), Uint128::zero(), "SCRT".to_string())
+        );
+        assert_eq!(
+            exchange_rates(0).unwrap(),
+            (Uint128::new(1_000_000), Uint128::zero(), "SCRT".to_string())
+        );
+
+        let error = extract_error_msg(exchange_rates(45));
+        assert!(error.contains("decimals too large for exchange rate"));
+
+        // without deposits or redeems there is no rate
+        let (init_result, deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let query_result = query(deps.as_ref(), mock_env(), QueryMsg::ExchangeRates {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ExchangeRates {
+                token_per_native,
+                native_per_token,
+                native_denom,
+            } => {
+                assert!(token_per_native.is_zero());
+                assert!(native_per_token.is_zero());
+                assert!(native_denom.is_empty());
+            }
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_query_allowance() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "giannis".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "lebron".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let info = mock_info("giannis", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let vk1 = "key1".to_string();
+        let vk2 = "key2".to_string();
+
+        let query_msg = QueryMsg::Allowance {
+            owner: "giannis".to_string(),
+            spender: "lebron".to_string(),
+            key: vk1.clone(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        assert!(
+            query_result.is_ok(),
+            "Query failed: {}",
+            query_result.err().unwrap()
+        );
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Wrong viewing key"));
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: vk1.clone(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("lebron", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let unwrapped_result: ExecuteAnswer =
+            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+        assert_eq!(
+            to_binary(&unwrapped_result).unwrap(),
+            to_binary(&ExecuteAnswer::SetViewingKey {
+                status: ResponseStatus::Success
+            })
+            .unwrap(),
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: vk2.clone(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("giannis", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let unwrapped_result: ExecuteAnswer =
+            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+        assert_eq!(
+            to_binary(&unwrapped_result).unwrap(),
+            to_binary(&ExecuteAnswer::SetViewingKey {
+                status: ResponseStatus::Success
+            })
+            .unwrap(),
+        );
+
+        let query_msg = QueryMsg::Allowance {
+            owner: "giannis".to_string(),
+            spender: "lebron".to_string(),
+            key: vk1.clone(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let allowance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Allowance { allowance, .. } => allowance,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(allowance, Uint128::new(2000));
+
+        let query_msg = QueryMsg::Allowance {
+            owner: "giannis".to_string(),
+            spender: "lebron".to_string(),
+            key: vk2.clone(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let allowance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Allowance { allowance, .. } => allowance,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(allowance, Uint128::new(2000));
+
+        let query_msg = QueryMsg::Allowance {
+            owner: "lebron".to_string(),
+            spender: "giannis".to_string(),
+            key: vk2.clone(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let allowance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Allowance { allowance, .. } => allowance,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(allowance, Uint128::new(0));
+    }
+
+    #[test]
+    fn test_query_allowance_effective_amount() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "giannis".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let expiration = mock_env().block.time.seconds() + 100;
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "lebron".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: Some(expiration),
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("giannis", &[]),
+            handle_msg,
+        );
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_allowance = |env: Env| {
+            let query_msg = QueryMsg::Allowance {
+                owner: "giannis".to_string(),
+                spender: "lebron".to_string(),
+                key: "key".to_string(),
+            };
+            let query_result = query(deps.as_ref(), env, query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::Allowance {
+                    allowance,
+                    effective_amount,
+                    ..
+                } => (allowance, effective_amount),
+                _ => panic!("Unexpected"),
+            }
+        };
+
+        // an active allowance can be spent in full
+        assert_eq!(
+            query_allowance(mock_env()),
+            (Uint128::new(2000), Uint128::new(2000))
+        );
+
+        // once expired, the raw amount is still reported but none of it can be spent
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(expiration);
+        assert_eq!(query_allowance(env), (Uint128::new(2000), Uint128::zero()));
+    }
+
+    #[test]
+    fn test_query_total_allowance_given() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "giannis".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let now = mock_env().block.time.seconds();
+        for (spender, amount, expiration) in [
+            ("lebron", 2000, None),
+            ("kobe", 300, Some(now + 100)),
+            ("jordan", 40, Some(now + 1000)),
+        ] {
+            let handle_msg = ExecuteMsg::IncreaseAllowance {
+                spender: spender.to_string(),
+                amount: Uint128::new(amount),
+                padding: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                expiration,
+                notify_self: None,
+                grace_seconds: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("giannis", &[]),
+                handle_msg,
+            );
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("giannis", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_totals = |env: Env| {
+            let query_msg = QueryMsg::TotalAllowanceGiven {
+                owner: "giannis".to_string(),
+                key: "key".to_string(),
+            };
+            let query_result = query(deps.as_ref(), env, query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::TotalAllowanceGiven {
+                    total,
+                    active_total,
+                    count,
+                } => (total.u128(), active_total.u128(), count),
+                _ => panic!("Unexpected"),
+            }
+        };
+
+        assert_eq!(query_totals(mock_env()), (2340, 2340, 3));
+
+        // kobe's allowance has expired
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(now + 100);
+        assert_eq!(query_totals(env), (2340, 2040, 3));
+
+        // both expiring allowances have expired
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(now + 1000);
+        assert_eq!(query_totals(env), (2340, 2000, 3));
+
+        let query_msg = QueryMsg::TotalAllowanceGiven {
+            owner: "giannis".to_string(),
+            key: "wrong".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Wrong viewing key"));
+    }
+
+    #[test]
+    fn test_query_allowance_key_of_second_address() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "giannis".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "lebron".to_string(),
+            amount: Uint128::new(2000),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("giannis", &[]),
+            handle_msg,
+        );
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // only the spender, the second address checked, has a viewing key
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "lebron_key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lebron", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_msg = QueryMsg::Allowance {
+            owner: "giannis".to_string(),
+            spender: "lebron".to_string(),
+            key: "lebron_key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let allowance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Allowance { allowance, .. } => allowance,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(allowance, Uint128::new(2000));
+
+        let query_msg = QueryMsg::Allowance {
+            owner: "giannis".to_string(),
+            spender: "lebron".to_string(),
+            key: "wrong_key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Wrong viewing key"));
+    }
+
+    #[test]
+    fn test_query_public_allowances_given() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".to_string(),
+            amount: Uint128::new(50),
+            padding: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            expiration: None,
+            notify_self: None,
+            grace_seconds: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_msg = QueryMsg::PublicAllowancesGiven {
+            owner: "bob".to_string(),
+            page: None,
+            page_size: 10,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg.clone());
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Allowances given by bob are not public"));
+
+        let handle_msg = ExecuteMsg::SetAllowancesPublic {
+            public: true,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_result = query(deps.as_ref(), mock_env(), query_msg.clone());
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesGiven {
+                owner,
+                allowances,
+                count,
+            } => {
+                assert_eq!(owner, "bob".to_string());
+                assert_eq!(count, 1);
+                assert_eq!(allowances[0].spender, "alice".to_string());
+                assert_eq!(allowances[0].allowance, Uint128::new(50));
+            }
+            _ => panic!("Unexpected"),
+        };
+
+        let handle_msg = ExecuteMsg::SetAllowancesPublic {
+            public: false,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("not public"));
+    }
+
+    #[test]
+    fn test_query_account_policies() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msgs = vec![
+            ExecuteMsg::SetViewingKey {
+                key: "key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            },
+            ExecuteMsg::SetChannelPreference {
+                channel: "spent".to_string(),
+                enabled: false,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            },
+            ExecuteMsg::SetAllowancesPublic {
+                public: true,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            },
+        ];
+        for handle_msg in handle_msgs {
+            let info = mock_info("bob", &[]);
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let query_msg = QueryMsg::AccountPolicies {
+            address: "bob".to_string(),
+            key: "wrong_key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Wrong viewing key"));
+
+        let query_msg = QueryMsg::AccountPolicies {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let policies = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AccountPolicies { policies } => policies,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(policies.preferred_redeem_denom, None);
+        assert_eq!(policies.disabled_channels, vec!["spent".to_string()]);
+        assert!(policies.allowances_public);
+        assert_eq!(policies.permit_epoch, 0);
+        assert_eq!(policies.memo_decryption_key, None);
+        assert_eq!(policies.receiver_code_hash, None);
+    }
+
+    #[test]
+    fn test_query_all_allowances() {
+        let num_owners = 3;
+        let num_spenders = 20;
+        let vk = "key".to_string();
+
+        let initial_balances: Vec<InitialBalance> = (0..num_owners)
+            .into_iter()
+            .map(|i| InitialBalance {
+                address: format!("owner{}", i),
+                amount: Uint128::new(5000),
+            })
+            .collect();
+        let (init_result, mut deps) = init_helper(initial_balances);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        for i in 0..num_owners {
+            let handle_msg = ExecuteMsg::SetViewingKey {
+                key: vk.clone(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let info = mock_info(format!("owner{}", i).as_str(), &[]);
+
+            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+            let unwrapped_result: ExecuteAnswer =
+                from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+            assert_eq!(
+                to_binary(&unwrapped_result).unwrap(),
+                to_binary(&ExecuteAnswer::SetViewingKey {
+                    status: ResponseStatus::Success
+                })
+                .unwrap(),
+            );
+        }
+
+        for i in 0..num_owners {
+            for j in 0..num_spenders {
+                let handle_msg = ExecuteMsg::IncreaseAllowance {
+                    spender: format!("spender{}", j),
+                    amount: Uint128::new(50),
+                    padding: None,
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    expiration: None,
+                    notify_self: None,
+                    grace_seconds: None,
+                };
+                let info = mock_info(format!("owner{}", i).as_str(), &[]);
+
+                let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+                assert!(
+                    handle_result.is_ok(),
+                    "handle() failed: {}",
+                    handle_result.err().unwrap()
+                );
+
+                let handle_msg = ExecuteMsg::SetViewingKey {
+                    key: vk.clone(),
+                    #[cfg(feature = "gas_evaporation")]
+                    gas_target: None,
+                    padding: None,
+                };
+                let info = mock_info(format!("spender{}", j).as_str(), &[]);
+
+                let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+                let unwrapped_result: ExecuteAnswer =
+                    from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+                assert_eq!(
+                    to_binary(&unwrapped_result).unwrap(),
+                    to_binary(&ExecuteAnswer::SetViewingKey {
+                        status: ResponseStatus::Success
+                    })
+                    .unwrap(),
+                );
+            }
+        }
+
+        let query_msg = QueryMsg::AllowancesGiven {
+            owner: "owner0".to_string(),
+            key: vk.clone(),
+            page: None,
+            page_size: 5,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesGiven {
+                owner,
+                allowances,
+                count,
+            } => {
+                assert_eq!(owner, "owner0".to_string());
+                assert_eq!(allowances.len(), 5);
+                assert_eq!(allowances[0].spender, "spender0");
+                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
+                assert_eq!(allowances[0].expiration, None);
+                assert_eq!(count, num_spenders);
+            }
+            _ => panic!("Unexpected"),
+        };
+
+        let query_msg = QueryMsg::AllowancesGiven {
+            owner: "owner1".to_string(),
+            key: vk.clone(),
+            page: Some(1),
+            page_size: 5,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesGiven {
+                owner,
+                allowances,
+                count,
+            } => {
+                assert_eq!(owner, "owner1".to_string());
+                assert_eq!(allowances.len(), 5);
+                assert_eq!(allowances[0].spender, "spender5");
+                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
+                assert_eq!(allowances[0].expiration, None);
+                assert_eq!(count, num_spenders);
+            }
+            _ => panic!("Unexpected"),
+        };
+
+        let query_msg = QueryMsg::AllowancesGiven {
+            owner: "owner1".to_string(),
+            key: vk.clone(),
+            page: Some(0),
+            page_size: 23,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesGiven {
+                owner,
+                allowances,
+                count,
+            } => {
+                assert_eq!(owner, "owner1".to_string());
+                assert_eq!(allowances.len(), 20);
+                assert_eq!(count, num_spenders);
+            }
+            _ => panic!("Unexpected"),
+        };
+
+        let query_msg = QueryMsg::AllowancesGiven {
+            owner: "owner1".to_string(),
+            key: vk.clone(),
+            page: Some(2),
+            page_size: 8,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesGiven {
+                owner,
+                allowances,
+                count,
+            } => {
+                assert_eq!(owner, "owner1".to_string());
+                assert_eq!(allowances.len(), 4);
+                assert_eq!(count, num_spenders);
+            }
+            _ => panic!("Unexpected"),
+        };
+
+        let query_msg = QueryMsg::AllowancesGiven {
+            owner: "owner2".to_string(),
+            key: vk.clone(),
+            page: Some(5),
+            page_size: 5,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesGiven {
+                owner,
+                allowances,
+                count,
+            } => {
+                assert_eq!(owner, "owner2".to_string());
+                assert_eq!(allowances.len(), 0);
+                assert_eq!(count, num_spenders);
+            }
+            _ => panic!("Unexpected"),
+        };
+
+        let query_msg = QueryMsg::AllowancesReceived {
+            spender: "spender0".to_string(),
+            key: vk.clone(),
+            page: None,
+            page_size: 10,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesReceived {
+                spender,
+                allowances,
+                count,
+            } => {
+                assert_eq!(spender, "spender0".to_string());
+                assert_eq!(allowances.len(), 3);
+                assert_eq!(allowances[0].owner, "owner0");
+                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
+                assert_eq!(allowances[0].expiration, None);
+                assert_eq!(count, num_owners);
+            }
+            _ => panic!("Unexpected"),
+        };
+
+        let query_msg = QueryMsg::AllowancesReceived {
+            spender: "spender1".to_string(),
+            key: vk.clone(),
+            page: Some(1),
+            page_size: 1,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::AllowancesReceived {
+                spender,
+                allowances,
+                count,
+            } => {
+                assert_eq!(spender, "spender1".to_string());
+                assert_eq!(allowances.len(), 1);
+                assert_eq!(allowances[0].owner, "owner1");
+                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
+                assert_eq!(allowances[0].expiration, None);
+                assert_eq!(count, num_owners);
+            }
+            _ => panic!("Unexpected"),
+        };
+    }
+
+    #[test]
+    fn test_query_balance() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(5000),
+        }]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let unwrapped_result: ExecuteAnswer =
+            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
+        assert_eq!(
+            to_binary(&unwrapped_result).unwrap(),
+            to_binary(&ExecuteAnswer::SetViewingKey {
+                status: ResponseStatus::Success
+            })
+            .unwrap(),
+        );
+
+        let query_msg = QueryMsg::Balance {
+            address: "bob".to_string(),
+            key: "wrong_key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Wrong viewing key"));
+
+        let query_msg = QueryMsg::Balance {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let balance = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::Balance { amount, .. } => amount,
+            _ => panic!("Unexpected"),
+        };
+        assert_eq!(balance, Uint128::new(5000));
+    }
+
+    #[test]
+    fn test_query_balance_at() {
+        let (init_result, mut deps) = init_helper_with_raw_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            r#"{ "snapshot_enabled": true }"#,
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        for account in ["bob", "alice"] {
+            let handle_msg = ExecuteMsg::SetViewingKey {
+                key: "key".to_string(),
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(account, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        // two transfers land in the same block at the last height
+        for (height, amount) in [(12350, 1000), (12360, 500), (12360, 200)] {
+            let mut env = mock_env();
+            env.block.height = height;
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: "alice".to_string(),
+                amount: Uint128::new(amount),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(deps.as_mut(), env, mock_info("bob", &[]), handle_msg);
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let balance_at =
+            |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, address: &str, height: u64| {
+                let query_msg = QueryMsg::BalanceAt {
+                    address: address.to_string(),
+                    key: "key".to_string(),
+                    height,
+                };
+                let query_result = query(deps.as_ref(), mock_env(), query_msg);
+                match from_binary(&query_result.unwrap()).unwrap() {
+                    QueryAnswer::BalanceAt { amount, .. } => amount.u128(),
+                    other => panic!("Unexpected: {:?}", other),
+                }
+            };
+
+        assert_eq!(balance_at(&deps, "bob", 12344), 0);
+        assert_eq!(balance_at(&deps, "bob", 12345), 5000);
+        assert_eq!(balance_at(&deps, "bob", 12349), 5000);
+        assert_eq!(balance_at(&deps, "bob", 12350), 4000);
+        assert_eq!(balance_at(&deps, "bob", 12359), 4000);
+        assert_eq!(balance_at(&deps, "bob", 12360), 3300);
+        assert_eq!(balance_at(&deps, "bob", 20000), 3300);
+        assert_eq!(balance_at(&deps, "alice", 12349), 0);
+        assert_eq!(balance_at(&deps, "alice", 12350), 1000);
+        assert_eq!(balance_at(&deps, "alice", 12360), 1700);
+
+        // snapshots are opt-in
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(init_result.is_ok());
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_msg = QueryMsg::BalanceAt {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+            height: 12345,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let error = extract_error_msg(query_result);
+        assert!(error.contains("Balance snapshots are not enabled for this token"));
+    }
+
+    #[test]
+    fn test_query_transaction_history() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(10000),
+            }],
+            true,
+            true,
+            true,
+            true,
+            1000,
+            vec!["uscrt".to_string()],
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Burn {
+            amount: Uint128::new(1),
+            memo: Some("my burn message".to_string()),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "Pause handle failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::Redeem {
+            amount: Uint128::new(1000),
+            denom: Option::from("uscrt".to_string()),
+            memo: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: Some("my mint message".to_string()),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("admin", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let handle_msg = ExecuteMsg::Deposit {
+            memo: None,
+            callback: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info(
+            "bob",
+            &[Coin {
+                denom: "uscrt".to_string(),
+                amount: Uint128::new(1000),
+            }],
+        );
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(1000),
+            memo: Some("my transfer message #1".to_string()),
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
 
-        let init_supply = Uint128::new(5000);
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
 
-        let mut deps = mock_dependencies_with_balance(&[]);
-        let info = mock_info("instantiator", &[]);
-        let env = mock_env();
-        let init_msg = InstantiateMsg {
-            name: init_name.clone(),
-            admin: Some(init_admin.into_string()),
-            symbol: init_symbol.clone(),
-            decimals: init_decimals.clone(),
-            initial_balances: Some(vec![InitialBalance {
-                address: "giannis".to_string(),
-                amount: init_supply,
-            }]),
-            prng_seed: Binary::from("lolz fun yay".as_bytes()),
-            config: None,
-            supported_denoms: None,
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result));
+
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "banana".to_string(),
+            amount: Uint128::new(500),
+            memo: Some("my transfer message #2".to_string()),
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
         };
-        let init_result = instantiate(deps.as_mut(), env, info, init_msg);
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result));
+
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "mango".to_string(),
+            amount: Uint128::new(2500),
+            memo: Some("my transfer message #3".to_string()),
+            memo_encrypted: None,
+            want_receipt: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let info = mock_info("bob", &[]);
+
+        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+
+        let result = handle_result.unwrap();
+        assert!(ensure_success(result));
+
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let transfers = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, .. } => txs,
+            other => panic!("Unexpected: {:?}", other),
+        };
+
+        use crate::transaction_history::TxAction;
+        let expected_transfers = [
+            Tx {
+                id: 8735437960206903,
+                action: TxAction::Transfer {
+                    from: Addr::unchecked("bob".to_string()),
+                    sender: Addr::unchecked("bob".to_string()),
+                    recipient: Addr::unchecked("mango".to_string()),
+                },
+                coins: Coin {
+                    denom: "SECSEC".to_string(),
+                    amount: Uint128::new(2500),
+                },
+                memo: Some("my transfer message #3".to_string()),
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+            Tx {
+                id: 6519057655056815,
+                action: TxAction::Transfer {
+                    from: Addr::unchecked("bob".to_string()),
+                    sender: Addr::unchecked("bob".to_string()),
+                    recipient: Addr::unchecked("banana".to_string()),
+                },
+                coins: Coin {
+                    denom: "SECSEC".to_string(),
+                    amount: Uint128::new(500),
+                },
+                memo: Some("my transfer message #2".to_string()),
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+            Tx {
+                id: 2105964828411645,
+                action: TxAction::Transfer {
+                    from: Addr::unchecked("bob".to_string()),
+                    sender: Addr::unchecked("bob".to_string()),
+                    recipient: Addr::unchecked("alice".to_string()),
+                },
+                coins: Coin {
+                    denom: "SECSEC".to_string(),
+                    amount: Uint128::new(1000),
+                },
+                memo: Some("my transfer message #1".to_string()),
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+            Tx {
+                id: 7517649082682890,
+                action: TxAction::Deposit {},
+                coins: Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint128::new(1000),
+                },
+                memo: None,
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+            Tx {
+                id: 5298675660782133,
+                action: TxAction::Mint {
+                    minter: Addr::unchecked("admin".to_string()),
+                    recipient: Addr::unchecked("bob".to_string()),
+                },
+                coins: Coin {
+                    denom: "SECSEC".to_string(),
+                    amount: Uint128::new(100),
+                },
+                memo: Some("my mint message".to_string()),
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+            Tx {
+                id: 3863562430182029,
+                action: TxAction::Redeem {},
+                coins: Coin {
+                    denom: "SECSEC".to_string(),
+                    amount: Uint128::new(1000),
+                },
+                memo: None,
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+            Tx {
+                id: 3942814133456943,
+                action: TxAction::Burn {
+                    burner: Addr::unchecked("bob".to_string()),
+                    owner: Addr::unchecked("bob".to_string()),
+                },
+                coins: Coin {
+                    denom: "SECSEC".to_string(),
+                    amount: Uint128::new(1),
+                },
+                memo: Some("my burn message".to_string()),
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+            Tx {
+                id: 5746099005188254,
+                action: TxAction::Mint {
+                    minter: Addr::unchecked("admin".to_string()),
+                    recipient: Addr::unchecked("bob".to_string()),
+                },
+                coins: Coin {
+                    denom: "SECSEC".to_string(),
+                    amount: Uint128::new(10000),
+                },
+
+                memo: Some("Initial Balance".to_string()),
+                memo_encrypted: false,
+                block_time: 1571797419,
+                block_height: 12345,
+            },
+        ];
+
+        assert_eq!(transfers, expected_transfers);
+    }
+
+    #[test]
+    fn test_query_transaction_history_memo_encrypted() {
+        let (init_result, mut deps) = init_helper(vec![InitialBalance {
+            address: "bob".to_string(),
+            amount: Uint128::new(10000),
+        }]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let query_msg = QueryMsg::ExchangeRate {};
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        assert!(
-            query_result.is_ok(),
-            "Init failed: {}",
-            query_result.err().unwrap()
-        );
-        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
-        match query_answer {
-            QueryAnswer::ExchangeRate { rate, denom } => {
-                assert_eq!(rate, Uint128::new(0));
-                assert_eq!(denom, String::new());
-            }
-            _ => panic!("unexpected"),
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let transfers = [
+            (Some("ciphertext".to_string()), Some(true)),
+            (Some("plaintext".to_string()), None),
+            // there is no memo to decrypt, so the flag is not recorded
+            (None, Some(true)),
+        ];
+        for (memo, memo_encrypted) in transfers {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: "alice".to_string(),
+                amount: Uint128::new(100),
+                memo,
+                memo_encrypted,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result =
+                execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+            assert!(ensure_success(handle_result.unwrap()));
         }
+
+        let handle_msg = ExecuteMsg::Send {
+            recipient: "alice".to_string(),
+            recipient_code_hash: None,
+            amount: Uint128::new(100),
+            msg: None,
+            memo: Some("sent ciphertext".to_string()),
+            memo_encrypted: Some(true),
+            want_receipt: None,
+            reply_on_error: None,
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
+
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: Some(TxActionFilter::Transfer),
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let txs = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, .. } => txs,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        let flags = txs
+            .into_iter()
+            .map(|tx| (tx.memo, tx.memo_encrypted))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            flags,
+            vec![
+                (Some("sent ciphertext".to_string()), true),
+                (None, false),
+                (Some("plaintext".to_string()), false),
+                (Some("ciphertext".to_string()), true),
+            ]
+        );
+
+        // txs serialized before the flag existed read back as unencrypted
+        let tx: Tx = from_binary(&Binary::from(
+            r#"{
+                "id": 1,
+                "action": { "deposit": {} },
+                "coins": { "denom": "uscrt", "amount": "1" },
+                "memo": "legacy",
+                "block_time": 0,
+                "block_height": 0
+            }"#
+            .as_bytes(),
+        ))
+        .unwrap();
+        assert!(!tx.memo_encrypted);
     }
 
     #[test]
-    fn test_query_allowance() {
-        let (init_result, mut deps) = init_helper(vec![InitialBalance {
-            address: "giannis".to_string(),
-            amount: Uint128::new(5000),
-        }]);
+    fn test_query_transaction_history_symbol_rename() {
+        let (init_result, mut deps) = init_helper_with_config(
+            vec![InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(5000),
+            }],
+            true,
+            false,
+            false,
+            false,
+            0,
+            vec!["uscrt".to_string()],
+        );
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
 
-        let handle_msg = ExecuteMsg::IncreaseAllowance {
-            spender: "lebron".to_string(),
-            amount: Uint128::new(2000),
-            padding: None,
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
-            expiration: None,
-        };
-        let info = mock_info("giannis", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-
-        let vk1 = "key1".to_string();
-        let vk2 = "key2".to_string();
-
-        let query_msg = QueryMsg::Allowance {
-            owner: "giannis".to_string(),
-            spender: "lebron".to_string(),
-            key: vk1.clone(),
+            padding: None,
         };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        assert!(
-            query_result.is_ok(),
-            "Query failed: {}",
-            query_result.err().unwrap()
-        );
-        let error = extract_error_msg(query_result);
-        assert!(error.contains("Wrong viewing key"));
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_msg = ExecuteMsg::SetViewingKey {
-            key: vk1.clone(),
+        let transfer = ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("lebron", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let unwrapped_result: ExecuteAnswer =
-            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-        assert_eq!(
-            to_binary(&unwrapped_result).unwrap(),
-            to_binary(&ExecuteAnswer::SetViewingKey {
-                status: ResponseStatus::Success
-            })
-            .unwrap(),
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            transfer.clone(),
         );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_msg = ExecuteMsg::SetViewingKey {
-            key: vk2.clone(),
+        let handle_msg = ExecuteMsg::Deposit {
+            memo: None,
+            callback: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("giannis", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let unwrapped_result: ExecuteAnswer =
-            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-        assert_eq!(
-            to_binary(&unwrapped_result).unwrap(),
-            to_binary(&ExecuteAnswer::SetViewingKey {
-                status: ResponseStatus::Success
-            })
-            .unwrap(),
+        let funds = vec![Coin {
+            denom: "uscrt".to_string(),
+            amount: Uint128::new(1000),
+        }];
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &funds),
+            handle_msg,
         );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let query_msg = QueryMsg::Allowance {
-            owner: "giannis".to_string(),
-            spender: "lebron".to_string(),
-            key: vk1.clone(),
-        };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        let allowance = match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::Allowance { allowance, .. } => allowance,
-            _ => panic!("Unexpected"),
-        };
-        assert_eq!(allowance, Uint128::new(2000));
+        // the stored denom doesn't depend on the symbol
+        let mut config = CONFIG.load(&deps.storage).unwrap();
+        config.symbol = "NEWSEC".to_string();
+        CONFIG.save(&mut deps.storage, &config).unwrap();
 
-        let query_msg = QueryMsg::Allowance {
-            owner: "giannis".to_string(),
-            spender: "lebron".to_string(),
-            key: vk2.clone(),
-        };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        let allowance = match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::Allowance { allowance, .. } => allowance,
-            _ => panic!("Unexpected"),
-        };
-        assert_eq!(allowance, Uint128::new(2000));
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), transfer);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let query_msg = QueryMsg::Allowance {
-            owner: "lebron".to_string(),
-            spender: "giannis".to_string(),
-            key: vk2.clone(),
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
         };
         let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        let allowance = match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::Allowance { allowance, .. } => allowance,
-            _ => panic!("Unexpected"),
+        let txs = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, .. } => txs,
+            other => panic!("Unexpected: {:?}", other),
         };
-        assert_eq!(allowance, Uint128::new(0));
+        let denoms = txs.into_iter().map(|tx| tx.coins.denom).collect::<Vec<_>>();
+        assert_eq!(denoms, vec!["NEWSEC", "uscrt", "NEWSEC", "NEWSEC"]);
     }
 
     #[test]
-    fn test_query_all_allowances() {
-        let num_owners = 3;
-        let num_spenders = 20;
-        let vk = "key".to_string();
-
-        let initial_balances: Vec<InitialBalance> = (0..num_owners)
-            .into_iter()
-            .map(|i| InitialBalance {
-                address: format!("owner{}", i),
-                amount: Uint128::new(5000),
-            })
-            .collect();
-        let (init_result, mut deps) = init_helper(initial_balances);
+    fn test_query_transaction_history_ascending() {
+        let (init_result, mut deps) = init_helper(vec![
+            InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(10000),
+            },
+            InitialBalance {
+                address: "alice".to_string(),
+                amount: Uint128::new(1000),
+            },
+        ]);
         assert!(
             init_result.is_ok(),
             "Init failed: {}",
             init_result.err().unwrap()
         );
-        for i in 0..num_owners {
-            let handle_msg = ExecuteMsg::SetViewingKey {
-                key: vk.clone(),
-                #[cfg(feature = "gas_evaporation")]
-                gas_target: None,
-                padding: None,
-            };
-            let info = mock_info(format!("owner{}", i).as_str(), &[]);
-
-            let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-            let unwrapped_result: ExecuteAnswer =
-                from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-            assert_eq!(
-                to_binary(&unwrapped_result).unwrap(),
-                to_binary(&ExecuteAnswer::SetViewingKey {
-                    status: ResponseStatus::Success
-                })
-                .unwrap(),
-            );
-        }
-
-        for i in 0..num_owners {
-            for j in 0..num_spenders {
-                let handle_msg = ExecuteMsg::IncreaseAllowance {
-                    spender: format!("spender{}", j),
-                    amount: Uint128::new(50),
-                    padding: None,
-                    #[cfg(feature = "gas_evaporation")]
-                    gas_target: None,
-                    expiration: None,
-                };
-                let info = mock_info(format!("owner{}", i).as_str(), &[]);
-
-                let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-                assert!(
-                    handle_result.is_ok(),
-                    "handle() failed: {}",
-                    handle_result.err().unwrap()
-                );
-
-                let handle_msg = ExecuteMsg::SetViewingKey {
-                    key: vk.clone(),
-                    #[cfg(feature = "gas_evaporation")]
-                    gas_target: None,
-                    padding: None,
-                };
-                let info = mock_info(format!("spender{}", j).as_str(), &[]);
 
-                let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-                let unwrapped_result: ExecuteAnswer =
-                    from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-                assert_eq!(
-                    to_binary(&unwrapped_result).unwrap(),
-                    to_binary(&ExecuteAnswer::SetViewingKey {
-                        status: ResponseStatus::Success
-                    })
-                    .unwrap(),
-                );
-            }
-        }
-
-        let query_msg = QueryMsg::AllowancesGiven {
-            owner: "owner0".to_string(),
-            key: vk.clone(),
-            page: None,
-            page_size: 5,
-        };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::AllowancesGiven {
-                owner,
-                allowances,
-                count,
-            } => {
-                assert_eq!(owner, "owner0".to_string());
-                assert_eq!(allowances.len(), 5);
-                assert_eq!(allowances[0].spender, "spender0");
-                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
-                assert_eq!(allowances[0].expiration, None);
-                assert_eq!(count, num_spenders);
-            }
-            _ => panic!("Unexpected"),
-        };
-
-        let query_msg = QueryMsg::AllowancesGiven {
-            owner: "owner1".to_string(),
-            key: vk.clone(),
-            page: Some(1),
-            page_size: 5,
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
         };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::AllowancesGiven {
-                owner,
-                allowances,
-                count,
-            } => {
-                assert_eq!(owner, "owner1".to_string());
-                assert_eq!(allowances.len(), 5);
-                assert_eq!(allowances[0].spender, "spender5");
-                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
-                assert_eq!(allowances[0].expiration, None);
-                assert_eq!(count, num_spenders);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // alice's sends settle her buffered receipts, so her history spans several settled
+        // bundles as well as the buffer
+        let transfers = [
+            ("bob", "alice"),
+            ("bob", "alice"),
+            ("bob", "alice"),
+            ("alice", "carol"),
+            ("bob", "alice"),
+            ("bob", "alice"),
+            ("alice", "dave"),
+            ("bob", "alice"),
+            ("bob", "alice"),
+        ];
+        for (i, (sender, recipient)) in transfers.into_iter().enumerate() {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(i as u128 + 1),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(sender, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let history_page = |page: u32,
+                            page_size: u32,
+                            action_filter: Option<TxActionFilter>,
+                            order: Option<Order>| {
+            let query_msg = QueryMsg::TransactionHistory {
+                address: "alice".to_string(),
+                key: "key".to_string(),
+                page: Some(page),
+                page_size,
+                action_filter,
+                order,
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::TransactionHistory { txs, total, .. } => (
+                    txs.into_iter().map(|tx| tx.id).collect::<Vec<u64>>(),
+                    total.unwrap(),
+                ),
+                other => panic!("Unexpected: {:?}", other),
             }
-            _ => panic!("Unexpected"),
         };
 
-        let query_msg = QueryMsg::AllowancesGiven {
-            owner: "owner1".to_string(),
-            key: vk.clone(),
-            page: Some(0),
-            page_size: 23,
-        };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::AllowancesGiven {
-                owner,
-                allowances,
-                count,
-            } => {
-                assert_eq!(owner, "owner1".to_string());
-                assert_eq!(allowances.len(), 20);
-                assert_eq!(count, num_spenders);
+        // the mint of the initial balance plus every transfer
+        let (descending, total) = history_page(0, 100, None, None);
+        assert_eq!(total, 10);
+        assert_eq!(descending.len(), 10);
+
+        let (ascending, ascending_total) = history_page(0, 100, None, Some(Order::Ascending));
+        assert_eq!(ascending_total, total);
+        let mut reversed = descending.clone();
+        reversed.reverse();
+        assert_eq!(ascending, reversed);
+
+        // pages of every size mirror the descending order, whichever of the buffer and settled
+        // bundles they start and end in
+        for page_size in 1..=10u32 {
+            for page in 0..=(10 / page_size) {
+                let (txs, page_total) = history_page(page, page_size, None, Some(Order::Ascending));
+                assert_eq!(page_total, total);
+
+                let page_start = ((page * page_size) as usize).min(10);
+                let page_end = ((page * page_size + page_size) as usize).min(10);
+                assert_eq!(
+                    txs,
+                    reversed[page_start..page_end].to_vec(),
+                    "page {page} of size {page_size}"
+                );
+
+                let (txs, _) = history_page(page, page_size, None, Some(Order::Descending));
+                assert_eq!(txs, descending[page_start..page_end].to_vec());
             }
-            _ => panic!("Unexpected"),
-        };
+        }
 
-        let query_msg = QueryMsg::AllowancesGiven {
-            owner: "owner1".to_string(),
-            key: vk.clone(),
-            page: Some(2),
-            page_size: 8,
+        // filtered pages are mirrored the same way, leaving out the mint
+        let (descending, _) = history_page(0, 100, Some(TxActionFilter::Transfer), None);
+        assert_eq!(descending.len(), 9);
+        let (txs, _) = history_page(1, 4, Some(TxActionFilter::Transfer), Some(Order::Ascending));
+        assert_eq!(
+            txs,
+            vec![descending[4], descending[3], descending[2], descending[1]]
+        );
+    }
+
+    #[test]
+    fn test_query_transaction_history_page_boundaries() {
+        let (init_result, mut deps) = init_helper(vec![
+            InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(10000),
+            },
+            InitialBalance {
+                address: "alice".to_string(),
+                amount: Uint128::new(1000),
+            },
+        ]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
         };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::AllowancesGiven {
-                owner,
-                allowances,
-                count,
-            } => {
-                assert_eq!(owner, "owner1".to_string());
-                assert_eq!(allowances.len(), 4);
-                assert_eq!(count, num_spenders);
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
+        // alice's send settles her earlier receipts, and the receipts after it stay buffered
+        let transfers = [
+            ("bob", "alice"),
+            ("bob", "alice"),
+            ("alice", "carol"),
+            ("bob", "alice"),
+            ("bob", "alice"),
+        ];
+        for (i, (sender, recipient)) in transfers.into_iter().enumerate() {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(i as u128 + 1),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(sender, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let history_page = |page: u32, page_size: u32, order: Option<Order>| {
+            let query_msg = QueryMsg::TransactionHistory {
+                address: "alice".to_string(),
+                key: "key".to_string(),
+                page: Some(page),
+                page_size,
+                action_filter: None,
+                order,
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::TransactionHistory { txs, total, .. } => (
+                    txs.into_iter().map(|tx| tx.id).collect::<Vec<u64>>(),
+                    total.unwrap(),
+                ),
+                other => panic!("Unexpected: {:?}", other),
             }
-            _ => panic!("Unexpected"),
         };
 
-        let query_msg = QueryMsg::AllowancesGiven {
-            owner: "owner2".to_string(),
-            key: vk.clone(),
-            page: Some(5),
-            page_size: 5,
-        };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::AllowancesGiven {
-                owner,
-                allowances,
-                count,
-            } => {
-                assert_eq!(owner, "owner2".to_string());
-                assert_eq!(allowances.len(), 0);
-                assert_eq!(count, num_spenders);
-            }
-            _ => panic!("Unexpected"),
+        // the mint of the initial balance plus every transfer
+        let (descending, total) = history_page(0, 100, None);
+        assert_eq!(total, 6);
+        assert_eq!(descending.len(), 6);
+
+        let alice_raw = deps.api.addr_canonicalize("alice").unwrap();
+        let dwb = DWB.load(&deps.storage).unwrap();
+        let txs_in_dwb = dwb.entries[dwb.recipient_match(&alice_raw)]
+            .list_len()
+            .unwrap() as usize;
+        assert_eq!(txs_in_dwb, 2);
+
+        // a page starting exactly on the boundary between the buffer and the settled txs begins
+        // with the newest settled tx
+        let (txs, page_total) = history_page(1, txs_in_dwb as u32, None);
+        assert_eq!(page_total, total);
+        assert_eq!(txs, descending[txs_in_dwb..txs_in_dwb * 2].to_vec());
+
+        // a page starting exactly at the end of the settled history is empty
+        let (txs, page_total) = history_page(2, 3, None);
+        assert_eq!(page_total, total);
+        assert!(txs.is_empty());
+        let (txs, _) = history_page(2, 3, Some(Order::Ascending));
+        assert!(txs.is_empty());
+
+        // a page ending past the end of the history is clamped
+        let (txs, _) = history_page(1, 4, None);
+        assert_eq!(txs, descending[4..].to_vec());
+
+        // pages starting well past the end are empty rather than wrapping around
+        for order in [None, Some(Order::Ascending)] {
+            let (txs, page_total) = history_page(5, 5, order);
+            assert_eq!(page_total, total);
+            assert!(txs.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_query_transactions_with() {
+        let (init_result, mut deps) = init_helper(vec![
+            InitialBalance {
+                address: "bob".to_string(),
+                amount: Uint128::new(10000),
+            },
+            InitialBalance {
+                address: "carol".to_string(),
+                amount: Uint128::new(1000),
+            },
+        ]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
         };
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let query_msg = QueryMsg::AllowancesReceived {
-            spender: "spender0".to_string(),
-            key: vk.clone(),
+        let transfers = [
+            ("bob", "alice", 1),
+            ("bob", "carol", 2),
+            ("bob", "alice", 3),
+            ("bob", "dave", 4),
+            ("bob", "alice", 5),
+            ("carol", "bob", 6),
+        ];
+        for (sender, recipient, amount) in transfers {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(amount),
+                memo: None,
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(sender, &[]),
+                handle_msg,
+            );
+            assert!(ensure_success(handle_result.unwrap()));
+        }
+
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "bob".to_string(),
+            key: "key".to_string(),
             page: None,
             page_size: 10,
+            action_filter: None,
+            order: None,
         };
         let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::AllowancesReceived {
-                spender,
-                allowances,
-                count,
-            } => {
-                assert_eq!(spender, "spender0".to_string());
-                assert_eq!(allowances.len(), 3);
-                assert_eq!(allowances[0].owner, "owner0");
-                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
-                assert_eq!(allowances[0].expiration, None);
-                assert_eq!(count, num_owners);
-            }
-            _ => panic!("Unexpected"),
+        let history_total = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { total, .. } => total.unwrap(),
+            other => panic!("Unexpected: {:?}", other),
         };
 
-        let query_msg = QueryMsg::AllowancesReceived {
-            spender: "spender1".to_string(),
-            key: vk.clone(),
-            page: Some(1),
-            page_size: 1,
-        };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::AllowancesReceived {
-                spender,
-                allowances,
-                count,
-            } => {
-                assert_eq!(spender, "spender1".to_string());
-                assert_eq!(allowances.len(), 1);
-                assert_eq!(allowances[0].owner, "owner1");
-                assert_eq!(allowances[0].allowance, Uint128::from(50_u128));
-                assert_eq!(allowances[0].expiration, None);
-                assert_eq!(count, num_owners);
+        let transactions_with = |counterparty: &str, page: u32, page_size: u32| {
+            let query_msg = QueryMsg::TransactionsWith {
+                address: "bob".to_string(),
+                key: "key".to_string(),
+                counterparty: counterparty.to_string(),
+                page: Some(page),
+                page_size,
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::TransactionsWith {
+                    txs,
+                    total,
+                    filtered_total,
+                } => {
+                    assert_eq!(total, history_total);
+                    let amounts: Vec<u128> = txs.iter().map(|tx| tx.coins.amount.u128()).collect();
+                    (amounts, filtered_total)
+                }
+                other => panic!("Unexpected: {:?}", other),
             }
-            _ => panic!("Unexpected"),
         };
+
+        // only transfers with the counterparty are returned, newest first
+        assert_eq!(transactions_with("alice", 0, 2), (vec![5, 3], 3));
+        assert_eq!(transactions_with("alice", 1, 2), (vec![1], 3));
+        // the counterparty can be either side of the transfer
+        assert_eq!(transactions_with("carol", 0, 10), (vec![6, 2], 2));
+        assert_eq!(transactions_with("dave", 0, 10), (vec![4], 1));
+        assert_eq!(transactions_with("eve", 0, 10), (vec![], 0));
     }
 
     #[test]
-    fn test_query_balance() {
+    fn test_query_transaction_history_retention_cap() {
         let (init_result, mut deps) = init_helper(vec![InitialBalance {
             address: "bob".to_string(),
-            amount: Uint128::new(5000),
+            amount: Uint128::new(10000),
         }]);
         assert!(
             init_result.is_ok(),
@@ -4644,59 +13030,134 @@ mod tests {
             init_result.err().unwrap()
         );
 
+        let handle_msg = ExecuteMsg::SetMaxTxHistoryPerAccount {
+            max: Some(3),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
+        };
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            handle_msg.clone(),
+        );
+        let error = extract_error_msg(handle_result);
+        assert!(error.contains("Admin commands can only be run from admin address"));
+
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("admin", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
+
         let handle_msg = ExecuteMsg::SetViewingKey {
             key: "key".to_string(),
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("bob", &[]);
+        let handle_result = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        for i in 1..=5 {
+            let handle_msg = ExecuteMsg::Transfer {
+                recipient: "alice".to_string(),
+                amount: Uint128::new(i),
+                memo: Some(format!("transfer #{i}")),
+                memo_encrypted: None,
+                want_receipt: None,
+                #[cfg(feature = "gas_evaporation")]
+                gas_target: None,
+                padding: None,
+            };
+            let handle_result =
+                execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), handle_msg);
+            assert!(ensure_success(handle_result.unwrap()));
+        }
 
-        let unwrapped_result: ExecuteAnswer =
-            from_binary(&handle_result.unwrap().data.unwrap()).unwrap();
-        assert_eq!(
-            to_binary(&unwrapped_result).unwrap(),
-            to_binary(&ExecuteAnswer::SetViewingKey {
-                status: ResponseStatus::Success
-            })
-            .unwrap(),
-        );
+        let history_page = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, page: u32| {
+            let query_msg = QueryMsg::TransactionHistory {
+                address: "bob".to_string(),
+                key: "key".to_string(),
+                page: Some(page),
+                page_size: 2,
+                action_filter: None,
+                order: None,
+            };
+            let query_result = query(deps.as_ref(), mock_env(), query_msg);
+            match from_binary(&query_result.unwrap()).unwrap() {
+                QueryAnswer::TransactionHistory { txs, total, .. } => (
+                    txs.into_iter()
+                        .map(|tx| tx.memo.unwrap())
+                        .collect::<Vec<String>>(),
+                    total,
+                ),
+                other => panic!("Unexpected: {:?}", other),
+            }
+        };
 
-        let query_msg = QueryMsg::Balance {
-            address: "bob".to_string(),
-            key: "wrong_key".to_string(),
+        // the pending tx plus the 3 most recent settled txs; the initial balance and the
+        // first transfer have been pruned
+        let (memos, total) = history_page(&deps, 0);
+        assert_eq!(total, Some(4));
+        assert_eq!(memos, vec!["transfer #5", "transfer #4"]);
+
+        let (memos, total) = history_page(&deps, 1);
+        assert_eq!(total, Some(4));
+        assert_eq!(memos, vec!["transfer #3", "transfer #2"]);
+
+        let (memos, _) = history_page(&deps, 2);
+        assert!(memos.is_empty());
+
+        // alice's history is unaffected by pruning bob's
+        let handle_msg = ExecuteMsg::SetViewingKey {
+            key: "key".to_string(),
+            #[cfg(feature = "gas_evaporation")]
+            gas_target: None,
+            padding: None,
         };
-        let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        let error = extract_error_msg(query_result);
-        assert!(error.contains("Wrong viewing key"));
+        let handle_result = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            handle_msg,
+        );
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let query_msg = QueryMsg::Balance {
-            address: "bob".to_string(),
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "alice".to_string(),
             key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
         };
         let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        let balance = match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::Balance { amount } => amount,
-            _ => panic!("Unexpected"),
-        };
-        assert_eq!(balance, Uint128::new(5000));
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory { txs, total, .. } => {
+                assert_eq!(txs.len(), 5);
+                assert_eq!(total, Some(5));
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
     }
 
     #[test]
-    fn test_query_transaction_history() {
+    fn test_query_transaction_history_action_filter() {
         let (init_result, mut deps) = init_helper_with_config(
             vec![InitialBalance {
                 address: "bob".to_string(),
                 amount: Uint128::new(10000),
             }],
+            false,
+            false,
             true,
-            true,
-            true,
-            true,
-            1000,
-            vec!["uscrt".to_string()],
+            false,
+            0,
+            vec![],
         );
         assert!(
             init_result.is_ok(),
@@ -4711,250 +13172,140 @@ mod tests {
             padding: None,
         };
         let info = mock_info("bob", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
         assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_msg = ExecuteMsg::Burn {
-            amount: Uint128::new(1),
-            memo: Some("my burn message".to_string()),
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(100),
+            memo: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("bob", &[]);
-
+        let info = mock_info("admin", &[]);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        assert!(
-            handle_result.is_ok(),
-            "Pause handle failed: {}",
-            handle_result.err().unwrap()
-        );
-
-        let handle_msg = ExecuteMsg::Redeem {
+        let handle_msg = ExecuteMsg::Transfer {
+            recipient: "alice".to_string(),
             amount: Uint128::new(1000),
-            denom: Option::from("uscrt".to_string()),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
         let info = mock_info("bob", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
+        assert!(ensure_success(handle_result.unwrap()));
 
         let handle_msg = ExecuteMsg::Mint {
             recipient: "bob".to_string(),
-            amount: Uint128::new(100),
-            memo: Some("my mint message".to_string()),
+            amount: Uint128::new(200),
+            memo: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
         let info = mock_info("admin", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
         assert!(ensure_success(handle_result.unwrap()));
 
-        let handle_msg = ExecuteMsg::Deposit {
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info(
-            "bob",
-            &[Coin {
-                denom: "uscrt".to_string(),
-                amount: Uint128::new(1000),
-            }],
-        );
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-        assert!(
-            handle_result.is_ok(),
-            "handle() failed: {}",
-            handle_result.err().unwrap()
-        );
-
-        let handle_msg = ExecuteMsg::Transfer {
-            recipient: "alice".to_string(),
-            amount: Uint128::new(1000),
-            memo: Some("my transfer message #1".to_string()),
-            #[cfg(feature = "gas_evaporation")]
-            gas_target: None,
-            padding: None,
-        };
-        let info = mock_info("bob", &[]);
-
-        let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let result = handle_result.unwrap();
-        assert!(ensure_success(result));
-
         let handle_msg = ExecuteMsg::Transfer {
             recipient: "banana".to_string(),
             amount: Uint128::new(500),
-            memo: Some("my transfer message #2".to_string()),
+            memo: None,
+            memo_encrypted: None,
+            want_receipt: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
         let info = mock_info("bob", &[]);
-
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
+        assert!(ensure_success(handle_result.unwrap()));
 
-        let result = handle_result.unwrap();
-        assert!(ensure_success(result));
-
-        let handle_msg = ExecuteMsg::Transfer {
-            recipient: "mango".to_string(),
-            amount: Uint128::new(2500),
-            memo: Some("my transfer message #3".to_string()),
+        let handle_msg = ExecuteMsg::Mint {
+            recipient: "bob".to_string(),
+            amount: Uint128::new(300),
+            memo: None,
             #[cfg(feature = "gas_evaporation")]
             gas_target: None,
             padding: None,
         };
-        let info = mock_info("bob", &[]);
-
+        let info = mock_info("admin", &[]);
         let handle_result = execute(deps.as_mut(), mock_env(), info, handle_msg);
-
-        let result = handle_result.unwrap();
-        assert!(ensure_success(result));
+        assert!(ensure_success(handle_result.unwrap()));
 
         let query_msg = QueryMsg::TransactionHistory {
             address: "bob".to_string(),
             key: "key".to_string(),
             page: None,
             page_size: 10,
+            action_filter: Some(TxActionFilter::Transfer),
+            order: None,
         };
         let query_result = query(deps.as_ref(), mock_env(), query_msg);
-        let transfers = match from_binary(&query_result.unwrap()).unwrap() {
-            QueryAnswer::TransactionHistory { txs, .. } => txs,
+        let (txs, total, filtered_total) = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory {
+                txs,
+                total,
+                filtered_total,
+            } => (txs, total, filtered_total),
             other => panic!("Unexpected: {:?}", other),
         };
+        assert_eq!(total, Some(6));
+        assert_eq!(filtered_total, Some(2));
+        let amounts: Vec<u128> = txs.iter().map(|tx| tx.coins.amount.u128()).collect();
+        assert_eq!(amounts, vec![500, 1000]);
+        assert!(txs
+            .iter()
+            .all(|tx| matches!(tx.action, TxAction::Transfer { .. })));
 
-        use crate::transaction_history::TxAction;
-        let expected_transfers = [
-            Tx {
-                id: 8735437960206903,
-                action: TxAction::Transfer {
-                    from: Addr::unchecked("bob".to_string()),
-                    sender: Addr::unchecked("bob".to_string()),
-                    recipient: Addr::unchecked("mango".to_string()),
-                },
-                coins: Coin {
-                    denom: "SECSEC".to_string(),
-                    amount: Uint128::new(2500),
-                },
-                memo: Some("my transfer message #3".to_string()),
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-            Tx {
-                id: 6519057655056815,
-                action: TxAction::Transfer {
-                    from: Addr::unchecked("bob".to_string()),
-                    sender: Addr::unchecked("bob".to_string()),
-                    recipient: Addr::unchecked("banana".to_string()),
-                },
-                coins: Coin {
-                    denom: "SECSEC".to_string(),
-                    amount: Uint128::new(500),
-                },
-                memo: Some("my transfer message #2".to_string()),
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-            Tx {
-                id: 2105964828411645,
-                action: TxAction::Transfer {
-                    from: Addr::unchecked("bob".to_string()),
-                    sender: Addr::unchecked("bob".to_string()),
-                    recipient: Addr::unchecked("alice".to_string()),
-                },
-                coins: Coin {
-                    denom: "SECSEC".to_string(),
-                    amount: Uint128::new(1000),
-                },
-                memo: Some("my transfer message #1".to_string()),
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-            Tx {
-                id: 7517649082682890,
-                action: TxAction::Deposit {},
-                coins: Coin {
-                    denom: "uscrt".to_string(),
-                    amount: Uint128::new(1000),
-                },
-                memo: None,
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-            Tx {
-                id: 5298675660782133,
-                action: TxAction::Mint {
-                    minter: Addr::unchecked("admin".to_string()),
-                    recipient: Addr::unchecked("bob".to_string()),
-                },
-                coins: Coin {
-                    denom: "SECSEC".to_string(),
-                    amount: Uint128::new(100),
-                },
-                memo: Some("my mint message".to_string()),
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-            Tx {
-                id: 3863562430182029,
-                action: TxAction::Redeem {},
-                coins: Coin {
-                    denom: "SECSEC".to_string(),
-                    amount: Uint128::new(1000),
-                },
-                memo: None,
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-            Tx {
-                id: 3942814133456943,
-                action: TxAction::Burn {
-                    burner: Addr::unchecked("bob".to_string()),
-                    owner: Addr::unchecked("bob".to_string()),
-                },
-                coins: Coin {
-                    denom: "SECSEC".to_string(),
-                    amount: Uint128::new(1),
-                },
-                memo: Some("my burn message".to_string()),
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-            Tx {
-                id: 5746099005188254,
-                action: TxAction::Mint {
-                    minter: Addr::unchecked("admin".to_string()),
-                    recipient: Addr::unchecked("bob".to_string()),
-                },
-                coins: Coin {
-                    denom: "SECSEC".to_string(),
-                    amount: Uint128::new(10000),
-                },
-
-                memo: Some("Initial Balance".to_string()),
-                block_time: 1571797419,
-                block_height: 12345,
-            },
-        ];
+        // paging counts matching txs only
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+            page: Some(1),
+            page_size: 2,
+            action_filter: Some(TxActionFilter::Mint),
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let (txs, total, filtered_total) = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory {
+                txs,
+                total,
+                filtered_total,
+            } => (txs, total, filtered_total),
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(total, Some(6));
+        assert_eq!(filtered_total, Some(4));
+        let amounts: Vec<u128> = txs.iter().map(|tx| tx.coins.amount.u128()).collect();
+        assert_eq!(amounts, vec![100, 10000]);
 
-        assert_eq!(transfers, expected_transfers);
+        // no filter leaves filtered_total unset
+        let query_msg = QueryMsg::TransactionHistory {
+            address: "bob".to_string(),
+            key: "key".to_string(),
+            page: None,
+            page_size: 10,
+            action_filter: None,
+            order: None,
+        };
+        let query_result = query(deps.as_ref(), mock_env(), query_msg);
+        let (txs, filtered_total) = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TransactionHistory {
+                txs,
+                filtered_total,
+                ..
+            } => (txs, filtered_total),
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(txs.len(), 6);
+        assert_eq!(filtered_total, None);
     }
 }
@@ -11,10 +11,10 @@ use crate::btbe::{settle_dwb_entry, stored_balance};
 use crate::gas_tracker::GasTracker;
 #[cfg(feature = "gas_tracking")]
 use crate::msg::QueryAnswer;
-use crate::state::{safe_add, safe_add_u64};
+use crate::state::{safe_add, safe_add_u64, BalanceCheckpointsStore, CONFIG};
 use crate::transaction_history::{Tx, TRANSACTIONS};
 #[cfg(feature = "gas_tracking")]
-use cosmwasm_std::{to_binary, Binary};
+use cosmwasm_std::{to_binary, Binary, Deps, Uint128};
 
 include!(concat!(env!("OUT_DIR"), "/config.rs"));
 
@@ -138,6 +138,31 @@ impl DelayedWriteBuffer {
         Ok(checked_balance.unwrap())
     }
 
+    /// settles an account's pending buffer entry, if any, into its stored balance
+    /// without recording a new tx
+    pub fn settle_account(
+        &mut self,
+        store: &mut dyn Storage,
+        address: &CanonicalAddr,
+        #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
+    ) -> StdResult<()> {
+        // nothing to settle if the account has no pending txs in the buffer
+        let dwb_index = self.recipient_match(address);
+        if dwb_index == 0 || self.entries[dwb_index].list_len()? == 0 {
+            return Ok(());
+        }
+
+        let (_, dwb_entry) = self.release_dwb_recipient(store, address)?;
+
+        settle_dwb_entry(
+            store,
+            &dwb_entry,
+            None,
+            #[cfg(feature = "gas_tracking")]
+            tracker,
+        )
+    }
+
     /// "releases" a given recipient from the buffer, removing their entry if one exists
     /// returns the new balance and the buffer entry
     fn release_dwb_recipient(
@@ -178,6 +203,54 @@ impl DelayedWriteBuffer {
         matched_index
     }
 
+    // returns the index of the first entry with no pending txs, searching the buffer in a
+    // constant-time pass that wraps around from `start`, or 0 if every entry has pending txs
+    fn settled_entry_match(&self, start: usize) -> StdResult<usize> {
+        let len = DWB_LEN as usize - 1;
+        let mut matched_index: usize = 0;
+        let mut found: u32 = 0;
+        for offset in 0..len {
+            let idx = 1 + (start - 1 + offset) % len;
+            let is_settled = 1 - constant_time_is_not_zero(self.entries[idx].list_len()? as i32);
+            matched_index |= idx * (is_settled & (1 - found)) as usize;
+            found |= is_settled;
+        }
+        Ok(matched_index)
+    }
+
+    // returns an account's full balance: its settled balance plus any amount pending in the buffer
+    pub fn account_balance(&self, store: &dyn Storage, address: &CanonicalAddr) -> StdResult<u128> {
+        let mut balance = stored_balance(store, address)?;
+        let dwb_index = self.recipient_match(address);
+        if dwb_index > 0 {
+            balance = balance.saturating_add(self.entries[dwb_index].amount()? as u128);
+        }
+        Ok(balance)
+    }
+
+    /// records the current balance of each account as a checkpoint at `block_height`, if
+    /// balance snapshots are enabled
+    pub fn checkpoint_balances(
+        &self,
+        store: &mut dyn Storage,
+        accounts: &[&CanonicalAddr],
+        block_height: u64,
+    ) -> StdResult<()> {
+        let snapshot_enabled = CONFIG
+            .may_load(store)?
+            .is_some_and(|config| config.snapshot_enabled);
+        if !snapshot_enabled {
+            return Ok(());
+        }
+
+        for account in accounts {
+            let balance = self.account_balance(store, account)?;
+            BalanceCheckpointsStore::record(store, account, block_height, balance)?;
+        }
+
+        Ok(())
+    }
+
     pub fn add_recipient<'a>(
         &mut self,
         store: &mut dyn Storage,
@@ -229,17 +302,26 @@ impl DelayedWriteBuffer {
         #[cfg(feature = "gas_tracking")]
         group1.logf(format!("@next_empty_index: {}", next_empty_index));
 
+        // entry to evict if the buffer is saturated: prefer reusing the slot of an entry that has
+        // already been settled, searching from a random offset, over evicting a random entry that
+        // still has pending txs
+        let random_index = random_in_range(rng, 1, DWB_LEN as u32)? as usize;
+        let settled_index = self.settled_entry_match(random_index)?;
+        let evict_index = constant_time_if_else(
+            constant_time_is_not_zero(settled_index as i32),
+            settled_index,
+            random_index,
+        );
+        #[cfg(feature = "gas_tracking")]
+        group1.logf(format!("@evict_index: {}", evict_index));
+
         // which entry to settle (not yet considering if recipient's entry has capacity in history list)
         //   if recipient is in buffer or buffer is undersaturated then settle the dummy entry
-        //   otherwise, settle a random entry
+        //   otherwise, settle the evicted entry
         let presumptive_settle_index = constant_time_if_else(
             if_recipient_in_buffer,
             0,
-            constant_time_if_else(
-                if_undersaturated,
-                0,
-                random_in_range(rng, 1, DWB_LEN as u32)? as usize,
-            ),
+            constant_time_if_else(if_undersaturated, 0, evict_index),
         );
         #[cfg(feature = "gas_tracking")]
         group1.logf(format!(
@@ -489,7 +571,7 @@ impl TxNode {
             let stored_tx = TRANSACTIONS
                 .add_suffix(&node.tx_id.to_be_bytes())
                 .load(store)?;
-            let tx = stored_tx.into_humanized(api, node.tx_id)?;
+            let tx = stored_tx.into_humanized(store, api, node.tx_id)?;
             result.push(tx);
             if node.next > 0 {
                 let next_node = TX_NODES.add_suffix(&node.next.to_be_bytes()).load(store)?;
@@ -540,6 +622,29 @@ pub fn log_dwb(storage: &dyn Storage) -> StdResult<Binary> {
     })
 }
 
+#[cfg(feature = "gas_tracking")]
+pub fn log_account_status(deps: Deps, address: String) -> StdResult<Binary> {
+    let address = deps.api.addr_canonicalize(address.as_str())?;
+    let dwb = DWB.load(deps.storage)?;
+
+    let dwb_index = dwb.recipient_match(&address);
+    let (dwb_amount, dwb_tx_count) = if dwb_index > 0 {
+        let entry = dwb.entries[dwb_index];
+        (entry.amount()?, entry.list_len()?)
+    } else {
+        (0, 0)
+    };
+
+    // a settled account's slot keeps its address until it is reused, so only an entry with
+    // pending txs counts as buffered
+    to_binary(&QueryAnswer::AccountStatus {
+        in_dwb: dwb_tx_count > 0,
+        dwb_amount: Uint128::from(dwb_amount),
+        settled_balance: Uint128::new(stored_balance(deps.storage, &address)?),
+        dwb_tx_count: dwb_tx_count as u32,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::contract::instantiate;
@@ -632,4 +737,79 @@ mod tests {
         let result = dwb_entry.add_tx_node(storage, tx_id).unwrap();
         assert_eq!(dwb_entry.head_node().unwrap(), result);
     }
+
+    #[test]
+    fn test_add_recipient_reuses_settled_slots() {
+        let (init_result, mut deps) = init_helper(vec![]);
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let storage = deps.as_mut().storage;
+        let mut rng = ContractPrng::new(b"seed", b"entropy");
+        #[cfg(feature = "gas_tracking")]
+        let api = MockApi::default();
+        #[cfg(feature = "gas_tracking")]
+        let mut tracker = GasTracker::new(&api);
+
+        let address = |n: u16| {
+            let mut bytes = [0xffu8; DWB_RECIPIENT_BYTES];
+            bytes[..2].copy_from_slice(&n.to_be_bytes());
+            CanonicalAddr::from(&bytes)
+        };
+        let pending_entries = |dwb: &DelayedWriteBuffer| {
+            dwb.entries[1..]
+                .iter()
+                .filter(|entry| entry.list_len().unwrap() > 0)
+                .count()
+        };
+
+        // a few hot recipients keep receiving while a stream of cold recipients each receive
+        // once and then settle their accounts, e.g. by spending
+        let hot: Vec<CanonicalAddr> = (0..8).map(address).collect();
+        let mut dwb = DelayedWriteBuffer::new().unwrap();
+        let mut tx_id = 0;
+        let mut pending_evictions = 0;
+        for cold in 8..(8 + 4 * DWB_LEN) {
+            let mut recipients = hot.clone();
+            recipients.push(address(cold));
+            for recipient in &recipients {
+                tx_id += 1;
+                let in_buffer = dwb.recipient_match(recipient) > 0;
+                let pending_before = pending_entries(&dwb);
+                dwb.add_recipient(
+                    storage,
+                    &mut rng,
+                    recipient,
+                    tx_id,
+                    100,
+                    #[cfg(feature = "gas_tracking")]
+                    &mut tracker,
+                )
+                .unwrap();
+                if !in_buffer && pending_entries(&dwb) == pending_before {
+                    pending_evictions += 1;
+                }
+            }
+
+            dwb.settle_account(
+                storage,
+                &address(cold),
+                #[cfg(feature = "gas_tracking")]
+                &mut tracker,
+            )
+            .unwrap();
+        }
+
+        // once saturated, new cold recipients take over settled slots instead of evicting
+        // entries that still have pending txs, so the hot recipients are never settled early
+        assert_eq!(dwb.empty_space_counter, 0);
+        assert_eq!(pending_evictions, 0);
+        for recipient in &hot {
+            let entry = dwb.entries[dwb.recipient_match(recipient)];
+            assert_eq!(entry.list_len().unwrap(), 4 * DWB_LEN);
+            assert_eq!(entry.amount().unwrap(), 100 * 4 * DWB_LEN as u64);
+        }
+    }
 }
@@ -1,15 +1,25 @@
 use cosmwasm_std::{
-    to_binary, Addr, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Storage, Uint128,
+    to_binary, Addr, Binary, BlockInfo, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    Storage, Uint128,
 };
+use rand_core::RngCore;
 use secret_toolkit::notification::Notification;
 use secret_toolkit::permit::{AllRevokedInterval, RevokedPermits, RevokedPermitsStore};
 use secret_toolkit::viewing_key::{ViewingKey, ViewingKeyStore};
 use secret_toolkit_crypto::ContractPrng;
 
+use crate::batch::{self, AllowanceOperation};
+use crate::btbe::initialize_stored_entry;
+use crate::constants::MAX_RECEIVER_LABEL_LENGTH;
+use crate::dwb::DWB;
+#[cfg(feature = "gas_tracking")]
+use crate::gas_tracker::GasTracker;
 use crate::msg::{ExecuteAnswer, ResponseStatus::Success};
-use crate::notifications::AllowanceNotification;
+use crate::notifications::{add_direct_notification, AllowanceNotification};
 use crate::state::{
-    AllowancesStore, ReceiverHashStore, INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED,
+    AllowancesStore, ChannelPreferencesStore, MemoDecryptionKeyStore, PermitEpochStore,
+    PublicAllowancesStore, ReceiverHashStore, ViewingKeyCreationStore, CHANNELS, CONFIG,
+    INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED, PENDING_ADMIN,
 };
 
 // viewing key functions
@@ -30,6 +40,9 @@ pub fn try_create_key(
     entropy: Option<String>,
     rng: &mut ContractPrng,
 ) -> StdResult<Response> {
+    // limit how often an account can regenerate its key, so it can't grind for a favorable one
+    ViewingKeyCreationStore::record(deps.storage, &info.sender, env.block.height)?;
+
     let entropy = [entropy.unwrap_or_default().as_bytes(), &rng.rand_bytes()].concat();
 
     let key = ViewingKey::create(deps.storage, &info, &env, info.sender.as_str(), &entropy);
@@ -43,8 +56,19 @@ pub fn try_register_receive(
     deps: DepsMut,
     info: MessageInfo,
     code_hash: String,
+    label: Option<String>,
 ) -> StdResult<Response> {
+    if let Some(label) = &label {
+        if label.len() > MAX_RECEIVER_LABEL_LENGTH {
+            return Err(StdError::generic_err(format!(
+                "label too long: length={}, max={MAX_RECEIVER_LABEL_LENGTH}",
+                label.len()
+            )));
+        }
+    }
+
     ReceiverHashStore::save(deps.storage, &info.sender, code_hash)?;
+    ReceiverHashStore::save_label(deps.storage, &info.sender, label)?;
 
     let data = to_binary(&ExecuteAnswer::RegisterReceive { status: Success })?;
     Ok(Response::new()
@@ -52,6 +76,87 @@ pub fn try_register_receive(
         .set_data(data))
 }
 
+pub fn try_settle_self(deps: DepsMut, info: MessageInfo) -> StdResult<Response> {
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    let mut dwb = DWB.load(deps.storage)?;
+
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker = GasTracker::new(deps.api);
+
+    dwb.settle_account(
+        deps.storage,
+        &sender_raw,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    )?;
+
+    DWB.save(deps.storage, &dwb)?;
+
+    Ok(Response::new().set_data(to_binary(&ExecuteAnswer::SettleSelf { status: Success })?))
+}
+
+pub fn try_initialize_account(deps: DepsMut, address: String) -> StdResult<Response> {
+    let address = deps.api.addr_validate(address.as_str())?;
+    let address_raw = deps.api.addr_canonicalize(address.as_str())?;
+
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker = GasTracker::new(deps.api);
+
+    initialize_stored_entry(
+        deps.storage,
+        &address_raw,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    )?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::InitializeAccount {
+            status: Success,
+        })?),
+    )
+}
+
+// notification preference functions
+
+pub fn try_set_channel_preference(
+    deps: DepsMut,
+    info: MessageInfo,
+    channel: String,
+    enabled: bool,
+) -> StdResult<Response> {
+    if !CHANNELS.contains(deps.storage, &channel) {
+        return Err(StdError::generic_err(format!(
+            "Channel {channel} does not exist",
+        )));
+    }
+
+    ChannelPreferencesStore::set(deps.storage, &info.sender, &channel, enabled)?;
+
+    let data = to_binary(&ExecuteAnswer::SetChannelPreference { status: Success })?;
+    Ok(Response::new().set_data(data))
+}
+
+// memo decryption key functions
+
+pub fn try_set_memo_decryption_key(
+    deps: DepsMut,
+    info: MessageInfo,
+    pubkey: Binary,
+) -> StdResult<Response> {
+    // only accept compressed secp256k1 public keys
+    if pubkey.len() != 33 || (pubkey[0] != 0x02 && pubkey[0] != 0x03) {
+        return Err(StdError::generic_err(
+            "Memo decryption key must be a 33-byte compressed secp256k1 public key",
+        ));
+    }
+
+    MemoDecryptionKeyStore::save(deps.storage, &info.sender, pubkey)?;
+
+    let data = to_binary(&ExecuteAnswer::SetMemoDecryptionKey { status: Success })?;
+    Ok(Response::new().set_data(data))
+}
+
 // allowance functions
 
 fn insufficient_allowance(allowance: u128, required: u128) -> StdError {
@@ -72,6 +177,9 @@ pub fn use_allowance(
     if allowance.is_expired_at(&env.block) || allowance.amount == 0 {
         return Err(insufficient_allowance(0, amount));
     }
+    if !allowance.is_active_at(&env.block) {
+        return Err(StdError::generic_err("allowance not yet active"));
+    }
     if let Some(new_allowance) = allowance.amount.checked_sub(amount) {
         allowance.amount = new_allowance;
     } else {
@@ -83,6 +191,7 @@ pub fn use_allowance(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn try_increase_allowance(
     deps: DepsMut,
     env: Env,
@@ -90,60 +199,136 @@ pub fn try_increase_allowance(
     spender: String,
     amount: Uint128,
     expiration: Option<u64>,
+    grace_seconds: Option<u64>,
+    notify_self: bool,
 ) -> StdResult<Response> {
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
     let spender = deps.api.addr_validate(spender.as_str())?;
-    let mut allowance = AllowancesStore::load(deps.storage, &info.sender, &spender);
+    let new_amount = update_allowance(
+        deps.storage,
+        &env.block,
+        &info.sender,
+        &spender,
+        AllowanceOperation::Increase,
+        amount.u128(),
+        expiration,
+        grace_seconds,
+    )?;
 
-    // If the previous allowance has expired, reset the allowance.
-    // Without this users can take advantage of an expired allowance given to
-    // them long ago.
-    if allowance.is_expired_at(&env.block) {
-        allowance.amount = amount.u128();
-        allowance.expiration = None;
-    } else {
-        allowance.amount = allowance.amount.saturating_add(amount.u128());
-    }
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::IncreaseAllowance {
+        owner: info.sender.clone(),
+        spender: spender.clone(),
+        allowance: Uint128::from(new_amount),
+    })?);
 
-    if expiration.is_some() {
-        allowance.expiration = expiration;
+    if NOTIFICATIONS_ENABLED.load(deps.storage)? {
+        resp = add_allowance_change_notifications(
+            deps,
+            &env,
+            secret,
+            resp,
+            &info.sender,
+            spender,
+            new_amount,
+            expiration,
+            notify_self,
+        )?;
     }
-    let new_amount = allowance.amount;
-    AllowancesStore::save(deps.storage, &info.sender, &spender, &allowance)?;
 
-    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::IncreaseAllowance {
+    Ok(resp)
+}
+
+pub fn try_decrease_allowance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    amount: Uint128,
+    expiration: Option<u64>,
+    notify_self: bool,
+) -> StdResult<Response> {
+    let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
+    let secret = secret.as_slice();
+
+    let spender = deps.api.addr_validate(spender.as_str())?;
+    let new_amount = update_allowance(
+        deps.storage,
+        &env.block,
+        &info.sender,
+        &spender,
+        AllowanceOperation::Decrease,
+        amount.u128(),
+        expiration,
+        None,
+    )?;
+
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::DecreaseAllowance {
         owner: info.sender.clone(),
         spender: spender.clone(),
         allowance: Uint128::from(new_amount),
     })?);
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
-        let notification = Notification::new(
+        resp = add_allowance_change_notifications(
+            deps,
+            &env,
+            secret,
+            resp,
+            &info.sender,
             spender,
+            new_amount,
+            expiration,
+            notify_self,
+        )?;
+    }
+
+    Ok(resp)
+}
+
+/// notifies the spender of their new allowance and, if `notify_self` is set, the owner too; the
+/// owner's notification carries the spender in its `allower` field so it identifies the grant
+#[allow(clippy::too_many_arguments)]
+fn add_allowance_change_notifications(
+    deps: DepsMut,
+    env: &Env,
+    secret: &[u8],
+    mut resp: Response,
+    owner: &Addr,
+    spender: Addr,
+    amount: u128,
+    expiration: Option<u64>,
+    notify_self: bool,
+) -> StdResult<Response> {
+    if notify_self {
+        let notification = Notification::new(
+            owner.clone(),
             AllowanceNotification {
-                amount: new_amount,
-                allower: info.sender,
+                amount,
+                allower: spender.clone(),
                 expiration,
             },
-        )
-        .to_txhash_notification(deps.api, &env, secret, None)?;
-
-        resp = resp
-            .add_attribute_plaintext(notification.id_plaintext(), notification.data_plaintext());
+        );
+        resp = add_direct_notification(deps.storage, deps.api, env, secret, resp, notification)?;
     }
 
-    Ok(resp)
+    let notification = Notification::new(
+        spender,
+        AllowanceNotification {
+            amount,
+            allower: owner.clone(),
+            expiration,
+        },
+    );
+    add_direct_notification(deps.storage, deps.api, env, secret, resp, notification)
 }
 
-pub fn try_decrease_allowance(
+pub fn try_clear_allowance_expiration(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     spender: String,
-    amount: Uint128,
-    expiration: Option<u64>,
 ) -> StdResult<Response> {
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
@@ -151,48 +336,135 @@ pub fn try_decrease_allowance(
     let spender = deps.api.addr_validate(spender.as_str())?;
     let mut allowance = AllowancesStore::load(deps.storage, &info.sender, &spender);
 
-    // If the previous allowance has expired, reset the allowance.
-    // Without this users can take advantage of an expired allowance given to
-    // them long ago.
+    // an allowance that has already expired must not be revived by clearing its expiration
     if allowance.is_expired_at(&env.block) {
         allowance.amount = 0;
-        allowance.expiration = None;
-    } else {
-        allowance.amount = allowance.amount.saturating_sub(amount.u128());
-    }
-
-    if expiration.is_some() {
-        allowance.expiration = expiration;
     }
-    let new_amount = allowance.amount;
+    allowance.expiration = None;
     AllowancesStore::save(deps.storage, &info.sender, &spender, &allowance)?;
 
-    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::DecreaseAllowance {
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::ClearAllowanceExpiration {
         owner: info.sender.clone(),
         spender: spender.clone(),
-        allowance: Uint128::from(new_amount),
+        allowance: Uint128::from(allowance.amount),
     })?);
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
         let notification = Notification::new(
             spender,
             AllowanceNotification {
-                amount: new_amount,
+                amount: allowance.amount,
                 allower: info.sender,
-                expiration,
+                expiration: None,
             },
-        )
-        .to_txhash_notification(deps.api, &env, secret, None)?;
+        );
+        resp = add_direct_notification(deps.storage, deps.api, &env, secret, resp, notification)?;
+    }
+
+    Ok(resp)
+}
 
-        resp = resp
-            .add_attribute_plaintext(notification.id_plaintext(), notification.data_plaintext());
+pub fn try_batch_set_allowance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    actions: Vec<batch::AllowanceAction>,
+) -> StdResult<Response> {
+    let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
+    let secret = secret.as_slice();
+
+    let notifications_enabled = NOTIFICATIONS_ENABLED.load(deps.storage)?;
+
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::BatchSetAllowance {
+        status: Success,
+    })?);
+
+    for action in actions {
+        let spender = deps.api.addr_validate(action.spender.as_str())?;
+        let new_amount = update_allowance(
+            deps.storage,
+            &env.block,
+            &info.sender,
+            &spender,
+            action.operation,
+            action.amount.u128(),
+            action.expiration,
+            None,
+        )?;
+
+        // each spender is notified of their new allowance, as with the single-action messages
+        if notifications_enabled {
+            let notification = Notification::new(
+                spender,
+                AllowanceNotification {
+                    amount: new_amount,
+                    allower: info.sender.clone(),
+                    expiration: action.expiration,
+                },
+            );
+            resp =
+                add_direct_notification(deps.storage, deps.api, &env, secret, resp, notification)?;
+        }
     }
 
     Ok(resp)
 }
 
+/// applies an allowance change from `owner` to `spender` and returns the new allowance amount
+#[allow(clippy::too_many_arguments)]
+fn update_allowance(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    owner: &Addr,
+    spender: &Addr,
+    operation: AllowanceOperation,
+    amount: u128,
+    expiration: Option<u64>,
+    grace_seconds: Option<u64>,
+) -> StdResult<u128> {
+    let mut allowance = AllowancesStore::load(storage, owner, spender);
+
+    // If the previous allowance has expired, reset the allowance.
+    // Without this users can take advantage of an expired allowance given to
+    // them long ago.
+    if allowance.is_expired_at(block) {
+        allowance.amount = 0;
+        allowance.expiration = None;
+        allowance.active_at = None;
+    }
+
+    allowance.amount = match operation {
+        AllowanceOperation::Increase => allowance.amount.saturating_add(amount),
+        AllowanceOperation::Decrease => allowance.amount.saturating_sub(amount),
+        AllowanceOperation::Set => amount,
+    };
+
+    if expiration.is_some() {
+        allowance.expiration = expiration;
+    }
+    // a grace period can only push back when the allowance becomes usable, never bring it forward
+    if let Some(grace_seconds) = grace_seconds.filter(|grace| *grace > 0) {
+        let active_at = block.time.seconds().saturating_add(grace_seconds);
+        allowance.active_at = Some(allowance.active_at.unwrap_or_default().max(active_at));
+    }
+    AllowancesStore::save(storage, owner, spender, &allowance)?;
+
+    Ok(allowance.amount)
+}
+
 // SNIP 24, 24.1 permit functions
 
+pub fn try_set_allowances_public(
+    deps: DepsMut,
+    info: MessageInfo,
+    public: bool,
+) -> StdResult<Response> {
+    PublicAllowancesStore::save(deps.storage, &info.sender, public)?;
+
+    let data = to_binary(&ExecuteAnswer::SetAllowancesPublic { status: Success })?;
+    Ok(Response::new().set_data(data))
+}
+
 pub fn revoke_permit(deps: DepsMut, info: MessageInfo, permit_name: String) -> StdResult<Response> {
     RevokedPermits::revoke_permit(deps.storage, info.sender.as_str(), &permit_name);
 
@@ -228,3 +500,44 @@ pub fn delete_permit_revocation(
         })?),
     )
 }
+
+/// Sets a new viewing key and invalidates every permit the account has signed so far by moving
+/// the account to a new permit epoch. The epoch is drawn at random, so that nobody can sign
+/// permits ahead of time for the epoch that follows a reset.
+pub fn try_reset_credentials(
+    deps: DepsMut,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    new_key: String,
+) -> StdResult<Response> {
+    ViewingKey::set(deps.storage, info.sender.as_str(), new_key.as_str());
+
+    // epoch 0 is reserved for accounts that never reset their credentials
+    let permit_epoch = rng.next_u64().max(1);
+    PermitEpochStore::save(deps.storage, info.sender.as_str(), permit_epoch)?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::ResetCredentials {
+            status: Success,
+            permit_epoch,
+        })?),
+    )
+}
+
+// admin transfer functions
+
+/// Completes an admin transfer started with `ChangeAdmin`, called by the nominated address.
+pub fn try_accept_admin(deps: DepsMut, info: MessageInfo) -> StdResult<Response> {
+    if PENDING_ADMIN.may_load(deps.storage)?.as_ref() != Some(&info.sender) {
+        return Err(StdError::generic_err(
+            "Only the pending admin can accept the admin role",
+        ));
+    }
+
+    let mut config = CONFIG.load(deps.storage)?;
+    config.admin = info.sender;
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_ADMIN.remove(deps.storage);
+
+    Ok(Response::new().set_data(to_binary(&ExecuteAnswer::AcceptAdmin { status: Success })?))
+}
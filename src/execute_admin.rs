@@ -1,20 +1,86 @@
-use cosmwasm_std::{to_binary, Addr, DepsMut, Response, StdError, StdResult};
+use cosmwasm_std::{to_binary, Addr, DepsMut, Env, MessageInfo, Response, StdError, StdResult};
+use secret_toolkit::notification::{BloomParameters, ChannelInfoData};
+use secret_toolkit::viewing_key::{ViewingKey, ViewingKeyStore};
+use secret_toolkit_crypto::{hkdf_sha_256, sha_256};
 
-use crate::msg::ContractStatusLevel;
+use crate::contract::validate_supported_denoms;
+use crate::msg::{ContractStatusLevel, RedeemRateLimit};
 use crate::msg::{ExecuteAnswer, ResponseStatus::Success};
-use crate::state::{Config, MintersStore, CONFIG, CONTRACT_STATUS, NOTIFICATIONS_ENABLED};
+use crate::notifications::BUILTIN_CHANNELS;
+use crate::state::{
+    Config, DenomFlags, DenomStatusStore, MintersStore, OperationFlagsStore, RedeemRateLimitStore,
+    CHANNELS, CHANNEL_SCHEMATA, CONFIG, CONTRACT_STATUS, INTERNAL_SECRET_RELAXED,
+    NOTIFICATIONS_ENABLED, OPERATION_BURN, OPERATION_MINT, OPERATION_SEND, OPERATION_TRANSFER,
+    PENDING_ADMIN,
+};
 
 // All the functions in this file MUST only be executed after confirming the sender is the admin
 
-pub fn change_admin(deps: DepsMut, constants: &mut Config, address: String) -> StdResult<Response> {
+pub fn change_admin(deps: DepsMut, address: String) -> StdResult<Response> {
     let address = deps.api.addr_validate(address.as_str())?;
 
-    constants.admin = address;
-    CONFIG.save(deps.storage, constants)?;
+    // the new admin only takes over once it accepts, so a mistyped address can be corrected
+    PENDING_ADMIN.save(deps.storage, &address)?;
 
     Ok(Response::new().set_data(to_binary(&ExecuteAnswer::ChangeAdmin { status: Success })?))
 }
 
+pub fn cancel_admin_transfer(deps: DepsMut) -> StdResult<Response> {
+    if PENDING_ADMIN.may_load(deps.storage)?.is_none() {
+        return Err(StdError::generic_err("There is no pending admin transfer"));
+    }
+    PENDING_ADMIN.remove(deps.storage);
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::CancelAdminTransfer {
+            status: Success,
+        })?),
+    )
+}
+
+/// re-derives the relaxed internal secret and the viewing key seed the same way instantiate does.
+/// the sensitive internal secret is left in place, since stored balances are located by it
+pub fn rotate_secrets(deps: DepsMut, env: &Env, info: &MessageInfo) -> StdResult<Response> {
+    let rng_seed = env
+        .block
+        .random
+        .as_ref()
+        .ok_or_else(|| StdError::generic_err("Block randomness is not available"))?;
+
+    // mix in the previous secret so the new secrets do not depend on block data alone
+    let previous_secret = INTERNAL_SECRET_RELAXED.load(deps.storage)?;
+    let mut rng_entropy =
+        Vec::with_capacity(16 + info.sender.as_str().len() + previous_secret.len());
+    rng_entropy.extend_from_slice(&env.block.height.to_be_bytes());
+    rng_entropy.extend_from_slice(&env.block.time.seconds().to_be_bytes());
+    rng_entropy.extend_from_slice(info.sender.as_bytes());
+    rng_entropy.extend_from_slice(&previous_secret);
+    let salt = Some(sha_256(&rng_entropy).to_vec());
+
+    let internal_secret_relaxed = hkdf_sha_256(
+        &salt,
+        rng_seed.0.as_slice(),
+        "contract_internal_secret_relaxed".as_bytes(),
+        32,
+    )?;
+    INTERNAL_SECRET_RELAXED.save(deps.storage, &internal_secret_relaxed)?;
+
+    // existing viewing keys are stored hashed and keep working; only new keys use the new seed
+    let vk_seed = hkdf_sha_256(
+        &salt,
+        rng_seed.0.as_slice(),
+        "contract_viewing_key".as_bytes(),
+        32,
+    )?;
+    ViewingKey::set_seed(deps.storage, &vk_seed);
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::RotateSecrets {
+            status: Success,
+        })?),
+    )
+}
+
 pub fn add_supported_denoms(
     deps: DepsMut,
     config: &mut Config,
@@ -31,6 +97,7 @@ pub fn add_supported_denoms(
             config.supported_denoms.push(denom.clone());
         }
     }
+    validate_supported_denoms(&config.supported_denoms)?;
 
     CONFIG.save(deps.storage, config)?;
 
@@ -54,6 +121,7 @@ pub fn remove_supported_denoms(
 
     for denom in denoms.iter() {
         config.supported_denoms.retain(|x| x != denom);
+        DenomStatusStore::remove(deps.storage, denom)?;
     }
 
     CONFIG.save(deps.storage, config)?;
@@ -70,6 +138,9 @@ pub fn set_contract_status(
     status_level: ContractStatusLevel,
 ) -> StdResult<Response> {
     CONTRACT_STATUS.save(deps.storage, &status_level)?;
+    // start counting redeems afresh, so resuming after the circuit breaker tripped doesn't
+    // immediately trip it again
+    RedeemRateLimitStore::reset_window(deps.storage);
 
     Ok(
         Response::new().set_data(to_binary(&ExecuteAnswer::SetContractStatus {
@@ -142,6 +213,105 @@ pub fn set_minters(
     Ok(Response::new().set_data(to_binary(&ExecuteAnswer::SetMinters { status: Success })?))
 }
 
+pub fn set_denom_status(
+    deps: DepsMut,
+    config: &Config,
+    denom: String,
+    deposit_enabled: bool,
+    redeem_enabled: bool,
+) -> StdResult<Response> {
+    if !config.can_modify_denoms {
+        return Err(StdError::generic_err(
+            "Cannot modify denoms for this contract",
+        ));
+    }
+    if !config.supported_denoms.contains(&denom) {
+        return Err(StdError::generic_err(format!(
+            "Cannot set the status of unsupported coin {denom}",
+        )));
+    }
+
+    DenomStatusStore::save(
+        deps.storage,
+        &denom,
+        DenomFlags {
+            deposit_enabled,
+            redeem_enabled,
+        },
+    )?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::SetDenomStatus {
+            status: Success,
+        })?),
+    )
+}
+
+pub fn set_redeem_rate_limit(deps: DepsMut, limit: Option<RedeemRateLimit>) -> StdResult<Response> {
+    if let Some(limit) = &limit {
+        if limit.window_seconds == 0 {
+            return Err(StdError::generic_err(
+                "Redeem rate limit window must be at least one second",
+            ));
+        }
+    }
+    RedeemRateLimitStore::save(deps.storage, limit)?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::SetRedeemRateLimit {
+            status: Success,
+        })?),
+    )
+}
+
+pub fn set_operation_flags(
+    deps: DepsMut,
+    disable_mint: bool,
+    disable_send: bool,
+    disable_burn: bool,
+    disable_transfer: bool,
+) -> StdResult<Response> {
+    let mut flags = 0u8;
+    for (disabled, flag) in [
+        (disable_mint, OPERATION_MINT),
+        (disable_send, OPERATION_SEND),
+        (disable_burn, OPERATION_BURN),
+        (disable_transfer, OPERATION_TRANSFER),
+    ] {
+        if disabled {
+            flags |= flag;
+        }
+    }
+    OperationFlagsStore::save(deps.storage, flags)?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::SetOperationFlags {
+            status: Success,
+        })?),
+    )
+}
+
+pub fn set_max_tx_history_per_account(
+    deps: DepsMut,
+    config: &mut Config,
+    max: Option<u32>,
+) -> StdResult<Response> {
+    if max == Some(0) {
+        return Err(StdError::generic_err(
+            "Max tx history per account must be greater than zero",
+        ));
+    }
+
+    config.max_tx_history_per_account = max;
+    CONFIG.save(deps.storage, config)?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::SetMaxTxHistoryPerAccount {
+            status: Success,
+        })?),
+    )
+}
+
 // SNIP-52 functions
 
 pub fn set_notification_status(deps: DepsMut, enabled: bool) -> StdResult<Response> {
@@ -154,4 +324,67 @@ pub fn set_notification_status(deps: DepsMut, enabled: bool) -> StdResult<Respon
     )
 }
 
+pub fn register_channel(
+    deps: DepsMut,
+    channel: String,
+    cddl: Option<String>,
+    bloom_params: Option<BloomParameters>,
+) -> StdResult<Response> {
+    if channel.is_empty() {
+        return Err(StdError::generic_err("Channel ID must not be empty"));
+    }
+    if CHANNELS.contains(deps.storage, &channel) {
+        return Err(StdError::generic_err(format!(
+            "`{channel}` channel already exists"
+        )));
+    }
+
+    let mode = if bloom_params.is_some() {
+        "bloom"
+    } else {
+        "txhash"
+    };
+    let schema = ChannelInfoData {
+        mode: mode.to_string(),
+        channel: channel.clone(),
+        answer_id: None,
+        parameters: bloom_params,
+        data: None,
+        next_id: None,
+        counter: None,
+        cddl,
+    };
+
+    CHANNELS.insert(deps.storage, &channel)?;
+    CHANNEL_SCHEMATA.insert(deps.storage, &channel, &schema)?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::RegisterChannel {
+            status: Success,
+        })?),
+    )
+}
+
+pub fn unregister_channel(deps: DepsMut, channel: String) -> StdResult<Response> {
+    if BUILTIN_CHANNELS.contains(&channel.as_str()) {
+        return Err(StdError::generic_err(format!(
+            "`{channel}` is a built-in channel and cannot be unregistered"
+        )));
+    }
+    if !CHANNELS.contains(deps.storage, &channel) {
+        return Err(StdError::generic_err(format!(
+            "`{channel}` channel is undefined"
+        )));
+    }
+
+    CHANNELS.remove(deps.storage, &channel)?;
+    CHANNEL_SCHEMATA.remove(deps.storage, &channel)?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::UnregisterChannel {
+            status: Success,
+        })?),
+    )
+}
+
 // end SNIP-52 functions
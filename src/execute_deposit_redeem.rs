@@ -1,37 +1,89 @@
 use cosmwasm_std::{
-    to_binary, BankMsg, BlockInfo, CanonicalAddr, Coin, CosmosMsg, DepsMut, Env, MessageInfo,
-    Response, StdError, StdResult, Storage, Uint128,
+    to_binary, Addr, BankMsg, BlockInfo, CanonicalAddr, Coin, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, Response, StdError, StdResult, Storage, Uint128,
 };
 use secret_toolkit_crypto::ContractPrng;
 
 use crate::dwb::DWB;
-use crate::msg::{ExecuteAnswer, ResponseStatus::Success};
-use crate::state::{safe_add, CONFIG, TOTAL_SUPPLY};
-use crate::transaction_history::{store_deposit_action, store_redeem_action};
+use crate::execute_transfer_send::try_send_impl;
+use crate::msg::{
+    ContractStatusLevel, DepositCallback, ExecuteAnswer,
+    ResponseStatus::{Failure, Success},
+};
+use crate::notifications::add_direct_notification;
+use crate::receiver::into_callback_messages;
+use crate::state::{
+    checked_safe_add, DenomStatusStore, PreferredRedeemDenomStore, RedeemAllowancesStore,
+    RedeemRateLimitStore, ACTIVE_DEPOSIT_DENOMS, CONFIG, CONTRACT_STATUS,
+    INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED, TOTAL_SUPPLY,
+};
+use crate::strings::SEND_TO_TOKEN_CONTRACT_ERR_MSG;
+use crate::transaction_history::{store_deposit_action, store_redeem_action, TOKEN_DENOM};
 #[cfg(feature = "gas_tracking")]
 use crate::gas_tracker::GasTracker;
 
 // deposit functions
 
 pub fn try_deposit(
-    deps: DepsMut,
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    memo: Option<String>,
+    callback: Option<DepositCallback>,
+    rng: &mut ContractPrng,
+) -> StdResult<Response> {
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    let result = perform_try_deposit(
+        &mut deps,
+        env,
+        info,
+        memo,
+        callback,
+        rng,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    );
+
+    #[cfg(feature = "gas_tracking")]
+    return tracker.finish(deps.storage, result);
+
+    #[cfg(not(feature = "gas_tracking"))]
+    result
+}
+
+fn perform_try_deposit(
+    deps: &mut DepsMut,
     env: Env,
     info: MessageInfo,
+    memo: Option<String>,
+    callback: Option<DepositCallback>,
     rng: &mut ContractPrng,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
 ) -> StdResult<Response> {
     let constants = CONFIG.load(deps.storage)?;
 
+    if memo.is_none() && constants.require_memo_on_native {
+        return Err(StdError::generic_err("memo required"));
+    }
+
     let mut amount = Uint128::zero();
 
     for coin in &info.funds {
-        if constants.supported_denoms.contains(&coin.denom) {
-            amount += coin.amount
-        } else {
+        if !constants.supported_denoms.contains(&coin.denom) {
             return Err(StdError::generic_err(format!(
                 "Tried to deposit an unsupported coin {}",
                 coin.denom
             )));
         }
+        if !DenomStatusStore::load(deps.storage, &constants, &coin.denom).deposit_enabled {
+            return Err(StdError::generic_err(format!(
+                "Deposit functionality is not enabled for {}",
+                coin.denom
+            )));
+        }
+        amount += coin.amount
     }
 
     if amount.is_zero() {
@@ -40,20 +92,18 @@ pub fn try_deposit(
 
     let mut raw_amount = amount.u128();
 
-    if !constants.deposit_is_enabled {
-        return Err(StdError::generic_err(
-            "Deposit functionality is not enabled.",
-        ));
-    }
-
     let mut total_supply = TOTAL_SUPPLY.load(deps.storage)?;
-    raw_amount = safe_add(&mut total_supply, raw_amount);
+    raw_amount = checked_safe_add(&mut total_supply, raw_amount)?;
     TOTAL_SUPPLY.save(deps.storage, &total_supply)?;
 
-    let sender_address = deps.api.addr_canonicalize(info.sender.as_str())?;
+    // remember which denoms have actually been used for deposits
+    for coin in &info.funds {
+        if !coin.amount.is_zero() {
+            ACTIVE_DEPOSIT_DENOMS.insert(deps.storage, &coin.denom)?;
+        }
+    }
 
-    #[cfg(feature = "gas_tracking")]
-    let mut tracker: GasTracker = GasTracker::new(deps.api);
+    let sender_address = deps.api.addr_canonicalize(info.sender.as_str())?;
 
     // we know that funds.len() > 0, because amount > 0
     // use the first denom given for tx record
@@ -65,17 +115,63 @@ pub fn try_deposit(
         &sender_address,
         raw_amount,
         denom,
+        memo.clone(),
         &env.block,
         #[cfg(feature = "gas_tracking")]
-        &mut tracker,
+        tracker,
     )?;
 
-    let resp = Response::new().set_data(to_binary(&ExecuteAnswer::Deposit { status: Success })?);
+    let mut resp =
+        Response::new().set_data(to_binary(&ExecuteAnswer::Deposit { status: Success })?);
 
-    #[cfg(feature = "gas_tracking")]
-    return Ok(tracker.add_to_response(resp));
+    // with a callback, the deposited tokens are sent on to the callback contract just like a
+    // `Send` from the depositor, so the `Receive` it gets is backed by tokens it now holds
+    if let Some(callback) = callback {
+        let contract = deps.api.addr_validate(callback.contract.as_str())?;
+        if contract == env.contract.address {
+            return Err(StdError::generic_err(SEND_TO_TOKEN_CONTRACT_ERR_MSG));
+        }
+
+        let mut callbacks = vec![];
+        let (received_notification, spent_notification) = try_send_impl(
+            deps,
+            rng,
+            &mut callbacks,
+            info.sender.clone(),
+            contract,
+            Some(callback.code_hash),
+            Uint128::new(raw_amount),
+            TOKEN_DENOM.to_string(),
+            memo,
+            false,
+            callback.msg,
+            &env.block,
+            #[cfg(feature = "gas_tracking")]
+            tracker,
+        )?;
+
+        if NOTIFICATIONS_ENABLED.load(deps.storage)? {
+            let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
+            resp = add_direct_notification(
+                deps.storage,
+                deps.api,
+                &env,
+                &secret,
+                resp,
+                received_notification,
+            )?;
+            resp = add_direct_notification(
+                deps.storage,
+                deps.api,
+                &env,
+                &secret,
+                resp,
+                spent_notification,
+            )?;
+        }
+        resp = resp.add_messages(into_callback_messages(callbacks, false)?);
+    }
 
-    #[cfg(not(feature = "gas_tracking"))]
     Ok(resp)
 }
 
@@ -85,11 +181,12 @@ fn perform_deposit(
     to: &CanonicalAddr,
     amount: u128,
     denom: String,
+    memo: Option<String>,
     block: &BlockInfo,
     #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
 ) -> StdResult<()> {
     // first store the tx information in the global append list of txs and get the new tx id
-    let tx_id = store_deposit_action(store, amount, denom, block)?;
+    let tx_id = store_deposit_action(store, amount, denom, memo, block)?;
 
     // load delayed write buffer
     let mut dwb = DWB.load(store)?;
@@ -105,6 +202,8 @@ fn perform_deposit(
         tracker,
     )?;
 
+    dwb.checkpoint_balances(store, &[to], block.height)?;
+
     DWB.save(store, &dwb)?;
 
     Ok(())
@@ -112,26 +211,169 @@ fn perform_deposit(
 
 // redeem functions
 
+pub fn try_set_preferred_redeem_denom(
+    deps: DepsMut,
+    info: MessageInfo,
+    denom: String,
+) -> StdResult<Response> {
+    let constants = CONFIG.load(deps.storage)?;
+    if !constants.supported_denoms.contains(&denom) {
+        return Err(StdError::generic_err(format!(
+            "Cannot set preferred redeem denom to unsupported coin {denom}",
+        )));
+    }
+
+    PreferredRedeemDenomStore::save(deps.storage, &info.sender, denom)?;
+
+    let data = to_binary(&ExecuteAnswer::SetPreferredRedeemDenom { status: Success })?;
+    Ok(Response::new().set_data(data))
+}
+
+pub fn try_set_redeem_allowance(
+    deps: DepsMut,
+    info: MessageInfo,
+    spender: String,
+    amount: Uint128,
+) -> StdResult<Response> {
+    let spender = deps.api.addr_validate(spender.as_str())?;
+    if info.sender == spender {
+        return Err(StdError::generic_err(
+            "You can't set a redeem allowance for yourself",
+        ));
+    }
+
+    RedeemAllowancesStore::save(deps.storage, &info.sender, &spender, amount.u128())?;
+
+    let data = to_binary(&ExecuteAnswer::SetRedeemAllowance { status: Success })?;
+    Ok(Response::new().set_data(data))
+}
+
 pub fn try_redeem(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     amount: Uint128,
     denom: Option<String>,
+    memo: Option<String>,
 ) -> StdResult<Response> {
+    if memo.is_none() && CONFIG.load(deps.storage)?.require_memo_on_native {
+        return Err(StdError::generic_err("memo required"));
+    }
+
+    // only a redeem that would otherwise go through counts against the rate limit
+    let withdraw_denom = check_redeem(deps.as_ref(), &env, &info.sender, amount, denom)?;
+    if !RedeemRateLimitStore::try_record(deps.storage, env.block.time.seconds(), amount.u128())? {
+        return trip_redeem_circuit_breaker(
+            deps.storage,
+            ExecuteAnswer::Redeem { status: Failure },
+        );
+    }
+
+    let message = perform_redeem(
+        deps,
+        &env,
+        &info.sender,
+        amount,
+        withdraw_denom,
+        memo,
+        &info.sender,
+    )?;
+
+    let data = to_binary(&ExecuteAnswer::Redeem { status: Success })?;
+    let res = Response::new().add_message(message).set_data(data);
+    Ok(res)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn try_redeem_from(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    amount: Uint128,
+    denom: Option<String>,
+    recipient: String,
+    memo: Option<String>,
+) -> StdResult<Response> {
+    if memo.is_none() && CONFIG.load(deps.storage)?.require_memo_on_native {
+        return Err(StdError::generic_err("memo required"));
+    }
+
+    let owner = deps.api.addr_validate(owner.as_str())?;
+    let recipient = deps.api.addr_validate(recipient.as_str())?;
+
+    // check the spender's redeem allowance before touching the owner's balance
+    let allowance = RedeemAllowancesStore::load(deps.storage, &owner, &info.sender);
+    let new_allowance = allowance.checked_sub(amount.u128()).ok_or_else(|| {
+        StdError::generic_err(format!(
+            "insufficient redeem allowance: allowance={allowance}, required={amount}",
+        ))
+    })?;
+
+    // only a redeem that would otherwise go through counts against the rate limit
+    let withdraw_denom = check_redeem(deps.as_ref(), &env, &owner, amount, denom)?;
+    if !RedeemRateLimitStore::try_record(deps.storage, env.block.time.seconds(), amount.u128())? {
+        return trip_redeem_circuit_breaker(
+            deps.storage,
+            ExecuteAnswer::RedeemFrom { status: Failure },
+        );
+    }
+
+    RedeemAllowancesStore::save(deps.storage, &owner, &info.sender, new_allowance)?;
+
+    let message = perform_redeem(deps, &env, &owner, amount, withdraw_denom, memo, &recipient)?;
+
+    let data = to_binary(&ExecuteAnswer::RedeemFrom { status: Success })?;
+    let res = Response::new().add_message(message).set_data(data);
+    Ok(res)
+}
+
+/// pauses redeems after the rate limit was exceeded. the redeem itself is rejected by
+/// returning a failure status rather than an error, which would also revert the pause
+fn trip_redeem_circuit_breaker(
+    storage: &mut dyn Storage,
+    answer: ExecuteAnswer,
+) -> StdResult<Response> {
+    CONTRACT_STATUS.save(storage, &ContractStatusLevel::RedeemPaused)?;
+
+    Ok(Response::new()
+        .add_attribute("redeem_circuit_breaker", "tripped")
+        .set_data(to_binary(&answer)?))
+}
+
+/// checks, without changing any state, that `owner` can redeem `amount` for the requested
+/// coin, and returns the denom of the coin to pay out
+fn check_redeem(
+    deps: Deps,
+    env: &Env,
+    owner: &Addr,
+    amount: Uint128,
+    denom: Option<String>,
+) -> StdResult<String> {
     let constants = CONFIG.load(deps.storage)?;
-    if !constants.redeem_is_enabled {
+    if !DenomStatusStore::any_redeem_enabled(deps.storage, &constants) {
         return Err(StdError::generic_err(
             "Redeem functionality is not enabled for this token.",
         ));
     }
 
+    // if denom is none, fall back to the owner's preferred redeem denom if it is still supported
+    let preferred_denom = if denom.is_none() && constants.supported_denoms.len() > 1 {
+        PreferredRedeemDenomStore::may_load(deps.storage, owner)?
+            .filter(|preferred| constants.supported_denoms.contains(preferred))
+    } else {
+        None
+    };
+
     // if denom is none and there is only 1 supported denom then we don't need to check anything
     let withdraw_denom = if denom.is_none() && constants.supported_denoms.len() == 1 {
         constants.supported_denoms.first().unwrap().clone()
     // if denom is specified make sure it's on the list before trying to withdraw with it
     } else if denom.is_some() && constants.supported_denoms.contains(denom.as_ref().unwrap()) {
         denom.unwrap()
+    // use the preferred denom if one has been set
+    } else if let Some(preferred_denom) = preferred_denom {
+        preferred_denom
     // error handling
     } else if denom.is_none() {
         return Err(StdError::generic_err(
@@ -143,10 +385,64 @@ pub fn try_redeem(
         ));
     };
 
-    let sender_address = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if !DenomStatusStore::load(deps.storage, &constants, &withdraw_denom).redeem_enabled {
+        return Err(StdError::generic_err(format!(
+            "Redeem functionality is not enabled for {withdraw_denom}",
+        )));
+    }
+
+    let owner_address = deps.api.addr_canonicalize(owner.as_str())?;
+    let balance = DWB
+        .load(deps.storage)?
+        .account_balance(deps.storage, &owner_address)?;
+    if balance < amount.u128() {
+        return Err(StdError::generic_err(format!(
+            "insufficient funds to redeem: balance={balance}, required={amount}",
+        )));
+    }
+
+    // then make sure the total supply can cover the redeemed amount
+    if TOTAL_SUPPLY.load(deps.storage)? < amount.u128() {
+        return Err(StdError::generic_err(
+            "You are trying to redeem more tokens than what is available in the total supply",
+        ));
+    }
+
+    // make sure the contract holds enough of the underlying coin
+    let token_reserve = deps
+        .querier
+        .query_balance(&env.contract.address, &withdraw_denom)?
+        .amount;
+    if amount > token_reserve {
+        return Err(StdError::generic_err(format!(
+            "You are trying to redeem for more {withdraw_denom} than the contract has in its reserve (requested: {amount}, reserve: {token_reserve})",
+        )));
+    }
+
+    Ok(withdraw_denom)
+}
+
+/// burns `amount` from `owner`'s balance and returns the bank message paying out
+/// `withdraw_denom` to `recipient`. the redeem must already have passed `check_redeem`
+fn perform_redeem(
+    deps: DepsMut,
+    env: &Env,
+    owner: &Addr,
+    amount: Uint128,
+    withdraw_denom: String,
+    memo: Option<String>,
+    recipient: &Addr,
+) -> StdResult<CosmosMsg> {
+    let owner_address = deps.api.addr_canonicalize(owner.as_str())?;
     let amount_raw = amount.u128();
 
-    let tx_id = store_redeem_action(deps.storage, amount.u128(), constants.symbol, &env.block)?;
+    let tx_id = store_redeem_action(
+        deps.storage,
+        amount.u128(),
+        TOKEN_DENOM.to_string(),
+        memo,
+        &env.block,
+    )?;
 
     // load delayed write buffer
     let mut dwb = DWB.load(deps.storage)?;
@@ -154,10 +450,10 @@ pub fn try_redeem(
     #[cfg(feature = "gas_tracking")]
     let mut tracker = GasTracker::new(deps.api);
 
-    // settle the signer's account in buffer
+    // settle the owner's account in buffer, which also checks that the owner has enough funds
     dwb.settle_sender_or_owner_account(
         deps.storage,
-        &sender_address,
+        &owner_address,
         tx_id,
         amount_raw,
         "redeem",
@@ -166,37 +462,21 @@ pub fn try_redeem(
         &mut tracker,
     )?;
 
-    DWB.save(deps.storage, &dwb)?;
+    // check_redeem already made sure the total supply covers the redeemed amount
+    let total_supply = TOTAL_SUPPLY.load(deps.storage)?.saturating_sub(amount_raw);
 
-    let total_supply = TOTAL_SUPPLY.load(deps.storage)?;
-    if let Some(total_supply) = total_supply.checked_sub(amount_raw) {
-        TOTAL_SUPPLY.save(deps.storage, &total_supply)?;
-    } else {
-        return Err(StdError::generic_err(
-            "You are trying to redeem more tokens than what is available in the total supply",
-        ));
-    }
-
-    let token_reserve = deps
-        .querier
-        .query_balance(&env.contract.address, &withdraw_denom)?
-        .amount;
-    if amount > token_reserve {
-        return Err(StdError::generic_err(format!(
-            "You are trying to redeem for more {withdraw_denom} than the contract has in its reserve",
-        )));
-    }
+    // only persist state once every check has passed
+    dwb.checkpoint_balances(deps.storage, &[&owner_address], env.block.height)?;
+    DWB.save(deps.storage, &dwb)?;
+    TOTAL_SUPPLY.save(deps.storage, &total_supply)?;
 
     let withdrawal_coins: Vec<Coin> = vec![Coin {
         denom: withdraw_denom,
         amount,
     }];
 
-    let message = CosmosMsg::Bank(BankMsg::Send {
-        to_address: info.sender.clone().into_string(),
+    Ok(CosmosMsg::Bank(BankMsg::Send {
+        to_address: recipient.clone().into_string(),
         amount: withdrawal_coins,
-    });
-    let data = to_binary(&ExecuteAnswer::Redeem { status: Success })?;
-    let res = Response::new().add_message(message).set_data(data);
-    Ok(res)
+    }))
 }
@@ -10,13 +10,16 @@ use crate::dwb::DWB;
 use crate::execute::use_allowance;
 use crate::msg::{ExecuteAnswer, ResponseStatus::Success};
 use crate::notifications::{
-    render_group_notification, MultiRecvdNotification, MultiSpentNotification, RecvdNotification,
-    SpentNotification,
+    add_direct_notification, render_group_notification, MultiRecvdNotification,
+    MultiSpentNotification, RecvdNotification, SpentNotification,
 };
 use crate::state::{
-    safe_add, MintersStore, CONFIG, INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED, TOTAL_SUPPLY,
+    checked_safe_add, IdempotencyKeysStore, MintersStore, CONFIG, INTERNAL_SECRET_SENSITIVE,
+    NOTIFICATIONS_ENABLED, TOTAL_BURNED_TO_SINK, TOTAL_SUPPLY,
+};
+use crate::transaction_history::{
+    store_burn_action, store_mint_action, store_transfer_action, TOKEN_DENOM,
 };
-use crate::transaction_history::{store_burn_action, store_mint_action};
 #[cfg(feature = "gas_tracking")]
 use crate::gas_tracker::GasTracker;
 
@@ -31,6 +34,39 @@ pub fn try_mint(
     recipient: String,
     amount: Uint128,
     memo: Option<String>,
+) -> StdResult<Response> {
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    let result = perform_try_mint(
+        &mut deps,
+        env,
+        info,
+        rng,
+        recipient,
+        amount,
+        memo,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    );
+
+    #[cfg(feature = "gas_tracking")]
+    return tracker.finish(deps.storage, result);
+
+    #[cfg(not(feature = "gas_tracking"))]
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn perform_try_mint(
+    deps: &mut DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipient: String,
+    amount: Uint128,
+    memo: Option<String>,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
 ) -> StdResult<Response> {
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
@@ -53,26 +89,23 @@ pub fn try_mint(
     }
 
     let mut total_supply = TOTAL_SUPPLY.load(deps.storage)?;
-    let minted_amount = safe_add(&mut total_supply, amount.u128());
+    let minted_amount = checked_safe_add(&mut total_supply, amount.u128())?;
     TOTAL_SUPPLY.save(deps.storage, &total_supply)?;
 
-    #[cfg(feature = "gas_tracking")]
-    let mut tracker: GasTracker = GasTracker::new(deps.api);
-
     let memo_len = memo.as_ref().map(|s| s.len()).unwrap_or_default();
 
     // Note that even when minted_amount is equal to 0 we still want to perform the operations for logic consistency
     try_mint_impl(
-        &mut deps,
+        deps,
         rng,
         info.sender,
         recipient.clone(),
         Uint128::new(minted_amount),
-        constants.symbol,
+        TOKEN_DENOM.to_string(),
         memo,
         &env.block,
         #[cfg(feature = "gas_tracking")]
-        &mut tracker,
+        tracker,
     )?;
 
     let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::Mint { status: Success })?);
@@ -86,19 +119,17 @@ pub fn try_mint(
                 memo_len,
                 sender_is_owner: true,
             },
-        )
-        .to_txhash_notification(deps.api, &env, secret, None)?;
-
-        resp = resp.add_attribute_plaintext(
-            received_notification.id_plaintext(),
-            received_notification.data_plaintext(),
         );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            received_notification,
+        )?;
     }
 
-    #[cfg(feature = "gas_tracking")]
-    return Ok(tracker.add_to_response(resp));
-
-    #[cfg(not(feature = "gas_tracking"))]
     Ok(resp)
 }
 
@@ -108,7 +139,12 @@ pub fn try_batch_mint(
     info: MessageInfo,
     rng: &mut ContractPrng,
     actions: Vec<batch::MintAction>,
+    idempotency_key: Option<String>,
 ) -> StdResult<Response> {
+    if let Some(key) = idempotency_key {
+        IdempotencyKeysStore::consume(deps.storage, &info.sender, &key, env.block.time.seconds())?;
+    }
+
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
@@ -129,12 +165,29 @@ pub fn try_batch_mint(
 
     let mut total_supply = TOTAL_SUPPLY.load(deps.storage)?;
 
-    let mut notifications = vec![];
-    // Quick loop to check that the total of amounts is valid
-    for action in actions {
-        let actual_amount = safe_add(&mut total_supply, action.amount.u128());
+    // Preflight every action before doing any work, so a bad action fails the batch up front
+    // and the error names it
+    let mut minted_total = Uint128::new(total_supply);
+    let mut recipients = Vec::with_capacity(actions.len());
+    for (index, action) in actions.iter().enumerate() {
+        let recipient = deps
+            .api
+            .addr_validate(action.recipient.as_str())
+            .map_err(|err| {
+                StdError::generic_err(format!("Invalid recipient in action {index}: {err}"))
+            })?;
+        recipients.push(recipient);
+
+        minted_total = minted_total.checked_add(action.amount).map_err(|_| {
+            StdError::generic_err(format!(
+                "mint would overflow total supply at action {index}"
+            ))
+        })?;
+    }
 
-        let recipient = deps.api.addr_validate(action.recipient.as_str())?;
+    let mut notifications = vec![];
+    for (action, recipient) in actions.into_iter().zip(recipients) {
+        let actual_amount = checked_safe_add(&mut total_supply, action.amount.u128())?;
 
         #[cfg(feature = "gas_tracking")]
         let mut tracker: GasTracker = GasTracker::new(deps.api);
@@ -155,7 +208,7 @@ pub fn try_batch_mint(
             info.sender.clone(),
             recipient,
             Uint128::new(actual_amount),
-            constants.symbol.clone(),
+            TOKEN_DENOM.to_string(),
             action.memo,
             &env.block,
             #[cfg(feature = "gas_tracking")]
@@ -170,8 +223,10 @@ pub fn try_batch_mint(
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
         resp = render_group_notification(
+            deps.storage,
             deps.api,
-            MultiRecvdNotification(notifications),
+            MultiRecvdNotification::new(notifications, constants.aggregate_batch_notifications),
+            env.block.height,
             &env.transaction.unwrap().hash,
             env.block.random.unwrap(),
             secret,
@@ -182,6 +237,32 @@ pub fn try_batch_mint(
     Ok(resp)
 }
 
+pub fn try_mint_campaign(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    actions: Vec<batch::MintAction>,
+    campaign_cap: Uint128,
+) -> StdResult<Response> {
+    // check the whole campaign against its cap before minting anything
+    let campaign_total = actions
+        .iter()
+        .try_fold(Uint128::zero(), |total, action| {
+            total.checked_add(action.amount)
+        })
+        .map_err(|_| StdError::generic_err("campaign total overflows"))?;
+    if campaign_total > campaign_cap {
+        return Err(StdError::generic_err(format!(
+            "campaign total {campaign_total} exceeds campaign cap {campaign_cap}",
+        )));
+    }
+
+    let resp = try_batch_mint(deps, env, info, rng, actions, None)?;
+
+    Ok(resp.set_data(to_binary(&ExecuteAnswer::MintCampaign { status: Success })?))
+}
+
 #[allow(clippy::too_many_arguments)]
 fn try_mint_impl(
     deps: &mut DepsMut,
@@ -258,6 +339,8 @@ pub fn perform_mint(
         tracker,
     )?;
 
+    dwb.checkpoint_balances(store, &[to], block.height)?;
+
     DWB.save(store, &dwb)?;
 
     Ok(())
@@ -297,7 +380,7 @@ pub fn try_burn(
         raw_burn_address.clone(),
         raw_burn_address.clone(),
         raw_amount,
-        constants.symbol,
+        TOKEN_DENOM.to_string(),
         memo,
         &env.block,
     )?;
@@ -320,6 +403,8 @@ pub fn try_burn(
         &mut tracker,
     )?;
 
+    dwb.checkpoint_balances(deps.storage, &[&raw_burn_address], env.block.height)?;
+
     DWB.save(deps.storage, &dwb)?;
 
     let mut total_supply = TOTAL_SUPPLY.load(deps.storage)?;
@@ -344,13 +429,122 @@ pub fn try_burn(
                 balance: owner_balance,
                 memo_len,
             },
-        )
-        .to_txhash_notification(deps.api, &env, secret, None)?;
+        );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
+    }
+
+    Ok(resp)
+}
+
+/// Burn tokens to the burn sink
+///
+/// Remove `amount` tokens from the system irreversibly, from signer account, recording the burn
+/// as a transfer to the configured burn sink. The sink is never credited.
+///
+/// @param amount the amount of money to burn
+pub fn try_burn_to(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+    memo: Option<String>,
+) -> StdResult<Response> {
+    let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
+    let secret = secret.as_slice();
 
-        resp = resp.add_attribute_plaintext(
-            spent_notification.id_plaintext(),
-            spent_notification.data_plaintext(),
+    let constants = CONFIG.load(deps.storage)?;
+    if !constants.burn_is_enabled {
+        return Err(StdError::generic_err(
+            "Burn functionality is not enabled for this token.",
+        ));
+    }
+    let sink = constants
+        .burn_sink
+        .ok_or_else(|| StdError::generic_err("No burn sink address is configured"))?;
+
+    let raw_amount = amount.u128();
+    let raw_owner = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let raw_sink = deps.api.addr_canonicalize(sink.as_str())?;
+
+    let memo_len = memo.as_ref().map(|s| s.len()).unwrap_or_default();
+
+    let tx_id = store_transfer_action(
+        deps.storage,
+        &raw_owner,
+        &raw_owner,
+        &raw_sink,
+        raw_amount,
+        TOKEN_DENOM.to_string(),
+        memo,
+        false,
+        &env.block,
+    )?;
+
+    // load delayed write buffer
+    let mut dwb = DWB.load(deps.storage)?;
+
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker = GasTracker::new(deps.api);
+
+    // settle the signer's account in buffer; the sink is deliberately not added as a recipient
+    let owner_balance = dwb.settle_sender_or_owner_account(
+        deps.storage,
+        &raw_owner,
+        tx_id,
+        raw_amount,
+        "burn",
+        false,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    )?;
+
+    dwb.checkpoint_balances(deps.storage, &[&raw_owner], env.block.height)?;
+
+    DWB.save(deps.storage, &dwb)?;
+
+    let mut total_supply = TOTAL_SUPPLY.load(deps.storage)?;
+    if let Some(new_total_supply) = total_supply.checked_sub(raw_amount) {
+        total_supply = new_total_supply;
+    } else {
+        return Err(StdError::generic_err(
+            "You're trying to burn more than is available in the total supply",
+        ));
+    }
+    TOTAL_SUPPLY.save(deps.storage, &total_supply)?;
+
+    let total_burned = TOTAL_BURNED_TO_SINK
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    TOTAL_BURNED_TO_SINK.save(deps.storage, &total_burned.saturating_add(raw_amount))?;
+
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::BurnTo { status: Success })?);
+
+    if NOTIFICATIONS_ENABLED.load(deps.storage)? {
+        let spent_notification = Notification::new(
+            info.sender,
+            SpentNotification {
+                amount: raw_amount,
+                actions: 1,
+                recipient: Some(sink),
+                balance: owner_balance,
+                memo_len,
+            },
         );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
     }
 
     Ok(resp)
@@ -378,9 +572,13 @@ pub fn try_burn_from(
     }
 
     let raw_amount = amount.u128();
-    use_allowance(deps.storage, env, &owner, &info.sender, raw_amount)?;
     let raw_burner = deps.api.addr_canonicalize(info.sender.as_str())?;
 
+    // an owner burning their own tokens does not need an allowance
+    if raw_burner != raw_owner {
+        use_allowance(deps.storage, env, &owner, &info.sender, raw_amount)?;
+    }
+
     let memo_len = memo.as_ref().map(|s| s.len()).unwrap_or_default();
 
     // store the event
@@ -389,7 +587,7 @@ pub fn try_burn_from(
         raw_owner.clone(),
         raw_burner.clone(),
         raw_amount,
-        constants.symbol,
+        TOKEN_DENOM.to_string(),
         memo,
         &env.block,
     )?;
@@ -427,6 +625,8 @@ pub fn try_burn_from(
         )?;
     }
 
+    dwb.checkpoint_balances(deps.storage, &[&raw_owner], env.block.height)?;
+
     DWB.save(deps.storage, &dwb)?;
 
     // remove from supply
@@ -455,13 +655,15 @@ pub fn try_burn_from(
                 balance: owner_balance,
                 memo_len,
             },
-        )
-        .to_txhash_notification(deps.api, env, secret, None)?;
-
-        resp = resp.add_attribute_plaintext(
-            spent_notification.id_plaintext(),
-            spent_notification.data_plaintext(),
         );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
     }
 
     Ok(resp)
@@ -472,7 +674,12 @@ pub fn try_batch_burn_from(
     env: &Env,
     info: MessageInfo,
     actions: Vec<batch::BurnFromAction>,
+    idempotency_key: Option<String>,
 ) -> StdResult<Response> {
+    if let Some(key) = idempotency_key {
+        IdempotencyKeysStore::consume(deps.storage, &info.sender, &key, env.block.time.seconds())?;
+    }
+
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
@@ -491,14 +698,16 @@ pub fn try_batch_burn_from(
         let owner = deps.api.addr_validate(action.owner.as_str())?;
         let raw_owner = deps.api.addr_canonicalize(owner.as_str())?;
         let amount = action.amount.u128();
-        use_allowance(deps.storage, env, &owner, &info.sender, amount)?;
+        if raw_spender != raw_owner {
+            use_allowance(deps.storage, env, &owner, &info.sender, amount)?;
+        }
 
         let tx_id = store_burn_action(
             deps.storage,
             raw_owner.clone(),
             raw_spender.clone(),
             amount,
-            constants.symbol.clone(),
+            TOKEN_DENOM.to_string(),
             action.memo.clone(),
             &env.block,
         )?;
@@ -536,6 +745,8 @@ pub fn try_batch_burn_from(
             )?;
         }
 
+        dwb.checkpoint_balances(deps.storage, &[&raw_owner], env.block.height)?;
+
         DWB.save(deps.storage, &dwb)?;
 
         // remove from supply
@@ -567,8 +778,13 @@ pub fn try_batch_burn_from(
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
         resp = render_group_notification(
+            deps.storage,
             deps.api,
-            MultiSpentNotification(spent_notifications),
+            MultiSpentNotification::new(
+                spent_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
             &env.transaction.clone().unwrap().hash,
             env.block.random.clone().unwrap(),
             secret,
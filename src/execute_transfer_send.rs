@@ -1,22 +1,31 @@
+use bech32::{ToBase32, Variant};
 use cosmwasm_std::{
     to_binary, Addr, Binary, BlockInfo, CanonicalAddr, CosmosMsg, DepsMut, Env, MessageInfo,
-    Response, StdError, StdResult, Storage, Uint128,
+    Response, StdError, StdResult, Storage, SubMsg, SubMsgResult, Uint128, WasmMsg,
 };
 use secret_toolkit::notification::Notification;
-use secret_toolkit_crypto::ContractPrng;
+use secret_toolkit::permit::{pubkey_to_account, PermitSignature};
+use secret_toolkit_crypto::{sha_256, ContractPrng};
 
 use crate::batch;
 use crate::dwb::DWB;
 use crate::execute::use_allowance;
-use crate::msg::{ExecuteAnswer, ResponseStatus::Success};
+use crate::msg::{ExecuteAnswer, RemainderPolicy, ResponseStatus::Success, TransferAuthorization};
 use crate::notifications::{
-    render_group_notification, MultiRecvdNotification, MultiSpentNotification, RecvdNotification,
-    SpentNotification,
+    add_direct_notification, render_group_notification, total_spent_amount, AllowanceNotification,
+    MultiRecvdNotification, MultiSpentNotification, RecvdNotification, SpentNotification,
+};
+use crate::receiver::{into_callback_messages, ReceiverCallback, Snip20ReceiveMsg};
+use crate::state::{
+    AllowancesStore, AuthNoncesStore, IdempotencyKeysStore, PendingCallbacksStore, PendingTransfer,
+    PendingTransferStatus, PendingTransfersStore, ReceiverHashStore, Stream, StreamsStore,
+    CALLBACK_REPLY_ID, CONFIG, INTERNAL_SECRET_RELAXED, INTERNAL_SECRET_SENSITIVE,
+    NOTIFICATIONS_ENABLED, TOTAL_SUPPLY, TX_COUNT,
+};
+use crate::strings::{SEND_TO_CONTRACT_ERR_MSG, SEND_TO_TOKEN_CONTRACT_ERR_MSG};
+use crate::transaction_history::{
+    obfuscated_tx_id, store_burn_action, store_transfer_action, TOKEN_DENOM,
 };
-use crate::receiver::Snip20ReceiveMsg;
-use crate::state::{ReceiverHashStore, CONFIG, INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED};
-use crate::strings::SEND_TO_CONTRACT_ERR_MSG;
-use crate::transaction_history::store_transfer_action;
 #[cfg(feature = "gas_tracking")]
 use crate::gas_tracker::GasTracker;
 
@@ -31,79 +40,462 @@ pub fn try_transfer(
     recipient: String,
     amount: Uint128,
     memo: Option<String>,
+    memo_encrypted: bool,
+    want_receipt: bool,
+) -> StdResult<Response> {
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    let result = perform_try_transfer(
+        &mut deps,
+        env,
+        info,
+        rng,
+        recipient,
+        amount,
+        memo,
+        memo_encrypted,
+        want_receipt,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    );
+
+    #[cfg(feature = "gas_tracking")]
+    return tracker.finish(deps.storage, result);
+
+    #[cfg(not(feature = "gas_tracking"))]
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn perform_try_transfer(
+    deps: &mut DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipient: String,
+    amount: Uint128,
+    memo: Option<String>,
+    memo_encrypted: bool,
+    want_receipt: bool,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
 ) -> StdResult<Response> {
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
     let recipient: Addr = deps.api.addr_validate(recipient.as_str())?;
 
-    let symbol = CONFIG.load(deps.storage)?.symbol;
-
-    // make sure the sender is not accidentally sending tokens to the contract address
-    if recipient == env.contract.address {
-        return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
-    }
+    let denom = TOKEN_DENOM.to_string();
 
-    #[cfg(feature = "gas_tracking")]
-    let mut tracker: GasTracker = GasTracker::new(deps.api);
+    // a plain transfer to the contract address is allowed (e.g. as a donation), since unlike a
+    // send it does not call back into the token contract
 
     // perform the transfer
     let (received_notification, spent_notification) = try_transfer_impl(
-        &mut deps,
+        deps,
         rng,
         &info.sender,
         &recipient,
         amount,
-        symbol,
+        denom,
         memo,
+        memo_encrypted,
         &env.block,
         #[cfg(feature = "gas_tracking")]
-        &mut tracker,
+        tracker,
     )?;
 
     #[cfg(feature = "gas_tracking")]
     let mut group1 = tracker.group("try_transfer.rest");
 
-    let mut resp =
-        Response::new().set_data(to_binary(&ExecuteAnswer::Transfer { status: Success })?);
+    let receipt = if want_receipt {
+        Some(transfer_receipt(
+            obfuscated_tx_id(
+                &INTERNAL_SECRET_RELAXED.load(deps.storage)?,
+                TX_COUNT.load(deps.storage)?,
+            ),
+            &info.sender,
+            &recipient,
+            amount,
+            env.block.height,
+        ))
+    } else {
+        None
+    };
+
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::Transfer {
+        status: Success,
+        receipt,
+    })?);
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
         // render the tokens received notification
-        let received_notification =
-            received_notification.to_txhash_notification(deps.api, &env, secret, None)?;
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            received_notification,
+        )?;
 
         // render the tokens spent notification
-        let spent_notification =
-            spent_notification.to_txhash_notification(deps.api, &env, secret, None)?;
-
-        resp = resp
-            .add_attribute_plaintext(
-                received_notification.id_plaintext(),
-                received_notification.data_plaintext(),
-            )
-            .add_attribute_plaintext(
-                spent_notification.id_plaintext(),
-                spent_notification.data_plaintext(),
-            );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
     }
 
     #[cfg(feature = "gas_tracking")]
     group1.log("rest");
 
-    #[cfg(feature = "gas_tracking")]
-    return Ok(tracker.add_to_response(resp));
-
-    #[cfg(not(feature = "gas_tracking"))]
     Ok(resp)
 }
 
+pub fn try_transfer_all(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipient: String,
+    memo: Option<String>,
+    leave: Uint128,
+) -> StdResult<Response> {
+    // resolve the full balance within this tx, so nothing pending in the buffer is left behind
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let balance = DWB
+        .load(deps.storage)?
+        .account_balance(deps.storage, &sender_raw)?;
+    let leave = leave.u128();
+    if leave > balance {
+        return Err(StdError::generic_err(format!(
+            "insufficient funds to leave: balance={balance}, leave={leave}"
+        )));
+    }
+    if balance == leave {
+        return Err(StdError::generic_err("No funds to transfer"));
+    }
+
+    let amount = Uint128::new(balance - leave);
+    let resp = try_transfer(deps, env, info, rng, recipient, amount, memo, false, false)?;
+
+    Ok(resp.set_data(to_binary(&ExecuteAnswer::TransferAll {
+        amount,
+        status: Success,
+    })?))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn try_transfer_with_timeout(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipient: String,
+    amount: Uint128,
+    refund_after: u64,
+    memo: Option<String>,
+) -> StdResult<Response> {
+    let recipient = deps.api.addr_validate(recipient.as_str())?;
+    if recipient == env.contract.address {
+        return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
+    }
+    if amount.is_zero() {
+        return Err(StdError::generic_err("transfer amount must be positive"));
+    }
+    if refund_after <= env.block.time.seconds() {
+        return Err(StdError::generic_err("refund_after must be in the future"));
+    }
+
+    // hold the amount in the contract's own account until the transfer is resolved
+    let denom = TOKEN_DENOM.to_string();
+    perform_escrow_transfer(
+        &mut deps,
+        rng,
+        &info.sender,
+        &env.contract.address,
+        amount.u128(),
+        denom,
+        memo.clone(),
+        &env.block,
+    )?;
+
+    let id = PendingTransfersStore::create(
+        deps.storage,
+        &PendingTransfer {
+            sender: info.sender,
+            recipient,
+            amount: amount.u128(),
+            refund_after,
+            memo,
+            status: PendingTransferStatus::Pending,
+        },
+    )?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::TransferWithTimeout {
+            status: Success,
+            id,
+        })?),
+    )
+}
+
+pub fn try_claim_pending(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    id: u64,
+) -> StdResult<Response> {
+    let mut transfer = PendingTransfersStore::load(deps.storage, id)?;
+    if transfer.recipient != info.sender {
+        return Err(StdError::generic_err(format!(
+            "Pending transfer {id} not found"
+        )));
+    }
+    ensure_unresolved(id, &transfer)?;
+    if env.block.time.seconds() >= transfer.refund_after {
+        return Err(StdError::generic_err(format!(
+            "Pending transfer {id} expired at {} and can only be refunded",
+            transfer.refund_after
+        )));
+    }
+
+    transfer.status = PendingTransferStatus::Claimed;
+    PendingTransfersStore::save(deps.storage, id, &transfer)?;
+
+    let denom = TOKEN_DENOM.to_string();
+    perform_escrow_transfer(
+        &mut deps,
+        rng,
+        &env.contract.address,
+        &transfer.recipient,
+        transfer.amount,
+        denom,
+        transfer.memo,
+        &env.block,
+    )?;
+
+    Ok(Response::new().set_data(to_binary(&ExecuteAnswer::ClaimPending { status: Success })?))
+}
+
+pub fn try_refund_pending(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    id: u64,
+) -> StdResult<Response> {
+    let mut transfer = PendingTransfersStore::load(deps.storage, id)?;
+    if transfer.sender != info.sender {
+        return Err(StdError::generic_err(format!(
+            "Pending transfer {id} not found"
+        )));
+    }
+    ensure_unresolved(id, &transfer)?;
+    if env.block.time.seconds() < transfer.refund_after {
+        return Err(StdError::generic_err(format!(
+            "Pending transfer {id} cannot be refunded before {}",
+            transfer.refund_after
+        )));
+    }
+
+    transfer.status = PendingTransferStatus::Refunded;
+    PendingTransfersStore::save(deps.storage, id, &transfer)?;
+
+    let denom = TOKEN_DENOM.to_string();
+    perform_escrow_transfer(
+        &mut deps,
+        rng,
+        &env.contract.address,
+        &transfer.sender,
+        transfer.amount,
+        denom,
+        None,
+        &env.block,
+    )?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::RefundPending {
+            status: Success,
+        })?),
+    )
+}
+
+/// a pending transfer can only be claimed or refunded once
+fn ensure_unresolved(id: u64, transfer: &PendingTransfer) -> StdResult<()> {
+    match transfer.status {
+        PendingTransferStatus::Pending => Ok(()),
+        PendingTransferStatus::Claimed => Err(StdError::generic_err(format!(
+            "Pending transfer {id} has already been claimed"
+        ))),
+        PendingTransferStatus::Refunded => Err(StdError::generic_err(format!(
+            "Pending transfer {id} has already been refunded"
+        ))),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn try_create_stream(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipient: String,
+    total: Uint128,
+    start_time: u64,
+    end_time: u64,
+) -> StdResult<Response> {
+    let recipient = deps.api.addr_validate(recipient.as_str())?;
+    if recipient == env.contract.address {
+        return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
+    }
+    if total.is_zero() {
+        return Err(StdError::generic_err("stream total must be positive"));
+    }
+    if end_time <= start_time {
+        return Err(StdError::generic_err(
+            "stream end_time must be after start_time",
+        ));
+    }
+    if end_time <= env.block.time.seconds() {
+        return Err(StdError::generic_err(
+            "stream end_time must be in the future",
+        ));
+    }
+
+    // hold the total in the contract's own account until it is withdrawn
+    let denom = TOKEN_DENOM.to_string();
+    perform_escrow_transfer(
+        &mut deps,
+        rng,
+        &info.sender,
+        &env.contract.address,
+        total.u128(),
+        denom,
+        None,
+        &env.block,
+    )?;
+
+    let id = StreamsStore::create(
+        deps.storage,
+        &Stream {
+            sender: info.sender,
+            recipient,
+            total: total.u128(),
+            claimed: 0,
+            start_time,
+            end_time,
+        },
+    )?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::CreateStream {
+            status: Success,
+            id,
+        })?),
+    )
+}
+
+pub fn try_withdraw_stream(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    id: u64,
+) -> StdResult<Response> {
+    let mut stream = StreamsStore::load(deps.storage, id)?;
+    if stream.recipient != info.sender {
+        return Err(StdError::generic_err(format!("Stream {id} not found")));
+    }
+
+    let amount = stream.claimable(env.block.time.seconds());
+    if amount == 0 {
+        return Err(StdError::generic_err(format!(
+            "Nothing to withdraw from stream {id}"
+        )));
+    }
+
+    stream.claimed += amount;
+    StreamsStore::save(deps.storage, id, &stream)?;
+
+    let denom = TOKEN_DENOM.to_string();
+    perform_escrow_transfer(
+        &mut deps,
+        rng,
+        &env.contract.address,
+        &stream.recipient,
+        amount,
+        denom,
+        None,
+        &env.block,
+    )?;
+
+    Ok(
+        Response::new().set_data(to_binary(&ExecuteAnswer::WithdrawStream {
+            status: Success,
+            amount: Uint128::new(amount),
+        })?),
+    )
+}
+
 pub fn try_batch_transfer(
     mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     rng: &mut ContractPrng,
     actions: Vec<batch::TransferAction>,
+    deadline: Option<u64>,
+    idempotency_key: Option<String>,
+) -> StdResult<Response> {
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    let result = perform_try_batch_transfer(
+        &mut deps,
+        env,
+        info,
+        rng,
+        actions,
+        deadline,
+        idempotency_key,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    );
+
+    #[cfg(feature = "gas_tracking")]
+    return tracker.finish(deps.storage, result);
+
+    #[cfg(not(feature = "gas_tracking"))]
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn perform_try_batch_transfer(
+    deps: &mut DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    actions: Vec<batch::TransferAction>,
+    deadline: Option<u64>,
+    idempotency_key: Option<String>,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
 ) -> StdResult<Response> {
+    if let Some(deadline) = deadline {
+        if env.block.time.seconds() > deadline {
+            return Err(StdError::generic_err(format!(
+                "Batch deadline {deadline} has passed",
+            )));
+        }
+    }
+    if let Some(key) = idempotency_key {
+        IdempotencyKeysStore::consume(deps.storage, &info.sender, &key, env.block.time.seconds())?;
+    }
+
     let num_actions = actions.len();
     if num_actions == 0 {
         return Ok(
@@ -116,85 +508,443 @@ pub fn try_batch_transfer(
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
-    let symbol = CONFIG.load(deps.storage)?.symbol;
-
-    let mut total_memo_len = 0;
-
-    #[cfg(feature = "gas_tracking")]
-    let mut tracker: GasTracker = GasTracker::new(deps.api);
+    let constants = CONFIG.load(deps.storage)?;
+
+    let mut total_memo_len = 0;
+
+    let mut notifications = vec![];
+    for action in actions {
+        let recipient = deps.api.addr_validate(action.recipient.as_str())?;
+
+        // make sure the sender is not accidentally sending tokens to the contract address
+        if recipient == env.contract.address {
+            return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
+        }
+
+        total_memo_len += action.memo.as_ref().map(|s| s.len()).unwrap_or_default();
+
+        let (received_notification, spent_notification) = try_transfer_impl(
+            deps,
+            rng,
+            &info.sender,
+            &recipient,
+            action.amount,
+            TOKEN_DENOM.to_string(),
+            action.memo,
+            false,
+            &env.block,
+            #[cfg(feature = "gas_tracking")]
+            tracker,
+        )?;
+
+        notifications.push((received_notification, spent_notification));
+    }
+
+    let (received_notifications, spent_notifications): (
+        Vec<Notification<RecvdNotification>>,
+        Vec<Notification<SpentNotification>>,
+    ) = notifications.into_iter().unzip();
+
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::BatchTransfer {
+        status: Success,
+    })?);
+
+    if NOTIFICATIONS_ENABLED.load(deps.storage)? {
+        resp = render_group_notification(
+            deps.storage,
+            deps.api,
+            MultiRecvdNotification::new(
+                received_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
+            &env.transaction.clone().unwrap().hash,
+            env.block.random.clone().unwrap(),
+            secret,
+            resp,
+        )?;
+
+        let total_amount_spent = total_spent_amount(&spent_notifications)?;
+
+        let spent_notification = Notification::new(
+            info.sender,
+            SpentNotification {
+                amount: total_amount_spent,
+                actions: num_actions as u32,
+                recipient: spent_notifications[0].data.recipient.clone(),
+                balance: spent_notifications.last().unwrap().data.balance,
+                memo_len: total_memo_len,
+            },
+        );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
+    }
+
+    Ok(resp)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn try_transfer_split(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipients: Vec<String>,
+    total: Uint128,
+    memo: Option<String>,
+    remainder_policy: RemainderPolicy,
+) -> StdResult<Response> {
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    let result = perform_try_transfer_split(
+        &mut deps,
+        env,
+        info,
+        rng,
+        recipients,
+        total,
+        memo,
+        remainder_policy,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    );
+
+    #[cfg(feature = "gas_tracking")]
+    return tracker.finish(deps.storage, result);
+
+    #[cfg(not(feature = "gas_tracking"))]
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn perform_try_transfer_split(
+    deps: &mut DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipients: Vec<String>,
+    total: Uint128,
+    memo: Option<String>,
+    remainder_policy: RemainderPolicy,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
+) -> StdResult<Response> {
+    if recipients.is_empty() {
+        return Err(StdError::generic_err("recipients must not be empty"));
+    }
+
+    let mut validated: Vec<Addr> = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let recipient = deps.api.addr_validate(recipient.as_str())?;
+
+        // make sure the sender is not accidentally sending tokens to the contract address
+        if recipient == env.contract.address {
+            return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
+        }
+        if validated.contains(&recipient) {
+            return Err(StdError::generic_err(format!(
+                "duplicate recipient: {recipient}"
+            )));
+        }
+        validated.push(recipient);
+    }
+
+    // every recipient gets an equal share, and the remainder either goes to the first or last
+    // recipient or is burned, so that the amounts debited add up to exactly `total`
+    let num_recipients = validated.len() as u128;
+    let share = total.u128() / num_recipients;
+    let remainder = total.u128() % num_recipients;
+    let remainder_index = match remainder_policy {
+        RemainderPolicy::FirstRecipient => Some(0),
+        RemainderPolicy::LastRecipient => Some(validated.len() - 1),
+        RemainderPolicy::Burn => None,
+    };
+
+    let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
+    let secret = secret.as_slice();
+
+    let constants = CONFIG.load(deps.storage)?;
+    if remainder_policy == RemainderPolicy::Burn && !constants.burn_is_enabled {
+        return Err(StdError::generic_err(
+            "Burn functionality is not enabled for this token.",
+        ));
+    }
+
+    let mut notifications = vec![];
+    for (i, recipient) in validated.iter().enumerate() {
+        let amount = if Some(i) == remainder_index {
+            share + remainder
+        } else {
+            share
+        };
+
+        let (received_notification, spent_notification) = try_transfer_impl(
+            deps,
+            rng,
+            &info.sender,
+            recipient,
+            Uint128::new(amount),
+            TOKEN_DENOM.to_string(),
+            memo.clone(),
+            false,
+            &env.block,
+            #[cfg(feature = "gas_tracking")]
+            tracker,
+        )?;
+
+        notifications.push((received_notification, spent_notification));
+    }
+
+    let (received_notifications, spent_notifications): (
+        Vec<Notification<RecvdNotification>>,
+        Vec<Notification<SpentNotification>>,
+    ) = notifications.into_iter().unzip();
+
+    let mut sender_balance = spent_notifications.last().unwrap().data.balance;
+    if remainder_index.is_none() && remainder > 0 {
+        sender_balance = burn_split_remainder(
+            deps.storage,
+            &env.block,
+            &deps.api.addr_canonicalize(info.sender.as_str())?,
+            remainder,
+            TOKEN_DENOM.to_string(),
+            memo.clone(),
+            #[cfg(feature = "gas_tracking")]
+            tracker,
+        )?;
+    }
+
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::TransferSplit {
+        status: Success,
+    })?);
+
+    if NOTIFICATIONS_ENABLED.load(deps.storage)? {
+        resp = render_group_notification(
+            deps.storage,
+            deps.api,
+            MultiRecvdNotification::new(
+                received_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
+            &env.transaction.clone().unwrap().hash,
+            env.block.random.clone().unwrap(),
+            secret,
+            resp,
+        )?;
+
+        let memo_len = memo.as_ref().map(|s| s.len()).unwrap_or_default();
+        let spent_notification = Notification::new(
+            info.sender,
+            SpentNotification {
+                amount: total.u128(),
+                actions: validated.len() as u32,
+                recipient: spent_notifications[0].data.recipient.clone(),
+                balance: sender_balance,
+                memo_len: memo_len * validated.len(),
+            },
+        );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
+    }
+
+    Ok(resp)
+}
+
+/// Burns the indivisible remainder of a split transfer from the sender's account, returning
+/// the sender's resulting balance
+#[allow(clippy::too_many_arguments)]
+fn burn_split_remainder(
+    store: &mut dyn Storage,
+    block: &BlockInfo,
+    sender: &CanonicalAddr,
+    remainder: u128,
+    denom: String,
+    memo: Option<String>,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
+) -> StdResult<u128> {
+    let tx_id = store_burn_action(
+        store,
+        sender.clone(),
+        sender.clone(),
+        remainder,
+        denom,
+        memo,
+        block,
+    )?;
+
+    let mut dwb = DWB.load(store)?;
+    let sender_balance = dwb.settle_sender_or_owner_account(
+        store,
+        sender,
+        tx_id,
+        remainder,
+        "burn",
+        false,
+        #[cfg(feature = "gas_tracking")]
+        tracker,
+    )?;
+    dwb.checkpoint_balances(store, &[sender], block.height)?;
+    DWB.save(store, &dwb)?;
+
+    let mut total_supply = TOTAL_SUPPLY.load(store)?;
+    if let Some(new_total_supply) = total_supply.checked_sub(remainder) {
+        total_supply = new_total_supply;
+    } else {
+        return Err(StdError::generic_err(
+            "You're trying to burn more than is available in the total supply",
+        ));
+    }
+    TOTAL_SUPPLY.save(store, &total_supply)?;
+
+    Ok(sender_balance)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn try_transfer_with_auth(
+    mut deps: DepsMut,
+    env: Env,
+    rng: &mut ContractPrng,
+    from: String,
+    to: String,
+    amount: Uint128,
+    nonce: u64,
+    signature: PermitSignature,
+) -> StdResult<Response> {
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    let result = perform_try_transfer_with_auth(
+        &mut deps,
+        env,
+        rng,
+        from,
+        to,
+        amount,
+        nonce,
+        signature,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    );
+
+    #[cfg(feature = "gas_tracking")]
+    return tracker.finish(deps.storage, result);
+
+    #[cfg(not(feature = "gas_tracking"))]
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn perform_try_transfer_with_auth(
+    deps: &mut DepsMut,
+    env: Env,
+    rng: &mut ContractPrng,
+    from: String,
+    to: String,
+    amount: Uint128,
+    nonce: u64,
+    signature: PermitSignature,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
+) -> StdResult<Response> {
+    let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
+    let secret = secret.as_slice();
+
+    let from = deps.api.addr_validate(from.as_str())?;
+    let to = deps.api.addr_validate(to.as_str())?;
 
-    let mut notifications = vec![];
-    for action in actions {
-        let recipient = deps.api.addr_validate(action.recipient.as_str())?;
+    // make sure the sender is not accidentally sending tokens to the contract address
+    if to == env.contract.address {
+        return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
+    }
 
-        // make sure the sender is not accidentally sending tokens to the contract address
-        if recipient == env.contract.address {
-            return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
-        }
+    // the signing key must belong to `from`
+    let pubkey = &signature.pub_key.value;
+    let signer = bech32::encode(
+        "secret",
+        pubkey_to_account(pubkey).as_slice().to_base32(),
+        Variant::Bech32,
+    )
+    .map_err(|err| StdError::generic_err(err.to_string()))?;
+    if signer != from.as_str() {
+        return Err(StdError::generic_err(format!(
+            "authorization must be signed by {from}, got {signer}"
+        )));
+    }
 
-        total_memo_len += action.memo.as_ref().map(|s| s.len()).unwrap_or_default();
+    // and the signature must cover this exact transfer on this contract and chain
+    let authorization = TransferAuthorization {
+        chain_id: env.block.chain_id.clone(),
+        contract: env.contract.address.clone(),
+        from: from.clone(),
+        to: to.clone(),
+        amount,
+        nonce,
+    };
+    let signed_bytes_hash = sha_256(to_binary(&authorization)?.as_slice());
+    let verified = deps
+        .api
+        .secp256k1_verify(&signed_bytes_hash, &signature.signature.0, &pubkey.0)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    if !verified {
+        return Err(StdError::generic_err(
+            "Failed to verify signature for transfer authorization",
+        ));
+    }
 
-        let (received_notification, spent_notification) = try_transfer_impl(
-            &mut deps,
-            rng,
-            &info.sender,
-            &recipient,
-            action.amount,
-            symbol.clone(),
-            action.memo,
-            &env.block,
-            #[cfg(feature = "gas_tracking")]
-            &mut tracker,
-        )?;
+    // reject reused or out-of-order authorizations
+    AuthNoncesStore::consume(deps.storage, &from, nonce)?;
 
-        notifications.push((received_notification, spent_notification));
-    }
+    let denom = TOKEN_DENOM.to_string();
 
-    let (received_notifications, spent_notifications): (
-        Vec<Notification<RecvdNotification>>,
-        Vec<Notification<SpentNotification>>,
-    ) = notifications.into_iter().unzip();
+    let (received_notification, spent_notification) = try_transfer_impl(
+        deps,
+        rng,
+        &from,
+        &to,
+        amount,
+        denom,
+        None,
+        false,
+        &env.block,
+        #[cfg(feature = "gas_tracking")]
+        tracker,
+    )?;
 
-    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::BatchTransfer {
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::TransferWithAuth {
         status: Success,
     })?);
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
-        resp = render_group_notification(
+        resp = add_direct_notification(
+            deps.storage,
             deps.api,
-            MultiRecvdNotification(received_notifications),
-            &env.transaction.clone().unwrap().hash,
-            env.block.random.clone().unwrap(),
+            &env,
             secret,
             resp,
+            received_notification,
+        )?;
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
         )?;
-
-        let total_amount_spent = spent_notifications.iter().fold(0u128, |acc, notification| {
-            acc.saturating_add(notification.data.amount)
-        });
-
-        let spent_notification = Notification::new(
-            info.sender,
-            SpentNotification {
-                amount: total_amount_spent,
-                actions: num_actions as u32,
-                recipient: spent_notifications[0].data.recipient.clone(),
-                balance: spent_notifications.last().unwrap().data.balance,
-                memo_len: total_memo_len,
-            },
-        )
-        .to_txhash_notification(deps.api, &env, secret, None)?;
-
-        resp = resp.add_attribute_plaintext(
-            spent_notification.id_plaintext(),
-            spent_notification.data_plaintext(),
-        );
     }
 
-    #[cfg(feature = "gas_tracking")]
-    return Ok(tracker.add_to_response(resp));
-
-    #[cfg(not(feature = "gas_tracking"))]
     Ok(resp)
 }
 
@@ -214,7 +964,7 @@ pub fn try_transfer_from(
 
     let owner = deps.api.addr_validate(owner.as_str())?;
     let recipient = deps.api.addr_validate(recipient.as_str())?;
-    let symbol = CONFIG.load(deps.storage)?.symbol;
+    let constants = CONFIG.load(deps.storage)?;
     let (received_notification, spent_notification) = try_transfer_from_impl(
         &mut deps,
         rng,
@@ -223,7 +973,7 @@ pub fn try_transfer_from(
         &owner,
         &recipient,
         amount,
-        symbol,
+        TOKEN_DENOM.to_string(),
         memo,
     )?;
 
@@ -231,21 +981,36 @@ pub fn try_transfer_from(
         Response::new().set_data(to_binary(&ExecuteAnswer::TransferFrom { status: Success })?);
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
-        let received_notification =
-            received_notification.to_txhash_notification(deps.api, env, secret, None)?;
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            env,
+            secret,
+            resp,
+            received_notification,
+        )?;
 
-        let spent_notification =
-            spent_notification.to_txhash_notification(deps.api, env, secret, None)?;
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
 
-        resp = resp
-            .add_attribute_plaintext(
-                received_notification.id_plaintext(),
-                received_notification.data_plaintext(),
-            )
-            .add_attribute_plaintext(
-                spent_notification.id_plaintext(),
-                spent_notification.data_plaintext(),
-            );
+        if constants.notify_allowance_use {
+            let allowance_notification =
+                allowance_use_notification(deps.storage, &owner, &info.sender);
+            resp = add_direct_notification(
+                deps.storage,
+                deps.api,
+                env,
+                secret,
+                resp,
+                allowance_notification,
+            )?;
+        }
     }
 
     Ok(resp)
@@ -257,13 +1022,18 @@ pub fn try_batch_transfer_from(
     info: MessageInfo,
     rng: &mut ContractPrng,
     actions: Vec<batch::TransferFromAction>,
+    idempotency_key: Option<String>,
 ) -> StdResult<Response> {
+    if let Some(key) = idempotency_key {
+        IdempotencyKeysStore::consume(deps.storage, &info.sender, &key, env.block.time.seconds())?;
+    }
+
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
     let mut notifications = vec![];
 
-    let symbol = CONFIG.load(deps.storage)?.symbol;
+    let constants = CONFIG.load(deps.storage)?;
     for action in actions {
         let owner = deps.api.addr_validate(action.owner.as_str())?;
         let recipient = deps.api.addr_validate(action.recipient.as_str())?;
@@ -276,7 +1046,7 @@ pub fn try_batch_transfer_from(
             &owner,
             &recipient,
             action.amount,
-            symbol.clone(),
+            TOKEN_DENOM.to_string(),
             action.memo,
         )?;
 
@@ -296,8 +1066,13 @@ pub fn try_batch_transfer_from(
         let tx_hash = env.transaction.clone().unwrap().hash;
 
         resp = render_group_notification(
+            deps.storage,
             deps.api,
-            MultiRecvdNotification(received_notifications),
+            MultiRecvdNotification::new(
+                received_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
             &tx_hash,
             env.block.random.clone().unwrap(),
             secret,
@@ -305,8 +1080,13 @@ pub fn try_batch_transfer_from(
         )?;
 
         resp = render_group_notification(
+            deps.storage,
             deps.api,
-            MultiSpentNotification(spent_notifications),
+            MultiSpentNotification::new(
+                spent_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
             &tx_hash,
             env.block.random.clone().unwrap(),
             secret,
@@ -329,75 +1109,187 @@ pub fn try_send(
     recipient_code_hash: Option<String>,
     amount: Uint128,
     memo: Option<String>,
+    memo_encrypted: bool,
+    msg: Option<Binary>,
+    reply_on_error: bool,
+    want_receipt: bool,
+) -> StdResult<Response> {
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    let result = perform_try_send(
+        &mut deps,
+        env,
+        info,
+        rng,
+        recipient,
+        recipient_code_hash,
+        amount,
+        memo,
+        memo_encrypted,
+        msg,
+        reply_on_error,
+        want_receipt,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    );
+
+    #[cfg(feature = "gas_tracking")]
+    return tracker.finish(deps.storage, result);
+
+    #[cfg(not(feature = "gas_tracking"))]
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn perform_try_send(
+    deps: &mut DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rng: &mut ContractPrng,
+    recipient: String,
+    recipient_code_hash: Option<String>,
+    amount: Uint128,
+    memo: Option<String>,
+    memo_encrypted: bool,
     msg: Option<Binary>,
+    reply_on_error: bool,
+    want_receipt: bool,
+    #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
 ) -> StdResult<Response> {
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
     let recipient = deps.api.addr_validate(recipient.as_str())?;
 
-    let mut messages = vec![];
-    let symbol = CONFIG.load(deps.storage)?.symbol;
+    let mut callbacks = vec![];
+    let denom = TOKEN_DENOM.to_string();
 
-    // make sure the sender is not accidentally sending tokens to the contract address
+    // a send to the contract address would make the receiver callback into the token itself
     if recipient == env.contract.address {
-        return Err(StdError::generic_err(SEND_TO_CONTRACT_ERR_MSG));
+        return Err(StdError::generic_err(SEND_TO_TOKEN_CONTRACT_ERR_MSG));
     }
 
-    #[cfg(feature = "gas_tracking")]
-    let mut tracker: GasTracker = GasTracker::new(deps.api);
-
     let (received_notification, spent_notification) = try_send_impl(
-        &mut deps,
+        deps,
         rng,
-        &mut messages,
-        info.sender,
-        recipient,
+        &mut callbacks,
+        info.sender.clone(),
+        recipient.clone(),
         recipient_code_hash,
         amount,
-        symbol,
+        denom,
         memo,
+        memo_encrypted,
         msg,
         &env.block,
         #[cfg(feature = "gas_tracking")]
-        &mut tracker,
+        tracker,
     )?;
-
-    let mut resp = Response::new()
-        .add_messages(messages)
-        .set_data(to_binary(&ExecuteAnswer::Send { status: Success })?);
-
+    let messages = into_callback_messages(callbacks, false)?;
+
+    let receipt = if want_receipt {
+        Some(transfer_receipt(
+            obfuscated_tx_id(
+                &INTERNAL_SECRET_RELAXED.load(deps.storage)?,
+                TX_COUNT.load(deps.storage)?,
+            ),
+            &info.sender,
+            &recipient,
+            amount,
+            env.block.height,
+        ))
+    } else {
+        None
+    };
+
+    // the notifications carry the balances as of the debit above and are rendered into the
+    // response before any callback is attached. the receiver's callback only runs after this
+    // execution completes, so anything it does (including re-entering this contract) cannot
+    // change what the sender and recipient are notified of
+    let mut resp = Response::new().set_data(to_binary(&ExecuteAnswer::Send {
+        status: Success,
+        receipt,
+    })?);
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
-        let received_notification =
-            received_notification.to_txhash_notification(deps.api, &env, secret, None)?;
-        let spent_notification =
-            spent_notification.to_txhash_notification(deps.api, &env, secret, None)?;
-
-        resp = resp
-            .add_attribute_plaintext(
-                received_notification.id_plaintext(),
-                received_notification.data_plaintext(),
-            )
-            .add_attribute_plaintext(
-                spent_notification.id_plaintext(),
-                spent_notification.data_plaintext(),
-            );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            received_notification,
+        )?;
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
     }
 
-    #[cfg(feature = "gas_tracking")]
-    return Ok(tracker.add_to_response(resp));
+    if reply_on_error {
+        for message in messages {
+            if let CosmosMsg::Wasm(WasmMsg::Execute { code_hash, msg, .. }) = &message {
+                PendingCallbacksStore::begin(
+                    deps.storage,
+                    &recipient,
+                    code_hash.clone(),
+                    msg.clone(),
+                )?;
+            }
+            resp = resp.add_submessage(SubMsg::reply_on_error(message, CALLBACK_REPLY_ID));
+        }
+    } else {
+        resp = resp.add_messages(messages);
+    }
 
-    #[cfg(not(feature = "gas_tracking"))]
     Ok(resp)
 }
 
+/// handles the reply to a `reply_on_error` send callback. the transfer is kept and the failed
+/// callback is recorded so the recipient can retry it
+pub fn try_record_failed_callback(deps: DepsMut, result: SubMsgResult) -> StdResult<Response> {
+    if let SubMsgResult::Err(_) = result {
+        PendingCallbacksStore::record_failure(deps.storage)?;
+    }
+
+    Ok(Response::new())
+}
+
+pub fn try_retry_callback(deps: DepsMut, info: MessageInfo, id: u64) -> StdResult<Response> {
+    let callback = PendingCallbacksStore::take(deps.storage, &info.sender, id)?
+        .ok_or_else(|| StdError::generic_err(format!("No pending callback with id {id}")))?;
+
+    let message = WasmMsg::Execute {
+        contract_addr: info.sender.into_string(),
+        code_hash: callback.code_hash,
+        msg: callback.msg,
+        funds: vec![],
+    };
+
+    Ok(Response::new()
+        .add_message(message)
+        .set_data(to_binary(&ExecuteAnswer::RetryCallback {
+            status: Success,
+        })?))
+}
+
 pub fn try_batch_send(
     mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     rng: &mut ContractPrng,
     actions: Vec<batch::SendAction>,
+    coalesce_callbacks: bool,
+    idempotency_key: Option<String>,
 ) -> StdResult<Response> {
+    if let Some(key) = idempotency_key {
+        IdempotencyKeysStore::consume(deps.storage, &info.sender, &key, env.block.time.seconds())?;
+    }
+
     let num_actions = actions.len();
     if num_actions == 0 {
         return Ok(
@@ -408,12 +1300,12 @@ pub fn try_batch_send(
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
-    let mut messages = vec![];
+    let mut callbacks = vec![];
 
     let mut notifications = vec![];
     let num_actions: usize = actions.len();
 
-    let symbol = CONFIG.load(deps.storage)?.symbol;
+    let constants = CONFIG.load(deps.storage)?;
 
     let mut total_memo_len = 0;
 
@@ -433,13 +1325,14 @@ pub fn try_batch_send(
         let (received_notification, spent_notification) = try_send_impl(
             &mut deps,
             rng,
-            &mut messages,
+            &mut callbacks,
             info.sender.clone(),
             recipient,
             action.recipient_code_hash,
             action.amount,
-            symbol.clone(),
+            TOKEN_DENOM.to_string(),
             action.memo,
+            false,
             action.msg,
             &env.block,
             #[cfg(feature = "gas_tracking")]
@@ -449,6 +1342,8 @@ pub fn try_batch_send(
         notifications.push((received_notification, spent_notification));
     }
 
+    let messages = into_callback_messages(callbacks, coalesce_callbacks)?;
+
     let mut resp = Response::new()
         .add_messages(messages)
         .set_data(to_binary(&ExecuteAnswer::BatchSend { status: Success })?);
@@ -460,17 +1355,20 @@ pub fn try_batch_send(
         ) = notifications.into_iter().unzip();
 
         resp = render_group_notification(
+            deps.storage,
             deps.api,
-            MultiRecvdNotification(received_notifications),
+            MultiRecvdNotification::new(
+                received_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
             &env.transaction.clone().unwrap().hash,
             env.block.random.clone().unwrap(),
             secret,
             resp,
         )?;
 
-        let total_amount_spent = spent_notifications
-            .iter()
-            .fold(0u128, |acc, notification| acc + notification.data.amount);
+        let total_amount_spent = total_spent_amount(&spent_notifications)?;
 
         let spent_notification = Notification::new(
             info.sender,
@@ -481,13 +1379,15 @@ pub fn try_batch_send(
                 balance: spent_notifications.last().unwrap().data.balance,
                 memo_len: total_memo_len,
             },
-        )
-        .to_txhash_notification(deps.api, &env, secret, None)?;
-
-        resp = resp.add_attribute_plaintext(
-            spent_notification.id_plaintext(),
-            spent_notification.data_plaintext(),
         );
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
     }
 
     Ok(resp)
@@ -511,14 +1411,14 @@ pub fn try_send_from(
 
     let owner = deps.api.addr_validate(owner.as_str())?;
     let recipient = deps.api.addr_validate(recipient.as_str())?;
-    let mut messages = vec![];
+    let mut callbacks = vec![];
     let (received_notification, spent_notification) = try_send_from_impl(
         &mut deps,
         env.clone(),
         info,
         rng,
-        &mut messages,
-        owner,
+        &mut callbacks,
+        owner.clone(),
         recipient,
         recipient_code_hash,
         amount,
@@ -526,25 +1426,42 @@ pub fn try_send_from(
         msg,
     )?;
 
+    let messages = into_callback_messages(callbacks, false)?;
+
     let mut resp = Response::new()
         .add_messages(messages)
         .set_data(to_binary(&ExecuteAnswer::SendFrom { status: Success })?);
 
     if NOTIFICATIONS_ENABLED.load(deps.storage)? {
-        let received_notification =
-            received_notification.to_txhash_notification(deps.api, &env, secret, None)?;
-        let spent_notification =
-            spent_notification.to_txhash_notification(deps.api, &env, secret, None)?;
-
-        resp = resp
-            .add_attribute_plaintext(
-                received_notification.id_plaintext(),
-                received_notification.data_plaintext(),
-            )
-            .add_attribute_plaintext(
-                spent_notification.id_plaintext(),
-                spent_notification.data_plaintext(),
-            )
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            received_notification,
+        )?;
+        resp = add_direct_notification(
+            deps.storage,
+            deps.api,
+            &env,
+            secret,
+            resp,
+            spent_notification,
+        )?;
+
+        if CONFIG.load(deps.storage)?.notify_allowance_use {
+            let allowance_notification =
+                allowance_use_notification(deps.storage, &owner, &info.sender);
+            resp = add_direct_notification(
+                deps.storage,
+                deps.api,
+                &env,
+                secret,
+                resp,
+                allowance_notification,
+            )?;
+        }
     }
 
     Ok(resp)
@@ -556,11 +1473,18 @@ pub fn try_batch_send_from(
     info: &MessageInfo,
     rng: &mut ContractPrng,
     actions: Vec<batch::SendFromAction>,
+    idempotency_key: Option<String>,
 ) -> StdResult<Response> {
+    if let Some(key) = idempotency_key {
+        IdempotencyKeysStore::consume(deps.storage, &info.sender, &key, env.block.time.seconds())?;
+    }
+
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
 
-    let mut messages = vec![];
+    let constants = CONFIG.load(deps.storage)?;
+
+    let mut callbacks = vec![];
     let mut notifications = vec![];
 
     for action in actions {
@@ -571,7 +1495,7 @@ pub fn try_batch_send_from(
             env.clone(),
             info,
             rng,
-            &mut messages,
+            &mut callbacks,
             owner,
             recipient,
             action.recipient_code_hash,
@@ -582,6 +1506,8 @@ pub fn try_batch_send_from(
         notifications.push((received_notification, spent_notification));
     }
 
+    let messages = into_callback_messages(callbacks, false)?;
+
     let mut resp = Response::new().add_messages(messages).set_data(to_binary(
         &ExecuteAnswer::BatchSendFrom { status: Success },
     )?);
@@ -595,8 +1521,13 @@ pub fn try_batch_send_from(
         let tx_hash = env.transaction.clone().unwrap().hash;
 
         resp = render_group_notification(
+            deps.storage,
             deps.api,
-            MultiRecvdNotification(received_notifications),
+            MultiRecvdNotification::new(
+                received_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
             &tx_hash,
             env.block.random.clone().unwrap(),
             secret,
@@ -604,8 +1535,13 @@ pub fn try_batch_send_from(
         )?;
 
         resp = render_group_notification(
+            deps.storage,
             deps.api,
-            MultiSpentNotification(spent_notifications),
+            MultiSpentNotification::new(
+                spent_notifications,
+                constants.aggregate_batch_notifications,
+            ),
+            env.block.height,
             &tx_hash,
             env.block.random.clone().unwrap(),
             secret,
@@ -627,12 +1563,17 @@ fn try_transfer_impl(
     amount: Uint128,
     denom: String,
     memo: Option<String>,
+    memo_encrypted: bool,
     block: &cosmwasm_std::BlockInfo,
     #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
 ) -> StdResult<(
     Notification<RecvdNotification>,
     Notification<SpentNotification>,
 )> {
+    if amount.is_zero() && !CONFIG.load(deps.storage)?.allow_zero_transfers {
+        return Err(StdError::generic_err("transfer amount must be positive"));
+    }
+
     // canonicalize owner and recipient addresses
     let raw_owner = deps.api.addr_canonicalize(owner.as_str())?;
     let raw_recipient = deps.api.addr_canonicalize(recipient.as_str())?;
@@ -661,6 +1602,7 @@ fn try_transfer_impl(
         amount.u128(),
         denom,
         memo.clone(),
+        memo_encrypted,
         block,
         false,
         #[cfg(feature = "gas_tracking")]
@@ -735,6 +1677,7 @@ fn try_transfer_from_impl(
         raw_amount,
         denom,
         memo,
+        false,
         &env.block,
         true,
         #[cfg(feature = "gas_tracking")]
@@ -756,17 +1699,20 @@ fn try_transfer_from_impl(
     Ok((received_notification, spent_notification))
 }
 
+/// debits the sender and queues the receiver callback. the returned notifications are built
+/// from the balances at debit time, before the callback has had a chance to run
 #[allow(clippy::too_many_arguments)]
-fn try_send_impl(
+pub(crate) fn try_send_impl(
     deps: &mut DepsMut,
     rng: &mut ContractPrng,
-    messages: &mut Vec<CosmosMsg>,
+    callbacks: &mut Vec<ReceiverCallback>,
     sender: Addr,
     recipient: Addr,
     recipient_code_hash: Option<String>,
     amount: Uint128,
     denom: String,
     memo: Option<String>,
+    memo_encrypted: bool,
     msg: Option<Binary>,
     block: &cosmwasm_std::BlockInfo,
     #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
@@ -782,6 +1728,7 @@ fn try_send_impl(
         amount,
         denom,
         memo.clone(),
+        memo_encrypted,
         block,
         #[cfg(feature = "gas_tracking")]
         tracker,
@@ -789,7 +1736,7 @@ fn try_send_impl(
 
     try_add_receiver_api_callback(
         deps.storage,
-        messages,
+        callbacks,
         recipient,
         recipient_code_hash,
         msg,
@@ -808,7 +1755,7 @@ fn try_send_from_impl(
     env: Env,
     info: &MessageInfo,
     rng: &mut ContractPrng,
-    messages: &mut Vec<CosmosMsg>,
+    callbacks: &mut Vec<ReceiverCallback>,
     owner: Addr,
     recipient: Addr,
     recipient_code_hash: Option<String>,
@@ -819,8 +1766,13 @@ fn try_send_from_impl(
     Notification<RecvdNotification>,
     Notification<SpentNotification>,
 )> {
+    // a send to the contract address would make the receiver callback into the token itself
+    if recipient == env.contract.address {
+        return Err(StdError::generic_err(SEND_TO_TOKEN_CONTRACT_ERR_MSG));
+    }
+
     let spender = info.sender.clone();
-    let symbol = CONFIG.load(deps.storage)?.symbol;
+    let denom = TOKEN_DENOM.to_string();
     let (received_notification, spent_notification) = try_transfer_from_impl(
         deps,
         rng,
@@ -829,13 +1781,13 @@ fn try_send_from_impl(
         &owner,
         &recipient,
         amount,
-        symbol,
+        denom,
         memo.clone(),
     )?;
 
     try_add_receiver_api_callback(
         deps.storage,
-        messages,
+        callbacks,
         recipient,
         recipient_code_hash,
         msg,
@@ -848,6 +1800,38 @@ fn try_send_from_impl(
     Ok((received_notification, spent_notification))
 }
 
+/// computes the receipt identifier for a transfer as the hex-encoded sha256 of
+/// `"{tx_id}:{from}:{to}:{amount}:{block_height}"`, so that both parties can reconstruct it
+pub(crate) fn transfer_receipt(
+    tx_id: u64,
+    from: &Addr,
+    to: &Addr,
+    amount: Uint128,
+    block_height: u64,
+) -> String {
+    let preimage = format!("{tx_id}:{from}:{to}:{amount}:{block_height}");
+    hex::encode(sha_256(preimage.as_bytes()))
+}
+
+/// creates an allowance notification for the owner with the remaining allowance after a spender
+/// used it. the notification's address field carries the spender, since the owner is the recipient
+pub fn allowance_use_notification(
+    store: &dyn Storage,
+    owner: &Addr,
+    spender: &Addr,
+) -> Notification<AllowanceNotification> {
+    let allowance = AllowancesStore::load(store, owner, spender);
+
+    Notification::new(
+        owner.clone(),
+        AllowanceNotification {
+            amount: allowance.amount,
+            allower: spender.clone(),
+            expiration: allowance.expiration,
+        },
+    )
+}
+
 #[allow(clippy::too_many_arguments)]
 fn perform_transfer(
     store: &mut dyn Storage,
@@ -858,6 +1842,7 @@ fn perform_transfer(
     amount: u128,
     denom: String,
     memo: Option<String>,
+    memo_encrypted: bool,
     block: &BlockInfo,
     is_from_action: bool,
     #[cfg(feature = "gas_tracking")] tracker: &mut GasTracker,
@@ -866,7 +1851,17 @@ fn perform_transfer(
     let mut group1 = tracker.group("perform_transfer.1");
 
     // first store the tx information in the global append list of txs and get the new tx id
-    let tx_id = store_transfer_action(store, from, sender, to, amount, denom, memo, block)?;
+    let tx_id = store_transfer_action(
+        store,
+        from,
+        sender,
+        to,
+        amount,
+        denom,
+        memo,
+        memo_encrypted,
+        block,
+    )?;
 
     #[cfg(feature = "gas_tracking")]
     group1.log("@store_transfer_action");
@@ -879,6 +1874,44 @@ fn perform_transfer(
 
     let transfer_str = "transfer";
 
+    // self-transfer is net-zero: record the event in the owner's history without moving funds
+    if from == to {
+        let owner_balance = dwb.settle_sender_or_owner_account(
+            store,
+            from,
+            tx_id,
+            0,
+            transfer_str,
+            is_from_action && sender == from,
+            #[cfg(feature = "gas_tracking")]
+            tracker,
+        )?;
+
+        // still require that the owner could have covered the transfer
+        if owner_balance < amount {
+            return Err(StdError::generic_err(format!(
+                "insufficient funds to {transfer_str}: balance={owner_balance}, required={amount}",
+            )));
+        }
+
+        if sender != from {
+            dwb.settle_sender_or_owner_account(
+                store,
+                sender,
+                tx_id,
+                0,
+                transfer_str,
+                false,
+                #[cfg(feature = "gas_tracking")]
+                tracker,
+            )?;
+        }
+
+        DWB.save(store, &dwb)?;
+
+        return Ok(owner_balance);
+    }
+
     // settle the owner's account
     let owner_balance = dwb.settle_sender_or_owner_account(
         store,
@@ -917,6 +1950,8 @@ fn perform_transfer(
         tracker,
     )?;
 
+    dwb.checkpoint_balances(store, &[from, to], block.height)?;
+
     #[cfg(feature = "gas_tracking")]
     let mut group2 = tracker.group("perform_transfer.2");
 
@@ -928,10 +1963,45 @@ fn perform_transfer(
     Ok(owner_balance)
 }
 
+/// moves funds into or out of the contract's own account, which holds pending transfers
+#[allow(clippy::too_many_arguments)]
+fn perform_escrow_transfer(
+    deps: &mut DepsMut,
+    rng: &mut ContractPrng,
+    from: &Addr,
+    to: &Addr,
+    amount: u128,
+    denom: String,
+    memo: Option<String>,
+    block: &BlockInfo,
+) -> StdResult<u128> {
+    let raw_from = deps.api.addr_canonicalize(from.as_str())?;
+    let raw_to = deps.api.addr_canonicalize(to.as_str())?;
+
+    #[cfg(feature = "gas_tracking")]
+    let mut tracker: GasTracker = GasTracker::new(deps.api);
+
+    perform_transfer(
+        deps.storage,
+        rng,
+        &raw_from,
+        &raw_to,
+        &raw_from,
+        amount,
+        denom,
+        memo,
+        false,
+        block,
+        false,
+        #[cfg(feature = "gas_tracking")]
+        &mut tracker,
+    )
+}
+
 #[allow(clippy::too_many_arguments)]
-fn try_add_receiver_api_callback(
+pub fn try_add_receiver_api_callback(
     storage: &dyn Storage,
-    messages: &mut Vec<CosmosMsg>,
+    callbacks: &mut Vec<ReceiverCallback>,
     recipient: Addr,
     recipient_code_hash: Option<String>,
     msg: Option<Binary>,
@@ -941,19 +2011,37 @@ fn try_add_receiver_api_callback(
     memo: Option<String>,
 ) -> StdResult<()> {
     if let Some(receiver_hash) = recipient_code_hash {
-        let receiver_msg = Snip20ReceiveMsg::new(sender, from, amount, memo, msg);
-        let callback_msg = receiver_msg.into_cosmos_msg(receiver_hash, recipient)?;
-
-        messages.push(callback_msg);
+        // catch a malformed hash here rather than letting the callback fail at dispatch
+        if receiver_hash.len() != 64 || !receiver_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(StdError::generic_err("invalid recipient code hash"));
+        }
+        ensure_send_msg(storage, &msg)?;
+        callbacks.push(ReceiverCallback {
+            contract_addr: recipient,
+            code_hash: receiver_hash,
+            msg: Snip20ReceiveMsg::new(sender, from, amount, memo, msg),
+        });
         return Ok(());
     }
 
     let receiver_hash = ReceiverHashStore::may_load(storage, &recipient)?;
     if let Some(receiver_hash) = receiver_hash {
-        let receiver_msg = Snip20ReceiveMsg::new(sender, from, amount, memo, msg);
-        let callback_msg = receiver_msg.into_cosmos_msg(receiver_hash, recipient)?;
+        ensure_send_msg(storage, &msg)?;
+        callbacks.push(ReceiverCallback {
+            contract_addr: recipient,
+            code_hash: receiver_hash,
+            msg: Snip20ReceiveMsg::new(sender, from, amount, memo, msg),
+        });
+    }
+    Ok(())
+}
 
-        messages.push(callback_msg);
+/// rejects a callback without a msg when the contract is configured to require one
+fn ensure_send_msg(storage: &dyn Storage, msg: &Option<Binary>) -> StdResult<()> {
+    if msg.is_none() && CONFIG.load(storage)?.require_send_msg {
+        return Err(StdError::generic_err(
+            "A msg is required when sending to a contract that receives a callback",
+        ));
     }
     Ok(())
 }
@@ -1,7 +1,22 @@
-use cosmwasm_std::{Api, Response};
+use cosmwasm_std::{to_binary, Api, Binary, Response, StdError, StdResult, Storage};
+use schemars::JsonSchema;
+use secret_toolkit::storage::Item;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::QueryAnswer;
+
+/// gas profile recorded by the most recent tracked execute
+pub static GAS_PROFILE: Item<Vec<GasLog>> = Item::new(b"gas-profile");
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+pub struct GasLog {
+    pub group: String,
+    pub label: String,
+    pub gas: u64,
+}
 
 pub struct GasTracker<'a> {
-    logs: Vec<(String, String)>,
+    logs: Vec<(usize, GasLog)>,
     api: &'a dyn Api,
 }
 
@@ -31,13 +46,61 @@ impl<'a> GasTracker<'a> {
     //     group
     // }
 
+    /// stores the recorded logs so they can be read back with the `gas_profile` query
+    pub fn save_profile(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let profile: Vec<GasLog> = self.logs.iter().map(|(_, log)| log.clone()).collect();
+        GAS_PROFILE.save(storage, &profile)
+    }
+
     pub fn add_to_response(self, resp: Response) -> Response {
         let mut new_resp = resp.clone();
-        for log in self.logs.into_iter() {
-            new_resp = new_resp.add_attribute_plaintext(log.0, log.1);
+        for (index, log) in self.logs.into_iter() {
+            new_resp = new_resp.add_attribute_plaintext(
+                format!("gas.{}", log.group),
+                format!("{}:{}:{}", index, log.gas, log.label),
+            );
         }
         new_resp
     }
+
+    /// finalizes the tracker for both outcomes of an execute. a failed execute reverts the
+    /// saved profile and cannot carry attributes, so the logs are appended to the error instead
+    pub fn finish(
+        mut self,
+        storage: &mut dyn Storage,
+        result: StdResult<Response>,
+    ) -> StdResult<Response> {
+        match result {
+            Ok(resp) => {
+                self.save_profile(storage)?;
+                Ok(self.add_to_response(resp))
+            }
+            Err(err) => {
+                let msg = match err {
+                    StdError::GenericErr { msg, .. } => msg,
+                    err => err.to_string(),
+                };
+                self.group("error").log(&msg);
+
+                let logs: Vec<String> = self
+                    .logs
+                    .iter()
+                    .map(|(index, log)| {
+                        format!("gas.{}={}:{}:{}", log.group, index, log.gas, log.label)
+                    })
+                    .collect();
+                Err(StdError::generic_err(format!(
+                    "{msg} [{}]",
+                    logs.join(", ")
+                )))
+            }
+        }
+    }
+}
+
+pub fn query_gas_profile(storage: &dyn Storage) -> StdResult<Binary> {
+    let logs = GAS_PROFILE.may_load(storage)?.unwrap_or_default();
+    to_binary(&QueryAnswer::GasProfile { logs })
 }
 
 pub struct GasGroup<'a, 'b> {
@@ -61,11 +124,12 @@ impl<'a, 'b> GasGroup<'a, 'b> {
 
     pub fn log(&mut self, comment: &str) {
         let gas = self.tracker.api.check_gas();
-        let log_entry = (
-            format!("gas.{}", self.name,),
-            format!("{}:{}:{}", self.index, gas.unwrap_or(0u64), comment),
-        );
-        self.tracker.logs.push(log_entry);
+        let log_entry = GasLog {
+            group: self.name.clone(),
+            label: comment.to_string(),
+            gas: gas.unwrap_or(0u64),
+        };
+        self.tracker.logs.push((self.index, log_entry));
         self.index += 1;
     }
 
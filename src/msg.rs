@@ -3,13 +3,19 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{batch, transaction_history::Tx};
+#[cfg(feature = "gas_tracking")]
+use crate::gas_tracker::GasLog;
+use crate::state::{ChannelEmission, PendingCallback};
+use crate::{
+    batch,
+    transaction_history::{Tx, TxActionFilter},
+};
 #[cfg(feature = "gas_evaporation")]
 use cosmwasm_std::Uint64;
-use cosmwasm_std::{Addr, Api, Binary, StdError, StdResult, Uint128, Uint64};
+use cosmwasm_std::{Addr, Api, Binary, Order, StdError, StdResult, Uint128, Uint64};
 use secret_toolkit::{
-    notification::ChannelInfoData,
-    permit::{AllRevocation, AllRevokedInterval, Permit},
+    notification::{BloomParameters, ChannelInfoData, Descriptor},
+    permit::{AllRevocation, AllRevokedInterval, Permit, PermitSignature, TokenPermissions},
 };
 
 #[cfg_attr(test, derive(Eq, PartialEq))]
@@ -44,8 +50,13 @@ impl InstantiateMsg {
 #[serde(rename_all = "snake_case")]
 pub struct InitConfig {
     /// Indicates whether the total supply is public or should be kept secret.
+    /// Superseded by `total_supply_visibility` when that is set.
     /// default: False
     public_total_supply: Option<bool>,
+    /// Who can see the total supply: everyone, only the admin (through the `total_supply` permit
+    /// query), or no one.
+    /// default: `public` if `public_total_supply` is true, otherwise `private`
+    total_supply_visibility: Option<TotalSupplyVisibility>,
     /// Indicates whether deposit functionality should be enabled
     /// default: False
     enable_deposit: Option<bool>,
@@ -61,11 +72,59 @@ pub struct InitConfig {
     /// Indicates whether an admin can modify supported denoms
     /// default: False
     can_modify_denoms: Option<bool>,
+    /// Indicates whether owners should receive an allowance notification with their
+    /// remaining allowance and the spender's address when a spender uses it via `TransferFrom`
+    /// or `SendFrom`
+    /// default: False
+    notify_allowance_use: Option<bool>,
+    /// Indicates whether batch executions should merge `multirecvd` and `multispent` packets
+    /// meant for the same account into a single packet, at the cost of per-action detail
+    /// default: False
+    aggregate_batch_notifications: Option<bool>,
+    /// Indicates whether emitted notifications should be recorded in a bounded per-channel
+    /// log that the admin can read with `ChannelEmissionLog`
+    /// default: False
+    enable_channel_emission_log: Option<bool>,
+    /// Number of decimals of the native coin accepted for deposits and redeems, used to
+    /// compute the exchange rate
+    /// default: 6
+    native_decimals: Option<u8>,
+    /// Display name of the native coin, used to label the native side of the exchange rate
+    /// default: "SCRT"
+    native_display_denom: Option<String>,
+    /// Indicates whether sends to a recipient that receives a `Receive` callback must include
+    /// a `msg` for it
+    /// default: False
+    require_send_msg: Option<bool>,
+    /// Indicates whether transfers and sends of zero tokens are accepted, for clients that
+    /// use them as pings
+    /// default: False
+    allow_zero_transfers: Option<bool>,
+    /// Maximum number of settled transactions retained in each account's history. Older
+    /// transactions are pruned as new ones are settled.
+    /// default: None (unlimited)
+    max_tx_history_per_account: Option<u32>,
+    /// Indicates whether balance checkpoints are recorded so that historical balances can be
+    /// queried with `BalanceAt`. Adds storage writes to every balance change.
+    /// default: False
+    snapshot_enabled: Option<bool>,
+    /// Indicates whether deposits and redeems must carry a memo, for deployments that require
+    /// a reference on every native coin movement
+    /// default: False
+    require_memo_on_native: Option<bool>,
+    /// Address that `BurnTo` records as the recipient of burned tokens. The sink is never
+    /// credited, so burns to it remain visible in history without becoming spendable.
+    /// default: None (`BurnTo` disabled)
+    burn_sink_address: Option<String>,
 }
 
 impl InitConfig {
-    pub fn public_total_supply(&self) -> bool {
-        self.public_total_supply.unwrap_or(false)
+    pub fn total_supply_visibility(&self) -> TotalSupplyVisibility {
+        match self.total_supply_visibility {
+            Some(visibility) => visibility,
+            None if self.public_total_supply.unwrap_or(false) => TotalSupplyVisibility::Public,
+            None => TotalSupplyVisibility::Private,
+        }
     }
 
     pub fn deposit_enabled(&self) -> bool {
@@ -87,6 +146,52 @@ impl InitConfig {
     pub fn can_modify_denoms(&self) -> bool {
         self.can_modify_denoms.unwrap_or(false)
     }
+
+    pub fn notify_allowance_use(&self) -> bool {
+        self.notify_allowance_use.unwrap_or(false)
+    }
+
+    pub fn aggregate_batch_notifications(&self) -> bool {
+        self.aggregate_batch_notifications.unwrap_or(false)
+    }
+
+    pub fn channel_emission_log_enabled(&self) -> bool {
+        self.enable_channel_emission_log.unwrap_or(false)
+    }
+
+    pub fn native_decimals(&self) -> u8 {
+        self.native_decimals.unwrap_or(6)
+    }
+
+    pub fn native_display_denom(&self) -> String {
+        self.native_display_denom
+            .clone()
+            .unwrap_or_else(|| "SCRT".to_string())
+    }
+
+    pub fn require_send_msg(&self) -> bool {
+        self.require_send_msg.unwrap_or(false)
+    }
+
+    pub fn allow_zero_transfers(&self) -> bool {
+        self.allow_zero_transfers.unwrap_or(false)
+    }
+
+    pub fn max_tx_history_per_account(&self) -> Option<u32> {
+        self.max_tx_history_per_account
+    }
+
+    pub fn snapshot_enabled(&self) -> bool {
+        self.snapshot_enabled.unwrap_or(false)
+    }
+
+    pub fn require_memo_on_native(&self) -> bool {
+        self.require_memo_on_native.unwrap_or(false)
+    }
+
+    pub fn burn_sink_address(&self) -> Option<String> {
+        self.burn_sink_address.clone()
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
@@ -96,11 +201,41 @@ pub enum ExecuteMsg {
     Redeem {
         amount: Uint128,
         denom: Option<String>,
+        memo: Option<String>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
     },
     Deposit {
+        memo: Option<String>,
+        /// a contract to `Send` the deposited tokens to, with its `Receive` callback
+        callback: Option<DepositCallback>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Sets the denom to redeem to when `Redeem` is called without a denom
+    SetPreferredRedeemDenom {
+        denom: String,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Sets the amount of the signer's tokens that `spender` may redeem with `RedeemFrom`
+    SetRedeemAllowance {
+        spender: String,
+        amount: Uint128,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Redeems `owner`'s tokens using the signer's redeem allowance, sending the coins to `recipient`
+    RedeemFrom {
+        owner: String,
+        amount: Uint128,
+        denom: Option<String>,
+        recipient: String,
+        memo: Option<String>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -111,6 +246,10 @@ pub enum ExecuteMsg {
         recipient: String,
         amount: Uint128,
         memo: Option<String>,
+        /// flags `memo` as encrypted client-side, recorded in the tx history (default: false)
+        memo_encrypted: Option<bool>,
+        /// returns a receipt identifier for the transfer in the answer (default: false)
+        want_receipt: Option<bool>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -121,18 +260,119 @@ pub enum ExecuteMsg {
         amount: Uint128,
         msg: Option<Binary>,
         memo: Option<String>,
+        /// flags `memo` as encrypted client-side, recorded in the tx history (default: false)
+        memo_encrypted: Option<bool>,
+        /// keeps the transfer if the recipient's `Receive` callback fails, recording the
+        /// callback so the recipient can retry it with `RetryCallback`
+        reply_on_error: Option<bool>,
+        /// returns a receipt identifier for the transfer in the answer (default: false)
+        want_receipt: Option<bool>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Dispatches a failed `Send` callback to the signer again
+    RetryCallback {
+        id: u64,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Debits the signer now and holds the amount for `recipient`, who can claim it with
+    /// `ClaimPending` before `refund_after` (seconds since epoch). After that, the signer can take
+    /// it back with `RefundPending`.
+    TransferWithTimeout {
+        recipient: String,
+        amount: Uint128,
+        refund_after: u64,
+        memo: Option<String>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Claims a pending transfer made to the signer with `TransferWithTimeout`
+    ClaimPending {
+        id: u64,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Returns an unclaimed pending transfer to the signer once it has timed out
+    RefundPending {
+        id: u64,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Debits the signer now and releases `total` to `recipient` linearly between `start_time`
+    /// and `end_time` (seconds since epoch). The recipient withdraws the vested part with
+    /// `WithdrawStream`.
+    CreateStream {
+        recipient: String,
+        total: Uint128,
+        start_time: u64,
+        end_time: u64,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Withdraws everything vested so far on a stream paying the signer
+    WithdrawStream {
+        id: u64,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Transfers the signer's entire balance, including any amount still pending settlement
+    TransferAll {
+        recipient: String,
+        memo: Option<String>,
+        /// amount to keep in the signer's account instead of sweeping it
+        leave: Option<Uint128>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
     },
     BatchTransfer {
         actions: Vec<batch::TransferAction>,
+        /// block time (in seconds) after which the batch is rejected
+        deadline: Option<u64>,
+        /// rejects the batch if the sender submitted one with the same key within the last day
+        idempotency_key: Option<String>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Divides `total` evenly between `recipients`, handling any remainder according to
+    /// `remainder_policy` (the first recipient gets it by default)
+    TransferSplit {
+        recipients: Vec<String>,
+        total: Uint128,
+        memo: Option<String>,
+        remainder_policy: Option<RemainderPolicy>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Transfers on behalf of `from`, who signed a `TransferAuthorization` for the next nonce of
+    /// their account, so that a relayer can submit it for them
+    TransferWithAuth {
+        from: String,
+        to: String,
+        amount: Uint128,
+        nonce: u64,
+        signature: PermitSignature,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
     },
     BatchSend {
         actions: Vec<batch::SendAction>,
+        /// combines callbacks to the same contract and code hash into a single `BatchReceive`
+        /// message (default: false)
+        coalesce_callbacks: Option<bool>,
+        /// rejects the batch if the sender submitted one with the same key within the last day
+        idempotency_key: Option<String>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -144,8 +384,48 @@ pub enum ExecuteMsg {
         gas_target: Option<Uint64>,
         padding: Option<String>,
     },
+    /// Burns `amount` like `Burn`, but records it in history as a transfer to the configured
+    /// burn sink address
+    BurnTo {
+        amount: Uint128,
+        memo: Option<String>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
     RegisterReceive {
         code_hash: String,
+        /// human-readable tag for the integration, returned by the `ReceiverHash` query
+        label: Option<String>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Flushes the signer's pending entry in the delayed write buffer into their stored balance
+    SettleSelf {
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Creates the stored balance entry for `address` ahead of its first settlement, so that
+    /// settlement costs the same as for any existing account. Has no effect on balances
+    InitializeAccount {
+        address: String,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Opts the signer in to or out of notifications on a SNIP-52 channel
+    SetChannelPreference {
+        channel: String,
+        enabled: bool,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Publishes a 33-byte compressed secp256k1 public key senders can encrypt memos to
+    SetMemoDecryptionKey {
+        pubkey: Binary,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -168,6 +448,10 @@ pub enum ExecuteMsg {
         spender: String,
         amount: Uint128,
         expiration: Option<u64>,
+        /// seconds from now before the allowance can be used (default: 0)
+        grace_seconds: Option<u64>,
+        /// also notify the owner, with the spender in place of the allower (default: false)
+        notify_self: Option<bool>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -176,6 +460,29 @@ pub enum ExecuteMsg {
         spender: String,
         amount: Uint128,
         expiration: Option<u64>,
+        /// also notify the owner, with the spender in place of the allower (default: false)
+        notify_self: Option<bool>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Removes the expiration of the signer's allowance for `spender`, leaving the amount as is
+    ClearAllowanceExpiration {
+        spender: String,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Increases, decreases or sets the signer's allowances for several spenders at once
+    BatchSetAllowance {
+        actions: Vec<batch::AllowanceAction>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Lets anyone query the allowances the signer has given with `PublicAllowancesGiven`
+    SetAllowancesPublic {
+        public: bool,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -202,12 +509,16 @@ pub enum ExecuteMsg {
     },
     BatchTransferFrom {
         actions: Vec<batch::TransferFromAction>,
+        /// rejects the batch if the sender submitted one with the same key within the last day
+        idempotency_key: Option<String>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
     },
     BatchSendFrom {
         actions: Vec<batch::SendFromAction>,
+        /// rejects the batch if the sender submitted one with the same key within the last day
+        idempotency_key: Option<String>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -222,6 +533,8 @@ pub enum ExecuteMsg {
     },
     BatchBurnFrom {
         actions: Vec<batch::BurnFromAction>,
+        /// rejects the batch if the sender submitted one with the same key within the last day
+        idempotency_key: Option<String>,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -238,6 +551,17 @@ pub enum ExecuteMsg {
     },
     BatchMint {
         actions: Vec<batch::MintAction>,
+        /// rejects the batch if the sender submitted one with the same key within the last day
+        idempotency_key: Option<String>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Mints to every recipient like `BatchMint`, but only if the amounts add up to no more
+    /// than `campaign_cap`
+    MintCampaign {
+        actions: Vec<batch::MintAction>,
+        campaign_cap: Uint128,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
@@ -262,12 +586,25 @@ pub enum ExecuteMsg {
     },
 
     // Admin
+    /// Nominates a new admin, who takes over once it calls `AcceptAdmin`
     ChangeAdmin {
         address: String,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
         padding: Option<String>,
     },
+    /// Withdraws the nomination made with `ChangeAdmin`
+    CancelAdminTransfer {
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Called by the address nominated with `ChangeAdmin` to become the admin
+    AcceptAdmin {
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
     SetContractStatus {
         level: ContractStatusLevel,
         #[cfg(feature = "gas_evaporation")]
@@ -286,12 +623,70 @@ pub enum ExecuteMsg {
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
     },
+    /// Enable deposit and redeem separately for a supported coin denom, overriding the
+    /// token-wide settings for it
+    SetDenomStatus {
+        denom: String,
+        deposit_enabled: bool,
+        redeem_enabled: bool,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Disable (or re-enable) individual operations, independently of the contract status
+    SetOperationFlags {
+        disable_mint: bool,
+        disable_send: bool,
+        disable_burn: bool,
+        disable_transfer: bool,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Set (or clear) the most that can be redeemed within a window before redeems are
+    /// automatically paused
+    SetRedeemRateLimit {
+        limit: Option<RedeemRateLimit>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Set (or clear) the maximum number of settled transactions retained per account
+    SetMaxTxHistoryPerAccount {
+        max: Option<u32>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
     /// Enable or disable SNIP-52 notifications
     SetNotificationStatus {
         enabled: bool,
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
     },
+    /// Re-derives the secret used to obfuscate tx ids and the seed used by `CreateViewingKey`
+    RotateSecrets {
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Registers a custom notification channel, a `bloom` channel if `bloom_params` is given or
+    /// a `txhash` channel otherwise, so that `ChannelInfo` can describe it
+    RegisterChannel {
+        channel: String,
+        cddl: Option<String>,
+        bloom_params: Option<BloomParameters>,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
+    /// Removes a channel added with `RegisterChannel`
+    UnregisterChannel {
+        channel: String,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
 
     // Permit
     RevokePermit {
@@ -320,6 +715,15 @@ pub enum ExecuteMsg {
         #[cfg(feature = "gas_evaporation")]
         gas_target: Option<Uint64>,
     },
+
+    /// Sets a new viewing key and invalidates all previously signed permits by moving the
+    /// caller to a new, randomly drawn permit epoch.
+    ResetCredentials {
+        new_key: String,
+        #[cfg(feature = "gas_evaporation")]
+        gas_target: Option<Uint64>,
+        padding: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Debug)]
@@ -332,26 +736,89 @@ pub enum ExecuteAnswer {
     Redeem {
         status: ResponseStatus,
     },
+    SetPreferredRedeemDenom {
+        status: ResponseStatus,
+    },
+    SetRedeemAllowance {
+        status: ResponseStatus,
+    },
+    RedeemFrom {
+        status: ResponseStatus,
+    },
 
     // Base
     Transfer {
         status: ResponseStatus,
+        /// hex-encoded sha256 of `"{tx_id}:{from}:{to}:{amount}:{block_height}"`, present if
+        /// `want_receipt` was set
+        #[serde(skip_serializing_if = "Option::is_none")]
+        receipt: Option<String>,
     },
     Send {
         status: ResponseStatus,
+        /// hex-encoded sha256 of `"{tx_id}:{from}:{to}:{amount}:{block_height}"`, present if
+        /// `want_receipt` was set
+        #[serde(skip_serializing_if = "Option::is_none")]
+        receipt: Option<String>,
+    },
+    RetryCallback {
+        status: ResponseStatus,
+    },
+    TransferWithTimeout {
+        status: ResponseStatus,
+        id: u64,
+    },
+    ClaimPending {
+        status: ResponseStatus,
+    },
+    RefundPending {
+        status: ResponseStatus,
+    },
+    CreateStream {
+        status: ResponseStatus,
+        id: u64,
+    },
+    WithdrawStream {
+        status: ResponseStatus,
+        amount: Uint128,
+    },
+    TransferAll {
+        amount: Uint128,
+        status: ResponseStatus,
     },
     BatchTransfer {
         status: ResponseStatus,
     },
+    TransferSplit {
+        status: ResponseStatus,
+    },
+    TransferWithAuth {
+        status: ResponseStatus,
+    },
     BatchSend {
         status: ResponseStatus,
     },
     Burn {
         status: ResponseStatus,
     },
+    BurnTo {
+        status: ResponseStatus,
+    },
     RegisterReceive {
         status: ResponseStatus,
     },
+    SettleSelf {
+        status: ResponseStatus,
+    },
+    InitializeAccount {
+        status: ResponseStatus,
+    },
+    SetChannelPreference {
+        status: ResponseStatus,
+    },
+    SetMemoDecryptionKey {
+        status: ResponseStatus,
+    },
     CreateViewingKey {
         key: String,
     },
@@ -370,6 +837,17 @@ pub enum ExecuteAnswer {
         owner: Addr,
         allowance: Uint128,
     },
+    ClearAllowanceExpiration {
+        spender: Addr,
+        owner: Addr,
+        allowance: Uint128,
+    },
+    BatchSetAllowance {
+        status: ResponseStatus,
+    },
+    SetAllowancesPublic {
+        status: ResponseStatus,
+    },
     TransferFrom {
         status: ResponseStatus,
     },
@@ -396,6 +874,9 @@ pub enum ExecuteAnswer {
     BatchMint {
         status: ResponseStatus,
     },
+    MintCampaign {
+        status: ResponseStatus,
+    },
     AddMinters {
         status: ResponseStatus,
     },
@@ -410,6 +891,12 @@ pub enum ExecuteAnswer {
     ChangeAdmin {
         status: ResponseStatus,
     },
+    CancelAdminTransfer {
+        status: ResponseStatus,
+    },
+    AcceptAdmin {
+        status: ResponseStatus,
+    },
     SetContractStatus {
         status: ResponseStatus,
     },
@@ -419,9 +906,30 @@ pub enum ExecuteAnswer {
     RemoveSupportedDenoms {
         status: ResponseStatus,
     },
+    SetDenomStatus {
+        status: ResponseStatus,
+    },
+    SetOperationFlags {
+        status: ResponseStatus,
+    },
+    SetRedeemRateLimit {
+        status: ResponseStatus,
+    },
+    SetMaxTxHistoryPerAccount {
+        status: ResponseStatus,
+    },
     SetNotificationStatus {
         status: ResponseStatus,
     },
+    RotateSecrets {
+        status: ResponseStatus,
+    },
+    RegisterChannel {
+        status: ResponseStatus,
+    },
+    UnregisterChannel {
+        status: ResponseStatus,
+    },
 
     // Permit
     RevokePermit {
@@ -437,6 +945,11 @@ pub enum ExecuteAnswer {
     DeletePermitRevocation {
         status: ResponseStatus,
     },
+
+    ResetCredentials {
+        status: ResponseStatus,
+        permit_epoch: u64,
+    },
 }
 
 #[cfg(feature = "gas_evaporation")]
@@ -450,16 +963,36 @@ impl Evaporator for ExecuteMsg {
         match self {
             ExecuteMsg::Redeem { gas_target, .. }
             | ExecuteMsg::Deposit { gas_target, .. }
+            | ExecuteMsg::SetPreferredRedeemDenom { gas_target, .. }
+            | ExecuteMsg::SetRedeemAllowance { gas_target, .. }
+            | ExecuteMsg::RedeemFrom { gas_target, .. }
             | ExecuteMsg::Transfer { gas_target, .. }
             | ExecuteMsg::Send { gas_target, .. }
+            | ExecuteMsg::RetryCallback { gas_target, .. }
+            | ExecuteMsg::TransferWithTimeout { gas_target, .. }
+            | ExecuteMsg::ClaimPending { gas_target, .. }
+            | ExecuteMsg::RefundPending { gas_target, .. }
+            | ExecuteMsg::CreateStream { gas_target, .. }
+            | ExecuteMsg::WithdrawStream { gas_target, .. }
+            | ExecuteMsg::TransferAll { gas_target, .. }
+            | ExecuteMsg::TransferSplit { gas_target, .. }
+            | ExecuteMsg::TransferWithAuth { gas_target, .. }
             | ExecuteMsg::BatchTransfer { gas_target, .. }
             | ExecuteMsg::BatchSend { gas_target, .. }
             | ExecuteMsg::Burn { gas_target, .. }
+            | ExecuteMsg::BurnTo { gas_target, .. }
             | ExecuteMsg::RegisterReceive { gas_target, .. }
+            | ExecuteMsg::SettleSelf { gas_target, .. }
+            | ExecuteMsg::InitializeAccount { gas_target, .. }
+            | ExecuteMsg::SetChannelPreference { gas_target, .. }
+            | ExecuteMsg::SetMemoDecryptionKey { gas_target, .. }
             | ExecuteMsg::CreateViewingKey { gas_target, .. }
             | ExecuteMsg::SetViewingKey { gas_target, .. }
             | ExecuteMsg::IncreaseAllowance { gas_target, .. }
             | ExecuteMsg::DecreaseAllowance { gas_target, .. }
+            | ExecuteMsg::ClearAllowanceExpiration { gas_target, .. }
+            | ExecuteMsg::BatchSetAllowance { gas_target, .. }
+            | ExecuteMsg::SetAllowancesPublic { gas_target, .. }
             | ExecuteMsg::TransferFrom { gas_target, .. }
             | ExecuteMsg::SendFrom { gas_target, .. }
             | ExecuteMsg::BatchTransferFrom { gas_target, .. }
@@ -468,17 +1001,28 @@ impl Evaporator for ExecuteMsg {
             | ExecuteMsg::BatchBurnFrom { gas_target, .. }
             | ExecuteMsg::Mint { gas_target, .. }
             | ExecuteMsg::BatchMint { gas_target, .. }
+            | ExecuteMsg::MintCampaign { gas_target, .. }
             | ExecuteMsg::AddMinters { gas_target, .. }
             | ExecuteMsg::RemoveMinters { gas_target, .. }
             | ExecuteMsg::SetMinters { gas_target, .. }
             | ExecuteMsg::ChangeAdmin { gas_target, .. }
+            | ExecuteMsg::CancelAdminTransfer { gas_target, .. }
+            | ExecuteMsg::AcceptAdmin { gas_target, .. }
             | ExecuteMsg::SetContractStatus { gas_target, .. }
             | ExecuteMsg::AddSupportedDenoms { gas_target, .. }
             | ExecuteMsg::RemoveSupportedDenoms { gas_target, .. }
+            | ExecuteMsg::SetDenomStatus { gas_target, .. }
+            | ExecuteMsg::SetOperationFlags { gas_target, .. }
+            | ExecuteMsg::SetRedeemRateLimit { gas_target, .. }
+            | ExecuteMsg::SetMaxTxHistoryPerAccount { gas_target, .. }
             | ExecuteMsg::SetNotificationStatus { gas_targe, .. }
+            | ExecuteMsg::RotateSecrets { gas_target, .. }
+            | ExecuteMsg::RegisterChannel { gas_target, .. }
+            | ExecuteMsg::UnregisterChannel { gas_target, .. }
             | ExecuteMsg::RevokePermit { gas_target, .. }
             | ExecuteMsg::RevokeAllPermits { gas_target, .. }
-            | ExecuteMsg::DeletePermitRevocation { gas_target, .. } => match gas_target {
+            | ExecuteMsg::DeletePermitRevocation { gas_target, .. }
+            | ExecuteMsg::ResetCredentials { gas_target, .. } => match gas_target {
                 Some(gas_target) => {
                     let gas_used = api.check_gas()?;
                     if gas_used < gas_target.u64() {
@@ -494,6 +1038,18 @@ impl Evaporator for ExecuteMsg {
     }
 }
 
+/// The payload an account signs to authorize a `TransferWithAuth`. The signature is made over the
+/// sha256 of its JSON serialization
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct TransferAuthorization {
+    pub chain_id: String,
+    pub contract: Addr,
+    pub from: Addr,
+    pub to: Addr,
+    pub amount: Uint128,
+    pub nonce: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 #[serde(rename_all = "snake_case")]
@@ -502,6 +1058,10 @@ pub enum QueryMsg {
     TokenConfig {},
     ContractStatus {},
     ExchangeRate {},
+    /// Returns the deposit/redeem exchange rate in both directions
+    ExchangeRates {},
+    /// Returns the burn sink address and, if the total supply is public, the total burned to it
+    TotalBurnedToSink {},
     Allowance {
         owner: String,
         spender: String,
@@ -519,10 +1079,32 @@ pub enum QueryMsg {
         page: Option<u32>,
         page_size: u32,
     },
+    /// Sums all of the owner's allowances. This scans every spender the owner has given an
+    /// allowance to, so its gas cost grows linearly with their number
+    TotalAllowanceGiven {
+        owner: String,
+        key: String,
+    },
+    /// Returns the nonce the account's next `TransferWithAuth` must be signed for
+    Nonce {
+        address: String,
+        key: String,
+    },
     Balance {
         address: String,
         key: String,
     },
+    /// Authenticated query returning the balance `address` held as of block `height`
+    BalanceAt {
+        address: String,
+        key: String,
+        height: u64,
+    },
+    /// Authenticated query returning the per-account settings that apply to `address`
+    AccountPolicies {
+        address: String,
+        key: String,
+    },
     TransferHistory {
         address: String,
         key: String,
@@ -534,12 +1116,74 @@ pub enum QueryMsg {
         key: String,
         page: Option<u32>,
         page_size: u32,
+        /// only return transactions of this kind
+        action_filter: Option<TxActionFilter>,
+        /// `ascending` returns the oldest transactions first (default: descending)
+        order: Option<Order>,
+    },
+    /// Returns the transactions of `address` in which `counterparty` is one of the parties,
+    /// newest first
+    TransactionsWith {
+        address: String,
+        key: String,
+        counterparty: String,
+        page: Option<u32>,
+        page_size: u32,
     },
     Minters {},
+    /// Public query returning the address nominated with `ChangeAdmin`, if any
+    PendingAdmin {},
+    /// Public query listing the coin denoms that have received at least one deposit
+    ActiveDepositDenoms {},
+    /// Public query listing every supported coin denom with whether it can be deposited and
+    /// redeemed
+    DenomInfo {},
+    /// Public query returning the code hash (and label, if any) an address registered with
+    /// `RegisterReceive`
+    ReceiverHash {
+        address: String,
+    },
+    /// Public query returning the memo encryption key an address published with `SetMemoDecryptionKey`
+    MemoDecryptionKey {
+        address: String,
+    },
+    /// Public query returning the allowances given by an owner who opted in with `SetAllowancesPublic`
+    PublicAllowancesGiven {
+        owner: String,
+        page: Option<u32>,
+        page_size: u32,
+    },
+    /// Public query returning the permit epoch an account's permit names must end with
+    PermitEpoch {
+        account: String,
+    },
+    /// Public query that validates a permit the same way `WithPermit` does, returning the
+    /// signing account and the permissions granted, without running a sub-query
+    ValidatePermit {
+        permit: Permit,
+    },
+    /// Public query reporting how full the delayed write buffer is, without revealing any
+    /// account data
+    DwbStats {},
+    /// Public query rendering a raw token amount in display units according to the token's
+    /// decimals
+    FormatAmount {
+        amount: Uint128,
+    },
 
     // SNIP-52 Private Push Notifications
-    /// Public query to list all notification channels
-    ListChannels {},
+    /// Public query to list notification channels, one page at a time if `page_size` is given,
+    /// or all of them otherwise
+    ListChannels {
+        page: Option<u32>,
+        page_size: Option<u32>,
+    },
+    /// Public query returning the static schema of a notification channel
+    ChannelSchema {
+        channel: String,
+    },
+    /// Public query listing the channels each kind of execute message emits notifications on
+    NotificationChannelsForTransfer {},
     /// Authenticated query allows clients to obtain the seed
     /// and schema for a specific channel.
     ChannelInfo {
@@ -547,6 +1191,21 @@ pub enum QueryMsg {
         txhash: Option<String>,
         viewer: ViewerInfo,
     },
+    /// Authenticated query listing the viewer's failed `Send` callbacks that can be retried
+    PendingCallbacks {
+        viewer: ViewerInfo,
+    },
+    /// Authenticated query listing whether the viewer receives notifications on each channel
+    ChannelPreferences {
+        viewer: ViewerInfo,
+    },
+    /// Admin-only query listing the most recent emissions on a channel, newest first
+    ChannelEmissionLog {
+        channel: String,
+        page: Option<u32>,
+        page_size: u32,
+        viewer: ViewerInfo,
+    },
 
     // SNIP 24.1
     ListPermitRevocations {
@@ -565,6 +1224,68 @@ pub enum QueryMsg {
     // for debug purposes only
     #[cfg(feature = "gas_tracking")]
     Dwb {},
+    /// Whether an account is buffered in the DWB, and how its balance is split between the
+    /// buffer and its settled entry
+    #[cfg(feature = "gas_tracking")]
+    AccountStatus {
+        address: String,
+    },
+    /// Structured gas logs recorded by the most recent tracked execute
+    #[cfg(feature = "gas_tracking")]
+    GasProfile {},
+}
+
+/// the most that can be redeemed, in the token's smallest denomination, within a window of
+/// `window_seconds` before the contract pauses redeems
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RedeemRateLimit {
+    pub amount: Uint128,
+    pub window_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OperationFlags {
+    pub mint_disabled: bool,
+    pub send_disabled: bool,
+    pub burn_disabled: bool,
+    pub transfer_disabled: bool,
+}
+
+/// the redeem rate limit along with how much has been redeemed in the window containing the
+/// current block. `window_start` is none when no window is running
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RedeemRateLimitStatus {
+    pub limit: RedeemRateLimit,
+    pub window_start: Option<u64>,
+    pub redeemed: Uint128,
+    pub remaining: Uint128,
+}
+
+/// the per-account settings that affect how the contract treats the account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AccountPolicies {
+    pub preferred_redeem_denom: Option<String>,
+    /// notification channels the account opted out of
+    pub disabled_channels: Vec<String>,
+    pub allowances_public: bool,
+    pub permit_epoch: u64,
+    pub memo_decryption_key: Option<Binary>,
+    pub receiver_code_hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct OperationChannels {
+    pub operation: String,
+    pub channels: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ChannelPreference {
+    pub channel: String,
+    pub enabled: bool,
 }
 
 /// the address and viewing key making an authenticated query request
@@ -579,7 +1300,15 @@ pub struct ViewerInfo {
 impl QueryMsg {
     pub fn get_validation_params(&self, api: &dyn Api) -> StdResult<(Vec<Addr>, String)> {
         match self {
-            Self::Balance { address, key } => {
+            Self::Balance { address, key } | Self::Nonce { address, key } => {
+                let address = api.addr_validate(address.as_str())?;
+                Ok((vec![address], key.clone()))
+            }
+            Self::BalanceAt { address, key, .. } => {
+                let address = api.addr_validate(address.as_str())?;
+                Ok((vec![address], key.clone()))
+            }
+            Self::AccountPolicies { address, key } => {
                 let address = api.addr_validate(address.as_str())?;
                 Ok((vec![address], key.clone()))
             }
@@ -591,6 +1320,10 @@ impl QueryMsg {
                 let address = api.addr_validate(address.as_str())?;
                 Ok((vec![address], key.clone()))
             }
+            Self::TransactionsWith { address, key, .. } => {
+                let address = api.addr_validate(address.as_str())?;
+                Ok((vec![address], key.clone()))
+            }
             Self::Allowance {
                 owner,
                 spender,
@@ -602,7 +1335,7 @@ impl QueryMsg {
 
                 Ok((vec![owner, spender], key.clone()))
             }
-            Self::AllowancesGiven { owner, key, .. } => {
+            Self::AllowancesGiven { owner, key, .. } | Self::TotalAllowanceGiven { owner, key } => {
                 let owner = api.addr_validate(owner.as_str())?;
                 Ok((vec![owner], key.clone()))
             }
@@ -614,6 +1347,18 @@ impl QueryMsg {
                 let address = api.addr_validate(viewer.address.as_str())?;
                 Ok((vec![address], viewer.viewing_key.clone()))
             }
+            Self::PendingCallbacks { viewer } => {
+                let address = api.addr_validate(viewer.address.as_str())?;
+                Ok((vec![address], viewer.viewing_key.clone()))
+            }
+            Self::ChannelPreferences { viewer } => {
+                let address = api.addr_validate(viewer.address.as_str())?;
+                Ok((vec![address], viewer.viewing_key.clone()))
+            }
+            Self::ChannelEmissionLog { viewer, .. } => {
+                let address = api.addr_validate(viewer.address.as_str())?;
+                Ok((vec![address], viewer.viewing_key.clone()))
+            }
             Self::ListPermitRevocations { viewer, .. } => {
                 let address = api.addr_validate(viewer.address.as_str())?;
                 Ok((vec![address], viewer.viewing_key.clone()))
@@ -642,6 +1387,9 @@ pub enum QueryWithPermit {
         page_size: u32,
     },
     Balance {},
+    BalanceAt {
+        height: u64,
+    },
     TransferHistory {
         page: Option<u32>,
         page_size: u32,
@@ -649,12 +1397,21 @@ pub enum QueryWithPermit {
     TransactionHistory {
         page: Option<u32>,
         page_size: u32,
+        action_filter: Option<TxActionFilter>,
+        order: Option<Order>,
     },
     // SNIP-52 Private Push Notifications
     ChannelInfo {
         channels: Vec<String>,
         txhash: Option<String>,
     },
+    NotificationId {
+        channel: String,
+        txhash: String,
+    },
+    ChannelPreferences {},
+    /// admin only; the supply is returned unless its visibility is `private`
+    TotalSupply {},
     // SNIP 24.1
     ListPermitRevocations {
         // `page` and `page_size` do nothing here because max revocations is only 10 but included
@@ -680,25 +1437,58 @@ pub enum QueryAnswer {
         mint_enabled: bool,
         burn_enabled: bool,
         supported_denoms: Vec<String>,
+        can_modify_denoms: bool,
     },
     ContractStatus {
         status: ContractStatusLevel,
+        /// operations the admin has disabled individually
+        operation_flags: OperationFlags,
+        /// the redeem rate limit and the current window's usage of it, if a limit is set
+        redeem_rate_limit: Option<RedeemRateLimitStatus>,
     },
     ExchangeRate {
         rate: Uint128,
         denom: String,
     },
+    ExchangeRates {
+        /// whole tokens worth one whole native coin, rounded down (zero when a native coin is
+        /// worth less than one token)
+        token_per_native: Uint128,
+        /// whole native coins worth one whole token, rounded down (zero when a token is worth
+        /// less than one native coin)
+        native_per_token: Uint128,
+        native_denom: String,
+    },
+    TotalBurnedToSink {
+        sink: Option<Addr>,
+        total_burned: Option<Uint128>,
+    },
+    TotalSupply {
+        total_supply: Option<Uint128>,
+    },
     Allowance {
         spender: Addr,
         owner: Addr,
         allowance: Uint128,
         expiration: Option<u64>,
+        /// the allowance that can currently be spent, which is zero once it has expired
+        effective_amount: Uint128,
     },
     AllowancesGiven {
         owner: Addr,
         allowances: Vec<AllowanceGivenResult>,
         count: u32,
     },
+    Nonce {
+        nonce: u64,
+    },
+    TotalAllowanceGiven {
+        /// sum of all allowances, saturating at the maximum Uint128
+        total: Uint128,
+        /// sum of the allowances that have not expired
+        active_total: Uint128,
+        count: u32,
+    },
     AllowancesReceived {
         spender: Addr,
         allowances: Vec<AllowanceReceivedResult>,
@@ -706,10 +1496,29 @@ pub enum QueryAnswer {
     },
     Balance {
         amount: Uint128,
+        /// the amount the account can withdraw from streams paying it, if it has any
+        #[serde(skip_serializing_if = "Option::is_none")]
+        claimable_streams: Option<Uint128>,
+    },
+    BalanceAt {
+        amount: Uint128,
+        height: u64,
+    },
+    AccountPolicies {
+        policies: AccountPolicies,
     },
     TransactionHistory {
         txs: Vec<Tx>,
         total: Option<u64>,
+        /// number of transactions matching `action_filter`, if one was given
+        #[serde(skip_serializing_if = "Option::is_none")]
+        filtered_total: Option<u64>,
+    },
+    TransactionsWith {
+        txs: Vec<Tx>,
+        total: u64,
+        /// number of transactions involving the counterparty
+        filtered_total: u64,
     },
     ViewingKeyError {
         msg: String,
@@ -717,10 +1526,71 @@ pub enum QueryAnswer {
     Minters {
         minters: Vec<Addr>,
     },
+    PendingAdmin {
+        address: Option<Addr>,
+    },
+    ActiveDepositDenoms {
+        count: u32,
+        denoms: Vec<String>,
+    },
+    DenomInfo {
+        denoms: Vec<DenomStatus>,
+    },
+    ReceiverHash {
+        code_hash: Option<String>,
+        label: Option<String>,
+    },
+    MemoDecryptionKey {
+        pubkey: Option<Binary>,
+    },
+    PermitEpoch {
+        permit_epoch: u64,
+    },
+    ValidatePermit {
+        account: Addr,
+        permissions: Vec<TokenPermissions>,
+    },
+    DwbStats {
+        capacity: u32,
+        used: u32,
+        empty_space_counter: u32,
+    },
+    FormatAmount {
+        display: String,
+    },
 
     // SNIP-52 Private Push Notifications
     ListChannels {
         channels: Vec<String>,
+        /// total number of channels across all pages
+        total: u32,
+    },
+    NotificationChannelsForTransfer {
+        operations: Vec<OperationChannels>,
+    },
+    PendingCallbacks {
+        callbacks: Vec<PendingCallback>,
+    },
+    ChannelPreferences {
+        channels: Vec<ChannelPreference>,
+    },
+    ChannelEmissionLog {
+        total: u64,
+        emissions: Vec<ChannelEmission>,
+    },
+    ChannelSchema {
+        channel: String,
+        /// "txhash" or "bloom"
+        mode: String,
+        /// CDDL schema of txhash channels
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cddl: Option<String>,
+        /// bloom filter parameters of bloom channels
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parameters: Option<BloomParameters>,
+        /// packet descriptor of bloom channels
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<Descriptor>,
     },
     ChannelInfo {
         /// scopes validity of this response
@@ -729,6 +1599,9 @@ pub enum QueryAnswer {
         seed: Binary,
         channels: Vec<ChannelInfoData>,
     },
+    NotificationId {
+        id: Binary,
+    },
 
     // SNIP-24.1
     ListPermitRevocations {
@@ -739,6 +1612,32 @@ pub enum QueryAnswer {
     Dwb {
         dwb: String,
     },
+    #[cfg(feature = "gas_tracking")]
+    AccountStatus {
+        in_dwb: bool,
+        dwb_amount: Uint128,
+        settled_balance: Uint128,
+        dwb_tx_count: u32,
+    },
+    #[cfg(feature = "gas_tracking")]
+    GasProfile {
+        logs: Vec<GasLog>,
+    },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct DepositCallback {
+    pub contract: String,
+    pub code_hash: String,
+    pub msg: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+pub struct DenomStatus {
+    pub denom: String,
+    pub deposit_enabled: bool,
+    pub redeem_enabled: bool,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
@@ -755,6 +1654,16 @@ pub struct AllowanceReceivedResult {
     pub expiration: Option<u64>,
 }
 
+/// What to do with the part of a `TransferSplit` total that does not divide evenly
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RemainderPolicy {
+    FirstRecipient,
+    LastRecipient,
+    /// the sender burns the remainder, reducing the total supply
+    Burn,
+}
+
 #[derive(Serialize, Deserialize, Clone, JsonSchema, Debug)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 #[serde(rename_all = "snake_case")]
@@ -763,12 +1672,25 @@ pub enum ResponseStatus {
     Failure,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TotalSupplyVisibility {
+    /// `token_info` includes the total supply
+    Public,
+    /// only the admin can query the total supply
+    AdminOnly,
+    /// the total supply is never revealed
+    Private,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ContractStatusLevel {
     NormalRun,
     StopAllButRedeems,
     StopAll,
+    /// set automatically when the redeem rate limit is exceeded; only redeems are stopped
+    RedeemPaused,
 }
 
 pub fn status_level_to_u8(status_level: ContractStatusLevel) -> u8 {
@@ -776,6 +1698,7 @@ pub fn status_level_to_u8(status_level: ContractStatusLevel) -> u8 {
         ContractStatusLevel::NormalRun => 0,
         ContractStatusLevel::StopAllButRedeems => 1,
         ContractStatusLevel::StopAll => 2,
+        ContractStatusLevel::RedeemPaused => 3,
     }
 }
 
@@ -784,6 +1707,7 @@ pub fn u8_to_status_level(status_level: u8) -> StdResult<ContractStatusLevel> {
         0 => Ok(ContractStatusLevel::NormalRun),
         1 => Ok(ContractStatusLevel::StopAllButRedeems),
         2 => Ok(ContractStatusLevel::StopAll),
+        3 => Ok(ContractStatusLevel::RedeemPaused),
         _ => Err(StdError::generic_err("Invalid state level")),
     }
 }
@@ -1,15 +1,20 @@
 use std::collections::HashMap;
 
-use cosmwasm_std::{Addr, Api, Binary, CanonicalAddr, Response, StdResult};
+use cosmwasm_std::{Addr, Api, Binary, CanonicalAddr, Env, Response, StdError, StdResult, Storage};
 use minicbor::Encoder;
 use primitive_types::{U256, U512};
 use secret_toolkit::notification::{
-    get_seed, notification_id, xor_bytes, DirectChannel, EncoderExt, GroupChannel, Notification,
+    get_seed, notification_id, xor_bytes, BloomParameters, ChannelInfoData, Descriptor,
+    DirectChannel, EncoderExt, FlatDescriptor, GroupChannel, Notification, StructDescriptor,
     CBL_ADDRESS, CBL_ARRAY_SHORT, CBL_BIGNUM_U64, CBL_TIMESTAMP, CBL_U8,
 };
 use secret_toolkit_crypto::{hkdf_sha_512, sha_256};
 use serde::{Deserialize, Serialize};
 
+use crate::state::{
+    ChannelEmission, ChannelEmissionLogStore, ChannelPreferencesStore, NotificationCountersStore,
+};
+
 const ZERO_ADDR: [u8; 20] = [0u8; 20];
 
 // maximum value that can be stored in 62 bits
@@ -18,6 +23,14 @@ const U62_MAX: u128 = (1 << 62) - 1;
 // maximum value that can be stored in 63 bits
 const U63_MAX: u128 = (1 << 63) - 1;
 
+/// Direct channel whose encrypted data is padded to a channel-specific block size, so every
+/// notification on the channel has the same length. Group channels are padded to their
+/// `PACKET_SIZE` instead.
+pub trait PaddedDirectChannel: DirectChannel {
+    /// the notification data is zero-padded to a multiple of this size before encryption
+    const BLOCK_SIZE: usize = Self::PAYLOAD_SIZE;
+}
+
 #[derive(Serialize, Debug, Deserialize, Clone)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 pub struct RecvdNotification {
@@ -67,6 +80,8 @@ impl DirectChannel for RecvdNotification {
     }
 }
 
+impl PaddedDirectChannel for RecvdNotification {}
+
 /// ```cddl
 ///  spent = [
 ///     amount: biguint .size 8,   ; transfer amount in base denomination
@@ -119,6 +134,18 @@ impl DirectChannel for SpentNotification {
     }
 }
 
+impl PaddedDirectChannel for SpentNotification {}
+
+/// sums the amounts of a batch's spent notifications, erroring instead of panicking on overflow
+pub fn total_spent_amount(notifications: &[Notification<SpentNotification>]) -> StdResult<u128> {
+    notifications
+        .iter()
+        .try_fold(0u128, |acc, notification| {
+            acc.checked_add(notification.data.amount)
+        })
+        .ok_or_else(|| StdError::generic_err("notification amount overflow"))
+}
+
 ///```cddl
 /// allowance = [
 ///    amount: biguint .size 8,   ; allowance amount in base denomination
@@ -161,8 +188,41 @@ impl DirectChannel for AllowanceNotification {
     }
 }
 
+impl PaddedDirectChannel for AllowanceNotification {}
+
 pub struct MultiRecvdNotification(pub Vec<Notification<RecvdNotification>>);
 
+impl MultiRecvdNotification {
+    /// creates the group, optionally merging notifications meant for the same recipient
+    /// into a single packet (summed amount) instead of dropping the repeated recipient
+    pub fn new(notifications: Vec<Notification<RecvdNotification>>, aggregate: bool) -> Self {
+        if !aggregate {
+            return Self(notifications);
+        }
+
+        let mut aggregated: Vec<Notification<RecvdNotification>> = vec![];
+        for notification in notifications {
+            match aggregated
+                .iter_mut()
+                .find(|existing| existing.notification_for == notification.notification_for)
+            {
+                Some(existing) => {
+                    let data = &mut existing.data;
+                    data.amount = data.amount.saturating_add(notification.data.amount);
+                    data.memo_len = data.memo_len.saturating_add(notification.data.memo_len);
+                    data.sender_is_owner &= notification.data.sender_is_owner;
+                    if data.sender != notification.data.sender {
+                        data.sender = None;
+                    }
+                }
+                None => aggregated.push(notification),
+            }
+        }
+
+        Self(aggregated)
+    }
+}
+
 impl GroupChannel<RecvdNotification> for MultiRecvdNotification {
     const CHANNEL_ID: &'static str = "multirecvd";
 
@@ -223,8 +283,43 @@ const_assert!(MultiRecvdNotification::BLOOM_K * MultiRecvdNotification::BLOOM_M_
 // this implementation is optimized to not check for packet sizes larger than 24 bytes
 const_assert!(MultiRecvdNotification::PACKET_SIZE <= 24);
 
+/// group of spent notifications; packets are packed strictly by input index, so entries whose
+/// amounts and recipients collide still render in the order they were given (aggregated entries
+/// take the position of the owner's first occurrence)
 pub struct MultiSpentNotification(pub Vec<Notification<SpentNotification>>);
 
+impl MultiSpentNotification {
+    /// creates the group, optionally merging notifications meant for the same owner
+    /// into a single packet (summed amount, latest balance, recipient only if shared) instead of dropping the repeated owner
+    pub fn new(notifications: Vec<Notification<SpentNotification>>, aggregate: bool) -> Self {
+        if !aggregate {
+            return Self(notifications);
+        }
+
+        let mut aggregated: Vec<Notification<SpentNotification>> = vec![];
+        for notification in notifications {
+            match aggregated
+                .iter_mut()
+                .find(|existing| existing.notification_for == notification.notification_for)
+            {
+                Some(existing) => {
+                    let data = &mut existing.data;
+                    data.amount = data.amount.saturating_add(notification.data.amount);
+                    data.actions = data.actions.saturating_add(notification.data.actions);
+                    data.memo_len = data.memo_len.saturating_add(notification.data.memo_len);
+                    data.balance = notification.data.balance;
+                    if data.recipient != notification.data.recipient {
+                        data.recipient = None;
+                    }
+                }
+                None => aggregated.push(notification),
+            }
+        }
+
+        Self(aggregated)
+    }
+}
+
 impl GroupChannel<SpentNotification> for MultiSpentNotification {
     const CHANNEL_ID: &str = "multispent";
 
@@ -288,6 +383,225 @@ const_assert!(MultiSpentNotification::BLOOM_K * MultiSpentNotification::BLOOM_M_
 // this implementation is optimized to not check for packet sizes larger than 24 bytes
 const_assert!(MultiSpentNotification::PACKET_SIZE <= 24);
 
+/// Counter-mode channel signalling each credit an account is notified of on `recvd` or
+/// `multirecvd`. Its notification IDs are derived from the account's counter on the channel
+/// rather than the tx hash, so a client can compute the next ID ahead of time and watch for it.
+/// The notifications carry no data.
+pub const RECVD_COUNTER_CHANNEL_ID: &str = "recvdcount";
+
+const RECVD: &str = RecvdNotification::CHANNEL_ID;
+const SPENT: &str = SpentNotification::CHANNEL_ID;
+const ALLOWANCE: &str = AllowanceNotification::CHANNEL_ID;
+const MULTIRECVD: &str = MultiRecvdNotification::CHANNEL_ID;
+const MULTISPENT: &str = MultiSpentNotification::CHANNEL_ID;
+
+/// the counter-mode channel that accompanies every notification on `channel`, if any. this is
+/// what both the notification renderers and the `NotificationChannelsForTransfer` query use
+pub fn counter_channel_for(channel: &str) -> Option<&'static str> {
+    if channel == RECVD || channel == MULTIRECVD {
+        Some(RECVD_COUNTER_CHANNEL_ID)
+    } else {
+        None
+    }
+}
+
+/// the channels each execute operation that moves tokens or allowances can notify on, keyed by
+/// the operation's name in `ExecuteMsg`. counter-mode channels are not listed since they follow
+/// from `counter_channel_for`. `transfer_from` and `send_from` additionally notify the owner on
+/// `allowance` when the contract is configured with `notify_allowance_use`
+pub const OPERATION_CHANNELS: [(&str, &[&str]); 30] = [
+    ("deposit", &[RECVD, SPENT]),
+    ("redeem", &[]),
+    ("redeem_from", &[]),
+    ("transfer", &[RECVD, SPENT]),
+    ("transfer_all", &[RECVD, SPENT]),
+    ("send", &[RECVD, SPENT]),
+    ("transfer_with_timeout", &[]),
+    ("claim_pending", &[]),
+    ("refund_pending", &[]),
+    ("create_stream", &[]),
+    ("withdraw_stream", &[]),
+    ("batch_transfer", &[MULTIRECVD, SPENT]),
+    ("transfer_split", &[MULTIRECVD, SPENT]),
+    ("transfer_with_auth", &[RECVD, SPENT]),
+    ("batch_send", &[MULTIRECVD, SPENT]),
+    ("burn", &[SPENT]),
+    ("burn_to", &[SPENT]),
+    ("increase_allowance", &[ALLOWANCE]),
+    ("decrease_allowance", &[ALLOWANCE]),
+    ("clear_allowance_expiration", &[ALLOWANCE]),
+    ("batch_set_allowance", &[ALLOWANCE]),
+    ("transfer_from", &[RECVD, SPENT]),
+    ("send_from", &[RECVD, SPENT]),
+    ("batch_transfer_from", &[MULTIRECVD, MULTISPENT]),
+    ("batch_send_from", &[MULTIRECVD, MULTISPENT]),
+    ("burn_from", &[SPENT]),
+    ("batch_burn_from", &[MULTISPENT]),
+    ("mint", &[RECVD]),
+    ("batch_mint", &[MULTIRECVD]),
+    ("mint_campaign", &[MULTIRECVD]),
+];
+
+/// the channels an operation from `OPERATION_CHANNELS` notifies on, including the counter-mode
+/// channels that accompany them
+pub fn operation_channels(operation: &str, notify_allowance_use: bool) -> Vec<&'static str> {
+    let listed = OPERATION_CHANNELS
+        .iter()
+        .find(|(name, _)| *name == operation)
+        .map(|(_, listed)| *listed)
+        .unwrap_or_default();
+
+    let mut channels = vec![];
+    for channel in listed {
+        channels.push(*channel);
+        channels.extend(counter_channel_for(channel));
+    }
+    if notify_allowance_use && (operation == "transfer_from" || operation == "send_from") {
+        channels.push(ALLOWANCE);
+    }
+    channels
+}
+
+/// channels this contract emits notifications on itself, which cannot be unregistered
+pub const BUILTIN_CHANNELS: [&str; 6] = [
+    RecvdNotification::CHANNEL_ID,
+    SpentNotification::CHANNEL_ID,
+    AllowanceNotification::CHANNEL_ID,
+    MultiRecvdNotification::CHANNEL_ID,
+    MultiSpentNotification::CHANNEL_ID,
+    RECVD_COUNTER_CHANNEL_ID,
+];
+
+/// the channel info templates of the built-in channels, which are stored at instantiation so
+/// that `ChannelInfo` can serve every channel from storage
+pub fn builtin_channel_schemata() -> Vec<ChannelInfoData> {
+    vec![
+        ChannelInfoData {
+            mode: "txhash".to_string(),
+            channel: RecvdNotification::CHANNEL_ID.to_string(),
+            answer_id: None,
+            parameters: None,
+            data: None,
+            next_id: None,
+            counter: None,
+            cddl: Some(RecvdNotification::CDDL_SCHEMA.to_string()),
+        },
+        ChannelInfoData {
+            mode: "txhash".to_string(),
+            channel: SpentNotification::CHANNEL_ID.to_string(),
+            answer_id: None,
+            parameters: None,
+            data: None,
+            next_id: None,
+            counter: None,
+            cddl: Some(SpentNotification::CDDL_SCHEMA.to_string()),
+        },
+        ChannelInfoData {
+            mode: "txhash".to_string(),
+            channel: AllowanceNotification::CHANNEL_ID.to_string(),
+            answer_id: None,
+            parameters: None,
+            data: None,
+            next_id: None,
+            counter: None,
+            cddl: Some(AllowanceNotification::CDDL_SCHEMA.to_string()),
+        },
+        ChannelInfoData {
+            mode: "bloom".to_string(),
+            channel: MultiRecvdNotification::CHANNEL_ID.to_string(),
+            answer_id: None,
+            parameters: Some(BloomParameters {
+                m: MultiRecvdNotification::BLOOM_M,
+                k: MultiRecvdNotification::BLOOM_K,
+                h: "sha256".to_string(),
+            }),
+            data: Some(Descriptor {
+                r#type: format!("packet[{}]", MultiRecvdNotification::BLOOM_N),
+                version: "1".to_string(),
+                packet_size: MultiRecvdNotification::PACKET_SIZE as u32,
+                data: StructDescriptor {
+                    r#type: "struct".to_string(),
+                    label: "transfer".to_string(),
+                    members: vec![
+                        FlatDescriptor {
+                            r#type: "uint64".to_string(),
+                            label: "flagsAndAmount".to_string(),
+                            description: Some(
+                                "Bit field of [0]: non-empty memo; [2]: sender is owner; [2..]: uint62 transfer amount in base denomination".to_string(),
+                            ),
+                        },
+                        FlatDescriptor {
+                            r#type: "bytes8".to_string(),
+                            label: "ownerId".to_string(),
+                            description: Some(
+                                "The last 8 bytes of the owner's canonical address".to_string(),
+                            ),
+                        },
+                    ],
+                },
+            }),
+            counter: None,
+            next_id: None,
+            cddl: None,
+        },
+        ChannelInfoData {
+            mode: "bloom".to_string(),
+            channel: MultiSpentNotification::CHANNEL_ID.to_string(),
+            answer_id: None,
+            parameters: Some(BloomParameters {
+                m: MultiSpentNotification::BLOOM_M,
+                k: MultiSpentNotification::BLOOM_K,
+                h: "sha256".to_string(),
+            }),
+            data: Some(Descriptor {
+                r#type: format!("packet[{}]", MultiSpentNotification::BLOOM_N),
+                version: "1".to_string(),
+                packet_size: MultiSpentNotification::PACKET_SIZE as u32,
+                data: StructDescriptor {
+                    r#type: "struct".to_string(),
+                    label: "transfer".to_string(),
+                    members: vec![
+                        FlatDescriptor {
+                            r#type: "uint64".to_string(),
+                            label: "flagsAndAmount".to_string(),
+                            description: Some(
+                                "Bit field of [0]: non-empty memo; [1]: reserved; [2..] uint62 transfer amount in base denomination".to_string(),
+                            ),
+                        },
+                        FlatDescriptor {
+                            r#type: "bytes8".to_string(),
+                            label: "recipientId".to_string(),
+                            description: Some(
+                                "The last 8 bytes of the recipient's canonical address".to_string(),
+                            ),
+                        },
+                        FlatDescriptor {
+                            r#type: "uint64".to_string(),
+                            label: "balance".to_string(),
+                            description: Some(
+                                "Spender's new balance after the transfer".to_string(),
+                            ),
+                        },
+                    ],
+                },
+            }),
+            counter: None,
+            next_id: None,
+            cddl: None,
+        },
+        ChannelInfoData {
+            mode: "counter".to_string(),
+            channel: RECVD_COUNTER_CHANNEL_ID.to_string(),
+            answer_id: None,
+            parameters: None,
+            data: None,
+            next_id: None,
+            counter: None,
+            cddl: None,
+        },
+    ]
+}
+
 struct BloomFilter {
     filter: U512,
     tx_hash: String,
@@ -330,14 +644,121 @@ impl BloomFilter {
     }
 }
 
+/// renders a direct channel notification into the response, unless the notified account has
+/// opted out of the channel
+pub fn add_direct_notification<T: PaddedDirectChannel>(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    env: &Env,
+    secret: &[u8],
+    resp: Response,
+    notification: Notification<T>,
+) -> StdResult<Response> {
+    // every credit also advances the recipient's counter on the received tokens counter channel
+    let mut resp = resp;
+    if let Some(counter_channel) = counter_channel_for(T::CHANNEL_ID) {
+        resp = add_counter_notification(
+            storage,
+            api,
+            env.block.height,
+            secret,
+            resp,
+            counter_channel,
+            &notification.notification_for,
+        )?;
+    }
+
+    if !ChannelPreferencesStore::is_enabled(storage, &notification.notification_for, T::CHANNEL_ID)
+    {
+        return Ok(resp);
+    }
+
+    let notification =
+        notification.to_txhash_notification(api, env, secret, Some(T::BLOCK_SIZE))?;
+    log_direct_emission(
+        storage,
+        T::CHANNEL_ID,
+        env.block.height,
+        &notification.id_plaintext(),
+    )?;
+
+    Ok(resp.add_attribute_plaintext(notification.id_plaintext(), notification.data_plaintext()))
+}
+
+/// records an emitted direct channel notification in the channel's emission log
+fn log_direct_emission(
+    storage: &mut dyn Storage,
+    channel: &str,
+    block_height: u64,
+    id_plaintext: &str,
+) -> StdResult<()> {
+    let id = Binary::from_base64(id_plaintext.trim_start_matches("snip52:"))?;
+    ChannelEmissionLogStore::push(
+        storage,
+        channel,
+        ChannelEmission {
+            block_height,
+            marker: hex::encode(&id.as_slice()[..id.len().min(8)]),
+        },
+    )
+}
+
+/// the ID of the notification sent on a counter-mode channel when the counter reaches `counter`
+pub fn counter_notification_id(seed: &Binary, channel: &str, counter: u64) -> StdResult<Binary> {
+    notification_id(seed, channel, &counter.to_string())
+}
+
+/// advances the account's counter on a counter-mode channel and renders the notification for the
+/// new value into the response, unless the account has opted out of the channel
+fn add_counter_notification(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    block_height: u64,
+    secret: &[u8],
+    resp: Response,
+    channel: &str,
+    notification_for: &Addr,
+) -> StdResult<Response> {
+    if !ChannelPreferencesStore::is_enabled(storage, notification_for, channel) {
+        return Ok(resp);
+    }
+
+    let counter = NotificationCountersStore::increment(storage, notification_for, channel)?;
+    let seed = get_seed(&api.addr_canonicalize(notification_for.as_str())?, secret)?;
+    let id_plaintext = format!(
+        "snip52:{}",
+        counter_notification_id(&seed, channel, counter)?.to_base64()
+    );
+    log_direct_emission(storage, channel, block_height, &id_plaintext)?;
+
+    Ok(resp.add_attribute_plaintext(id_plaintext, ""))
+}
+
 pub fn render_group_notification<D: DirectChannel, G: GroupChannel<D>>(
+    storage: &mut dyn Storage,
     api: &dyn Api,
     group: G,
+    block_height: u64,
     tx_hash: &String,
     env_random: Binary,
     secret: &[u8],
-    resp: Response,
+    mut resp: Response,
 ) -> StdResult<Response> {
+    // every credit also advances the recipient's counter on the received tokens counter channel
+    if let Some(counter_channel) = counter_channel_for(G::CHANNEL_ID) {
+        for notification in group.notifications() {
+            resp = add_counter_notification(
+                storage,
+                api,
+                block_height,
+                secret,
+                resp,
+                counter_channel,
+                &notification.notification_for,
+            )?;
+        }
+    }
+
     // bloom filter
     let mut bloom_filter = BloomFilter {
         filter: U512::from(0),
@@ -351,8 +772,19 @@ pub fn render_group_notification<D: DirectChannel, G: GroupChannel<D>>(
     // keep track of how many times an address shows up in packet data
     let mut recipient_counts: HashMap<CanonicalAddr, u16> = HashMap::new();
 
-    // each notification
+    // each notification; packets are emitted in the same order as the group's notifications
+    // (after dropping opted-out and repeated accounts) and are never shuffled, so indexers can
+    // map the i-th real packet back to the i-th surviving action
     for notification in group.notifications() {
+        // leave out accounts that opted out of this channel
+        if !ChannelPreferencesStore::is_enabled(
+            storage,
+            &notification.notification_for,
+            G::CHANNEL_ID,
+        ) {
+            continue;
+        }
+
         // who notification is intended for
         let notification_for = api.addr_canonicalize(notification.notification_for.as_str())?;
         let notifyee = notification_for.clone();
@@ -390,6 +822,18 @@ pub fn render_group_notification<D: DirectChannel, G: GroupChannel<D>>(
         packets = packets[0..G::BLOOM_N].to_vec();
     }
 
+    // log the real packets before any decoys are added, using each packet ID as its marker
+    for packet in &packets {
+        ChannelEmissionLogStore::push(
+            storage,
+            G::CHANNEL_ID,
+            ChannelEmission {
+                block_height,
+                marker: hex::encode(&packet[0..8]),
+            },
+        )?;
+    }
+
     // now add extra packets, if needed, to hide number of packets
     let padding_size = G::BLOOM_N.saturating_sub(packets.len());
     if padding_size > 0 {
@@ -436,3 +880,251 @@ pub fn render_group_notification<D: DirectChannel, G: GroupChannel<D>>(
         Binary::from(output_bytes).to_base64(),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+
+    use super::*;
+
+    // ChaCha20-Poly1305 authentication tag appended to the padded data
+    const TAG_SIZE: usize = 16;
+
+    fn direct_notification_data_len<T: PaddedDirectChannel>(data: T) -> usize {
+        let api = MockApi::default();
+        let mut storage = MockStorage::new();
+        let notification = Notification::new(Addr::unchecked("alice"), data);
+
+        let resp = add_direct_notification(
+            &mut storage,
+            &api,
+            &mock_env(),
+            &[7u8; 32],
+            Response::new(),
+            notification,
+        )
+        .unwrap();
+
+        assert_eq!(resp.attributes.len(), 1);
+        Binary::from_base64(&resp.attributes[0].value)
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    fn test_direct_notification_block_size() {
+        let recvd_len = direct_notification_data_len(RecvdNotification {
+            amount: 1000,
+            sender: Some(Addr::unchecked("bob")),
+            memo_len: 12,
+            sender_is_owner: true,
+        });
+        assert_eq!(recvd_len, RecvdNotification::BLOCK_SIZE + TAG_SIZE);
+
+        let spent_len = direct_notification_data_len(SpentNotification {
+            amount: 1000,
+            actions: 1,
+            recipient: None,
+            balance: 5000,
+            memo_len: 0,
+        });
+        assert_eq!(spent_len, SpentNotification::BLOCK_SIZE + TAG_SIZE);
+
+        let allowance_len = direct_notification_data_len(AllowanceNotification {
+            amount: 1000,
+            allower: Addr::unchecked("bob"),
+            expiration: None,
+        });
+        assert_eq!(allowance_len, AllowanceNotification::BLOCK_SIZE + TAG_SIZE);
+    }
+
+    #[test]
+    fn test_total_spent_amount_overflow() {
+        let spent = |amount: u128| {
+            Notification::new(
+                Addr::unchecked("alice"),
+                SpentNotification {
+                    amount,
+                    actions: 1,
+                    recipient: Some(Addr::unchecked("bob")),
+                    balance: 0,
+                    memo_len: 0,
+                },
+            )
+        };
+
+        assert_eq!(
+            total_spent_amount(&[spent(1000), spent(2000), spent(3000)]).unwrap(),
+            6000
+        );
+        assert_eq!(
+            total_spent_amount(&[spent(u128::MAX - 1), spent(1)]).unwrap(),
+            u128::MAX
+        );
+
+        match total_spent_amount(&[spent(u128::MAX), spent(1)]) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "notification amount overflow")
+            }
+            other => panic!("Unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_multi_recvd_packet_order() {
+        let api = MockApi::default();
+        let mut storage = MockStorage::new();
+        let secret = [7u8; 32];
+        let tx_hash = "4A3B2C1D".to_string();
+
+        let actions: Vec<(&str, u128, usize, bool)> = vec![
+            ("alice", 1000, 0, true),
+            ("bob", 2000, 4, false),
+            ("charlie", 3000, 0, false),
+            ("bob", 4000, 0, true),
+            ("dave", 5000, 9, true),
+        ];
+        let notifications = actions
+            .iter()
+            .map(|(recipient, amount, memo_len, sender_is_owner)| {
+                Notification::new(
+                    Addr::unchecked(*recipient),
+                    RecvdNotification {
+                        amount: *amount,
+                        sender: None,
+                        memo_len: *memo_len,
+                        sender_is_owner: *sender_is_owner,
+                    },
+                )
+            })
+            .collect();
+
+        let resp = render_group_notification(
+            &mut storage,
+            &api,
+            MultiRecvdNotification::new(notifications, false),
+            12345,
+            &tx_hash,
+            Binary::from(vec![1u8; 32]),
+            &secret,
+            Response::new(),
+        )
+        .unwrap();
+
+        assert_eq!(resp.attributes.len(), 1);
+        assert_eq!(resp.attributes[0].key, "snip52:#multirecvd");
+        let output = Binary::from_base64(&resp.attributes[0].value).unwrap();
+
+        // bloom filter followed by BLOOM_N packets of packet ID (8 bytes) + ciphertext
+        let bloom_len = (MultiRecvdNotification::BLOOM_M / 8) as usize;
+        let packet_len = 8 + MultiRecvdNotification::PACKET_SIZE;
+        assert_eq!(
+            output.len(),
+            bloom_len + MultiRecvdNotification::BLOOM_N * packet_len
+        );
+        let packets = &output.as_slice()[bloom_len..];
+
+        // bob appears twice so is left out; the rest keep their input order
+        let expected = [&actions[0], &actions[2], &actions[4]];
+        for (i, (recipient, amount, memo_len, sender_is_owner)) in expected.iter().enumerate() {
+            let packet = &packets[i * packet_len..(i + 1) * packet_len];
+
+            let recipient_raw = api.addr_canonicalize(recipient).unwrap();
+            let seed = get_seed(&recipient_raw, &secret).unwrap();
+            let id = notification_id(&seed, MultiRecvdNotification::CHANNEL_ID, &tx_hash).unwrap();
+            assert_eq!(&packet[0..8], &id.0.as_slice()[0..8]);
+
+            let plaintext = xor_bytes(
+                &packet[8..],
+                &id.0.as_slice()[8..8 + MultiRecvdNotification::PACKET_SIZE],
+            );
+            let flags_and_amount = u64::from_be_bytes(plaintext[0..8].try_into().unwrap());
+            assert_eq!(flags_and_amount as u128 & U62_MAX, *amount);
+            assert_eq!(flags_and_amount >> 63 == 1, *memo_len != 0);
+            assert_eq!((flags_and_amount >> 62) & 1 == 1, *sender_is_owner);
+            assert_eq!(&plaintext[8..16], &ZERO_ADDR[12..]);
+        }
+    }
+
+    #[test]
+    fn test_multi_spent_packet_order_with_collisions() {
+        let api = MockApi::default();
+        let secret = [7u8; 32];
+        let tx_hash = "4A3B2C1D".to_string();
+
+        // every entry spends the same amount to the same recipient
+        let owners = ["alice", "bob", "charlie", "dave"];
+        let render = || {
+            let mut storage = MockStorage::new();
+            let notifications = owners
+                .iter()
+                .map(|owner| {
+                    Notification::new(
+                        Addr::unchecked(*owner),
+                        SpentNotification {
+                            amount: 1000,
+                            actions: 1,
+                            recipient: Some(Addr::unchecked("erin")),
+                            balance: 5000,
+                            memo_len: 0,
+                        },
+                    )
+                })
+                .collect();
+
+            let resp = render_group_notification(
+                &mut storage,
+                &api,
+                MultiSpentNotification::new(notifications, false),
+                12345,
+                &tx_hash,
+                Binary::from(vec![1u8; 32]),
+                &secret,
+                Response::new(),
+            )
+            .unwrap();
+            assert_eq!(resp.attributes.len(), 1);
+            assert_eq!(resp.attributes[0].key, "snip52:#multispent");
+            Binary::from_base64(&resp.attributes[0].value).unwrap()
+        };
+
+        // same seed renders byte-identical output on every run
+        let output = render();
+        for _ in 0..3 {
+            assert_eq!(render(), output);
+        }
+
+        let bloom_len = (MultiSpentNotification::BLOOM_M / 8) as usize;
+        let packet_len = 8 + MultiSpentNotification::PACKET_SIZE;
+        assert_eq!(
+            output.len(),
+            bloom_len + MultiSpentNotification::BLOOM_N * packet_len
+        );
+        let packets = &output.as_slice()[bloom_len..];
+
+        // the i-th packet belongs to the i-th input entry
+        let recipient_raw = api.addr_canonicalize("erin").unwrap();
+        for (i, owner) in owners.iter().enumerate() {
+            let packet = &packets[i * packet_len..(i + 1) * packet_len];
+
+            let owner_raw = api.addr_canonicalize(owner).unwrap();
+            let seed = get_seed(&owner_raw, &secret).unwrap();
+            let id = notification_id(&seed, MultiSpentNotification::CHANNEL_ID, &tx_hash).unwrap();
+            assert_eq!(&packet[0..8], &id.0.as_slice()[0..8]);
+
+            let plaintext = xor_bytes(
+                &packet[8..],
+                &id.0.as_slice()[8..8 + MultiSpentNotification::PACKET_SIZE],
+            );
+            assert_eq!(
+                u64::from_be_bytes(plaintext[0..8].try_into().unwrap()),
+                1000
+            );
+            assert_eq!(&plaintext[8..16], &recipient_raw.as_slice()[12..]);
+            assert_eq!(
+                u64::from_be_bytes(plaintext[16..24].try_into().unwrap()),
+                5000
+            );
+        }
+    }
+}
@@ -1,27 +1,36 @@
 use cosmwasm_std::{
-    to_binary, Addr, Binary, CanonicalAddr, Deps, Env, StdError, StdResult, Storage, Uint128,
-    Uint64,
-};
-use rand_chacha::ChaChaRng;
-use rand_core::{RngCore, SeedableRng};
-use secret_toolkit::notification::{
-    get_seed, notification_id, BloomParameters, ChannelInfoData, Descriptor, DirectChannel,
-    FlatDescriptor, GroupChannel, StructDescriptor,
+    to_binary, Addr, Binary, CanonicalAddr, Deps, Env, Order, StdError, StdResult, Storage,
+    Uint128, Uint64,
 };
+use secret_toolkit::notification::{get_seed, notification_id, ChannelInfoData};
 use secret_toolkit::permit::{RevokedPermits, RevokedPermitsStore};
 
-use crate::btbe::{find_start_bundle, stored_balance, stored_entry, stored_tx_count};
-use crate::dwb::{DWB, TX_NODES};
-use crate::msg::{AllowanceGivenResult, AllowanceReceivedResult, QueryAnswer};
-use crate::notifications::{
-    AllowanceNotification, MultiRecvdNotification, MultiSpentNotification, RecvdNotification,
-    SpentNotification,
+use crate::btbe::{find_start_bundle, pruned_tx_count, stored_entry, stored_tx_count};
+use crate::dwb::{DWB, TX_NODES, ZERO_ADDR};
+use crate::msg::{
+    AccountPolicies, AllowanceGivenResult, AllowanceReceivedResult, ChannelPreference, DenomStatus,
+    OperationChannels, OperationFlags, QueryAnswer, RedeemRateLimitStatus, TotalSupplyVisibility,
 };
+use crate::notifications::{counter_notification_id, operation_channels, OPERATION_CHANNELS};
 use crate::state::{
-    AllowancesStore, MintersStore, CHANNELS, CONFIG, CONTRACT_STATUS, INTERNAL_SECRET_RELAXED,
-    INTERNAL_SECRET_SENSITIVE, TOTAL_SUPPLY,
+    AllowancesStore, AuthNoncesStore, BalanceCheckpointsStore, ChannelEmissionLogStore,
+    ChannelPreferencesStore, DenomStatusStore, MemoDecryptionKeyStore, MintersStore,
+    NotificationCountersStore, OperationFlagsStore, PendingCallbacksStore, PermitEpochStore,
+    PreferredRedeemDenomStore, PublicAllowancesStore, ReceiverHashStore, RedeemRateLimitStore,
+    StreamsStore, ACTIVE_DEPOSIT_DENOMS, CHANNELS, CHANNEL_SCHEMATA, CONFIG, CONTRACT_STATUS,
+    INTERNAL_SECRET_RELAXED, INTERNAL_SECRET_SENSITIVE, NOTIFICATIONS_ENABLED, OPERATION_BURN,
+    OPERATION_MINT, OPERATION_SEND, OPERATION_TRANSFER, PENDING_ADMIN, TOTAL_BURNED_TO_SINK,
+    TOTAL_SUPPLY,
 };
-use crate::transaction_history::Tx;
+use crate::transaction_history::{obfuscated_tx_id, Tx, TxAction, TxActionFilter, TOKEN_DENOM};
+
+/// returns 10^exp, guarding against exchange rates that cannot fit in u128 (decimals are capped
+/// at instantiation)
+fn pow10(exp: u32) -> StdResult<u128> {
+    10u128
+        .checked_pow(exp)
+        .ok_or_else(|| StdError::generic_err("decimals too large for exchange rate"))
+}
 
 pub fn query_exchange_rate(storage: &dyn Storage) -> StdResult<Binary> {
     let constants = CONFIG.load(storage)?;
@@ -29,13 +38,14 @@ pub fn query_exchange_rate(storage: &dyn Storage) -> StdResult<Binary> {
     if constants.deposit_is_enabled || constants.redeem_is_enabled {
         let rate: Uint128;
         let denom: String;
-        // if token has more decimals than SCRT, you get magnitudes of SCRT per token
-        if constants.decimals >= 6 {
-            rate = Uint128::new(10u128.pow(constants.decimals as u32 - 6));
-            denom = "SCRT".to_string();
-        // if token has less decimals, you get magnitudes token for SCRT
+        let native_decimals = constants.native_decimals as u32;
+        // if token has more decimals than the native coin, you get magnitudes of native coin per token
+        if constants.decimals as u32 >= native_decimals {
+            rate = Uint128::new(pow10(constants.decimals as u32 - native_decimals)?);
+            denom = constants.native_display_denom;
+        // if token has less decimals, you get magnitudes token for native coin
         } else {
-            rate = Uint128::new(10u128.pow(6 - constants.decimals as u32));
+            rate = Uint128::new(pow10(native_decimals - constants.decimals as u32)?);
             denom = constants.symbol;
         }
         return to_binary(&QueryAnswer::ExchangeRate { rate, denom });
@@ -46,10 +56,72 @@ pub fn query_exchange_rate(storage: &dyn Storage) -> StdResult<Binary> {
     })
 }
 
+pub fn query_exchange_rates(storage: &dyn Storage) -> StdResult<Binary> {
+    let constants = CONFIG.load(storage)?;
+
+    if constants.deposit_is_enabled || constants.redeem_is_enabled {
+        // deposits and redeems convert 1:1 in base units, so a whole token is worth
+        // 10^(decimals - native_decimals) whole native coins
+        let token_unit = pow10(constants.decimals as u32)?;
+        let native_unit = pow10(constants.native_decimals as u32)?;
+        return to_binary(&QueryAnswer::ExchangeRates {
+            token_per_native: Uint128::new(native_unit / token_unit),
+            native_per_token: Uint128::new(token_unit / native_unit),
+            native_denom: constants.native_display_denom,
+        });
+    }
+    to_binary(&QueryAnswer::ExchangeRates {
+        token_per_native: Uint128::zero(),
+        native_per_token: Uint128::zero(),
+        native_denom: String::new(),
+    })
+}
+
+pub fn query_dwb_stats(storage: &dyn Storage) -> StdResult<Binary> {
+    let dwb = DWB.load(storage)?;
+
+    // skip the dummy entry; a slot stays occupied by its address even after being settled
+    let used = dwb
+        .entries
+        .iter()
+        .skip(1)
+        .filter(|entry| entry.recipient_slice() != ZERO_ADDR.as_slice())
+        .count();
+
+    to_binary(&QueryAnswer::DwbStats {
+        capacity: (dwb.entries.len() - 1) as u32,
+        used: used as u32,
+        empty_space_counter: dwb.empty_space_counter as u32,
+    })
+}
+
+pub fn query_format_amount(storage: &dyn Storage, amount: Uint128) -> StdResult<Binary> {
+    let decimals = CONFIG.load(storage)?.decimals as usize;
+
+    to_binary(&QueryAnswer::FormatAmount {
+        display: format_amount(amount.u128(), decimals),
+    })
+}
+
+/// renders a raw amount in display units, e.g. 123456 with 8 decimals is "0.00123456".
+/// trailing zeros of the fraction are trimmed, and whole amounts have no decimal point
+fn format_amount(amount: u128, decimals: usize) -> String {
+    // left-pad so that there is at least one digit before the decimal point
+    let digits = format!("{amount:0>width$}", width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{whole}.{fraction}")
+    }
+}
+
 pub fn query_token_info(storage: &dyn Storage) -> StdResult<Binary> {
     let constants = CONFIG.load(storage)?;
 
-    let total_supply = if constants.total_supply_is_public {
+    // an admin-only supply is served by the authenticated `TotalSupply` query instead
+    let total_supply = if constants.total_supply_visibility == TotalSupplyVisibility::Public {
         Some(Uint128::new(TOTAL_SUPPLY.load(storage)?))
     } else {
         None
@@ -63,24 +135,85 @@ pub fn query_token_info(storage: &dyn Storage) -> StdResult<Binary> {
     })
 }
 
+pub fn query_total_burned_to_sink(storage: &dyn Storage) -> StdResult<Binary> {
+    let constants = CONFIG.load(storage)?;
+
+    // the burned total reveals supply changes, so it's as private as the total supply
+    let total_burned = if constants.total_supply_visibility == TotalSupplyVisibility::Public {
+        Some(Uint128::new(
+            TOTAL_BURNED_TO_SINK.may_load(storage)?.unwrap_or_default(),
+        ))
+    } else {
+        None
+    };
+
+    to_binary(&QueryAnswer::TotalBurnedToSink {
+        sink: constants.burn_sink,
+        total_burned,
+    })
+}
+
+pub fn query_total_supply(storage: &dyn Storage, account: &str) -> StdResult<Binary> {
+    let constants = CONFIG.load(storage)?;
+    if constants.admin.as_str() != account {
+        return Err(StdError::generic_err(
+            "Total supply can only be queried by the admin",
+        ));
+    }
+
+    let total_supply = match constants.total_supply_visibility {
+        TotalSupplyVisibility::Public | TotalSupplyVisibility::AdminOnly => {
+            Some(Uint128::new(TOTAL_SUPPLY.load(storage)?))
+        }
+        TotalSupplyVisibility::Private => None,
+    };
+
+    to_binary(&QueryAnswer::TotalSupply { total_supply })
+}
+
 pub fn query_token_config(storage: &dyn Storage) -> StdResult<Binary> {
     let constants = CONFIG.load(storage)?;
 
     to_binary(&QueryAnswer::TokenConfig {
-        public_total_supply: constants.total_supply_is_public,
+        public_total_supply: constants.total_supply_visibility == TotalSupplyVisibility::Public,
         deposit_enabled: constants.deposit_is_enabled,
         redeem_enabled: constants.redeem_is_enabled,
         mint_enabled: constants.mint_is_enabled,
         burn_enabled: constants.burn_is_enabled,
         supported_denoms: constants.supported_denoms,
+        can_modify_denoms: constants.can_modify_denoms,
     })
 }
 
-pub fn query_contract_status(storage: &dyn Storage) -> StdResult<Binary> {
+pub fn query_contract_status(storage: &dyn Storage, now: u64) -> StdResult<Binary> {
     let contract_status = CONTRACT_STATUS.load(storage)?;
 
+    let flags = OperationFlagsStore::load(storage);
+    let operation_flags = OperationFlags {
+        mint_disabled: flags & OPERATION_MINT != 0,
+        send_disabled: flags & OPERATION_SEND != 0,
+        burn_disabled: flags & OPERATION_BURN != 0,
+        transfer_disabled: flags & OPERATION_TRANSFER != 0,
+    };
+
+    let redeem_rate_limit = match RedeemRateLimitStore::may_load(storage)? {
+        Some(limit) => {
+            let window = RedeemRateLimitStore::current_window(storage, &limit, now)?;
+            let redeemed = window.map(|window| window.redeemed).unwrap_or_default();
+            Some(RedeemRateLimitStatus {
+                window_start: window.map(|window| window.start),
+                redeemed: Uint128::new(redeemed),
+                remaining: Uint128::new(limit.amount.u128().saturating_sub(redeemed)),
+                limit,
+            })
+        }
+        None => None,
+    };
+
     to_binary(&QueryAnswer::ContractStatus {
         status: contract_status,
+        operation_flags,
+        redeem_rate_limit,
     })
 }
 
@@ -89,11 +222,15 @@ pub fn query_transactions(
     account: String,
     page: u32,
     page_size: u32,
+    action_filter: Option<TxActionFilter>,
+    order: Option<Order>,
 ) -> StdResult<Binary> {
     if page_size == 0 {
         return Err(StdError::generic_err("invalid page size"));
     }
 
+    let ascending = order == Some(Order::Ascending);
+
     // Notice that if query_transactions() was called by a viewing-key call, the address of
     // 'account' has already been validated.
     // The address of 'account' should not be validated if query_transactions() was called by a
@@ -101,14 +238,72 @@ pub fn query_transactions(
     let account = Addr::unchecked(account);
     let account_raw = deps.api.addr_canonicalize(account.as_str())?;
 
-    let start = page * page_size;
-    let mut end = start + page_size; // one more than end index
+    let page_start = page * page_size;
 
     // first check if there are any transactions in dwb
     let dwb = DWB.load(deps.storage)?;
     let dwb_index = dwb.recipient_match(&account_raw);
-    let mut txs_in_dwb = vec![];
     let txs_in_dwb_count = dwb.entries[dwb_index].list_len()?;
+
+    //let account_slice = account_raw.as_slice();
+    let account_stored_entry = stored_entry(deps.storage, &account_raw)?;
+    let settled_tx_count = stored_tx_count(deps.storage, &account_stored_entry)?;
+    // settled txs older than the history retention cap are no longer stored
+    let pruned_tx_count = pruned_tx_count(deps.storage, &account_stored_entry)?;
+    let history_floor = account_stored_entry
+        .as_ref()
+        .map_or(0, |entry| entry.history_floor(deps.storage));
+    let total = txs_in_dwb_count as u32 + settled_tx_count - pruned_tx_count;
+
+    if let Some(action_filter) = action_filter {
+        let (txs, filtered_total) = if ascending {
+            // count the matches first, then take the mirror-image page from the newest end
+            let (_, filtered_total) =
+                query_filtered_transactions(deps, &account_raw, 0, 0, |action| {
+                    action_filter.matches(action)
+                })?;
+            let page_end = page_start.saturating_add(page_size).min(filtered_total);
+            let (mut txs, filtered_total) = query_filtered_transactions(
+                deps,
+                &account_raw,
+                filtered_total - page_end,
+                page_end.saturating_sub(page_start),
+                |action| action_filter.matches(action),
+            )?;
+            txs.reverse();
+            (txs, filtered_total)
+        } else {
+            query_filtered_transactions(deps, &account_raw, page_start, page_size, |action| {
+                action_filter.matches(action)
+            })?
+        };
+        let result = QueryAnswer::TransactionHistory {
+            txs: obfuscate_tx_ids(deps.storage, txs)?,
+            total: Some(total as u64),
+            filtered_total: Some(filtered_total as u64),
+        };
+        return to_binary(&result);
+    }
+
+    // the window of reverse chronological indices to return. an ascending page is the
+    // mirror-image window counted from the oldest end, which is reversed once it is collected
+    let page_end = page_start.saturating_add(page_size).min(total);
+    let (start, end) = if ascending {
+        (total - page_end, total.saturating_sub(page_start))
+    } else {
+        (page_start, page_end) // end is one more than end index
+    };
+
+    // a page starting at or past the end of the history is empty
+    if start >= end {
+        return to_binary(&QueryAnswer::TransactionHistory {
+            txs: vec![],
+            total: Some(total as u64),
+            filtered_total: None,
+        });
+    }
+
+    let mut txs_in_dwb = vec![];
     if dwb_index > 0 && txs_in_dwb_count > 0 && start < txs_in_dwb_count as u32 {
         // skip if start is after buffer entries
         let head_node_index = dwb.entries[dwb_index].head_node()?;
@@ -122,14 +317,6 @@ pub fn query_transactions(
         }
     }
 
-    //let account_slice = account_raw.as_slice();
-    let account_stored_entry = stored_entry(deps.storage, &account_raw)?;
-    let settled_tx_count = stored_tx_count(deps.storage, &account_stored_entry)?;
-    let total = txs_in_dwb_count as u32 + settled_tx_count as u32;
-    if end > total {
-        end = total;
-    }
-
     let mut txs: Vec<Tx> = vec![];
 
     let txs_in_dwb_count = txs_in_dwb_count as u32;
@@ -166,7 +353,7 @@ pub fn query_transactions(
                         txs.extend(head_node.as_vec(deps.storage, deps.api)?);
                         txs_left = txs_left.saturating_sub(list_len);
                     }
-                    if bundle_idx > 0 {
+                    if bundle_idx > history_floor {
                         bundle_idx -= 1;
                     } else {
                         break;
@@ -181,13 +368,17 @@ pub fn query_transactions(
         // bundle tx offsets are chronological, but we need reverse chronological
         // so get the settled start index as if order is reversed
         //println!("OPTION 3");
-        let settled_start = settled_tx_count
-            .saturating_sub(start - txs_in_dwb_count)
-            .saturating_sub(1);
+        // the offset into the settled txs must not saturate: a start just past the oldest
+        // settled tx would otherwise wrap around to index 0 and return the oldest bundle
+        let settled_start = settled_tx_count.checked_sub(start - txs_in_dwb_count + 1);
 
-        if let Some((bundle_idx, tx_bundle, start_at)) =
-            find_start_bundle(deps.storage, &account_raw, settled_start)?
-        {
+        let start_bundle = match settled_start {
+            Some(settled_start) if settled_start >= pruned_tx_count => {
+                find_start_bundle(deps.storage, &account_raw, settled_start)?
+            }
+            _ => None,
+        };
+        if let Some((bundle_idx, tx_bundle, start_at)) = start_bundle {
             let mut txs_left = end - start;
             let list_len = tx_bundle.list_len as u32;
             if start_at + txs_left <= list_len {
@@ -212,7 +403,7 @@ pub fn query_transactions(
                     txs_left = txs_left.saturating_sub(list_len - start_at);
                 }
 
-                if bundle_idx > 0 && txs_left > 0 {
+                if bundle_idx > history_floor && txs_left > 0 {
                     // get the next earlier bundle
                     let mut bundle_idx = bundle_idx - 1;
                     if let Some(entry) = account_stored_entry {
@@ -234,7 +425,7 @@ pub fn query_transactions(
                                 txs.extend(head_node.as_vec(deps.storage, deps.api)?);
                                 txs_left = txs_left.saturating_sub(list_len);
                             }
-                            if bundle_idx > 0 {
+                            if bundle_idx > history_floor {
                                 bundle_idx -= 1;
                             } else {
                                 break;
@@ -246,55 +437,181 @@ pub fn query_transactions(
         }
     }
 
-    // deterministically obfuscate ids so they are not serial to prevent metadata leak
-    let internal_secret = INTERNAL_SECRET_RELAXED.load(deps.storage)?;
-    let internal_secret_u64: u64 = u64::from_be_bytes(internal_secret[..8].try_into().unwrap());
+    if ascending {
+        txs.reverse();
+    }
+
+    let result = QueryAnswer::TransactionHistory {
+        txs: obfuscate_tx_ids(deps.storage, txs)?,
+        total: Some(total as u64),
+        filtered_total: None,
+    };
+    to_binary(&result)
+}
+
+pub fn query_transactions_with(
+    deps: Deps,
+    account: String,
+    counterparty: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    if page_size == 0 {
+        return Err(StdError::generic_err("invalid page size"));
+    }
+
+    // the account has already been validated by the viewing key check
+    let account_raw = deps.api.addr_canonicalize(account.as_str())?;
+    let counterparty = deps.api.addr_validate(counterparty.as_str())?;
+
+    let dwb = DWB.load(deps.storage)?;
+    let txs_in_dwb_count = dwb.entries[dwb.recipient_match(&account_raw)].list_len()?;
+    let account_stored_entry = stored_entry(deps.storage, &account_raw)?;
+    let total = txs_in_dwb_count as u32 + stored_tx_count(deps.storage, &account_stored_entry)?
+        - pruned_tx_count(deps.storage, &account_stored_entry)?;
+
+    let (txs, filtered_total) =
+        query_filtered_transactions(deps, &account_raw, page * page_size, page_size, |action| {
+            action.involves(&counterparty)
+        })?;
+
+    to_binary(&QueryAnswer::TransactionsWith {
+        txs: obfuscate_tx_ids(deps.storage, txs)?,
+        total: total as u64,
+        filtered_total: filtered_total as u64,
+    })
+}
+
+/// walks the account's entire history from newest to oldest, returning the requested page of
+/// transactions whose action satisfies `matches` along with the number of matching transactions
+fn query_filtered_transactions(
+    deps: Deps,
+    account_raw: &CanonicalAddr,
+    start: u32,
+    page_size: u32,
+    matches: impl Fn(&TxAction) -> bool,
+) -> StdResult<(Vec<Tx>, u32)> {
+    let mut txs: Vec<Tx> = vec![];
+    let mut filtered_total: u32 = 0;
+    let mut collect = |tx_list: Vec<Tx>| {
+        for tx in tx_list {
+            if matches(&tx.action) {
+                if filtered_total >= start && filtered_total < start.saturating_add(page_size) {
+                    txs.push(tx);
+                }
+                filtered_total += 1;
+            }
+        }
+    };
+
+    // txs in the dwb are the most recent
+    let dwb = DWB.load(deps.storage)?;
+    let dwb_index = dwb.recipient_match(account_raw);
+    if dwb_index > 0 {
+        let head_node_index = dwb.entries[dwb_index].head_node()?;
+        if head_node_index > 0 {
+            let head_node = TX_NODES
+                .add_suffix(&head_node_index.to_be_bytes())
+                .load(deps.storage)?;
+            collect(head_node.as_vec(deps.storage, deps.api)?);
+        }
+    }
+
+    // then go back through the settled bundles
+    if let Some(entry) = stored_entry(deps.storage, account_raw)? {
+        let tx_bundles_idx_len = entry.history_len()?;
+        for bundle_idx in (entry.history_floor(deps.storage)..tx_bundles_idx_len).rev() {
+            let tx_bundle = entry.get_tx_bundle_at(deps.storage, bundle_idx)?;
+            // only look if head node is not null
+            if tx_bundle.head_node > 0 {
+                let head_node = TX_NODES
+                    .add_suffix(&tx_bundle.head_node.to_be_bytes())
+                    .load(deps.storage)?;
+                collect(head_node.as_vec(deps.storage, deps.api)?);
+            }
+        }
+    }
+
+    Ok((txs, filtered_total))
+}
+
+/// deterministically obfuscate ids so they are not serial to prevent metadata leak, and show
+/// the token's current symbol as the denom of its own coins
+fn obfuscate_tx_ids(storage: &dyn Storage, txs: Vec<Tx>) -> StdResult<Vec<Tx>> {
+    let internal_secret = INTERNAL_SECRET_RELAXED.load(storage)?;
+    let symbol = CONFIG.load(storage)?.symbol;
     let txs = txs
         .iter()
         .map(|tx| {
-            // PRNG(PRNG(serial_id) ^ secret)
-            let mut rng = ChaChaRng::seed_from_u64(tx.id);
-            let serial_id_rand = rng.next_u64();
-            let new_seed = serial_id_rand ^ internal_secret_u64;
-            let mut rng = ChaChaRng::seed_from_u64(new_seed);
-            let new_id = rng.next_u64() >> (64 - 53);
+            let new_id = obfuscated_tx_id(&internal_secret, tx.id);
+            let mut coins = tx.coins.clone();
+            if coins.denom == TOKEN_DENOM {
+                coins.denom = symbol.clone();
+            }
             Tx {
                 id: new_id,
                 action: tx.action.clone(),
-                coins: tx.coins.clone(),
+                coins,
                 memo: tx.memo.clone(),
+                memo_encrypted: tx.memo_encrypted,
                 block_height: tx.block_height,
                 block_time: tx.block_time,
             }
         })
         .collect();
 
-    let result = QueryAnswer::TransactionHistory {
-        txs,
-        total: Some(total as u64),
-    };
-    to_binary(&result)
+    Ok(txs)
 }
 
-pub fn query_balance(deps: Deps, account: String) -> StdResult<Binary> {
+pub fn query_balance(deps: Deps, env: Env, account: String) -> StdResult<Binary> {
     // Notice that if query_balance() was called by a viewing key call, the address of 'account'
     // has already been validated.
     // The address of 'account' should not be validated if query_balance() was called by a permit
     // call, for compatibility with non-Secret addresses.
     let account = Addr::unchecked(account);
-    let account = deps.api.addr_canonicalize(account.as_str())?;
+    let account_raw = deps.api.addr_canonicalize(account.as_str())?;
 
-    let mut amount = stored_balance(deps.storage, &account)?;
     let dwb = DWB.load(deps.storage)?;
-    let dwb_index = dwb.recipient_match(&account);
-    if dwb_index > 0 {
-        amount = amount.saturating_add(dwb.entries[dwb_index].amount()? as u128);
-    }
-    let amount = Uint128::new(amount);
-    let response = QueryAnswer::Balance { amount };
+    let amount = Uint128::new(dwb.account_balance(deps.storage, &account_raw)?);
+
+    // vested stream amounts are not part of the balance until they are withdrawn
+    let claimable_streams =
+        StreamsStore::claimable_by(deps.storage, &account, env.block.time.seconds())?
+            .map(Uint128::new);
+
+    let response = QueryAnswer::Balance {
+        amount,
+        claimable_streams,
+    };
     to_binary(&response)
 }
 
+pub fn query_balance_at(deps: Deps, account: String, height: u64) -> StdResult<Binary> {
+    if !CONFIG.load(deps.storage)?.snapshot_enabled {
+        return Err(StdError::generic_err(
+            "Balance snapshots are not enabled for this token",
+        ));
+    }
+
+    // as in query_balance(), 'account' is only validated for viewing key calls
+    let account = deps.api.addr_canonicalize(account.as_str())?;
+
+    // accounts without a checkpoint at or before `height` held no tokens at that height
+    let amount =
+        BalanceCheckpointsStore::balance_at(deps.storage, &account, height)?.unwrap_or_default();
+
+    to_binary(&QueryAnswer::BalanceAt {
+        amount: Uint128::new(amount),
+        height,
+    })
+}
+
+pub fn query_pending_admin(storage: &dyn Storage) -> StdResult<Binary> {
+    to_binary(&QueryAnswer::PendingAdmin {
+        address: PENDING_ADMIN.may_load(storage)?,
+    })
+}
+
 pub fn query_minters(deps: Deps) -> StdResult<Binary> {
     let minters = MintersStore::load(deps.storage)?;
 
@@ -302,7 +619,76 @@ pub fn query_minters(deps: Deps) -> StdResult<Binary> {
     to_binary(&response)
 }
 
-pub fn query_allowance(deps: Deps, owner: String, spender: String) -> StdResult<Binary> {
+pub fn query_active_deposit_denoms(storage: &dyn Storage) -> StdResult<Binary> {
+    let denoms = ACTIVE_DEPOSIT_DENOMS
+        .iter(storage)?
+        .collect::<StdResult<Vec<String>>>()?;
+
+    to_binary(&QueryAnswer::ActiveDepositDenoms {
+        count: denoms.len() as u32,
+        denoms,
+    })
+}
+
+pub fn query_denom_info(storage: &dyn Storage) -> StdResult<Binary> {
+    let constants = CONFIG.load(storage)?;
+
+    let denoms = constants
+        .supported_denoms
+        .iter()
+        .map(|denom| {
+            let flags = DenomStatusStore::load(storage, &constants, denom);
+            DenomStatus {
+                denom: denom.clone(),
+                deposit_enabled: flags.deposit_enabled,
+                redeem_enabled: flags.redeem_enabled,
+            }
+        })
+        .collect();
+
+    to_binary(&QueryAnswer::DenomInfo { denoms })
+}
+
+pub fn query_receiver_hash(deps: Deps, address: String) -> StdResult<Binary> {
+    let address = deps.api.addr_validate(address.as_str())?;
+    let code_hash = ReceiverHashStore::may_load(deps.storage, &address)?;
+    let label = ReceiverHashStore::may_load_label(deps.storage, &address)?;
+
+    to_binary(&QueryAnswer::ReceiverHash { code_hash, label })
+}
+
+pub fn query_memo_decryption_key(deps: Deps, address: String) -> StdResult<Binary> {
+    let address = deps.api.addr_validate(address.as_str())?;
+    let pubkey = MemoDecryptionKeyStore::may_load(deps.storage, &address)?;
+
+    to_binary(&QueryAnswer::MemoDecryptionKey { pubkey })
+}
+
+pub fn query_account_policies(deps: Deps, account: String) -> StdResult<Binary> {
+    // Notice that if query_account_policies() was called by a viewing-key call, the address
+    // of 'account' has already been validated.
+    let account = Addr::unchecked(account);
+
+    let policies = AccountPolicies {
+        preferred_redeem_denom: PreferredRedeemDenomStore::may_load(deps.storage, &account)?,
+        disabled_channels: ChannelPreferencesStore::disabled_channels(deps.storage, &account)?,
+        allowances_public: PublicAllowancesStore::is_public(deps.storage, &account),
+        permit_epoch: PermitEpochStore::load(deps.storage, account.as_str()),
+        memo_decryption_key: MemoDecryptionKeyStore::may_load(deps.storage, &account)?,
+        receiver_code_hash: ReceiverHashStore::may_load(deps.storage, &account)?,
+    };
+
+    to_binary(&QueryAnswer::AccountPolicies { policies })
+}
+
+pub fn query_permit_epoch(deps: Deps, account: String) -> StdResult<Binary> {
+    let account = deps.api.addr_validate(account.as_str())?;
+    let permit_epoch = PermitEpochStore::load(deps.storage, account.as_str());
+
+    to_binary(&QueryAnswer::PermitEpoch { permit_epoch })
+}
+
+pub fn query_allowance(deps: Deps, env: Env, owner: String, spender: String) -> StdResult<Binary> {
     // Notice that if query_allowance() was called by a viewing-key call, the addresses of 'owner'
     // and 'spender' have already been validated.
     // The addresses of 'owner' and 'spender' should not be validated if query_allowance() was
@@ -311,12 +697,18 @@ pub fn query_allowance(deps: Deps, owner: String, spender: String) -> StdResult<
     let spender = Addr::unchecked(spender);
 
     let allowance = AllowancesStore::load(deps.storage, &owner, &spender);
+    let effective_amount = if allowance.is_expired_at(&env.block) {
+        0
+    } else {
+        allowance.amount
+    };
 
     let response = QueryAnswer::Allowance {
         owner,
         spender,
         allowance: Uint128::new(allowance.amount),
         expiration: allowance.expiration,
+        effective_amount: Uint128::new(effective_amount),
     };
     to_binary(&response)
 }
@@ -353,6 +745,42 @@ pub fn query_allowances_given(
     to_binary(&response)
 }
 
+pub fn query_nonce(deps: Deps, address: String) -> StdResult<Binary> {
+    let address = Addr::unchecked(address);
+
+    to_binary(&QueryAnswer::Nonce {
+        nonce: AuthNoncesStore::load(deps.storage, &address),
+    })
+}
+
+pub fn query_total_allowance_given(deps: Deps, env: Env, owner: String) -> StdResult<Binary> {
+    let owner = Addr::unchecked(owner);
+    let (total, active_total) =
+        AllowancesStore::allowance_totals(deps.storage, &owner, &env.block)?;
+
+    to_binary(&QueryAnswer::TotalAllowanceGiven {
+        total: Uint128::from(total),
+        active_total: Uint128::from(active_total),
+        count: AllowancesStore::num_allowances(deps.storage, &owner),
+    })
+}
+
+pub fn query_public_allowances_given(
+    deps: Deps,
+    owner: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let owner = deps.api.addr_validate(owner.as_str())?;
+    if !PublicAllowancesStore::is_public(deps.storage, &owner) {
+        return Err(StdError::generic_err(format!(
+            "Allowances given by {owner} are not public, query AllowancesGiven with a viewing key or permit instead",
+        )));
+    }
+
+    query_allowances_given(deps, owner.into_string(), page, page_size)
+}
+
 pub fn query_allowances_received(
     deps: Deps,
     spender: String,
@@ -402,14 +830,96 @@ pub fn query_list_permit_revocations(deps: Deps, account: &str) -> StdResult<Bin
 ///
 /// ListChannels query
 ///
-///   Public query to list all notification channels.
+///   Public query to list notification channels, paginated when a page size is given.
 ///
-pub fn query_list_channels(deps: Deps) -> StdResult<Binary> {
-    let channels: Vec<String> = CHANNELS
-        .iter(deps.storage)?
-        .map(|channel| channel.unwrap())
+pub fn query_list_channels(
+    deps: Deps,
+    page: Option<u32>,
+    page_size: Option<u32>,
+) -> StdResult<Binary> {
+    let channels: Vec<String> = match page_size {
+        Some(page_size) => CHANNELS.paging(deps.storage, page.unwrap_or(0), page_size)?,
+        None => CHANNELS
+            .iter(deps.storage)?
+            .map(|channel| channel.unwrap())
+            .collect(),
+    };
+    let total = CHANNELS.get_len(deps.storage)?;
+    to_binary(&QueryAnswer::ListChannels { channels, total })
+}
+
+///
+/// NotificationChannelsForTransfer query
+///
+///   Public query to list the channels each execute message that moves tokens or allowances
+///   emits notifications on, with counter-mode channels derived by the same rule the
+///   notification renderers use.
+///   Send callbacks are not notifications and are not listed.
+///
+pub fn query_notification_channels_for_transfer(storage: &dyn Storage) -> StdResult<Binary> {
+    let constants = CONFIG.load(storage)?;
+    let notifications_enabled = NOTIFICATIONS_ENABLED.load(storage)?;
+
+    let operations = OPERATION_CHANNELS
+        .iter()
+        .map(|(operation, _)| OperationChannels {
+            operation: operation.to_string(),
+            channels: if notifications_enabled {
+                operation_channels(operation, constants.notify_allowance_use)
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            } else {
+                vec![]
+            },
+        })
         .collect();
-    to_binary(&QueryAnswer::ListChannels { channels })
+
+    to_binary(&QueryAnswer::NotificationChannelsForTransfer { operations })
+}
+
+pub fn query_channel_preferences(deps: Deps, account: String) -> StdResult<Binary> {
+    // Notice that if query_channel_preferences() was called by a viewing-key call, the address
+    // of 'account' has already been validated.
+    let account = Addr::unchecked(account);
+    let disabled = ChannelPreferencesStore::disabled_channels(deps.storage, &account)?;
+
+    let channels = CHANNELS
+        .iter(deps.storage)?
+        .map(|channel| {
+            channel.map(|channel| ChannelPreference {
+                enabled: !disabled.contains(&channel),
+                channel,
+            })
+        })
+        .collect::<StdResult<Vec<ChannelPreference>>>()?;
+
+    to_binary(&QueryAnswer::ChannelPreferences { channels })
+}
+
+pub fn query_channel_emission_log(
+    deps: Deps,
+    viewer: String,
+    channel: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<Binary> {
+    let constants = CONFIG.load(deps.storage)?;
+    if constants.admin.as_str() != viewer.as_str() {
+        return Err(StdError::generic_err(
+            "Channel emission logs can only be queried by the admin",
+        ));
+    }
+    if !CHANNELS.contains(deps.storage, &channel) {
+        return Err(StdError::generic_err(format!(
+            "Channel {channel} does not exist",
+        )));
+    }
+
+    let (total, emissions) =
+        ChannelEmissionLogStore::page(deps.storage, &channel, page, page_size)?;
+
+    to_binary(&QueryAnswer::ChannelEmissionLog { total, emissions })
 }
 
 ///
@@ -428,150 +938,19 @@ pub fn query_channel_info(
     let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
     let secret = secret.as_slice();
     let seed = get_seed(&sender_raw, secret)?;
+    let account = deps.api.addr_humanize(&sender_raw)?;
     let mut channels_data = vec![];
     for channel in channels {
-        let answer_id;
-        if let Some(tx_hash) = &txhash {
-            answer_id = Some(notification_id(&seed, &channel, tx_hash)?);
-        } else {
-            answer_id = None;
-        }
-        match channel.as_str() {
-            RecvdNotification::CHANNEL_ID => {
-                let channel_info_data = ChannelInfoData {
-                    mode: "txhash".to_string(),
-                    channel,
-                    answer_id,
-                    parameters: None,
-                    data: None,
-                    next_id: None,
-                    counter: None,
-                    cddl: Some(RecvdNotification::CDDL_SCHEMA.to_string()),
-                };
-                channels_data.push(channel_info_data);
-            }
-            SpentNotification::CHANNEL_ID => {
-                let channel_info_data = ChannelInfoData {
-                    mode: "txhash".to_string(),
-                    channel,
-                    answer_id,
-                    parameters: None,
-                    data: None,
-                    next_id: None,
-                    counter: None,
-                    cddl: Some(SpentNotification::CDDL_SCHEMA.to_string()),
-                };
-                channels_data.push(channel_info_data);
-            }
-            AllowanceNotification::CHANNEL_ID => {
-                let channel_info_data = ChannelInfoData {
-                    mode: "txhash".to_string(),
-                    channel,
-                    answer_id,
-                    parameters: None,
-                    data: None,
-                    next_id: None,
-                    counter: None,
-                    cddl: Some(AllowanceNotification::CDDL_SCHEMA.to_string()),
-                };
-                channels_data.push(channel_info_data);
-            }
-            MultiRecvdNotification::CHANNEL_ID => {
-                let channel_info_data = ChannelInfoData {
-                    mode: "bloom".to_string(),
-                    channel,
-                    answer_id,
-                    parameters: Some(BloomParameters {
-                        m: MultiRecvdNotification::BLOOM_M,
-                        k: MultiRecvdNotification::BLOOM_K,
-                        h: "sha256".to_string(),
-                    }),
-                    data: Some(Descriptor {
-                        r#type: format!("packet[{}]", MultiRecvdNotification::BLOOM_N),
-                        version: "1".to_string(),
-                        packet_size: MultiRecvdNotification::PACKET_SIZE as u32,
-                        data: StructDescriptor {
-                            r#type: "struct".to_string(),
-                            label: "transfer".to_string(),
-                            members: vec![
-                                FlatDescriptor {
-                                    r#type: "uint64".to_string(),
-                                    label: "flagsAndAmount".to_string(),
-                                    description: Some(
-                                        "Bit field of [0]: non-empty memo; [2]: sender is owner; [2..]: uint62 transfer amount in base denomination".to_string(),
-                                    ),
-                                },
-                                FlatDescriptor {
-                                    r#type: "bytes8".to_string(),
-                                    label: "ownerId".to_string(),
-                                    description: Some(
-                                        "The last 8 bytes of the owner's canonical address".to_string(),
-                                    ),
-                                },
-                            ],
-                        },
-                    }),
-                    counter: None,
-                    next_id: None,
-                    cddl: None,
-                };
-                channels_data.push(channel_info_data);
-            }
-            MultiSpentNotification::CHANNEL_ID => {
-                let channel_info_data = ChannelInfoData {
-                    mode: "bloom".to_string(),
-                    channel,
-                    answer_id,
-                    parameters: Some(BloomParameters {
-                        m: MultiSpentNotification::BLOOM_M,
-                        k: MultiSpentNotification::BLOOM_K,
-                        h: "sha256".to_string(),
-                    }),
-                    data: Some(Descriptor {
-                        r#type: format!("packet[{}]", MultiSpentNotification::BLOOM_N),
-                        version: "1".to_string(),
-                        packet_size: MultiSpentNotification::PACKET_SIZE as u32,
-                        data: StructDescriptor {
-                            r#type: "struct".to_string(),
-                            label: "transfer".to_string(),
-                            members: vec![
-                                FlatDescriptor {
-                                    r#type: "uint64".to_string(),
-                                    label: "flagsAndAmount".to_string(),
-                                    description: Some(
-                                        "Bit field of [0]: non-empty memo; [1]: reserved; [2..] uint62 transfer amount in base denomination".to_string(),
-                                    ),
-                                },
-                                FlatDescriptor {
-                                    r#type: "bytes8".to_string(),
-                                    label: "recipientId".to_string(),
-                                    description: Some(
-                                        "The last 8 bytes of the recipient's canonical address".to_string(),
-                                    ),
-                                },
-                                FlatDescriptor {
-                                    r#type: "uint64".to_string(),
-                                    label: "balance".to_string(),
-                                    description: Some(
-                                        "Spender's new balance after the transfer".to_string(),
-                                    ),
-                                },
-                            ],
-                        },
-                    }),
-                    counter: None,
-                    next_id: None,
-                    cddl: None,
-                };
-                channels_data.push(channel_info_data);
-            }
-            _ => {
-                return Err(StdError::generic_err(format!(
-                    "`{}` channel is undefined",
-                    channel
-                )));
-            }
+        let mut info = channel_info_data(deps.storage, channel, None)?;
+        if info.mode == "counter" {
+            // counter-mode IDs do not depend on the tx, so report the viewer's counter instead
+            let counter = NotificationCountersStore::load(deps.storage, &account, &info.channel);
+            info.next_id = Some(counter_notification_id(&seed, &info.channel, counter + 1)?);
+            info.counter = Some(Uint64::from(counter));
+        } else if let Some(tx_hash) = &txhash {
+            info.answer_id = Some(notification_id(&seed, &info.channel, tx_hash)?);
         }
+        channels_data.push(info);
     }
 
     to_binary(&QueryAnswer::ChannelInfo {
@@ -581,6 +960,72 @@ pub fn query_channel_info(
     })
 }
 
+pub fn query_pending_callbacks(deps: Deps, account: String) -> StdResult<Binary> {
+    // Notice that if query_pending_callbacks() was called by a viewing-key call, the address
+    // of 'account' has already been validated.
+    let account = Addr::unchecked(account);
+    let callbacks = PendingCallbacksStore::list(deps.storage, &account)?;
+
+    to_binary(&QueryAnswer::PendingCallbacks { callbacks })
+}
+
+/// computes the notification ID the viewer should expect on `channel` for the given tx, without
+/// the rest of the channel metadata
+pub fn query_notification_id(
+    deps: Deps,
+    channel: String,
+    txhash: String,
+    sender_raw: CanonicalAddr,
+) -> StdResult<Binary> {
+    // reject undefined channels the same way `ChannelInfo` does
+    channel_info_data(deps.storage, channel.clone(), None)?;
+
+    let secret = INTERNAL_SECRET_SENSITIVE.load(deps.storage)?;
+    let seed = get_seed(&sender_raw, secret.as_slice())?;
+    let id = notification_id(&seed, &channel, &txhash)?;
+
+    to_binary(&QueryAnswer::NotificationId { id })
+}
+
+pub fn query_channel_schema(storage: &dyn Storage, channel: String) -> StdResult<Binary> {
+    // the non-secret parts of the channel info: no seed or notification ID
+    let ChannelInfoData {
+        channel,
+        mode,
+        parameters,
+        data,
+        cddl,
+        ..
+    } = channel_info_data(storage, channel, None)?;
+
+    to_binary(&QueryAnswer::ChannelSchema {
+        channel,
+        mode,
+        cddl,
+        parameters,
+        data,
+    })
+}
+
+/// describes a channel, including the notification ID for a given tx when `answer_id` is set
+fn channel_info_data(
+    storage: &dyn Storage,
+    channel: String,
+    answer_id: Option<Binary>,
+) -> StdResult<ChannelInfoData> {
+    // every channel's template is stored when it is created; only the answer ID is per-request
+    match CHANNEL_SCHEMATA.get(storage, &channel) {
+        Some(schema) => Ok(ChannelInfoData {
+            answer_id,
+            ..schema
+        }),
+        None => Err(StdError::generic_err(format!(
+            "`{}` channel is undefined",
+            channel
+        ))),
+    }
+}
+
 // *****************
 // End SNIP-52 query functions
 // *****************
@@ -57,9 +57,118 @@ impl Snip20ReceiveMsg {
     }
 }
 
-// This is just a helper to properly serialize the above message
+/// Snip20BatchReceiveMsg combines several callbacks from the same sender to the same contract,
+/// and should be de/serialized under `BatchReceive()` variant in a HandleMsg
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Snip20BatchReceiveMsg {
+    pub sender: Addr,
+    pub actions: Vec<Snip20BatchReceiveAction>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct Snip20BatchReceiveAction {
+    pub from: Addr,
+    pub amount: Uint128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    pub msg: Option<Binary>,
+}
+
+impl Snip20BatchReceiveMsg {
+    /// serializes the message, and pads it to 256 bytes
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = ReceiverHandleMsg::BatchReceive(self);
+        let mut data = to_binary(&msg)?;
+        space_pad(&mut data.0, RESPONSE_BLOCK_SIZE);
+        Ok(data)
+    }
+
+    /// creates a cosmos_msg sending this struct to the named contract
+    pub fn into_cosmos_msg(self, code_hash: String, contract_addr: Addr) -> StdResult<CosmosMsg> {
+        let msg = self.into_binary()?;
+        let execute = WasmMsg::Execute {
+            msg,
+            code_hash,
+            contract_addr: contract_addr.into_string(),
+            funds: vec![],
+        };
+        Ok(execute.into())
+    }
+}
+
+/// a receiver callback that has not been turned into a message yet
+pub struct ReceiverCallback {
+    pub contract_addr: Addr,
+    pub code_hash: String,
+    pub msg: Snip20ReceiveMsg,
+}
+
+/// turns callbacks into messages in order. if `coalesce` is set, callbacks from the same sender to
+/// the same contract and code hash are combined into a single `BatchReceive` message at the
+/// position of the first one
+pub fn into_callback_messages(
+    callbacks: Vec<ReceiverCallback>,
+    coalesce: bool,
+) -> StdResult<Vec<CosmosMsg>> {
+    if !coalesce {
+        return callbacks
+            .into_iter()
+            .map(|callback| {
+                callback
+                    .msg
+                    .into_cosmos_msg(callback.code_hash, callback.contract_addr)
+            })
+            .collect();
+    }
+
+    let mut groups: Vec<(Addr, String, Vec<Snip20ReceiveMsg>)> = vec![];
+    for callback in callbacks {
+        let group = groups.iter_mut().find(|(contract_addr, code_hash, msgs)| {
+            *contract_addr == callback.contract_addr
+                && *code_hash == callback.code_hash
+                && msgs[0].sender == callback.msg.sender
+        });
+        match group {
+            Some((_, _, msgs)) => msgs.push(callback.msg),
+            None => groups.push((
+                callback.contract_addr,
+                callback.code_hash,
+                vec![callback.msg],
+            )),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(contract_addr, code_hash, mut msgs)| {
+            // a lone callback is sent as a regular `Receive`
+            if msgs.len() == 1 {
+                return msgs.remove(0).into_cosmos_msg(code_hash, contract_addr);
+            }
+
+            let batch_msg = Snip20BatchReceiveMsg {
+                sender: msgs[0].sender.clone(),
+                actions: msgs
+                    .into_iter()
+                    .map(|msg| Snip20BatchReceiveAction {
+                        from: msg.from,
+                        amount: msg.amount,
+                        memo: msg.memo,
+                        msg: msg.msg,
+                    })
+                    .collect(),
+            };
+            batch_msg.into_cosmos_msg(code_hash, contract_addr)
+        })
+        .collect()
+}
+
+// This is just a helper to properly serialize the above messages
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 enum ReceiverHandleMsg {
     Receive(Snip20ReceiveMsg),
+    BatchReceive(Snip20BatchReceiveMsg),
 }
@@ -1,11 +1,16 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, StdError, StdResult, Storage};
+use cosmwasm_std::{Addr, Binary, CanonicalAddr, StdError, StdResult, Storage, Uint128};
+use secret_toolkit::notification::ChannelInfoData;
 use secret_toolkit::serialization::Json;
 use secret_toolkit::storage::{Item, Keymap, Keyset};
+use secret_toolkit_crypto::sha_256;
 
-use crate::msg::ContractStatusLevel;
+use crate::constants::{
+    IDEMPOTENCY_KEY_TTL_SECONDS, MAX_IDEMPOTENCY_KEY_LENGTH, VIEWING_KEY_CREATION_COOLDOWN_BLOCKS,
+};
+use crate::msg::{ContractStatusLevel, RedeemRateLimit, TotalSupplyVisibility};
 
 pub const KEY_CONFIG: &[u8] = b"config";
 pub const KEY_TOTAL_SUPPLY: &[u8] = b"total_supply";
@@ -29,7 +34,7 @@ pub struct Config {
     pub symbol: String,
     pub decimals: u8,
     // privacy configuration
-    pub total_supply_is_public: bool,
+    pub total_supply_visibility: TotalSupplyVisibility,
     // is deposit enabled
     pub deposit_is_enabled: bool,
     // is redeem enabled
@@ -44,18 +49,183 @@ pub struct Config {
     pub supported_denoms: Vec<String>,
     // can admin add or remove supported denoms
     pub can_modify_denoms: bool,
+    // notify owners of their remaining allowance when a spender uses it
+    pub notify_allowance_use: bool,
+    // merge batch group notifications per recipient instead of emitting one packet per action
+    pub aggregate_batch_notifications: bool,
+    // decimals of the native coin that is deposited and redeemed
+    pub native_decimals: u8,
+    // display name of the native coin, used to label the native side of the exchange rate
+    pub native_display_denom: String,
+    // reject sends without a msg to recipients that receive a callback
+    pub require_send_msg: bool,
+    // accept transfers and sends of zero tokens
+    pub allow_zero_transfers: bool,
+    // maximum number of settled txs retained in each account's history
+    pub max_tx_history_per_account: Option<u32>,
+    // record balance checkpoints for historical balance queries
+    pub snapshot_enabled: bool,
+    // reject deposits and redeems that don't carry a memo
+    pub require_memo_on_native: bool,
+    // recipient recorded in history for `BurnTo`, never credited
+    pub burn_sink: Option<Addr>,
 }
 
 pub static CONFIG: Item<Config> = Item::new(KEY_CONFIG);
 
 pub static TOTAL_SUPPLY: Item<u128> = Item::new(KEY_TOTAL_SUPPLY);
 
+// running total of tokens burned with `BurnTo`
+pub static TOTAL_BURNED_TO_SINK: Item<u128> = Item::new(b"total-burned-to-sink");
+
 pub static CONTRACT_STATUS: Item<ContractStatusLevel, Json> = Item::new(KEY_CONTRACT_STATUS);
 
 pub static MINTERS: Item<Vec<Addr>> = Item::new(KEY_MINTERS);
 
+// address that `ChangeAdmin` nominated, which becomes admin once it calls `AcceptAdmin`
+pub static PENDING_ADMIN: Item<Addr> = Item::new(b"pending-admin");
+
 pub static TX_COUNT: Item<u64> = Item::new(KEY_TX_COUNT);
 
+// Operations disabled by the admin, as a bitset of the OPERATION_* flags below
+pub const OPERATION_MINT: u8 = 1;
+pub const OPERATION_SEND: u8 = 1 << 1;
+pub const OPERATION_BURN: u8 = 1 << 2;
+pub const OPERATION_TRANSFER: u8 = 1 << 3;
+
+pub static OPERATION_FLAGS: Item<u8> = Item::new(b"operation-flags");
+pub struct OperationFlagsStore {}
+impl OperationFlagsStore {
+    pub fn load(store: &dyn Storage) -> u8 {
+        OPERATION_FLAGS
+            .may_load(store)
+            .unwrap_or_default()
+            .unwrap_or_default()
+    }
+
+    pub fn save(store: &mut dyn Storage, flags: u8) -> StdResult<()> {
+        OPERATION_FLAGS.save(store, &flags)
+    }
+
+    /// fails if any of the given operations has been disabled
+    pub fn ensure_enabled(store: &dyn Storage, operation: u8) -> StdResult<()> {
+        if Self::load(store) & operation != 0 {
+            return Err(StdError::generic_err("operation temporarily disabled"));
+        }
+        Ok(())
+    }
+}
+
+// Redeem circuit breaker
+
+pub static REDEEM_RATE_LIMIT: Item<RedeemRateLimit> = Item::new(b"redeem-rate-limit");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct RedeemWindow {
+    /// block time (in seconds) at which the current window started
+    pub start: u64,
+    pub redeemed: u128,
+}
+
+pub static REDEEM_WINDOW: Item<RedeemWindow> = Item::new(b"redeem-window");
+pub struct RedeemRateLimitStore {}
+impl RedeemRateLimitStore {
+    pub fn save(store: &mut dyn Storage, limit: Option<RedeemRateLimit>) -> StdResult<()> {
+        Self::reset_window(store);
+        match limit {
+            Some(limit) => REDEEM_RATE_LIMIT.save(store, &limit),
+            None => {
+                REDEEM_RATE_LIMIT.remove(store);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn reset_window(store: &mut dyn Storage) {
+        REDEEM_WINDOW.remove(store);
+    }
+
+    pub fn may_load(store: &dyn Storage) -> StdResult<Option<RedeemRateLimit>> {
+        REDEEM_RATE_LIMIT.may_load(store)
+    }
+
+    /// the window containing `now`, or none if the last window has elapsed
+    pub fn current_window(
+        store: &dyn Storage,
+        limit: &RedeemRateLimit,
+        now: u64,
+    ) -> StdResult<Option<RedeemWindow>> {
+        Ok(REDEEM_WINDOW
+            .may_load(store)?
+            .filter(|window| now < window.start.saturating_add(limit.window_seconds)))
+    }
+
+    /// adds `amount` to the running total of the window containing `now`, returning false
+    /// without recording anything if that would exceed the rate limit
+    pub fn try_record(store: &mut dyn Storage, now: u64, amount: u128) -> StdResult<bool> {
+        let limit = match REDEEM_RATE_LIMIT.may_load(store)? {
+            Some(limit) => limit,
+            None => return Ok(true),
+        };
+
+        // a new window starts with the first redeem after the previous one has elapsed
+        let mut window = Self::current_window(store, &limit, now)?.unwrap_or(RedeemWindow {
+            start: now,
+            redeemed: 0,
+        });
+
+        match window.redeemed.checked_add(amount) {
+            Some(redeemed) if redeemed <= limit.amount.u128() => {
+                window.redeemed = redeemed;
+                REDEEM_WINDOW.save(store, &window)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+// Per-denom deposit and redeem status, overriding the global flags in the config
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DenomFlags {
+    pub deposit_enabled: bool,
+    pub redeem_enabled: bool,
+}
+
+pub static DENOM_STATUS: Keymap<String, DenomFlags> = Keymap::new(b"denom-status");
+pub struct DenomStatusStore {}
+impl DenomStatusStore {
+    /// the denom's own status if the admin has set one, otherwise the global flags
+    pub fn load(store: &dyn Storage, config: &Config, denom: &str) -> DenomFlags {
+        DENOM_STATUS
+            .get(store, &denom.to_string())
+            .unwrap_or(DenomFlags {
+                deposit_enabled: config.deposit_is_enabled,
+                redeem_enabled: config.redeem_is_enabled,
+            })
+    }
+
+    pub fn save(store: &mut dyn Storage, denom: &str, flags: DenomFlags) -> StdResult<()> {
+        DENOM_STATUS.insert(store, &denom.to_string(), &flags)
+    }
+
+    pub fn remove(store: &mut dyn Storage, denom: &str) -> StdResult<()> {
+        DENOM_STATUS.remove(store, &denom.to_string())
+    }
+
+    /// whether any supported denom can be redeemed
+    pub fn any_redeem_enabled(store: &dyn Storage, config: &Config) -> bool {
+        config
+            .supported_denoms
+            .iter()
+            .any(|denom| Self::load(store, config, denom).redeem_enabled)
+    }
+}
+
+/// coin denoms that have received at least one deposit
+pub static ACTIVE_DEPOSIT_DENOMS: Keyset<String> = Keyset::new(b"active-deposit-denoms");
+
 pub struct MintersStore {}
 impl MintersStore {
     pub fn load(store: &dyn Storage) -> StdResult<Vec<Addr>> {
@@ -94,6 +264,8 @@ impl MintersStore {
 // To avoid balance guessing attacks based on balance overflow we need to perform safe addition and don't expose overflows to the caller.
 // Assuming that max of u128 is probably an unreachable balance, we want the addition to be bounded the max of u128
 // Currently the logic here is very straight forward yet the existence of the function is mandatory for future changes if needed.
+// Note that this saturates, so the amount actually added can be less than `amount`. It is meant for internal paths such
+// as balance settlement; paths that credit a caller-given amount should use `checked_safe_add` instead.
 pub fn safe_add(balance: &mut u128, amount: u128) -> u128 {
     // Note that new_amount can be equal to base after this operation.
     // Currently we do nothing maybe on other implementations we will have something to add here
@@ -104,6 +276,16 @@ pub fn safe_add(balance: &mut u128, amount: u128) -> u128 {
     *balance - prev_balance
 }
 
+// Adds `amount` to the total supply in full, or fails without modifying it if the sum would overflow u128.
+// Used by mint and deposit, where the caller expects either the full amount to be credited or a clean failure.
+pub fn checked_safe_add(total_supply: &mut u128, amount: u128) -> StdResult<u128> {
+    *total_supply = total_supply
+        .checked_add(amount)
+        .ok_or_else(|| StdError::generic_err("amount would overflow total supply"))?;
+
+    Ok(amount)
+}
+
 // To avoid balance guessing attacks based on balance overflow we need to perform safe addition and don't expose overflows to the caller.
 // Assuming that max of u64 is probably an unreachable balance, we want the addition to be bounded the max of u64
 // Currently the logic here is very straight forward yet the existence of the function is mandatory for future changes if needed.
@@ -123,6 +305,8 @@ pub fn safe_add_u64(balance: &mut u64, amount: u64) -> u64 {
 pub struct Allowance {
     pub amount: u128,
     pub expiration: Option<u64>,
+    /// block time (in seconds) before which the allowance cannot be used
+    pub active_at: Option<u64>,
 }
 
 impl Allowance {
@@ -132,6 +316,13 @@ impl Allowance {
             None => false, // allowance has no expiration
         }
     }
+
+    pub fn is_active_at(&self, block: &cosmwasm_std::BlockInfo) -> bool {
+        match self.active_at {
+            Some(time) => block.time.seconds() >= time,
+            None => true, // allowance has no grace period
+        }
+    }
 }
 
 pub static ALLOWANCES: Keymap<Addr, Allowance> = Keymap::new(PREFIX_ALLOWANCES);
@@ -170,6 +361,27 @@ impl AllowancesStore {
             .paging(store, page, page_size)
     }
 
+    /// sums all of the owner's allowances, returning the total and the total of those not yet
+    /// expired at `block`. this iterates every spender, so its cost grows with their number
+    pub fn allowance_totals(
+        store: &dyn Storage,
+        owner: &Addr,
+        block: &cosmwasm_std::BlockInfo,
+    ) -> StdResult<(u128, u128)> {
+        let allowances = ALLOWANCES.add_suffix(owner.as_bytes());
+        let mut total: u128 = 0;
+        let mut active_total: u128 = 0;
+        for item in allowances.iter(store)? {
+            let (_, allowance) = item?;
+            total = total.saturating_add(allowance.amount);
+            if !allowance.is_expired_at(block) {
+                active_total = active_total.saturating_add(allowance.amount);
+            }
+        }
+
+        Ok((total, active_total))
+    }
+
     pub fn num_allowances(store: &dyn Storage, owner: &Addr) -> u32 {
         ALLOWANCES
             .add_suffix(owner.as_bytes())
@@ -209,6 +421,7 @@ impl AllowancesStore {
 
 // Receiver Interface
 pub static RECEIVER_HASH: Item<String> = Item::new(PREFIX_RECEIVERS);
+pub static RECEIVER_LABEL: Item<String> = Item::new(b"receiver-label");
 pub struct ReceiverHashStore {}
 impl ReceiverHashStore {
     pub fn may_load(store: &dyn Storage, account: &Addr) -> StdResult<Option<String>> {
@@ -220,6 +433,264 @@ impl ReceiverHashStore {
         let receiver_hash = RECEIVER_HASH.add_suffix(account.as_str().as_bytes());
         receiver_hash.save(store, &code_hash)
     }
+
+    pub fn may_load_label(store: &dyn Storage, account: &Addr) -> StdResult<Option<String>> {
+        let receiver_label = RECEIVER_LABEL.add_suffix(account.as_str().as_bytes());
+        receiver_label.may_load(store)
+    }
+
+    /// replaces the account's label, clearing it if none is given
+    pub fn save_label(
+        store: &mut dyn Storage,
+        account: &Addr,
+        label: Option<String>,
+    ) -> StdResult<()> {
+        let receiver_label = RECEIVER_LABEL.add_suffix(account.as_str().as_bytes());
+        match label {
+            Some(label) => receiver_label.save(store, &label),
+            None => {
+                receiver_label.remove(store);
+                Ok(())
+            }
+        }
+    }
+}
+
+// Memo decryption keys
+pub static MEMO_DECRYPTION_KEY: Item<Binary> = Item::new(b"memo-decryption-key");
+pub struct MemoDecryptionKeyStore {}
+impl MemoDecryptionKeyStore {
+    pub fn may_load(store: &dyn Storage, account: &Addr) -> StdResult<Option<Binary>> {
+        let memo_key = MEMO_DECRYPTION_KEY.add_suffix(account.as_str().as_bytes());
+        memo_key.may_load(store)
+    }
+
+    pub fn save(store: &mut dyn Storage, account: &Addr, pubkey: Binary) -> StdResult<()> {
+        let memo_key = MEMO_DECRYPTION_KEY.add_suffix(account.as_str().as_bytes());
+        memo_key.save(store, &pubkey)
+    }
+}
+
+// Accounts whose given allowances can be queried without authentication
+pub static PUBLIC_ALLOWANCES: Item<bool> = Item::new(b"public-allowances");
+pub struct PublicAllowancesStore {}
+impl PublicAllowancesStore {
+    pub fn is_public(store: &dyn Storage, owner: &Addr) -> bool {
+        let public_allowances = PUBLIC_ALLOWANCES.add_suffix(owner.as_str().as_bytes());
+        public_allowances.load(store).unwrap_or(false)
+    }
+
+    pub fn save(store: &mut dyn Storage, owner: &Addr, public: bool) -> StdResult<()> {
+        let public_allowances = PUBLIC_ALLOWANCES.add_suffix(owner.as_str().as_bytes());
+        public_allowances.save(store, &public)
+    }
+}
+
+// Preferred redeem denom
+pub static PREFERRED_REDEEM_DENOM: Item<String> = Item::new(b"preferred-redeem-denom");
+pub struct PreferredRedeemDenomStore {}
+impl PreferredRedeemDenomStore {
+    pub fn may_load(store: &dyn Storage, account: &Addr) -> StdResult<Option<String>> {
+        let preferred_denom = PREFERRED_REDEEM_DENOM.add_suffix(account.as_str().as_bytes());
+        preferred_denom.may_load(store)
+    }
+
+    pub fn save(store: &mut dyn Storage, account: &Addr, denom: String) -> StdResult<()> {
+        let preferred_denom = PREFERRED_REDEEM_DENOM.add_suffix(account.as_str().as_bytes());
+        preferred_denom.save(store, &denom)
+    }
+}
+
+// Redeem allowances
+pub static REDEEM_ALLOWANCES: Keymap<Addr, u128> = Keymap::new(b"redeem-allowances");
+pub struct RedeemAllowancesStore {}
+impl RedeemAllowancesStore {
+    pub fn load(store: &dyn Storage, owner: &Addr, spender: &Addr) -> u128 {
+        REDEEM_ALLOWANCES
+            .add_suffix(owner.as_bytes())
+            .get(store, spender)
+            .unwrap_or_default()
+    }
+
+    pub fn save(
+        store: &mut dyn Storage,
+        owner: &Addr,
+        spender: &Addr,
+        amount: u128,
+    ) -> StdResult<()> {
+        REDEEM_ALLOWANCES
+            .add_suffix(owner.as_bytes())
+            .insert(store, spender, &amount)
+    }
+}
+
+// Send callbacks whose receiver failed, kept for the recipient to retry
+
+/// reply id of send callbacks dispatched with `reply_on_error`
+pub const CALLBACK_REPLY_ID: u64 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PendingCallback {
+    pub id: u64,
+    pub code_hash: String,
+    /// the padded `Receive` message the recipient failed to process
+    pub msg: Binary,
+}
+
+pub static CALLBACK_IN_FLIGHT: Item<(Addr, PendingCallback)> = Item::new(b"callback-in-flight");
+pub static PENDING_CALLBACKS: Keymap<u64, PendingCallback> = Keymap::new(b"pending-callbacks");
+pub static PENDING_CALLBACK_COUNT: Item<u64> = Item::new(b"pending-callback-count");
+pub struct PendingCallbacksStore {}
+impl PendingCallbacksStore {
+    /// remembers a callback that is about to be dispatched, so the reply handler can
+    /// record it if the recipient fails
+    pub fn begin(
+        store: &mut dyn Storage,
+        recipient: &Addr,
+        code_hash: String,
+        msg: Binary,
+    ) -> StdResult<()> {
+        let id = PENDING_CALLBACK_COUNT.may_load(store)?.unwrap_or_default() + 1;
+        PENDING_CALLBACK_COUNT.save(store, &id)?;
+        CALLBACK_IN_FLIGHT.save(
+            store,
+            &(recipient.clone(), PendingCallback { id, code_hash, msg }),
+        )
+    }
+
+    /// records the callback dispatched last as pending for its recipient
+    pub fn record_failure(store: &mut dyn Storage) -> StdResult<()> {
+        let (recipient, callback) = CALLBACK_IN_FLIGHT.load(store)?;
+        PENDING_CALLBACKS
+            .add_suffix(recipient.as_bytes())
+            .insert(store, &callback.id, &callback)
+    }
+
+    /// removes and returns a pending callback of the recipient
+    pub fn take(
+        store: &mut dyn Storage,
+        recipient: &Addr,
+        id: u64,
+    ) -> StdResult<Option<PendingCallback>> {
+        let pending = PENDING_CALLBACKS.add_suffix(recipient.as_bytes());
+        let callback = pending.get(store, &id);
+        if callback.is_some() {
+            pending.remove(store, &id)?;
+        }
+        Ok(callback)
+    }
+
+    pub fn list(store: &dyn Storage, recipient: &Addr) -> StdResult<Vec<PendingCallback>> {
+        PENDING_CALLBACKS
+            .add_suffix(recipient.as_bytes())
+            .iter(store)?
+            .map(|item| item.map(|(_, callback)| callback))
+            .collect()
+    }
+}
+
+// Transfers held for their recipient until claimed or refunded
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PendingTransferStatus {
+    Pending,
+    Claimed,
+    Refunded,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PendingTransfer {
+    pub sender: Addr,
+    pub recipient: Addr,
+    pub amount: u128,
+    /// seconds since epoch after which the transfer can no longer be claimed, only refunded
+    pub refund_after: u64,
+    pub memo: Option<String>,
+    pub status: PendingTransferStatus,
+}
+
+pub static PENDING_TRANSFERS: Keymap<u64, PendingTransfer> = Keymap::new(b"pending-transfers");
+pub static PENDING_TRANSFER_COUNT: Item<u64> = Item::new(b"pending-transfer-count");
+pub struct PendingTransfersStore {}
+impl PendingTransfersStore {
+    /// stores a new pending transfer and returns its id
+    pub fn create(store: &mut dyn Storage, transfer: &PendingTransfer) -> StdResult<u64> {
+        let id = PENDING_TRANSFER_COUNT.may_load(store)?.unwrap_or_default() + 1;
+        PENDING_TRANSFER_COUNT.save(store, &id)?;
+        PENDING_TRANSFERS.insert(store, &id, transfer)?;
+        Ok(id)
+    }
+
+    pub fn load(store: &dyn Storage, id: u64) -> StdResult<PendingTransfer> {
+        PENDING_TRANSFERS
+            .get(store, &id)
+            .ok_or_else(|| StdError::generic_err(format!("Pending transfer {id} not found")))
+    }
+
+    pub fn save(store: &mut dyn Storage, id: u64, transfer: &PendingTransfer) -> StdResult<()> {
+        PENDING_TRANSFERS.insert(store, &id, transfer)
+    }
+}
+
+// Permit epochs
+pub static PERMIT_EPOCH: Item<u64> = Item::new(b"permit-epoch");
+pub struct PermitEpochStore {}
+impl PermitEpochStore {
+    pub fn load(store: &dyn Storage, account: &str) -> u64 {
+        PERMIT_EPOCH
+            .add_suffix(account.as_bytes())
+            .may_load(store)
+            .unwrap_or_default()
+            .unwrap_or_default()
+    }
+
+    pub fn save(store: &mut dyn Storage, account: &str, epoch: u64) -> StdResult<()> {
+        PERMIT_EPOCH
+            .add_suffix(account.as_bytes())
+            .save(store, &epoch)
+    }
+}
+
+// Nonces for transfers authorized off-chain with `TransferWithAuth`
+pub static AUTH_NONCES: Item<u64> = Item::new(b"auth-nonces");
+pub struct AuthNoncesStore {}
+impl AuthNoncesStore {
+    pub fn load(store: &dyn Storage, account: &Addr) -> u64 {
+        AUTH_NONCES
+            .add_suffix(account.as_bytes())
+            .may_load(store)
+            .unwrap_or_default()
+            .unwrap_or_default()
+    }
+
+    /// uses up `nonce` for the account, rejecting it unless it is the next one expected
+    pub fn consume(store: &mut dyn Storage, account: &Addr, nonce: u64) -> StdResult<()> {
+        let auth_nonce = AUTH_NONCES.add_suffix(account.as_bytes());
+        let expected = auth_nonce.may_load(store)?.unwrap_or_default();
+        if nonce != expected {
+            return Err(StdError::generic_err(format!(
+                "invalid nonce: expected {expected}, got {nonce}"
+            )));
+        }
+        auth_nonce.save(store, &(expected + 1))
+    }
+}
+
+// Block height of each account's most recent `CreateViewingKey`
+pub static VIEWING_KEY_CREATED_AT: Item<u64> = Item::new(b"viewing-key-created-at");
+pub struct ViewingKeyCreationStore {}
+impl ViewingKeyCreationStore {
+    /// records a key creation at `height`, rejecting it if the account's previous creation
+    /// was less than the cooldown ago
+    pub fn record(store: &mut dyn Storage, account: &Addr, height: u64) -> StdResult<()> {
+        let created_at = VIEWING_KEY_CREATED_AT.add_suffix(account.as_bytes());
+        if let Some(last) = created_at.may_load(store)? {
+            if height < last.saturating_add(VIEWING_KEY_CREATION_COOLDOWN_BLOCKS) {
+                return Err(StdError::generic_err("viewing key creation rate-limited"));
+            }
+        }
+        created_at.save(store, &height)
+    }
 }
 
 /// internal secret used for sensitive data such as address hashes in the btbe and notifications
@@ -231,5 +702,386 @@ pub static INTERNAL_SECRET_RELAXED: Item<Vec<u8>> = Item::new(b"internal-secret-
 /// SNIP-52 channels
 pub static CHANNELS: Keyset<String> = Keyset::new(b"channel-ids");
 
+/// SNIP-52 channel info templates for the built-in channels and those registered with
+/// `RegisterChannel`, served by `ChannelInfo` with the per-request fields filled in
+pub static CHANNEL_SCHEMATA: Keymap<String, ChannelInfoData, Json> =
+    Keymap::new(b"channel-schemata");
+
 /// SNIP-52 status
 pub static NOTIFICATIONS_ENABLED: Item<bool> = Item::new(b"notify-status");
+
+// SNIP-52 channel preferences
+
+/// channels each account has opted out of, used with add_suffix(address)
+pub static CHANNEL_OPT_OUTS: Keyset<String> = Keyset::new(b"channel-opt-outs");
+pub struct ChannelPreferencesStore {}
+impl ChannelPreferencesStore {
+    pub fn is_enabled(store: &dyn Storage, account: &Addr, channel: &str) -> bool {
+        !CHANNEL_OPT_OUTS
+            .add_suffix(account.as_bytes())
+            .contains(store, &channel.to_string())
+    }
+
+    pub fn set(
+        store: &mut dyn Storage,
+        account: &Addr,
+        channel: &str,
+        enabled: bool,
+    ) -> StdResult<()> {
+        let opt_outs = CHANNEL_OPT_OUTS.add_suffix(account.as_bytes());
+        if enabled {
+            opt_outs.remove(store, &channel.to_string())
+        } else {
+            opt_outs.insert(store, &channel.to_string()).map(|_| ())
+        }
+    }
+
+    pub fn disabled_channels(store: &dyn Storage, account: &Addr) -> StdResult<Vec<String>> {
+        CHANNEL_OPT_OUTS
+            .add_suffix(account.as_bytes())
+            .iter(store)?
+            .collect()
+    }
+}
+
+// Number of notifications emitted to each account on each counter-mode channel
+pub static NOTIFICATION_COUNTERS: Keymap<String, u64> = Keymap::new(b"notification-counters");
+pub struct NotificationCountersStore {}
+impl NotificationCountersStore {
+    pub fn load(store: &dyn Storage, account: &Addr, channel: &str) -> u64 {
+        NOTIFICATION_COUNTERS
+            .add_suffix(account.as_bytes())
+            .get(store, &channel.to_string())
+            .unwrap_or_default()
+    }
+
+    /// advances the account's counter on the channel, returning the new value
+    pub fn increment(store: &mut dyn Storage, account: &Addr, channel: &str) -> StdResult<u64> {
+        let counter = Self::load(store, account, channel) + 1;
+        NOTIFICATION_COUNTERS
+            .add_suffix(account.as_bytes())
+            .insert(store, &channel.to_string(), &counter)?;
+        Ok(counter)
+    }
+}
+
+// Streams releasing escrowed tokens to their recipient over time
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Stream {
+    pub sender: Addr,
+    pub recipient: Addr,
+    pub total: u128,
+    /// amount the recipient has already withdrawn
+    pub claimed: u128,
+    /// seconds since epoch at which tokens start vesting
+    pub start_time: u64,
+    /// seconds since epoch by which the whole total has vested
+    pub end_time: u64,
+}
+
+impl Stream {
+    /// the part of the total released by `now`, growing linearly from `start_time` to `end_time`
+    pub fn vested(&self, now: u64) -> u128 {
+        if now <= self.start_time {
+            0
+        } else if now >= self.end_time {
+            self.total
+        } else {
+            Uint128::new(self.total)
+                .multiply_ratio(now - self.start_time, self.end_time - self.start_time)
+                .u128()
+        }
+    }
+
+    /// the vested amount the recipient has not withdrawn yet
+    pub fn claimable(&self, now: u64) -> u128 {
+        self.vested(now).saturating_sub(self.claimed)
+    }
+}
+
+pub static STREAMS: Keymap<u64, Stream> = Keymap::new(b"streams");
+pub static STREAM_COUNT: Item<u64> = Item::new(b"stream-count");
+/// ids of the streams each account has yet to fully withdraw, used with add_suffix(recipient)
+pub static RECIPIENT_STREAMS: Keyset<u64> = Keyset::new(b"recipient-streams");
+pub struct StreamsStore {}
+impl StreamsStore {
+    /// stores a new stream and returns its id
+    pub fn create(store: &mut dyn Storage, stream: &Stream) -> StdResult<u64> {
+        let id = STREAM_COUNT.may_load(store)?.unwrap_or_default() + 1;
+        STREAM_COUNT.save(store, &id)?;
+        STREAMS.insert(store, &id, stream)?;
+        RECIPIENT_STREAMS
+            .add_suffix(stream.recipient.as_bytes())
+            .insert(store, &id)?;
+        Ok(id)
+    }
+
+    pub fn load(store: &dyn Storage, id: u64) -> StdResult<Stream> {
+        STREAMS
+            .get(store, &id)
+            .ok_or_else(|| StdError::generic_err(format!("Stream {id} not found")))
+    }
+
+    /// saves the stream, dropping it from its recipient's open streams once fully withdrawn
+    pub fn save(store: &mut dyn Storage, id: u64, stream: &Stream) -> StdResult<()> {
+        if stream.claimed >= stream.total {
+            RECIPIENT_STREAMS
+                .add_suffix(stream.recipient.as_bytes())
+                .remove(store, &id)?;
+        }
+        STREAMS.insert(store, &id, stream)
+    }
+
+    /// the total the account can withdraw from its open streams at `now`, or None if it has none
+    pub fn claimable_by(
+        store: &dyn Storage,
+        recipient: &Addr,
+        now: u64,
+    ) -> StdResult<Option<u128>> {
+        let ids = RECIPIENT_STREAMS
+            .add_suffix(recipient.as_bytes())
+            .iter(store)?
+            .collect::<StdResult<Vec<u64>>>()?;
+        if ids.is_empty() {
+            return Ok(None);
+        }
+
+        let mut claimable: u128 = 0;
+        for id in ids {
+            claimable = claimable.saturating_add(Self::load(store, id)?.claimable(now));
+        }
+        Ok(Some(claimable))
+    }
+}
+
+// Idempotency keys recently used by each account
+
+/// maximum number of an account's stored keys checked for expiry each time it uses a new one
+pub const IDEMPOTENCY_KEY_PRUNE_LIMIT: u32 = 8;
+
+/// sha256 of (account, key) -> block time (in seconds) the key was used at, used with
+/// add_suffix(account)
+pub static IDEMPOTENCY_KEYS: Keymap<[u8; 32], u64> = Keymap::new(b"idempotency-keys");
+pub struct IdempotencyKeysStore {}
+impl IdempotencyKeysStore {
+    /// records the key for the account, failing if the account already used it within the last
+    /// IDEMPOTENCY_KEY_TTL_SECONDS. also prunes a bounded number of the account's expired keys
+    pub fn consume(store: &mut dyn Storage, account: &Addr, key: &str, now: u64) -> StdResult<()> {
+        let key_len = key.len();
+        if key_len > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Err(StdError::generic_err(format!(
+                "idempotency key too long: length={key_len}, max={MAX_IDEMPOTENCY_KEY_LENGTH}",
+            )));
+        }
+
+        let used_keys = IDEMPOTENCY_KEYS.add_suffix(account.as_bytes());
+        let is_expired = |used_at: u64| now >= used_at.saturating_add(IDEMPOTENCY_KEY_TTL_SECONDS);
+
+        let expired = used_keys
+            .paging(store, 0, IDEMPOTENCY_KEY_PRUNE_LIMIT)?
+            .into_iter()
+            .filter(|(_, used_at)| is_expired(*used_at))
+            .map(|(hash, _)| hash)
+            .collect::<Vec<[u8; 32]>>();
+        for hash in expired {
+            used_keys.remove(store, &hash)?;
+        }
+
+        let hash = sha_256(format!("{account}:{key}").as_bytes());
+        if let Some(used_at) = used_keys.get(store, &hash) {
+            if !is_expired(used_at) {
+                return Err(StdError::generic_err("duplicate idempotency key"));
+            }
+        }
+        used_keys.insert(store, &hash, &now)
+    }
+}
+
+// Balance checkpoints
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BalanceCheckpoint {
+    pub block_height: u64,
+    pub balance: u128,
+}
+
+/// checkpoints of each account in increasing block height order, used with
+/// add_suffix(canonical address) and keyed by checkpoint index
+pub static BALANCE_CHECKPOINTS: Keymap<u32, BalanceCheckpoint> =
+    Keymap::new(b"balance-checkpoints");
+pub static BALANCE_CHECKPOINT_COUNT: Item<u32> = Item::new(b"balance-checkpoint-count");
+pub struct BalanceCheckpointsStore {}
+impl BalanceCheckpointsStore {
+    /// records the account's balance as of `block_height`, replacing any checkpoint already
+    /// written at that height
+    pub fn record(
+        store: &mut dyn Storage,
+        account: &CanonicalAddr,
+        block_height: u64,
+        balance: u128,
+    ) -> StdResult<()> {
+        let checkpoints = BALANCE_CHECKPOINTS.add_suffix(account.as_slice());
+        let count_store = BALANCE_CHECKPOINT_COUNT.add_suffix(account.as_slice());
+        let count = count_store.may_load(store)?.unwrap_or_default();
+
+        let checkpoint = BalanceCheckpoint {
+            block_height,
+            balance,
+        };
+        if count > 0 {
+            let last_idx = count - 1;
+            if let Some(last) = checkpoints.get(store, &last_idx) {
+                if last.block_height == block_height {
+                    return checkpoints.insert(store, &last_idx, &checkpoint);
+                }
+            }
+        }
+
+        checkpoints.insert(store, &count, &checkpoint)?;
+        count_store.save(store, &(count + 1))
+    }
+
+    /// returns the balance of the last checkpoint at or before `block_height`, if any
+    pub fn balance_at(
+        store: &dyn Storage,
+        account: &CanonicalAddr,
+        block_height: u64,
+    ) -> StdResult<Option<u128>> {
+        let checkpoints = BALANCE_CHECKPOINTS.add_suffix(account.as_slice());
+        let count = BALANCE_CHECKPOINT_COUNT
+            .add_suffix(account.as_slice())
+            .may_load(store)?
+            .unwrap_or_default();
+
+        // binary search for the number of checkpoints at or before `block_height`
+        let (mut lo, mut hi) = (0u32, count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let checkpoint = checkpoints
+                .get(store, &mid)
+                .ok_or_else(|| StdError::generic_err("Balance checkpoint not found"))?;
+            if checkpoint.block_height <= block_height {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        if lo == 0 {
+            return Ok(None);
+        }
+        Ok(checkpoints
+            .get(store, &(lo - 1))
+            .map(|checkpoint| checkpoint.balance))
+    }
+}
+
+// SNIP-52 emission log
+
+/// maximum number of emissions kept per channel, older records are overwritten
+pub const MAX_CHANNEL_EMISSION_LOG_LEN: u32 = 256;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ChannelEmission {
+    pub block_height: u64,
+    /// hex of the top 64 bits of the notification ID
+    pub marker: String,
+}
+
+pub static CHANNEL_EMISSION_LOG_ENABLED: Item<bool> = Item::new(b"emission-log-status");
+pub static CHANNEL_EMISSIONS: Keymap<u32, ChannelEmission> = Keymap::new(b"channel-emissions");
+pub static CHANNEL_EMISSION_COUNT: Item<u64> = Item::new(b"channel-emission-count");
+pub struct ChannelEmissionLogStore {}
+impl ChannelEmissionLogStore {
+    pub fn push(
+        store: &mut dyn Storage,
+        channel: &str,
+        emission: ChannelEmission,
+    ) -> StdResult<()> {
+        if !CHANNEL_EMISSION_LOG_ENABLED
+            .may_load(store)?
+            .unwrap_or_default()
+        {
+            return Ok(());
+        }
+
+        let emission_count = CHANNEL_EMISSION_COUNT.add_suffix(channel.as_bytes());
+        let count = emission_count.may_load(store)?.unwrap_or_default();
+
+        // ring buffer, overwriting the oldest record once the log is full
+        let slot = (count % MAX_CHANNEL_EMISSION_LOG_LEN as u64) as u32;
+        CHANNEL_EMISSIONS
+            .add_suffix(channel.as_bytes())
+            .insert(store, &slot, &emission)?;
+        emission_count.save(store, &(count + 1))
+    }
+
+    /// returns the total number of emissions ever logged for the channel and the requested
+    /// page of retained emissions, newest first
+    pub fn page(
+        store: &dyn Storage,
+        channel: &str,
+        page: u32,
+        page_size: u32,
+    ) -> StdResult<(u64, Vec<ChannelEmission>)> {
+        let count = CHANNEL_EMISSION_COUNT
+            .add_suffix(channel.as_bytes())
+            .may_load(store)?
+            .unwrap_or_default();
+        let retained = count.min(MAX_CHANNEL_EMISSION_LOG_LEN as u64);
+
+        let emissions_store = CHANNEL_EMISSIONS.add_suffix(channel.as_bytes());
+        let start = (page as u64).saturating_mul(page_size as u64);
+        let end = start.saturating_add(page_size as u64).min(retained);
+        let mut emissions = vec![];
+        for i in start..end {
+            let slot = ((count - 1 - i) % MAX_CHANNEL_EMISSION_LOG_LEN as u64) as u32;
+            if let Some(emission) = emissions_store.get(store, &slot) {
+                emissions.push(emission);
+            }
+        }
+
+        Ok((count, emissions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_add_saturates() {
+        let mut balance = 1000u128;
+        assert_eq!(safe_add(&mut balance, 500), 500);
+        assert_eq!(balance, 1500);
+
+        // at the overflow boundary only the remaining headroom is added
+        let mut balance = u128::MAX - 10;
+        assert_eq!(safe_add(&mut balance, 10), 10);
+        assert_eq!(balance, u128::MAX);
+
+        let mut balance = u128::MAX - 10;
+        assert_eq!(safe_add(&mut balance, 11), 10);
+        assert_eq!(balance, u128::MAX);
+    }
+
+    #[test]
+    fn test_checked_safe_add_errors_on_overflow() {
+        let mut total_supply = 1000u128;
+        assert_eq!(checked_safe_add(&mut total_supply, 500).unwrap(), 500);
+        assert_eq!(total_supply, 1500);
+
+        let mut total_supply = u128::MAX - 10;
+        assert_eq!(checked_safe_add(&mut total_supply, 10).unwrap(), 10);
+        assert_eq!(total_supply, u128::MAX);
+
+        // one past the boundary fails and leaves the total untouched
+        let mut total_supply = u128::MAX - 10;
+        let error = checked_safe_add(&mut total_supply, 11).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("amount would overflow total supply"));
+        assert_eq!(total_supply, u128::MAX - 10);
+    }
+}
@@ -1,3 +1,4 @@
 pub const TRANSFER_HISTORY_UNSUPPORTED_MSG: &str =
     "`transfer_history` query is UNSUPPORTED. Use `transaction_history` instead.";
 pub const SEND_TO_CONTRACT_ERR_MSG: &str = "Tokens cannot be sent to token contract.";
+pub const SEND_TO_TOKEN_CONTRACT_ERR_MSG: &str = "cannot send to the token contract";
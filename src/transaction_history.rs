@@ -1,3 +1,5 @@
+use rand_chacha::ChaChaRng;
+use rand_core::{RngCore, SeedableRng};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -7,9 +9,16 @@ use cosmwasm_std::{
 
 use secret_toolkit::storage::Item;
 
+use crate::constants::MAX_MEMO_LENGTH;
 use crate::state::TX_COUNT;
 
 const PREFIX_TXS: &[u8] = b"transactions";
+const PREFIX_ENCRYPTED_MEMOS: &[u8] = b"encrypted-memos";
+
+/// denom stored in the coins of txs moving this token, rather than its symbol, so renaming the
+/// symbol doesn't change how history is keyed. queries show the current symbol in its place.
+/// native denoms must start with a letter, so this can't be confused with a deposited coin
+pub const TOKEN_DENOM: &str = "_token";
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -31,6 +40,46 @@ pub enum TxAction {
     Redeem {},
 }
 
+/// Restricts a transaction history query to a single kind of action
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TxActionFilter {
+    Transfer,
+    Mint,
+    Burn,
+    Deposit,
+    Redeem,
+}
+
+impl TxActionFilter {
+    pub fn matches(&self, action: &TxAction) -> bool {
+        matches!(
+            (self, action),
+            (TxActionFilter::Transfer, TxAction::Transfer { .. })
+                | (TxActionFilter::Mint, TxAction::Mint { .. })
+                | (TxActionFilter::Burn, TxAction::Burn { .. })
+                | (TxActionFilter::Deposit, TxAction::Deposit {})
+                | (TxActionFilter::Redeem, TxAction::Redeem {})
+        )
+    }
+}
+
+impl TxAction {
+    /// whether `address` is one of the parties named in this action
+    pub fn involves(&self, address: &Addr) -> bool {
+        match self {
+            TxAction::Transfer {
+                from,
+                sender,
+                recipient,
+            } => from == address || sender == address || recipient == address,
+            TxAction::Mint { minter, recipient } => minter == address || recipient == address,
+            TxAction::Burn { burner, owner } => burner == address || owner == address,
+            TxAction::Deposit {} | TxAction::Redeem {} => false,
+        }
+    }
+}
+
 // Note that id is a globally incrementing counter.
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -40,6 +89,9 @@ pub struct Tx {
     pub coins: Coin,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub memo: Option<String>,
+    /// Whether the memo was encrypted client-side, so clients should attempt to decrypt it
+    #[serde(default)]
+    pub memo_encrypted: bool,
     // The block time and block height are optional so that the JSON schema
     // reflects that some SNIP-20 contracts may not include this info.
     pub block_time: u64,
@@ -207,6 +259,11 @@ impl StoredTxAction {
 // does not need to be an AppendStore because we never need to iterate over global list of txs
 pub static TRANSACTIONS: Item<StoredTx> = Item::new(PREFIX_TXS);
 
+// use with add_suffix tx id (u64 to_be_bytes)
+// only set for txs whose memo was flagged as encrypted, so txs stored without the flag read back as
+// unencrypted
+pub static ENCRYPTED_MEMOS: Item<bool> = Item::new(PREFIX_ENCRYPTED_MEMOS);
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct StoredTx {
@@ -218,18 +275,37 @@ pub struct StoredTx {
 }
 
 impl StoredTx {
-    pub fn into_humanized(self, api: &dyn Api, id: u64) -> StdResult<Tx> {
+    pub fn into_humanized(self, store: &dyn Storage, api: &dyn Api, id: u64) -> StdResult<Tx> {
+        let memo_encrypted = ENCRYPTED_MEMOS
+            .add_suffix(&id.to_be_bytes())
+            .may_load(store)?
+            .unwrap_or_default();
+
         Ok(Tx {
             id,
             action: self.action.into_tx_action(api)?,
             coins: self.coins.into(),
             memo: self.memo,
+            memo_encrypted,
             block_time: self.block_time,
             block_height: self.block_height,
         })
     }
 }
 
+/// the id a tx is shown under in queried history. ids are obfuscated deterministically so they
+/// are not serial, which would leak metadata
+pub fn obfuscated_tx_id(internal_secret: &[u8], serial_id: u64) -> u64 {
+    let internal_secret_u64: u64 = u64::from_be_bytes(internal_secret[..8].try_into().unwrap());
+
+    // PRNG(PRNG(serial_id) ^ secret)
+    let mut rng = ChaChaRng::seed_from_u64(serial_id);
+    let serial_id_rand = rng.next_u64();
+    let new_seed = serial_id_rand ^ internal_secret_u64;
+    let mut rng = ChaChaRng::seed_from_u64(new_seed);
+    rng.next_u64() >> (64 - 53)
+}
+
 // Storage functions:
 
 pub fn append_new_stored_tx(
@@ -240,6 +316,13 @@ pub fn append_new_stored_tx(
     memo: Option<String>,
     block: &BlockInfo,
 ) -> StdResult<u64> {
+    let memo_len = memo.as_ref().map_or(0, String::len);
+    if memo_len > MAX_MEMO_LENGTH {
+        return Err(StdError::generic_err(format!(
+            "memo too long: length={memo_len}, max={MAX_MEMO_LENGTH}",
+        )));
+    }
+
     // tx ids are serialized starting at 1
     let serial_id = TX_COUNT.load(store).unwrap_or_default() + 1;
     let coins = StoredCoin { denom, amount };
@@ -267,10 +350,22 @@ pub fn store_transfer_action(
     amount: u128,
     denom: String,
     memo: Option<String>,
+    memo_encrypted: bool,
     block: &BlockInfo,
 ) -> StdResult<u64> {
+    // the flag is meaningless without a memo to decrypt
+    let memo_encrypted = memo_encrypted && memo.is_some();
+
     let action = StoredTxAction::transfer(owner.clone(), sender.clone(), receiver.clone());
-    append_new_stored_tx(store, &action, amount, denom, memo, block)
+    let tx_id = append_new_stored_tx(store, &action, amount, denom, memo, block)?;
+
+    if memo_encrypted {
+        ENCRYPTED_MEMOS
+            .add_suffix(&tx_id.to_be_bytes())
+            .save(store, &true)?;
+    }
+
+    Ok(tx_id)
 }
 
 pub fn store_mint_action(
@@ -304,18 +399,20 @@ pub fn store_deposit_action(
     store: &mut dyn Storage,
     amount: u128,
     denom: String,
+    memo: Option<String>,
     block: &cosmwasm_std::BlockInfo,
 ) -> StdResult<u64> {
     let action = StoredTxAction::deposit();
-    append_new_stored_tx(store, &action, amount, denom, None, block)
+    append_new_stored_tx(store, &action, amount, denom, memo, block)
 }
 
 pub fn store_redeem_action(
     store: &mut dyn Storage,
     amount: u128,
     denom: String,
+    memo: Option<String>,
     block: &cosmwasm_std::BlockInfo,
 ) -> StdResult<u64> {
     let action = StoredTxAction::redeem();
-    append_new_stored_tx(store, &action, amount, denom, None, block)
+    append_new_stored_tx(store, &action, amount, denom, memo, block)
 }